@@ -0,0 +1,39 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/camera.proto");
+    // The gRPC service, see src/grpc.rs. Generating it needs `protoc`.
+    #[cfg(feature = "grpc")]
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/camera.proto"], &["proto"])?;
+    // The C interface of the Sony backend, see src/backend/sony/shim.cpp.
+    #[cfg(feature = "sony")]
+    sony_shim()?;
+    Ok(())
+}
+
+/// Builds the shim over Sony's Camera Remote SDK and links the SDK, which
+/// is unpacked at `SONY_CRSDK_DIR` with its headers in `app` and its
+/// libraries in `external/crsdk`, as Sony ships it.
+#[cfg(feature = "sony")]
+fn sony_shim() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=src/backend/sony/shim.cpp");
+    println!("cargo:rerun-if-env-changed=SONY_CRSDK_DIR");
+    let sdk = std::path::PathBuf::from(
+        std::env::var("SONY_CRSDK_DIR")
+            .map_err(|_| "the sony feature needs SONY_CRSDK_DIR set to the Camera Remote SDK")?,
+    );
+
+    cc::Build::new()
+        .cpp(true)
+        .std("c++17")
+        .include(sdk.join("app"))
+        .file("src/backend/sony/shim.cpp")
+        .compile("sony_shim");
+
+    let libraries = sdk.join("external/crsdk");
+    println!("cargo:rustc-link-search=native={}", libraries.display());
+    println!("cargo:rustc-link-lib=dylib=Cr_Core");
+    // The SDK loads its adapters from next to the binary or from here.
+    println!("cargo:rustc-link-arg=-Wl,-rpath,{}", libraries.display());
+    Ok(())
+}
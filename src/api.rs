@@ -0,0 +1,247 @@
+//! Local REST control API for on-board scripts and web dashboards, served
+//! by the HTTP server when [`crate::HttpServerOptions::api`] is set:
+//!
+//! - `GET /api/status`: health of the component, the state of every camera
+//!   and the ground stations and autopilots heard from
+//! - `POST /api/cameras/<camera>/capture`: takes a picture like a trigger
+//! - `POST /api/cameras/<camera>/offload`: downloads the pictures left on the
+//!   camera, see [`crate::OFFLOAD_COMMAND`]
+//! - `GET /api/cameras/<camera>/images`: the camera's images, oldest first
+//! - `GET /api/cameras/<camera>/parameters`: the settings by parameter name
+//! - `PUT /api/cameras/<camera>/parameters/<name>`: changes a setting to the
+//!   request body, as the camera names it, e.g. `1/1000` for `CAM_SHUTTERSPD`
+//! - `GET /api/events`: a WebSocket pushing every [`crate::CameraEvent`] and
+//!   camera state change as it happens, each a JSON object with a `type`, for
+//!   ground station add-ons in the browser
+//!
+//! where `<camera>` is the camera's component id. Responses are JSON, errors
+//! `{"error": "..."}`. There is no authentication, so only turn it on where
+//! the network is trusted.
+
+use crate::capture_log::json_string;
+use crate::control::{Control, Refused, SettingChange};
+use crate::http::encode_path;
+use crate::storage;
+use crate::thumbnail::thumbnail_path;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::Router;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::debug;
+
+/// What the handlers work with.
+#[derive(Clone)]
+struct Api {
+    control: Control,
+    image_dirs: Arc<HashMap<u8, PathBuf>>,
+}
+
+impl Api {
+    /// Where `path` of `camera` is served from `route`, `None` if it isn't.
+    fn url(&self, route: &str, camera: u8, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(self.image_dirs.get(&camera)?).ok()?;
+        Some(format!("/{route}/{camera}/{}", encode_path(relative)?))
+    }
+}
+
+pub(crate) fn routes(control: Control, image_dirs: Arc<HashMap<u8, PathBuf>>) -> Router {
+    Router::new()
+        .route("/api/status", get(status))
+        .route("/api/events", get(events))
+        .route("/api/cameras/{camera}/capture", post(capture))
+        .route("/api/cameras/{camera}/offload", post(offload))
+        .route("/api/cameras/{camera}/images", get(images))
+        .route("/api/cameras/{camera}/parameters", get(parameters))
+        .route(
+            "/api/cameras/{camera}/parameters/{name}",
+            put(set_parameter),
+        )
+        .with_state(Api {
+            control,
+            image_dirs,
+        })
+}
+
+async fn status(State(api): State<Api>) -> Response {
+    match api.control.status_json() {
+        Ok(body) => json(StatusCode::OK, body),
+        Err(_) => error(StatusCode::INTERNAL_SERVER_ERROR, "status unavailable"),
+    }
+}
+
+async fn events(upgrade: WebSocketUpgrade, State(api): State<Api>) -> Response {
+    upgrade.on_upgrade(move |socket| stream_events(socket, api))
+}
+
+/// Pushes the state of every camera, then every event and state change until
+/// the client goes away.
+async fn stream_events(mut socket: WebSocket, api: Api) {
+    debug!(target: "rx", "WebSocket client connected");
+    let mut events = api.control.subscribe();
+    let mut changes = api.control.state_changes();
+
+    loop {
+        let message = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => event.json(|route, camera, path| api.url(route, camera, path)),
+                Err(RecvError::Lagged(skipped)) => {
+                    format!(r#"{{"type":"lagged","skipped":{skipped}}}"#)
+                }
+                Err(RecvError::Closed) => break,
+            },
+            Some((camera, state)) = changes.recv() => {
+                format!(r#"{{"type":"state","camera":{camera},{}}}"#, state.json_fields())
+            }
+            received = socket.recv() => match received {
+                // Clients only listen, pings are answered underneath.
+                Some(Ok(_)) => continue,
+                None | Some(Err(_)) => break,
+            },
+        };
+        if socket.send(Message::text(message)).await.is_err() {
+            break;
+        }
+    }
+
+    debug!(target: "rx", "WebSocket client disconnected");
+}
+
+/// Queues a picture like a trigger from the autopilot, without waiting for
+/// it. It shows up in the images once downloaded.
+async fn capture(State(api): State<Api>, UrlPath(camera): UrlPath<u8>) -> Response {
+    match api.control.capture(camera) {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(refused) => refused_error(refused),
+    }
+}
+
+/// Downloads the pictures left on the camera without waiting for it. They
+/// show up in the images and events as they're downloaded.
+async fn offload(State(api): State<Api>, UrlPath(camera): UrlPath<u8>) -> Response {
+    match api.control.offload(camera) {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(refused) => refused_error(refused),
+    }
+}
+
+async fn images(State(api): State<Api>, UrlPath(camera): UrlPath<u8>) -> Response {
+    if !api.control.has_camera(camera) {
+        return refused_error(Refused::NoSuchCamera);
+    }
+    let Some(directory) = api.image_dirs.get(&camera).cloned() else {
+        return error(StatusCode::NOT_FOUND, "images not served");
+    };
+
+    let listed = {
+        let directory = directory.clone();
+        tokio::task::spawn_blocking(move || storage::list_images(&directory)).await
+    };
+    let images = match listed {
+        Ok(Ok(images)) => images,
+        Ok(Err(error)) => {
+            debug!(target: "rx", directory = %directory.display(), "Can't list images: {error}");
+            Vec::new()
+        }
+        Err(_) => return error(StatusCode::INTERNAL_SERVER_ERROR, "listing failed"),
+    };
+
+    let mut body = String::from("[");
+    for (path, modified, size) in images {
+        let Ok(relative) = path.strip_prefix(&directory) else {
+            continue;
+        };
+        let Some(url_path) = encode_path(relative) else {
+            continue;
+        };
+        if body.len() > 1 {
+            body.push(',');
+        }
+        let thumbnail = thumbnail_path(&path)
+            .filter(|thumbnail| thumbnail.exists())
+            .map_or("null".to_owned(), |_| {
+                json_string(&format!("/thumbnails/{camera}/{url_path}"))
+            });
+        let modified = DateTime::<Utc>::from(modified).to_rfc3339_opts(SecondsFormat::Secs, true);
+        let _ = write!(
+            body,
+            r#"{{"path":{},"size":{size},"modified":{},"url":{},"thumbnail":{thumbnail}}}"#,
+            json_string(&relative.display().to_string()),
+            json_string(&modified),
+            json_string(&format!("/images/{camera}/{url_path}"))
+        );
+    }
+    body.push(']');
+
+    json(StatusCode::OK, body)
+}
+
+async fn parameters(State(api): State<Api>, UrlPath(camera): UrlPath<u8>) -> Response {
+    let settings = match api.control.settings(camera).await {
+        Ok(settings) => settings,
+        Err(refused) => return refused_error(refused),
+    };
+
+    let mut body = String::from("{");
+    for (index, (name, setting)) in settings.iter().enumerate() {
+        if index > 0 {
+            body.push(',');
+        }
+        let _ = write!(body, "{}:{}", json_string(name), json_string(setting));
+    }
+    body.push('}');
+
+    json(StatusCode::OK, body)
+}
+
+async fn set_parameter(
+    State(api): State<Api>,
+    UrlPath((camera, name)): UrlPath<(u8, String)>,
+    setting: String,
+) -> Response {
+    let change = api
+        .control
+        .set_setting(camera, name.clone(), setting.trim().to_owned())
+        .await;
+
+    match change {
+        Ok(SettingChange::Changed(setting)) => json(
+            StatusCode::OK,
+            format!(
+                r#"{{"name":{},"setting":{}}}"#,
+                json_string(&name),
+                json_string(&setting)
+            ),
+        ),
+        Ok(SettingChange::UnknownParameter) => error(StatusCode::NOT_FOUND, "no such parameter"),
+        Ok(SettingChange::Unsupported) => error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "the camera doesn't take this setting",
+        ),
+        Ok(SettingChange::Failed(message)) => error(StatusCode::BAD_GATEWAY, &message),
+        Err(refused) => refused_error(refused),
+    }
+}
+
+fn json(status: StatusCode, body: String) -> Response {
+    (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    json(status, format!(r#"{{"error":{}}}"#, json_string(message)))
+}
+
+fn refused_error(refused: Refused) -> Response {
+    match refused {
+        Refused::NoSuchCamera => error(StatusCode::NOT_FOUND, "no such camera"),
+        Refused::Busy => error(StatusCode::SERVICE_UNAVAILABLE, "camera busy"),
+        Refused::Stopped => error(StatusCode::SERVICE_UNAVAILABLE, "camera stopped"),
+    }
+}
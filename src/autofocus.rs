@@ -0,0 +1,25 @@
+//! Autofocus before every shot, as a half-press of the shutter button would,
+//! so frames don't come out soft when the light changes between lines of a
+//! survey. Ground stations turn it on and off with the `CAM_AFSHOT`
+//! parameter. A camera that doesn't focus in time shoots anyway.
+
+use std::time::Duration;
+
+/// Whether and for how long cameras focus before each shot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutofocusOptions {
+    /// Focuses before each shot, until `CAM_AFSHOT` changes it.
+    pub enabled: bool,
+    /// Longest the camera may take to focus before it shoots anyway.
+    pub timeout: Duration,
+}
+
+impl Default for AutofocusOptions {
+    /// Off, with two seconds to focus once turned on.
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! Canon bodies through the Camera Control API (CCAPI), their REST interface
+//! over WiFi or USB Ethernet, for rigs where USB tethering to gphoto2 drops
+//! out. CCAPI has to be enabled on the camera first, see Canon's developer
+//! programme.
+//!
+//! Settings go by the keys gphoto2 uses for Canon bodies, e.g. `aperture`,
+//! with values in the same form, e.g. `5.6`, so the camera definition and
+//! parameter overrides work with either backend.
+
+use super::{
+    verify_download, CameraBackend, CameraFile, CameraModel, Capabilities, CapturedImage,
+    DownloadFormat, Lens, PendingShot, RetryOptions, SettingChoices, SettingRange, StorageInfo,
+};
+use anyhow::{anyhow, bail, Context as _, Result};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// The CCAPI settings the backend has, by gphoto2 key.
+const SETTINGS: [(&str, &str); 7] = [
+    ("iso", "iso"),
+    ("shutterspeed", "tv"),
+    ("aperture", "av"),
+    ("exposurecompensation", "exposure"),
+    ("whitebalance", "wb"),
+    ("colortemperature", "colortemperature"),
+    ("autoexposuremode", "shootingmodedial"),
+];
+
+/// Settings recorded in the capture log.
+const CAPTURE_SETTINGS: [&str; 5] = [
+    "iso",
+    "shutterspeed",
+    "aperture",
+    "exposurecompensation",
+    "whitebalance",
+];
+
+/// How long a request may take before the camera counts as gone.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for the first file of a shot to show up on the card.
+const SHOT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for another file of a shot, e.g. the JPEG of a RAW+JPEG
+/// capture, once one showed up.
+const SHOT_FILE_WAIT: Duration = Duration::from_secs(1);
+
+/// Pause between two polls for the files of a shot.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Backend for Canon bodies with CCAPI.
+pub struct CanonBackend {
+    agent: ureq::Agent,
+    /// `http://<address>/ccapi/ver100`.
+    base: String,
+    model: String,
+    image_dir: PathBuf,
+    download: DownloadFormat,
+    retry: RetryOptions,
+    delete_after_download: bool,
+}
+
+impl CanonBackend {
+    /// Connects to the camera's CCAPI at `address`, e.g. `192.168.1.2:8080`.
+    /// Captures are downloaded to `image_dir`.
+    pub fn open(address: &str, image_dir: impl Into<PathBuf>) -> Result<Self> {
+        let address = address.trim_end_matches('/');
+        let address = address
+            .strip_prefix("http://")
+            .unwrap_or(address)
+            .to_owned();
+        let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+
+        let mut backend = Self {
+            agent,
+            base: format!("http://{address}/ccapi/ver100"),
+            model: String::new(),
+            image_dir: image_dir.into(),
+            download: DownloadFormat::All,
+            retry: RetryOptions::NONE,
+            delete_after_download: false,
+        };
+        let information = backend
+            .get("deviceinformation")
+            .with_context(|| format!("No CCAPI camera at {address}"))?;
+        backend.model = information["productname"]
+            .as_str()
+            .unwrap_or("Canon")
+            .to_owned();
+        info!(target: "backend", model = %backend.model, %address, "Opened CCAPI camera");
+
+        std::fs::create_dir_all(&backend.image_dir).with_context(|| {
+            format!(
+                "Failed to create image directory {}",
+                backend.image_dir.display()
+            )
+        })?;
+        Ok(backend)
+    }
+
+    /// Only downloads these files of each shot.
+    pub fn with_download_format(mut self, download: DownloadFormat) -> Self {
+        self.download = download;
+        self
+    }
+
+    /// Tries failed downloads again, rather than losing the shot.
+    pub fn with_download_retry(mut self, retry: RetryOptions) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Deletes downloaded files from the camera, so its card doesn't fill up.
+    pub fn with_delete_after_download(mut self, delete: bool) -> Self {
+        self.delete_after_download = delete;
+        self
+    }
+
+    /// The URLs of every file on the camera's cards, oldest first on each.
+    pub fn files(&self) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+        for storage in paths(&self.get("contents")?) {
+            for directory in paths(&self.get_url(&storage)?) {
+                // Directories are listed a page of 100 files at a time.
+                for page in 1.. {
+                    let listing = self.get_url(&format!("{directory}?page={page}"))?;
+                    let page_files = paths(&listing);
+                    if page_files.is_empty() {
+                        break;
+                    }
+                    files.extend(page_files);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{path}", self.base)
+    }
+
+    fn get(&self, path: &str) -> Result<Value> {
+        self.get_url(&self.url(path))
+    }
+
+    /// Reads `url`, either relative to the camera, as the content URLs the
+    /// camera lists are, or absolute.
+    fn get_url(&self, url: &str) -> Result<Value> {
+        let url = self.absolute(url);
+        let response = self.agent.get(&url).call().map_err(request_error)?;
+        Ok(response.into_json()?)
+    }
+
+    fn put(&self, path: &str, body: Value) -> Result<Value> {
+        let response = self
+            .agent
+            .put(&self.url(path))
+            .send_json(body)
+            .map_err(request_error)?;
+        Ok(response.into_json()?)
+    }
+
+    fn post(&self, path: &str, body: Value) -> Result<Value> {
+        let response = self
+            .agent
+            .post(&self.url(path))
+            .send_json(body)
+            .map_err(request_error)?;
+        Ok(response.into_json()?)
+    }
+
+    fn absolute(&self, url: &str) -> String {
+        if url.starts_with('/') {
+            let host = self
+                .base
+                .strip_suffix("/ccapi/ver100")
+                .unwrap_or(&self.base);
+            format!("{host}{url}")
+        } else {
+            url.to_owned()
+        }
+    }
+
+    /// Reads a setting, `None` if the camera doesn't have it.
+    fn setting(&self, key: &str) -> Result<Option<Value>> {
+        let Some(name) = ccapi_name(key) else {
+            return Ok(None);
+        };
+        match self.get(&format!("shooting/settings/{name}")) {
+            Ok(setting) if !setting["value"].is_null() => Ok(Some(setting)),
+            Ok(_) | Err(_) => Ok(None),
+        }
+    }
+
+    /// The files added to the card since the last poll.
+    fn added_files(&self) -> Result<Vec<String>> {
+        let events = self.get("event/polling?timeout=immediately")?;
+        Ok(events["addedcontents"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|url| url.as_str().map(str::to_owned))
+            .collect())
+    }
+
+    /// Takes a single picture and returns the URLs of its files.
+    fn shoot(&self) -> Result<Vec<String>> {
+        // Forget files added before this shot.
+        self.added_files()?;
+        // Shoots at the current focus, a mapping rig is focused before takeoff.
+        self.post("shooting/control/shutterbutton", json!({ "af": false }))?;
+        self.shot_files()
+    }
+
+    /// Waits for the files of the shot just taken to show up on the card.
+    fn shot_files(&self) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+        let mut deadline = Instant::now() + SHOT_TIMEOUT;
+
+        while Instant::now() < deadline {
+            let added = self.added_files()?;
+            if !added.is_empty() {
+                files.extend(added);
+                deadline = Instant::now() + SHOT_FILE_WAIT;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        if files.is_empty() {
+            bail!("No image from the camera after the capture");
+        }
+        Ok(files)
+    }
+
+    /// Downloads the wanted `files` of one shot.
+    fn download_shot(&self, files: Vec<String>) -> Result<CapturedImage> {
+        let files = self.download.select(files, |url| file_name(url).to_owned());
+
+        let mut paths = Vec::with_capacity(files.len());
+        for url in files {
+            let path = self.image_dir.join(file_name(&url));
+            debug!(target: "backend", %url, "Downloading capture");
+
+            let mut retries = 0;
+            let verified = loop {
+                match self.download_file(&url, &path) {
+                    Ok(verified) => break verified,
+                    Err(error) if retries == self.retry.retries => return Err(error),
+                    Err(error) => {
+                        let delay = self.retry.delay(retries);
+                        warn!(target: "backend", %url, retry = retries + 1, "Download failed, retrying in {delay:?}: {error:#}");
+                        std::thread::sleep(delay);
+                        retries += 1;
+                    }
+                }
+            };
+            if self.delete_after_download {
+                self.delete_downloaded(&url, verified);
+            }
+            paths.push(path);
+        }
+
+        let path = paths.remove(0);
+        Ok(CapturedImage {
+            path,
+            companions: paths,
+        })
+    }
+
+    /// Downloads `url` to `path` and checks it, returning whether the camera
+    /// told its size, which the download then has.
+    fn download_file(&self, url: &str, path: &PathBuf) -> Result<bool> {
+        let response = self
+            .agent
+            .get(&self.absolute(url))
+            .call()
+            .map_err(request_error)?;
+        let size = response
+            .header("Content-Length")
+            .and_then(|length| length.parse::<u64>().ok());
+        let mut file = File::create(path)?;
+        std::io::copy(&mut response.into_reader(), &mut file)?;
+        verify_download(path, size)?;
+        Ok(size.is_some())
+    }
+
+    /// Deletes `url` from the camera if its download was `verified`, and
+    /// leaves it on the card otherwise.
+    fn delete_downloaded(&self, url: &str, verified: bool) {
+        if !verified {
+            warn!(target: "backend", %url, "Keeping the file on the camera: it didn't tell the file size");
+            return;
+        }
+        match self.agent.delete(&self.absolute(url)).call() {
+            Ok(_) => debug!(target: "backend", %url, "Deleted download from the camera"),
+            Err(error) => {
+                warn!(target: "backend", %url, "Keeping the file on the camera: {:#}", request_error(error))
+            }
+        }
+    }
+}
+
+/// The error of a failed request, with the camera's message, e.g. `Device
+/// busy`.
+fn request_error(error: ureq::Error) -> anyhow::Error {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let message = response
+                .into_json::<Value>()
+                .ok()
+                .and_then(|body| body["message"].as_str().map(str::to_owned))
+                .unwrap_or_default();
+            anyhow!("The camera answered {status}: {message}")
+        }
+        error => error.into(),
+    }
+}
+
+/// The URLs in a content listing.
+fn paths(listing: &Value) -> Vec<String> {
+    listing["path"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|url| url.as_str().map(str::to_owned))
+        .collect()
+}
+
+fn file_name(url: &str) -> &str {
+    url.rsplit('/').next().unwrap_or(url)
+}
+
+/// The file on the camera at `url`.
+fn camera_file(url: &str) -> CameraFile {
+    let (folder, name) = url.rsplit_once('/').unwrap_or(("", url));
+    CameraFile {
+        folder: folder.to_owned(),
+        name: name.to_owned(),
+    }
+}
+
+fn ccapi_name(key: &str) -> Option<&'static str> {
+    SETTINGS
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, ccapi)| *ccapi)
+}
+
+/// The CCAPI `value` of the setting `key` the way gphoto2 shows it.
+fn format(key: &str, value: &str) -> String {
+    match key {
+        "iso" if value == "auto" => "Auto".to_owned(),
+        // `0"5` for half a second, `30"` for thirty.
+        "shutterspeed" => match value.split_once('"') {
+            Some((seconds, "")) => seconds.to_owned(),
+            Some((seconds, tenths)) => format!("{seconds}.{tenths}"),
+            None => value.to_owned(),
+        },
+        "aperture" => value.trim_start_matches('f').to_owned(),
+        "exposurecompensation" => exposure(value).unwrap_or_else(|| value.to_owned()),
+        "whitebalance" => match value {
+            "auto" => "Auto",
+            "awbwhite" => "AWB White",
+            "daylight" => "Daylight",
+            "shade" => "Shade",
+            "cloudy" => "Cloudy",
+            "tungsten" => "Tungsten",
+            "whitefluorescent" => "White Fluorescent",
+            "flash" => "Flash",
+            "colortemp" => "Color Temperature",
+            custom => custom,
+        }
+        .to_owned(),
+        "autoexposuremode" => match value {
+            "m" => "Manual",
+            "av" => "Av",
+            "tv" => "Tv",
+            "p" => "P",
+            "fv" => "Fv",
+            other => other,
+        }
+        .to_owned(),
+        _ => value.to_owned(),
+    }
+}
+
+/// An exposure compensation such as `+1_1/3` in stops, e.g. `1.333`.
+fn exposure(value: &str) -> Option<String> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.trim_start_matches('+')),
+    };
+    let (whole, fraction) = value.split_once('_').unwrap_or((value, "0"));
+    let fraction = match fraction.split_once('/') {
+        Some((numerator, denominator)) => {
+            numerator.parse::<f32>().ok()? / denominator.parse::<f32>().ok()?
+        }
+        None => fraction.parse().ok()?,
+    };
+    let stops = whole.parse::<f32>().ok()? + fraction;
+    let stops = if negative { -stops } else { stops };
+
+    let stops = format!("{stops:.3}");
+    let stops = stops.trim_end_matches('0').trim_end_matches('.');
+    Some(if stops == "-0" { "0" } else { stops }.to_owned())
+}
+
+/// The value of a setting as text, which CCAPI gives as a string or, for the
+/// colour temperature, a number.
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+impl CameraBackend for CanonBackend {
+    fn capture_image(&mut self) -> Result<CapturedImage> {
+        let files = self.shoot()?;
+        self.download_shot(files)
+    }
+
+    fn trigger(&mut self) -> Result<PendingShot> {
+        let files = self.shoot()?;
+        Ok(PendingShot::OnCamera(
+            files.iter().map(|url| camera_file(url)).collect(),
+        ))
+    }
+
+    fn download(&mut self, shot: PendingShot) -> Result<CapturedImage> {
+        match shot {
+            PendingShot::OnCamera(files) => {
+                self.download_shot(files.iter().map(CameraFile::path).collect())
+            }
+            PendingShot::Downloaded(image) => Ok(image),
+        }
+    }
+
+    fn start_recording(&mut self) -> Result<()> {
+        self.post("shooting/control/moviemode", json!({ "action": "on" }))?;
+        // Forget files added before the movie.
+        self.added_files()?;
+        self.post("shooting/control/recbutton", json!({ "action": "start" }))?;
+        info!(target: "backend", "Started recording");
+        Ok(())
+    }
+
+    fn stop_recording(&mut self) -> Result<Option<CameraFile>> {
+        self.post("shooting/control/recbutton", json!({ "action": "stop" }))?;
+        info!(target: "backend", "Stopped recording");
+        let movie = match self.shot_files() {
+            Ok(files) => files.first().map(|url| camera_file(url)),
+            Err(error) => {
+                warn!(target: "backend", "The camera didn't tell the movie's file: {error}");
+                None
+            }
+        };
+        if let Err(error) = self.post("shooting/control/moviemode", json!({ "action": "off" })) {
+            warn!(target: "backend", "Failed to leave movie mode: {error}");
+        }
+        Ok(movie)
+    }
+
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        // Every CCAPI body records movies, none zooms or focuses over it.
+        Ok(Capabilities {
+            video: true,
+            ..Capabilities::default()
+        })
+    }
+
+    fn check_connection(&mut self) -> Result<()> {
+        self.get("deviceinformation")?;
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        // Every request connects anew, the camera only has to be back.
+        self.check_connection()?;
+        info!(target: "backend", model = %self.model, "CCAPI camera is back");
+        Ok(())
+    }
+
+    fn set_config(&mut self, key: &str, value: &str) -> Result<()> {
+        let name = ccapi_name(key).ok_or_else(|| anyhow!("The camera has no setting {key}"))?;
+        let setting = self
+            .setting(key)?
+            .ok_or_else(|| anyhow!("The camera has no setting {key} now"))?;
+
+        let raw = if setting["ability"].is_object() {
+            json!(value.trim().parse::<f64>()?.round() as i64)
+        } else {
+            setting["ability"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|choice| format(key, &value_text(choice)) == value)
+                .cloned()
+                .ok_or_else(|| anyhow!("{value} is not a choice of {key}"))?
+        };
+
+        debug!(target: "backend", key, value, "Writing camera setting");
+        self.put(
+            &format!("shooting/settings/{name}"),
+            json!({ "value": raw }),
+        )?;
+        Ok(())
+    }
+
+    fn capture_settings(&mut self) -> Result<BTreeMap<String, String>> {
+        let mut settings = BTreeMap::new();
+        for key in CAPTURE_SETTINGS {
+            if let Some(setting) = self.setting(key)? {
+                settings.insert(key.to_owned(), format(key, &value_text(&setting["value"])));
+            }
+        }
+        Ok(settings)
+    }
+
+    fn setting_choices(&mut self, key: &str) -> Result<Option<SettingChoices>> {
+        let Some(setting) = self.setting(key)? else {
+            return Ok(None);
+        };
+        let Some(choices) = setting["ability"].as_array() else {
+            return Ok(None);
+        };
+
+        Ok(Some(SettingChoices {
+            current: format(key, &value_text(&setting["value"])),
+            choices: choices
+                .iter()
+                .map(|choice| format(key, &value_text(choice)))
+                .collect(),
+        }))
+    }
+
+    fn setting_range(&mut self, key: &str) -> Result<Option<SettingRange>> {
+        let Some(setting) = self.setting(key)? else {
+            return Ok(None);
+        };
+        let ability = &setting["ability"];
+        let (Some(current), Some(min), Some(max)) = (
+            setting["value"].as_f64(),
+            ability["min"].as_f64(),
+            ability["max"].as_f64(),
+        ) else {
+            return Ok(None);
+        };
+
+        Ok(Some(SettingRange {
+            current: current as f32,
+            range: min as f32..=max as f32,
+            step: ability["step"].as_f64().unwrap_or(0.0) as f32,
+        }))
+    }
+
+    fn model(&mut self) -> Result<Option<CameraModel>> {
+        Ok(Some(CameraModel {
+            vendor: "Canon".to_owned(),
+            model: self.model.clone(),
+        }))
+    }
+
+    fn lens(&mut self) -> Result<Option<Lens>> {
+        let lens = self.get("devicestatus/lens")?;
+        if lens["mount"].as_bool() != Some(true) {
+            return Ok(None);
+        }
+        Ok(lens["name"].as_str().map(|name| Lens {
+            model: name.trim().to_owned(),
+            focal_length_mm: None,
+        }))
+    }
+
+    fn storage_info(&mut self) -> Result<Vec<StorageInfo>> {
+        let storage = self.get("devicestatus/storage")?;
+        Ok(storage["storagelist"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|card| {
+                Some(StorageInfo {
+                    total_bytes: card["maxsize"].as_u64()?,
+                    available_bytes: card["spacesize"].as_u64()?,
+                    name: card["name"].as_str().map(str::to_owned),
+                    root: card["path"].as_str().map(str::to_owned),
+                    usage: None,
+                })
+            })
+            .collect())
+    }
+
+    fn format_storage(&mut self, storage: usize) -> Result<()> {
+        let storages = self.get("devicestatus/storage")?;
+        let name = storages["storagelist"][storage]["name"]
+            .as_str()
+            .ok_or_else(|| anyhow!("The camera has no storage {}", storage + 1))?
+            .to_owned();
+        self.post("functions/cardformat", json!({ "storagename": name }))?;
+        info!(target: "backend", %name, "Formatted camera card");
+        Ok(())
+    }
+
+    fn battery_level(&mut self) -> Result<Option<u8>> {
+        // Only told in steps.
+        let battery = self.get("devicestatus/battery")?;
+        Ok(match battery["level"].as_str() {
+            Some("full") => Some(100),
+            Some("high") => Some(75),
+            Some("half") => Some(50),
+            Some("quarter") => Some(25),
+            Some("low") => Some(10),
+            _ => None,
+        })
+    }
+
+    fn preview_frame(&mut self) -> Result<Vec<u8>> {
+        // Switching live view on again when it's on is harmless.
+        self.post(
+            "shooting/liveview",
+            json!({ "liveviewsize": "small", "cameradisplay": "on" }),
+        )?;
+        let response = self
+            .agent
+            .get(&self.url("shooting/liveview/flip"))
+            .call()
+            .map_err(request_error)?;
+        let mut frame = Vec::new();
+        std::io::copy(&mut response.into_reader(), &mut frame)?;
+        Ok(frame)
+    }
+}
@@ -0,0 +1,803 @@
+use super::{
+    verify_download, CameraBackend, CameraFile, CameraModel, Capabilities, CapturedImage,
+    DownloadFormat, Lens, PendingShot, RetryOptions, SettingChoices, SettingRange, StorageInfo,
+    Unsupported, Zoom,
+};
+use anyhow::{anyhow, bail, Context as _, Result};
+use chrono::{DateTime, Utc};
+use gphoto2::camera::CameraEvent;
+use gphoto2::file::CameraFilePath;
+use gphoto2::list::CameraDescriptor;
+use gphoto2::widget::Widget;
+use gphoto2::{Camera, Context};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Settings recorded in the capture log, where the camera has them. Names
+/// differ between drivers, hence both `f-number` and `aperture`.
+const CAPTURE_SETTINGS: [&str; 6] = [
+    "iso",
+    "shutterspeed",
+    "f-number",
+    "aperture",
+    "exposurecompensation",
+    "whitebalance",
+];
+
+/// Prefix of the ports of cameras tethered over a network with PTP/IP, e.g.
+/// `ptpip:192.168.1.1`.
+const PTPIP_PORT: &str = "ptpip:";
+
+/// The libgphoto2 driver for any PTP/IP camera.
+const PTPIP_MODEL: &str = "PTP/IP Camera";
+
+/// How long the camera has to stay quiet after an autofocus for it to count as
+/// done, as Canon bodies report changes while the lens moves but nothing once
+/// it's focused.
+const AUTOFOCUS_SETTLE: Duration = Duration::from_millis(300);
+
+/// How long to wait for another file of a shot, e.g. the JPEG of a RAW+JPEG
+/// capture. The camera announces it right after the capture returns.
+const SHOT_FILE_WAIT: Duration = Duration::from_millis(200);
+
+/// Longest to spend collecting the files of a shot, as some cameras keep
+/// sending unrelated events.
+const SHOT_FILES_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait for the next file of a burst, longer than a frame takes
+/// at the slowest continuous drive.
+const BURST_FILE_WAIT: Duration = Duration::from_secs(1);
+
+/// How long after a bulb exposure to wait for its file, on top of the
+/// exposure itself for long exposure noise reduction.
+const BULB_FILE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long after a movie stops to wait for its file, which takes a while
+/// to finish on the card.
+const MOVIE_FILE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Backend for any camera supported by libgphoto2.
+pub struct GPhotoBackend {
+    context: Context,
+    /// `None` until a camera was found.
+    camera: Option<Camera>,
+    port: Option<String>,
+    /// libgphoto2's name of the camera, once one was found.
+    model: Option<String>,
+    image_dir: PathBuf,
+    download: DownloadFormat,
+    retry: RetryOptions,
+    delete_after_download: bool,
+}
+
+impl GPhotoBackend {
+    /// Opens the camera on the gphoto2 `port` (e.g. `usb:001,004`, or
+    /// `ptpip:192.168.1.1` for a camera on the network), or the first detected
+    /// camera when no port is given. Captures are downloaded to `image_dir`.
+    ///
+    /// Without a camera there the backend starts anyway, failing
+    /// [`CameraBackend::check_connection`] until [`CameraBackend::reconnect`]
+    /// finds one, e.g. when it's plugged in or switched on later.
+    pub fn open(port: Option<&str>, image_dir: impl Into<PathBuf>) -> Result<Self> {
+        let context = Context::new()?;
+        let (camera, model) = match attach(&context, port, None) {
+            Ok(camera) => {
+                let model = camera.abilities().model().into_owned();
+                info!(target: "backend", %model, "Opened gphoto2 camera");
+                (Some(camera), Some(model))
+            }
+            Err(error) => {
+                warn!(target: "backend", "Starting without a camera: {error:#}");
+                (None, None)
+            }
+        };
+
+        let image_dir = image_dir.into();
+        std::fs::create_dir_all(&image_dir)
+            .with_context(|| format!("Failed to create image directory {}", image_dir.display()))?;
+
+        Ok(Self {
+            context,
+            camera,
+            port: port.map(str::to_owned),
+            model,
+            image_dir,
+            download: DownloadFormat::All,
+            retry: RetryOptions::NONE,
+            delete_after_download: false,
+        })
+    }
+
+    /// Only downloads these files of each shot.
+    pub fn with_download_format(mut self, download: DownloadFormat) -> Self {
+        self.download = download;
+        self
+    }
+
+    /// Tries failed downloads again, rather than losing the shot.
+    pub fn with_download_retry(mut self, retry: RetryOptions) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Deletes downloaded files from the camera, so its card doesn't fill up.
+    pub fn with_delete_after_download(mut self, delete: bool) -> Self {
+        self.delete_after_download = delete;
+        self
+    }
+
+    /// The attached camera, an error until one was found.
+    fn camera(&self) -> Result<&Camera> {
+        self.camera.as_ref().context("No camera attached")
+    }
+
+    /// The files the camera announces after a capture, the other halves of
+    /// RAW+JPEG shots and further frames of a burst. Stops once no file came
+    /// for `wait`, or after `timeout`.
+    fn other_shot_files(&self, wait: Duration, timeout: Duration) -> Result<Vec<CameraFilePath>> {
+        let deadline = Instant::now() + timeout;
+        let mut files = Vec::new();
+
+        while Instant::now() < deadline {
+            match self.camera()?.wait_event(wait).wait()? {
+                CameraEvent::NewFile(file) => files.push(file),
+                CameraEvent::Timeout | CameraEvent::CaptureComplete => break,
+                _ => {}
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Takes a single picture and returns its files.
+    fn shoot(&self) -> Result<Vec<CameraFilePath>> {
+        let mut files = vec![self.camera()?.capture_image().wait()?];
+        files.extend(self.other_shot_files(SHOT_FILE_WAIT, SHOT_FILES_TIMEOUT)?);
+        Ok(files)
+    }
+
+    /// Downloads the wanted `files` of one shot.
+    fn download_shot(&self, files: Vec<CameraFilePath>) -> Result<CapturedImage> {
+        self.download_files(files.iter().map(camera_file).collect())
+    }
+
+    /// Downloads the wanted `files` of one shot, as they're named on the
+    /// camera.
+    fn download_files(&self, files: Vec<CameraFile>) -> Result<CapturedImage> {
+        let files = self.download.select(files, |file| file.name.clone());
+
+        let mut paths = Vec::with_capacity(files.len());
+        for file in files {
+            let path = self.image_dir.join(&file.name);
+            debug!(target: "backend", folder = %file.folder, name = %file.name, "Downloading capture");
+
+            let mut retries = 0;
+            let verified = loop {
+                match self.download_file(&file, &path) {
+                    Ok(verified) => break verified,
+                    Err(error) if retries == self.retry.retries => return Err(error),
+                    Err(error) => {
+                        let delay = self.retry.delay(retries);
+                        warn!(target: "backend", name = %file.name, retry = retries + 1, "Download failed, retrying in {delay:?}: {error:#}");
+                        std::thread::sleep(delay);
+                        retries += 1;
+                    }
+                }
+            };
+            if self.delete_after_download {
+                self.delete_downloaded(&file, verified);
+            }
+            paths.push(path);
+        }
+
+        let path = paths.remove(0);
+        Ok(CapturedImage {
+            path,
+            companions: paths,
+        })
+    }
+
+    /// Downloads `file` to `path` and checks it, returning whether the camera
+    /// told its size, which the download then has.
+    fn download_file(&self, file: &CameraFile, path: &Path) -> Result<bool> {
+        let (folder, name) = (&file.folder, &file.name);
+        self.camera()?.fs().download_to(folder, name, path).wait()?;
+
+        // Not every driver knows the size of its files.
+        let size = match self.camera()?.fs().file_info(folder, name).wait() {
+            Ok(info) => info.file().and_then(|file| file.size()),
+            Err(error) => {
+                debug!(target: "backend", %name, "Failed to read file info: {error}");
+                None
+            }
+        };
+        verify_download(path, size)?;
+        Ok(size.is_some())
+    }
+
+    /// Deletes every file below `folder` on the camera, returning how many.
+    fn erase_folder(&self, folder: &str) -> Result<usize> {
+        let fs = self.camera()?.fs();
+        let mut deleted = 0;
+        for subfolder in fs.list_folders(folder).wait()? {
+            deleted += self.erase_folder(&folder_path(folder, &subfolder))?;
+        }
+        for name in fs.list_files(folder).wait()? {
+            fs.delete_file(folder, &name).wait()?;
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+
+    /// Deletes `file` from the camera if its download was `verified`, and
+    /// leaves it on the card otherwise.
+    fn delete_downloaded(&self, file: &CameraFile, verified: bool) {
+        let (folder, name) = (&file.folder, &file.name);
+        if !verified {
+            warn!(target: "backend", %folder, %name, "Keeping the file on the camera: it didn't tell the file size");
+            return;
+        }
+        let deleted = self
+            .camera()
+            .and_then(|camera| Ok(camera.fs().delete_file(folder, name).wait()?));
+        match deleted {
+            Ok(()) => debug!(target: "backend", %folder, %name, "Deleted download from the camera"),
+            Err(error) => {
+                warn!(target: "backend", %folder, %name, "Keeping the file on the camera: {error}")
+            }
+        }
+    }
+
+    /// Holds the shutter open for `exposure` and returns the files of the shot.
+    /// The shutter speed must be at bulb.
+    fn expose_bulb(&self, exposure: Duration) -> Result<Vec<CameraFilePath>> {
+        self.set_bulb_release(true)?;
+        std::thread::sleep(exposure);
+        self.set_bulb_release(false)?;
+
+        let deadline = Instant::now() + exposure + BULB_FILE_TIMEOUT;
+        while Instant::now() < deadline {
+            if let CameraEvent::NewFile(file) = self.camera()?.wait_event(BURST_FILE_WAIT).wait()? {
+                let mut files = vec![file];
+                files.extend(self.other_shot_files(SHOT_FILE_WAIT, SHOT_FILES_TIMEOUT)?);
+                return Ok(files);
+            }
+        }
+        bail!("No image from the camera after the bulb exposure")
+    }
+
+    /// Opens or closes the shutter in bulb mode, with `eosremoterelease` on
+    /// Canon bodies and the `bulb` toggle on others.
+    fn set_bulb_release(&self, open: bool) -> Result<()> {
+        if let Ok(Widget::Radio(widget)) = self
+            .camera()?
+            .config_key::<Widget>("eosremoterelease")
+            .wait()
+        {
+            widget.set_choice(if open { "Press Full" } else { "Release Full" })?;
+            self.camera()?.set_config(&widget).wait()?;
+            return Ok(());
+        }
+
+        match self.camera()?.config_key::<Widget>("bulb").wait() {
+            Ok(Widget::Toggle(widget)) => {
+                widget.set_toggled(open);
+                self.camera()?.set_config(&widget).wait()?;
+                Ok(())
+            }
+            _ => Err(Unsupported("Bulb exposure").into()),
+        }
+    }
+
+    /// Starts or stops a movie with the `movie` toggle of Canon and Nikon
+    /// bodies.
+    fn set_movie(&self, recording: bool) -> Result<()> {
+        match self.camera()?.config_key::<Widget>("movie").wait() {
+            Ok(Widget::Toggle(widget)) => {
+                widget.set_toggled(recording);
+                self.camera()?.set_config(&widget).wait()?;
+                Ok(())
+            }
+            _ => Err(Unsupported("Video recording").into()),
+        }
+    }
+
+    /// Takes a burst in the continuous drive of Nikon bodies, which shoot
+    /// `burstnumber` frames per capture. Returns the files of each shot.
+    fn capture_continuous(&self, count: u32) -> Result<Vec<Vec<CameraFilePath>>> {
+        let Widget::Range(widget) = self.camera()?.config_key::<Widget>("burstnumber").wait()?
+        else {
+            bail!("burstnumber is not a range");
+        };
+        let previous = widget.value();
+        widget.set_value(count as f32)?;
+        self.camera()?.set_config(&widget).wait()?;
+
+        let capture = || -> Result<Vec<CameraFilePath>> {
+            let mut files = vec![self.camera()?.capture_image().wait()?];
+            files.extend(self.other_shot_files(BURST_FILE_WAIT, SHOT_FILES_TIMEOUT * count)?);
+            Ok(files)
+        };
+        let files = capture();
+
+        // Single shots again, also after a failed burst. The frames are
+        // still worth downloading if that fails.
+        let restore = || -> Result<()> {
+            widget.set_value(previous)?;
+            self.camera()?.set_config(&widget).wait()?;
+            Ok(())
+        };
+        if let Err(error) = restore() {
+            warn!(target: "backend", "Failed to restore burstnumber: {error}");
+        }
+
+        // The files of a shot share their name but for the extension.
+        let mut shots: Vec<(String, Vec<CameraFilePath>)> = Vec::new();
+        for file in files? {
+            let name = file.name();
+            let stem = name
+                .rsplit_once('.')
+                .map_or(&*name, |(stem, _)| stem)
+                .to_owned();
+            match shots.iter_mut().find(|(shot, _)| *shot == stem) {
+                Some((_, shot)) => shot.push(file),
+                None => shots.push((stem, vec![file])),
+            }
+        }
+        Ok(shots.into_iter().map(|(_, files)| files).collect())
+    }
+}
+
+/// Finds the camera on `port`. A USB camera usually comes back on a different
+/// port after being unplugged, so when `model` is given a camera of the same
+/// model on any port is accepted as well.
+fn attach(context: &Context, port: Option<&str>, model: Option<&str>) -> Result<Camera> {
+    if port.is_none() && model.is_none() {
+        return Ok(context.autodetect_camera().wait()?);
+    }
+    // Cameras on the network aren't detected, and keep their address.
+    if let Some(port) = port.filter(|port| port.starts_with(PTPIP_PORT)) {
+        let descriptor = CameraDescriptor {
+            model: PTPIP_MODEL.to_owned(),
+            port: port.to_owned(),
+        };
+        return context
+            .get_camera(&descriptor)
+            .wait()
+            .with_context(|| format!("No PTP/IP camera at {port}"));
+    }
+
+    let descriptors: Vec<_> = context.list_cameras().wait()?.collect();
+    let descriptor = descriptors
+        .iter()
+        .find(|descriptor| Some(descriptor.port.as_str()) == port)
+        .or_else(|| {
+            descriptors
+                .iter()
+                .find(|descriptor| Some(descriptor.model.as_str()) == model)
+        })
+        .ok_or_else(|| match port {
+            Some(port) => anyhow!("No camera found on port {port}"),
+            None => anyhow!("No camera found"),
+        })?;
+
+    Ok(context.get_camera(descriptor).wait()?)
+}
+
+/// Subfolder `name` of `folder` on the camera.
+fn folder_path(folder: &str, name: &str) -> String {
+    format!("{}/{name}", folder.trim_end_matches('/'))
+}
+
+fn camera_file(file: &CameraFilePath) -> CameraFile {
+    CameraFile {
+        folder: file.folder().into_owned(),
+        name: file.name().into_owned(),
+    }
+}
+
+impl CameraBackend for GPhotoBackend {
+    fn capture_image(&mut self) -> Result<CapturedImage> {
+        let files = self.shoot()?;
+        self.download_shot(files)
+    }
+
+    fn trigger(&mut self) -> Result<PendingShot> {
+        let files = self.shoot()?;
+        Ok(PendingShot::OnCamera(
+            files.iter().map(camera_file).collect(),
+        ))
+    }
+
+    fn download(&mut self, shot: PendingShot) -> Result<CapturedImage> {
+        match shot {
+            PendingShot::OnCamera(files) => self.download_files(files),
+            PendingShot::Downloaded(image) => Ok(image),
+        }
+    }
+
+    fn capture_bulb(&mut self, exposure: Duration) -> Result<CapturedImage> {
+        let Ok(Widget::Radio(shutter)) = self.camera()?.config_key::<Widget>("shutterspeed").wait()
+        else {
+            return Err(Unsupported("Bulb exposure").into());
+        };
+        let bulb = shutter
+            .choices_iter()
+            .find(|choice| choice.eq_ignore_ascii_case("bulb"))
+            .ok_or(Unsupported("Bulb exposure"))?;
+        let previous = shutter.choice();
+
+        debug!(target: "backend", ?exposure, "Capturing bulb exposure");
+        shutter.set_choice(&bulb)?;
+        self.camera()?.set_config(&shutter).wait()?;
+        let files = self.expose_bulb(exposure);
+
+        let restore = || -> Result<()> {
+            shutter.set_choice(&previous)?;
+            self.camera()?.set_config(&shutter).wait()?;
+            Ok(())
+        };
+        if let Err(error) = restore() {
+            warn!(target: "backend", "Failed to restore the shutter speed: {error}");
+        }
+
+        self.download_shot(files?)
+    }
+
+    fn start_recording(&mut self) -> Result<()> {
+        self.set_movie(true)?;
+        info!(target: "backend", "Started recording");
+        Ok(())
+    }
+
+    fn stop_recording(&mut self) -> Result<Option<CameraFile>> {
+        self.set_movie(false)?;
+        info!(target: "backend", "Stopped recording");
+
+        let deadline = Instant::now() + MOVIE_FILE_TIMEOUT;
+        while Instant::now() < deadline {
+            if let CameraEvent::NewFile(file) = self.camera()?.wait_event(BURST_FILE_WAIT).wait()? {
+                return Ok(Some(camera_file(&file)));
+            }
+        }
+        warn!(target: "backend", "The camera didn't tell the movie's file");
+        Ok(None)
+    }
+
+    fn capture_burst(&mut self, count: u32) -> Vec<Result<CapturedImage>> {
+        // Other bodies have no continuous drive that works over USB.
+        let burst_number = self
+            .camera()
+            .map(|camera| camera.config_key::<Widget>("burstnumber").wait());
+        if !matches!(burst_number, Ok(Ok(Widget::Range(_)))) {
+            return (0..count).map(|_| self.capture_image()).collect();
+        }
+
+        debug!(target: "backend", count, "Capturing burst in continuous drive");
+        let shots = match self.capture_continuous(count) {
+            Ok(shots) => shots,
+            Err(error) => return vec![Err(error)],
+        };
+        let taken = shots.len();
+        let mut frames: Vec<_> = shots
+            .into_iter()
+            .map(|files| self.download_shot(files))
+            .collect();
+        frames.extend(
+            (taken..count as usize)
+                .map(|_| Err(anyhow!("Camera took only {taken} of {count} burst frames"))),
+        );
+        frames
+    }
+
+    fn check_connection(&mut self) -> Result<()> {
+        self.camera()?.storages().wait()?;
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        let camera = attach(&self.context, self.port.as_deref(), self.model.as_deref())?;
+        let model = camera.abilities().model().into_owned();
+
+        // The old handle refers to a device that's gone; replacing it closes it.
+        self.camera = Some(camera);
+        info!(target: "backend", %model, "Attached gphoto2 camera");
+        self.model = Some(model);
+        Ok(())
+    }
+
+    fn set_config(&mut self, key: &str, value: &str) -> Result<()> {
+        debug!(target: "backend", key, value, "Writing camera setting");
+        match self.camera()?.config_key::<Widget>(key).wait()? {
+            Widget::Radio(widget) => {
+                widget.set_choice(value)?;
+                self.camera()?.set_config(&widget).wait()?;
+            }
+            Widget::Text(widget) => {
+                widget.set_value(value)?;
+                self.camera()?.set_config(&widget).wait()?;
+            }
+            Widget::Range(widget) => {
+                widget.set_value(value.parse()?)?;
+                self.camera()?.set_config(&widget).wait()?;
+            }
+            Widget::Toggle(widget) => {
+                widget.set_toggled(matches!(value, "1" | "true" | "on"));
+                self.camera()?.set_config(&widget).wait()?;
+            }
+            _ => bail!("Camera setting {key} is not writable"),
+        }
+
+        Ok(())
+    }
+
+    fn capture_settings(&mut self) -> Result<BTreeMap<String, String>> {
+        let mut settings = BTreeMap::new();
+
+        for key in CAPTURE_SETTINGS {
+            let value = match self.camera()?.config_key::<Widget>(key).wait() {
+                Ok(Widget::Radio(widget)) => widget.choice(),
+                Ok(Widget::Text(widget)) => widget.value(),
+                Ok(Widget::Range(widget)) => widget.value().to_string(),
+                _ => continue,
+            };
+            settings.insert(key.to_owned(), value);
+        }
+
+        Ok(settings)
+    }
+
+    fn setting_choices(&mut self, key: &str) -> Result<Option<SettingChoices>> {
+        // Drivers name settings differently, a missing one isn't an error.
+        let Ok(Widget::Radio(widget)) = self.camera()?.config_key::<Widget>(key).wait() else {
+            return Ok(None);
+        };
+
+        Ok(Some(SettingChoices {
+            current: widget.choice(),
+            choices: widget.choices_iter().collect(),
+        }))
+    }
+
+    fn setting_range(&mut self, key: &str) -> Result<Option<SettingRange>> {
+        let Ok(Widget::Range(widget)) = self.camera()?.config_key::<Widget>(key).wait() else {
+            return Ok(None);
+        };
+
+        let (range, step) = widget.range_and_step();
+        Ok(Some(SettingRange {
+            current: widget.value(),
+            range,
+            step,
+        }))
+    }
+
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        // The same widgets zoom() and drive_focus() use.
+        let zoom = self.camera()?.config_key::<Widget>("zoom").wait();
+        let focus = self
+            .camera()?
+            .config_key::<Widget>("manualfocusdrive")
+            .wait();
+        let autofocus = self.camera()?.config_key::<Widget>("autofocusdrive").wait();
+        let movie = self.camera()?.config_key::<Widget>("movie").wait();
+        Ok(Capabilities {
+            zoom: matches!(zoom, Ok(Widget::Range(_))),
+            focus: matches!(focus, Ok(Widget::Range(_) | Widget::Radio(_))),
+            autofocus: matches!(autofocus, Ok(Widget::Toggle(_))),
+            video: matches!(movie, Ok(Widget::Toggle(_))),
+            track_point: false,
+            track_rectangle: false,
+        })
+    }
+
+    fn model(&mut self) -> Result<Option<CameraModel>> {
+        // libgphoto2 names the maker first, e.g. `Sony Alpha-A7r II`.
+        Ok(self
+            .model
+            .as_deref()
+            .and_then(|model| model.split_once(' '))
+            .map(|(vendor, model)| CameraModel {
+                vendor: vendor.to_owned(),
+                model: model.trim().to_owned(),
+            }))
+    }
+
+    fn lens(&mut self) -> Result<Option<Lens>> {
+        // Canon and Nikon name the lens, only Nikon tells its focal length.
+        let Ok(Widget::Text(name)) = self.camera()?.config_key::<Widget>("lensname").wait() else {
+            return Ok(None);
+        };
+        let model = name.value().trim().to_owned();
+        if model.is_empty() {
+            return Ok(None);
+        }
+
+        let focal_length_mm = match self.camera()?.config_key::<Widget>("focallength").wait() {
+            Ok(Widget::Range(widget)) => Some(widget.value()),
+            Ok(Widget::Text(widget)) => widget.value().trim_end_matches("mm").trim().parse().ok(),
+            _ => None,
+        };
+        Ok(Some(Lens {
+            model,
+            focal_length_mm: focal_length_mm.filter(|length| *length > 0.0),
+        }))
+    }
+
+    fn shutter_count(&mut self) -> Result<Option<u64>> {
+        // Nikon and some Canon and Sony drivers have it, as text or a range.
+        let count = match self.camera()?.config_key::<Widget>("shuttercounter").wait() {
+            Ok(Widget::Text(widget)) => widget.value().trim().parse().ok(),
+            Ok(Widget::Range(widget)) => Some(widget.value().max(0.0).round() as u64),
+            _ => None,
+        };
+        Ok(count)
+    }
+
+    fn temperature(&mut self) -> Result<Option<f32>> {
+        // Few drivers expose it and they name it differently, some as text
+        // such as "41°C".
+        for key in ["bodytemperature", "cameratemperature", "temperature"] {
+            match self.camera()?.config_key::<Widget>(key).wait() {
+                Ok(Widget::Range(widget)) => return Ok(Some(widget.value())),
+                Ok(Widget::Text(widget)) => {
+                    let value = widget.value();
+                    let value = value.trim().trim_end_matches(['C', 'c', '°']).trim_end();
+                    return Ok(value.parse().ok());
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn storage_info(&mut self) -> Result<Vec<StorageInfo>> {
+        let storages = self.camera()?.storages().wait()?;
+
+        // libgphoto2 reports capacities in KiB.
+        Ok(storages
+            .iter()
+            .filter_map(|storage| {
+                Some(StorageInfo {
+                    total_bytes: storage.capacity()? as u64 * 1024,
+                    available_bytes: storage.free()? as u64 * 1024,
+                    name: storage
+                        .label()
+                        .or_else(|| storage.description())
+                        .map(|name| name.into_owned()),
+                    root: storage.base_directory().map(|root| root.into_owned()),
+                    usage: None,
+                })
+            })
+            .collect())
+    }
+
+    fn format_storage(&mut self, storage: usize) -> Result<()> {
+        let storages = self.camera()?.storages().wait()?;
+        let root = storages
+            .get(storage)
+            .ok_or_else(|| anyhow!("The camera has no storage {}", storage + 1))?
+            .base_directory()
+            .ok_or_else(|| anyhow!("The camera doesn't tell where storage {} is", storage + 1))?
+            .into_owned();
+
+        // libgphoto2 can't format, so every file is deleted instead.
+        let deleted = self.erase_folder(&root)?;
+        info!(target: "backend", %root, deleted, "Erased camera storage");
+        Ok(())
+    }
+
+    fn battery_level(&mut self) -> Result<Option<u8>> {
+        // Only some drivers expose this, usually as text such as "75%".
+        let Ok(Widget::Text(widget)) = self.camera()?.config_key::<Widget>("batterylevel").wait()
+        else {
+            return Ok(None);
+        };
+
+        Ok(widget.value().trim().trim_end_matches('%').parse().ok())
+    }
+
+    fn preview_frame(&mut self) -> Result<Vec<u8>> {
+        let file = self.camera()?.capture_preview().wait()?;
+        Ok(file.get_data(&self.context).wait()?.into_vec())
+    }
+
+    fn zoom(&mut self, zoom: Zoom) -> Result<()> {
+        // Only cameras with a motorised zoom, mostly compacts, have this.
+        let Ok(Widget::Range(widget)) = self.camera()?.config_key::<Widget>("zoom").wait() else {
+            return Err(Unsupported("Zoom").into());
+        };
+
+        let (range, _) = widget.range_and_step();
+        let position = match zoom {
+            Zoom::Absolute(position) => position,
+            Zoom::Step(step) => widget.value() + step,
+            Zoom::Range(share) => range.start() + share * (range.end() - range.start()),
+        };
+        let position = position.clamp(*range.start(), *range.end());
+
+        debug!(target: "backend", position, "Zooming");
+        widget.set_value(position)?;
+        self.camera()?.set_config(&widget).wait()?;
+        Ok(())
+    }
+
+    fn drive_focus(&mut self, steps: i32) -> Result<()> {
+        // Canon bodies only drive the focus in live view.
+        match self
+            .camera()?
+            .config_key::<Widget>("manualfocusdrive")
+            .wait()
+        {
+            // Nikon: a drive by that many steps, positive towards infinity.
+            Ok(Widget::Range(widget)) => {
+                debug!(target: "backend", steps, "Driving focus");
+                widget.set_value(steps as f32)?;
+                self.camera()?.set_config(&widget).wait()?;
+            }
+            // Canon: "Near 1" to "Near 3" and "Far 1" to "Far 3", 1 being the
+            // smallest step.
+            Ok(Widget::Radio(widget)) => {
+                let choice = if steps < 0 { "Near 1" } else { "Far 1" };
+                debug!(target: "backend", steps, "Driving focus");
+                for _ in 0..steps.unsigned_abs() {
+                    widget.set_choice(choice)?;
+                    self.camera()?.set_config(&widget).wait()?;
+                }
+            }
+            _ => return Err(Unsupported("Manual focus").into()),
+        }
+        Ok(())
+    }
+
+    fn set_clock(&mut self, time: DateTime<Utc>) -> Result<()> {
+        // Canon bodies keep UTC apart from the time zone they show, other
+        // drivers take the companion's local time, UTC on most companions.
+        for key in ["datetimeutc", "datetime"] {
+            if let Ok(Widget::Date(widget)) = self.camera()?.config_key::<Widget>(key).wait() {
+                debug!(target: "backend", key, %time, "Setting camera clock");
+                widget.set_timestamp(time.timestamp().try_into()?);
+                self.camera()?.set_config(&widget).wait()?;
+                return Ok(());
+            }
+        }
+        Err(Unsupported("Setting the clock").into())
+    }
+
+    fn autofocus(&mut self, timeout: Duration) -> Result<()> {
+        // Canon and Nikon bodies. Nikon focuses before the write returns and
+        // fails it if it can't, Canon starts focusing and goes on until it's
+        // switched off again.
+        let Ok(Widget::Toggle(widget)) =
+            self.camera()?.config_key::<Widget>("autofocusdrive").wait()
+        else {
+            return Err(Unsupported("Autofocus").into());
+        };
+
+        debug!(target: "backend", "Autofocusing");
+        let deadline = Instant::now() + timeout;
+        widget.set_toggled(true);
+        self.camera()?.set_config(&widget).wait()?;
+
+        let mut focused = false;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            if let CameraEvent::Timeout = self
+                .camera()?
+                .wait_event(left.min(AUTOFOCUS_SETTLE))
+                .wait()?
+            {
+                focused = left >= AUTOFOCUS_SETTLE;
+                break;
+            }
+        }
+
+        widget.set_toggled(false);
+        self.camera()?.set_config(&widget).wait()?;
+        if !focused {
+            bail!("Camera didn't focus within {timeout:?}");
+        }
+        Ok(())
+    }
+}
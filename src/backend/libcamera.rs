@@ -0,0 +1,463 @@
+//! Raspberry Pi cameras, e.g. the HQ and Global Shutter cameras, through
+//! libcamera's `rpicam-still`. It runs for as long as the backend, with the
+//! camera streaming, and takes a still whenever it gets `SIGUSR1`, so a
+//! capture doesn't wait for the camera to start. With RAW downloads each shot
+//! comes with a DNG of the full sensor.
+//!
+//! Shots are written straight to the image directory, so there's no copy on
+//! a card to delete.
+//!
+//! Settings go by gphoto2's keys and values, like with the other backends.
+//! Changing one restarts `rpicam-still`.
+
+use super::{
+    verify_download, CameraBackend, CameraModel, CapturedImage, DownloadFormat, RetryOptions,
+    Sensor, SettingChoices, SettingRange,
+};
+use anyhow::{anyhow, bail, Context as _, Result};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// The still apps of libcamera, `libcamera-still` before Bookworm.
+const STILL_APPS: [&str; 2] = ["rpicam-still", "libcamera-still"];
+
+/// How long the camera takes to start streaming, before which `SIGUSR1`
+/// would kill the app.
+const STARTUP: Duration = Duration::from_secs(2);
+
+/// How long to wait for the files of a shot.
+const SHOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause between two looks at the files of a shot. A file is taken as written
+/// once its size stayed the same over one.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Prefix of the files written, followed by the frame number.
+const FILE_PREFIX: &str = "pi_";
+
+/// The settings with choices as gphoto2 key, initial value and choices.
+const SETTING_CHOICES: [(&str, &str, &[&str]); 3] = [
+    (
+        "shutterspeed",
+        "Auto",
+        &[
+            "Auto", "1/8000", "1/4000", "1/2000", "1/1000", "1/500", "1/250", "1/125", "1/60",
+            "1/30",
+        ],
+    ),
+    ("iso", "Auto", &["Auto", "100", "200", "400", "800", "1600"]),
+    (
+        "whitebalance",
+        "Auto",
+        &[
+            "Auto",
+            "Incandescent",
+            "Tungsten",
+            "Fluorescent",
+            "Indoor",
+            "Daylight",
+            "Cloudy",
+        ],
+    ),
+];
+
+/// Exposure compensation in stops, as `rpicam-still --ev` takes it.
+const EV_RANGE: (f32, f32, f32) = (-4.0, 4.0, 1.0 / 3.0);
+
+/// Backend for Raspberry Pi cameras through libcamera.
+pub struct LibcameraBackend {
+    app: &'static str,
+    camera: u32,
+    model: String,
+    sensor: Option<Sensor>,
+    image_dir: PathBuf,
+    download: DownloadFormat,
+    retry: RetryOptions,
+    settings: BTreeMap<String, String>,
+    /// Number of the next shot's files.
+    frame: u32,
+    process: Option<Child>,
+}
+
+impl LibcameraBackend {
+    /// Opens the camera with the libcamera index `camera`, `0` when not
+    /// given. Captures are written to `image_dir`.
+    pub fn open(camera: Option<&str>, image_dir: impl Into<PathBuf>) -> Result<Self> {
+        let camera = match camera {
+            Some(camera) => camera
+                .parse()
+                .with_context(|| format!("Invalid libcamera camera index {camera}"))?,
+            None => 0,
+        };
+        let (app, cameras) = list_cameras()?;
+        let sensor = cameras
+            .get(&camera)
+            .ok_or_else(|| anyhow!("No libcamera camera {camera}"))?;
+        let model = model(sensor);
+        let sensor = sensor_geometry(sensor);
+        info!(target: "backend", %model, camera, "Opened libcamera camera");
+
+        let image_dir = image_dir.into();
+        std::fs::create_dir_all(&image_dir)
+            .with_context(|| format!("Failed to create image directory {}", image_dir.display()))?;
+        let frame = next_frame(&image_dir)?;
+
+        let mut backend = Self {
+            app,
+            camera,
+            model,
+            sensor,
+            image_dir,
+            download: DownloadFormat::All,
+            retry: RetryOptions::NONE,
+            settings: SETTING_CHOICES
+                .iter()
+                .map(|(key, value, _)| (key.to_string(), value.to_string()))
+                .chain([("exposurecompensation".to_owned(), "0".to_owned())])
+                .collect(),
+            frame,
+            process: None,
+        };
+        backend.start()?;
+        Ok(backend)
+    }
+
+    /// Only keeps these files of each shot. The DNG is only written when
+    /// RAW files are wanted.
+    pub fn with_download_format(mut self, download: DownloadFormat) -> Self {
+        let raw = self.raw();
+        self.download = download;
+        if self.raw() != raw {
+            // Started again with or without `--raw` when next used.
+            self.stop();
+        }
+        self
+    }
+
+    /// Waits again for shot files that are late or don't check out yet, e.g.
+    /// on a slow SD card, rather than losing the shot.
+    pub fn with_download_retry(mut self, retry: RetryOptions) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn raw(&self) -> bool {
+        self.download != DownloadFormat::Jpeg
+    }
+
+    /// (Re)starts `rpicam-still` with the current settings.
+    fn start(&mut self) -> Result<()> {
+        self.stop();
+
+        let mut command = Command::new(self.app);
+        command
+            .arg("--camera")
+            .arg(self.camera.to_string())
+            .args(["--nopreview", "--signal", "--timeout", "0"])
+            .arg("--output")
+            .arg(self.image_dir.join(format!("{FILE_PREFIX}%05d.jpg")))
+            .arg("--framestart")
+            .arg(self.frame.to_string());
+        if self.raw() {
+            command.arg("--raw");
+        }
+        if let Some(seconds) = shutter_seconds(&self.settings["shutterspeed"])? {
+            command
+                .arg("--shutter")
+                .arg(((seconds * 1e6).round() as u64).to_string());
+        }
+        if let Ok(iso) = self.settings["iso"].parse::<f32>() {
+            command.arg("--gain").arg((iso / 100.0).to_string());
+        }
+        command
+            .arg("--ev")
+            .arg(&self.settings["exposurecompensation"])
+            .arg("--awb")
+            .arg(self.settings["whitebalance"].to_ascii_lowercase());
+
+        debug!(target: "backend", ?command, "Starting libcamera");
+        let process = command
+            .env("LIBCAMERA_LOG_LEVELS", "*:WARN")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.app))?;
+        self.process = Some(process);
+
+        std::thread::sleep(STARTUP);
+        self.check_connection()
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+
+    /// The files of shot `frame`, once written.
+    fn shot_files(&mut self, frame: u32) -> Result<Vec<PathBuf>> {
+        let stem = format!("{FILE_PREFIX}{frame:05}");
+        let mut files = vec![self.image_dir.join(format!("{stem}.jpg"))];
+        if self.raw() {
+            files.push(self.image_dir.join(format!("{stem}.dng")));
+        }
+
+        let deadline = Instant::now() + SHOT_TIMEOUT;
+        let mut sizes = Vec::new();
+        loop {
+            let now: Option<Vec<u64>> = files
+                .iter()
+                .map(|file| std::fs::metadata(file).ok().map(|metadata| metadata.len()))
+                .collect();
+            match now {
+                Some(now) if now == sizes && !now.contains(&0) => return Ok(files),
+                Some(now) => sizes = now,
+                None => {}
+            }
+
+            if Instant::now() >= deadline {
+                self.check_connection()?;
+                bail!("No image from {} after the capture", self.app);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// The files of shot `frame` once written and checked, waited for again
+    /// as `retry` says.
+    fn verified_shot_files(&mut self, frame: u32) -> Result<Vec<PathBuf>> {
+        let mut retries = 0;
+        loop {
+            let files = self.shot_files(frame).and_then(|files| {
+                for file in &files {
+                    verify_download(file, None)?;
+                }
+                Ok(files)
+            });
+            match files {
+                Ok(files) => return Ok(files),
+                Err(error) if retries == self.retry.retries => return Err(error),
+                Err(error) => {
+                    let delay = self.retry.delay(retries);
+                    warn!(target: "backend", frame, retry = retries + 1, "Shot not written yet, waiting again in {delay:?}: {error:#}");
+                    std::thread::sleep(delay);
+                    retries += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for LibcameraBackend {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// The cameras libcamera has, by index, with their sensor, e.g. `imx477`,
+/// and the still app that listed them.
+fn list_cameras() -> Result<(&'static str, BTreeMap<u32, String>)> {
+    for app in STILL_APPS {
+        let output = match Command::new(app).arg("--list-cameras").output() {
+            Ok(output) => output,
+            Err(error) if error.kind() == ErrorKind::NotFound => continue,
+            Err(error) => return Err(error).with_context(|| format!("Failed to run {app}")),
+        };
+
+        // e.g. `0 : imx477 [4056x3040 12-bit RGGB] (/base/soc/i2c0mux/...)`.
+        let cameras = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let (index, rest) = line.split_once(" : ")?;
+                let sensor = rest.split_whitespace().next()?;
+                Some((index.trim().parse().ok()?, sensor.to_owned()))
+            })
+            .collect();
+        return Ok((app, cameras));
+    }
+    bail!("Neither of {} is installed", STILL_APPS.join(" and "))
+}
+
+/// The camera module with the `sensor`.
+fn model(sensor: &str) -> String {
+    match sensor {
+        "imx477" => "HQ Camera",
+        "imx296" => "Global Shutter Camera",
+        "imx708" => "Camera Module 3",
+        "imx219" => "Camera Module 2",
+        sensor => sensor,
+    }
+    .to_owned()
+}
+
+/// The active area and full resolution of the `sensor`, for those of the
+/// Raspberry Pi camera modules.
+fn sensor_geometry(sensor: &str) -> Option<Sensor> {
+    let (size_mm, resolution) = match sensor {
+        "imx477" => ((6.287, 4.712), (4056, 3040)),
+        "imx296" => ((5.023, 3.754), (1456, 1088)),
+        "imx708" => ((6.451, 3.629), (4608, 2592)),
+        "imx219" => ((3.674, 2.760), (3280, 2464)),
+        _ => return None,
+    };
+    Some(Sensor {
+        size_mm,
+        resolution,
+    })
+}
+
+/// The frame number after the last shot in `image_dir`, so earlier shots
+/// aren't overwritten.
+fn next_frame(image_dir: &Path) -> Result<u32> {
+    let mut next = 0;
+    for entry in std::fs::read_dir(image_dir)? {
+        let name = entry?.file_name();
+        let frame = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(FILE_PREFIX))
+            .and_then(|name| name.split_once('.'))
+            .and_then(|(frame, _)| frame.parse::<u32>().ok());
+        if let Some(frame) = frame {
+            next = next.max(frame + 1);
+        }
+    }
+    Ok(next)
+}
+
+/// A shutter speed like `1/1000` or `2` in seconds, `None` for `Auto`.
+fn shutter_seconds(value: &str) -> Result<Option<f64>> {
+    if value.eq_ignore_ascii_case("auto") {
+        return Ok(None);
+    }
+    let seconds = match value.split_once('/') {
+        Some((numerator, denominator)) => {
+            numerator.trim().parse::<f64>()? / denominator.trim().parse::<f64>()?
+        }
+        None => value.trim().parse()?,
+    };
+    if seconds <= 0.0 {
+        bail!("Invalid shutter speed {value}");
+    }
+    Ok(Some(seconds))
+}
+
+impl CameraBackend for LibcameraBackend {
+    fn capture_image(&mut self) -> Result<CapturedImage> {
+        self.check_connection()?;
+        let pid = self.process.as_ref().map_or(0, Child::id) as libc::pid_t;
+
+        let frame = self.frame;
+        // SAFETY: `pid` is our own child, which isn't waited for yet, so the
+        // id can't have been reused. 0 would signal our own process group,
+        // but check_connection leaves a running child.
+        if unsafe { libc::kill(pid, libc::SIGUSR1) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to trigger libcamera");
+        }
+        self.frame += 1;
+
+        let files = self.verified_shot_files(frame)?;
+        let mut files = self
+            .download
+            .select(files, |file| file.to_string_lossy().into_owned());
+        let path = files.remove(0);
+        Ok(CapturedImage {
+            path,
+            companions: files,
+        })
+    }
+
+    fn check_connection(&mut self) -> Result<()> {
+        let Some(process) = self.process.as_mut() else {
+            return self.start();
+        };
+        if let Some(status) = process.try_wait()? {
+            self.process = None;
+            bail!("{} exited with {status}", self.app);
+        }
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.start()?;
+        info!(target: "backend", model = %self.model, "Restarted libcamera");
+        Ok(())
+    }
+
+    fn set_config(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "exposurecompensation" => {
+                let (min, max, _) = EV_RANGE;
+                let ev: f32 = value.trim().parse()?;
+                if !(min..=max).contains(&ev) {
+                    bail!("{value} is out of range for {key}");
+                }
+            }
+            "shutterspeed" => {
+                shutter_seconds(value)?;
+            }
+            _ => {
+                let (_, _, choices) = SETTING_CHOICES
+                    .iter()
+                    .find(|(name, _, _)| *name == key)
+                    .ok_or_else(|| anyhow!("The camera has no setting {key}"))?;
+                if !choices.contains(&value) {
+                    bail!("{value} is not a choice of {key}");
+                }
+            }
+        }
+
+        debug!(target: "backend", key, value, "Writing camera setting");
+        let previous = self.settings.insert(key.to_owned(), value.to_owned());
+        if let Err(error) = self.start() {
+            warn!(target: "backend", key, value, "libcamera didn't start with the new setting: {error:#}");
+            if let Some(previous) = previous {
+                self.settings.insert(key.to_owned(), previous);
+            }
+            self.start()?;
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    fn capture_settings(&mut self) -> Result<BTreeMap<String, String>> {
+        Ok(self.settings.clone())
+    }
+
+    fn setting_choices(&mut self, key: &str) -> Result<Option<SettingChoices>> {
+        Ok(SETTING_CHOICES
+            .iter()
+            .find(|(name, _, _)| *name == key)
+            .map(|(_, _, choices)| SettingChoices {
+                current: self.settings[key].clone(),
+                choices: choices.iter().map(|choice| choice.to_string()).collect(),
+            }))
+    }
+
+    fn setting_range(&mut self, key: &str) -> Result<Option<SettingRange>> {
+        if key != "exposurecompensation" {
+            return Ok(None);
+        }
+        let (min, max, step) = EV_RANGE;
+        Ok(Some(SettingRange {
+            current: self.settings[key].parse()?,
+            range: min..=max,
+            step,
+        }))
+    }
+
+    fn model(&mut self) -> Result<Option<CameraModel>> {
+        Ok(Some(CameraModel {
+            vendor: "Raspberry Pi".to_owned(),
+            model: self.model.clone(),
+        }))
+    }
+
+    fn sensor(&mut self) -> Result<Option<Sensor>> {
+        Ok(self.sensor)
+    }
+}
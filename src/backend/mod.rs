@@ -0,0 +1,533 @@
+//! Camera backends that do the actual capture work for the MAVLink component.
+
+#[cfg(feature = "ccapi")]
+mod canon;
+mod gphoto;
+#[cfg(feature = "libcamera")]
+mod libcamera;
+#[cfg(feature = "sim")]
+mod sim;
+#[cfg(feature = "sony")]
+mod sony;
+
+#[cfg(feature = "ccapi")]
+pub use canon::CanonBackend;
+pub use gphoto::GPhotoBackend;
+#[cfg(feature = "libcamera")]
+pub use libcamera::LibcameraBackend;
+#[cfg(feature = "sim")]
+pub use sim::SimCamera;
+#[cfg(feature = "sony")]
+pub use sony::SonyBackend;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::debug;
+
+/// A photo taken by a backend and stored on the companion computer.
+#[derive(Debug, Clone)]
+pub struct CapturedImage {
+    /// Local path of the downloaded file, the JPEG if there is one.
+    pub path: PathBuf,
+    /// The other downloaded files of the same shot, e.g. the RAW of a
+    /// RAW+JPEG capture.
+    pub companions: Vec<PathBuf>,
+}
+
+/// A file still on the camera, e.g. `IMG_0001.JPG` in
+/// `/store_00020001/DCIM/100CANON`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraFile {
+    pub folder: String,
+    pub name: String,
+}
+
+impl CameraFile {
+    /// Where the file is on the camera, e.g. for the capture log.
+    pub fn path(&self) -> String {
+        format!("{}/{}", self.folder.trim_end_matches('/'), self.name)
+    }
+}
+
+/// A photo taken by [`CameraBackend::trigger`].
+#[derive(Debug, Clone)]
+pub enum PendingShot {
+    /// The files of the shot, still on the camera.
+    OnCamera(Vec<CameraFile>),
+    /// Downloaded right away by a backend that can't leave it on the camera.
+    Downloaded(CapturedImage),
+}
+
+/// Which files of a shot are downloaded, e.g. only the JPEG of a RAW+JPEG
+/// capture to not wait for the RAW over USB 2. This is independent of what
+/// the camera saves: a shot without any of the wanted files is downloaded
+/// whole.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadFormat {
+    #[default]
+    All,
+    Jpeg,
+    Raw,
+}
+
+/// Where the camera saves a shot until it's downloaded, gphoto2's
+/// `capturetarget` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureTarget {
+    /// The memory card, which keeps a copy of every shot.
+    Card,
+    /// Only the camera's RAM: shots go straight to the companion over USB
+    /// without being written to the card, which is faster for JPEG-only
+    /// surveys.
+    Ram,
+}
+
+impl CaptureTarget {
+    /// The `capturetarget` choice of gphoto2.
+    pub fn setting(self) -> &'static str {
+        match self {
+            CaptureTarget::Card => "Memory card",
+            CaptureTarget::Ram => "Internal RAM",
+        }
+    }
+
+    /// The target of the gphoto2 `capturetarget` choice, `None` for choices
+    /// other than these two.
+    pub fn from_setting(setting: &str) -> Option<Self> {
+        [CaptureTarget::Card, CaptureTarget::Ram]
+            .into_iter()
+            .find(|target| target.setting() == setting)
+    }
+}
+
+impl DownloadFormat {
+    fn wants(self, name: &str) -> bool {
+        match self {
+            DownloadFormat::All => true,
+            DownloadFormat::Jpeg => is_jpeg(name),
+            DownloadFormat::Raw => !is_jpeg(name),
+        }
+    }
+
+    /// The `files` of a shot to download, the JPEG first.
+    pub(crate) fn select<T>(self, files: Vec<T>, name: impl Fn(&T) -> String) -> Vec<T> {
+        let (mut wanted, skipped): (Vec<_>, Vec<_>) =
+            files.into_iter().partition(|file| self.wants(&name(file)));
+        if wanted.is_empty() {
+            debug!(target: "backend", format = ?self, "No wanted files in the shot, downloading all");
+            wanted = skipped;
+        } else {
+            for file in &skipped {
+                debug!(target: "backend", name = %name(file), "Skipping download");
+            }
+        }
+
+        wanted.sort_by_key(|file| !is_jpeg(&name(file)));
+        wanted
+    }
+}
+
+fn is_jpeg(name: &str) -> bool {
+    let extension = name.rsplit_once('.').map(|(_, extension)| extension);
+    matches!(
+        extension.map(str::to_ascii_lowercase).as_deref(),
+        Some("jpg" | "jpeg")
+    )
+}
+
+/// Checks that the file downloaded to `path` has the `size` the camera told,
+/// if it did, and that a JPEG has its start and end of image markers. Neither
+/// PTP nor CCAPI give a checksum of the file to compare against.
+pub(crate) fn verify_download(path: &Path, size: Option<u64>) -> Result<()> {
+    let corrupt = |reason: String| {
+        CorruptDownload {
+            path: path.to_owned(),
+            reason,
+        }
+        .into()
+    };
+
+    let downloaded = std::fs::metadata(path)?.len();
+    if let Some(size) = size.filter(|size| *size != downloaded) {
+        return Err(corrupt(format!("has {downloaded} of {size} bytes")));
+    }
+    if is_jpeg(&path.to_string_lossy()) {
+        let data = std::fs::read(path)?;
+        // Some cameras pad the file after the end of image marker.
+        let end = data
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |last| last + 1);
+        if !data.starts_with(&[0xff, 0xd8]) || !data[..end].ends_with(&[0xff, 0xd9]) {
+            return Err(corrupt(
+                "is missing its JPEG start or end marker".to_owned(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Capacity of one storage medium of the camera, e.g. a memory card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageInfo {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+    /// Name of the card slot, e.g. `SD1`, if the camera tells.
+    pub name: Option<String>,
+    /// The folder the storage's files are below, if the camera tells.
+    pub root: Option<String>,
+    /// What the storage is used for, on bodies with several that tell.
+    pub usage: Option<StorageUsage>,
+}
+
+/// Whether a storage gets the photos and videos, on bodies with two card
+/// slots.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageUsage {
+    pub photos: bool,
+    pub videos: bool,
+}
+
+/// A camera setting that takes one of a list of values, e.g. the ISO.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingChoices {
+    /// The value the setting has now, one of `choices`.
+    pub current: String,
+    /// Every value the camera accepts, in the camera's order.
+    pub choices: Vec<String>,
+}
+
+/// A numeric camera setting, e.g. the white balance colour temperature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingRange {
+    pub current: f32,
+    pub range: RangeInclusive<f32>,
+    /// Smallest change the camera takes, 0 for any.
+    pub step: f32,
+}
+
+/// A zoom movement, in the camera's own zoom units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zoom {
+    /// Move to this position.
+    Absolute(f32),
+    /// Move by this much from the current position, negative to zoom out.
+    Step(f32),
+    /// Move to this share of the zoom range, 0 at the widest and 1 at the
+    /// longest.
+    Range(f32),
+}
+
+/// What to follow in the image, with coordinates from 0 at the left or top
+/// to 1 at the right or bottom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackingTarget {
+    /// Whatever is around `x`, `y`, within `radius` as a share of the width.
+    Point { x: f32, y: f32, radius: f32 },
+    /// Whatever is inside the rectangle.
+    Rectangle {
+        left: f32,
+        top: f32,
+        right: f32,
+        bottom: f32,
+    },
+}
+
+/// Who made the camera and which model it is, as `CAMERA_INFORMATION` names
+/// it unless configured otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraModel {
+    /// The maker, e.g. `Sony`.
+    pub vendor: String,
+    /// The body, e.g. `ILCE-7RM4`.
+    pub model: String,
+}
+
+/// The image sensor, for the field of view ground stations are told and the
+/// capture footprints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sensor {
+    /// Width and height in mm, the long side of the image first.
+    pub size_mm: (f32, f32),
+    /// Width and height of a full resolution image in pixels.
+    pub resolution: (u16, u16),
+}
+
+/// The lens mounted on the camera.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lens {
+    /// The lens as the camera names it, e.g. `EF24-70mm f/2.8L II USM`.
+    pub model: String,
+    /// The current focal length, which follows a zoom lens, if known.
+    pub focal_length_mm: Option<f32>,
+}
+
+/// What a camera can do besides taking pictures, so ground stations only
+/// offer what works.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// [`CameraBackend::zoom`] moves the lens.
+    pub zoom: bool,
+    /// [`CameraBackend::drive_focus`] moves the focus.
+    pub focus: bool,
+    /// [`CameraBackend::autofocus`] focuses on command.
+    pub autofocus: bool,
+    /// [`CameraBackend::start_recording`] records a movie.
+    pub video: bool,
+    /// [`CameraBackend::track`] follows a [`TrackingTarget::Point`].
+    pub track_point: bool,
+    /// [`CameraBackend::track`] follows a [`TrackingTarget::Rectangle`].
+    pub track_rectangle: bool,
+}
+
+/// How often and how long after failing a capture or download is tried
+/// again, for errors that pass such as a busy camera or a USB glitch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryOptions {
+    /// Tries after the first one, 0 to give up right away.
+    pub retries: u32,
+    /// Pause before the first retry, doubled for every further one.
+    pub backoff: Duration,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryOptions {
+    /// Never tries again.
+    pub const NONE: Self = Self {
+        retries: 0,
+        backoff: Duration::ZERO,
+    };
+
+    /// The pause before retry number `retry`, counting from 0.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(1 << retry.min(16))
+    }
+}
+
+/// Returned by backends for something the camera can't do at all, which is
+/// acked as `MAV_RESULT_UNSUPPORTED` instead of as a failure.
+#[derive(Debug, Error)]
+#[error("{0} is not supported by this camera")]
+pub struct Unsupported(pub &'static str);
+
+/// Returned by backends for a file that still didn't download whole after the
+/// retries, e.g. as the USB connection kept dropping out. The file is kept and
+/// flagged in the capture log, so the area can be flown again.
+#[derive(Debug, Error)]
+#[error("Download {} {reason}", path.display())]
+pub struct CorruptDownload {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// A camera the MAVLink component can drive.
+pub trait CameraBackend: Send {
+    /// Takes a single photo and downloads it to the local image directory.
+    fn capture_image(&mut self) -> Result<CapturedImage>;
+
+    /// Takes a single photo and leaves it on the camera for
+    /// [`download`](Self::download), so the next one can be taken meanwhile.
+    /// By default the photo is downloaded right away.
+    fn trigger(&mut self) -> Result<PendingShot> {
+        self.capture_image().map(PendingShot::Downloaded)
+    }
+
+    /// Downloads a photo taken by [`trigger`](Self::trigger) to the local
+    /// image directory.
+    fn download(&mut self, shot: PendingShot) -> Result<CapturedImage> {
+        match shot {
+            PendingShot::Downloaded(image) => Ok(image),
+            PendingShot::OnCamera(_) => Err(Unsupported("Downloading later").into()),
+        }
+    }
+
+    /// Takes `count` photos as fast as the camera can and downloads them,
+    /// with one result per photo. Backends use the camera's continuous drive
+    /// where it has one, by default the photos are taken one after another.
+    fn capture_burst(&mut self, count: u32) -> Vec<Result<CapturedImage>> {
+        (0..count).map(|_| self.capture_image()).collect()
+    }
+
+    /// Takes a photo in bulb mode with the shutter held open for `exposure`,
+    /// for exposures longer than the camera's own shutter speeds, and
+    /// downloads it. The camera's shutter speed is left as it was.
+    fn capture_bulb(&mut self, exposure: Duration) -> Result<CapturedImage> {
+        let _ = exposure;
+        Err(Unsupported("Bulb exposure").into())
+    }
+
+    /// Starts recording a movie to the camera's card.
+    fn start_recording(&mut self) -> Result<()> {
+        Err(Unsupported("Video recording").into())
+    }
+
+    /// Stops the movie being recorded and returns its file, still on the
+    /// camera, `None` if the camera doesn't tell which it is.
+    fn stop_recording(&mut self) -> Result<Option<CameraFile>> {
+        Err(Unsupported("Video recording").into())
+    }
+
+    /// Checks that the camera still responds. Called periodically so the
+    /// heartbeat can report a disconnected camera.
+    fn check_connection(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Re-opens the camera after [`CameraBackend::check_connection`] failed, e.g.
+    /// once it has been plugged back in.
+    fn reconnect(&mut self) -> Result<()> {
+        bail!("Reconnecting is not supported by this backend")
+    }
+
+    /// Writes a camera setting by its backend specific key, e.g. `iso`.
+    fn set_config(&mut self, key: &str, value: &str) -> Result<()> {
+        bail!("Setting {key}={value} is not supported by this backend")
+    }
+
+    /// Reads the current exposure settings by backend specific key, e.g.
+    /// `iso`, for the capture log. Empty if the backend can't tell.
+    fn capture_settings(&mut self) -> Result<BTreeMap<String, String>> {
+        Ok(BTreeMap::new())
+    }
+
+    /// Reads a setting that takes one of a list of values by its backend
+    /// specific key, `None` if the camera doesn't have it.
+    fn setting_choices(&mut self, key: &str) -> Result<Option<SettingChoices>> {
+        let _ = key;
+        Ok(None)
+    }
+
+    /// Reads a numeric setting by its backend specific key, `None` if the
+    /// camera doesn't have it.
+    fn setting_range(&mut self, key: &str) -> Result<Option<SettingRange>> {
+        let _ = key;
+        Ok(None)
+    }
+
+    /// What the attached camera supports, asked again whenever a ground
+    /// station asks for the camera's information, e.g. after a lens change.
+    /// Nothing besides pictures by default.
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        Ok(Capabilities::default())
+    }
+
+    /// Reads the vendor and model of the camera, `None` if the backend can't
+    /// tell.
+    fn model(&mut self) -> Result<Option<CameraModel>> {
+        Ok(None)
+    }
+
+    /// Reads the sensor size and image resolution, `None` if the backend
+    /// can't tell.
+    fn sensor(&mut self) -> Result<Option<Sensor>> {
+        Ok(None)
+    }
+
+    /// Reads the mounted lens, `None` if the backend can't tell, in which
+    /// case it's taken from the EXIF of the captures.
+    fn lens(&mut self) -> Result<Option<Lens>> {
+        Ok(None)
+    }
+
+    /// Reads how often the shutter fired, `None` if the camera doesn't tell,
+    /// in which case the component counts the captures itself.
+    fn shutter_count(&mut self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Reads the body temperature in °C, `None` if the camera doesn't tell.
+    fn temperature(&mut self) -> Result<Option<f32>> {
+        Ok(None)
+    }
+
+    /// Reports the camera's own storage media. Empty if the backend can't tell.
+    fn storage_info(&mut self) -> Result<Vec<StorageInfo>> {
+        Ok(Vec::new())
+    }
+
+    /// Erases storage `storage`, an index into
+    /// [`CameraBackend::storage_info`].
+    fn format_storage(&mut self, storage: usize) -> Result<()> {
+        let _ = storage;
+        Err(Unsupported("Formatting storage").into())
+    }
+
+    /// Picks what storage `storage`, an index into
+    /// [`CameraBackend::storage_info`], is used for.
+    fn set_storage_usage(&mut self, storage: usize, usage: StorageUsage) -> Result<()> {
+        let _ = (storage, usage);
+        Err(Unsupported("Choosing the storage").into())
+    }
+
+    /// Reports the camera battery in percent, `None` if the backend can't tell.
+    fn battery_level(&mut self) -> Result<Option<u8>> {
+        Ok(None)
+    }
+
+    /// Grabs one live-view frame as a JPEG, switching live view on if needed.
+    fn preview_frame(&mut self) -> Result<Vec<u8>> {
+        Err(Unsupported("Live view").into())
+    }
+
+    /// Moves the zoom lens.
+    fn zoom(&mut self, zoom: Zoom) -> Result<()> {
+        let _ = zoom;
+        Err(Unsupported("Zoom").into())
+    }
+
+    /// Locks the focus where it is, or unlocks it so the camera focuses again.
+    fn set_focus_lock(&mut self, locked: bool) -> Result<()> {
+        let _ = locked;
+        Err(Unsupported("Focus lock").into())
+    }
+
+    /// Drives the focus by `steps` of the camera's smallest manual focus
+    /// step, positive away from the camera and negative towards it.
+    fn drive_focus(&mut self, steps: i32) -> Result<()> {
+        let _ = steps;
+        Err(Unsupported("Manual focus").into())
+    }
+
+    /// Sets the camera body's clock to `time`, so the times in its EXIF
+    /// match the flight log.
+    fn set_clock(&mut self, time: DateTime<Utc>) -> Result<()> {
+        let _ = time;
+        Err(Unsupported("Setting the clock").into())
+    }
+
+    /// Focuses as a half-press of the shutter button would, failing if the
+    /// camera hasn't found focus within `timeout`.
+    fn autofocus(&mut self, timeout: Duration) -> Result<()> {
+        let _ = timeout;
+        Err(Unsupported("Autofocus").into())
+    }
+
+    /// Starts following `target`, e.g. with the camera's subject tracking or
+    /// a computer vision pipeline on the live view wrapped around a backend,
+    /// replacing the previous target.
+    fn track(&mut self, target: TrackingTarget) -> Result<()> {
+        let _ = target;
+        Err(Unsupported("Tracking").into())
+    }
+
+    /// Stops following the target.
+    fn stop_tracking(&mut self) -> Result<()> {
+        Err(Unsupported("Tracking").into())
+    }
+}
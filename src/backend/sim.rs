@@ -0,0 +1,608 @@
+use super::{
+    CameraBackend, CameraFile, CameraModel, Capabilities, CapturedImage, DownloadFormat, Lens,
+    PendingShot, Sensor, SettingChoices, SettingRange, StorageInfo, StorageUsage, Zoom,
+};
+use anyhow::{bail, Context as _, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use jpeg_encoder::{ColorType, Encoder};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+const WIDTH: u16 = 640;
+const HEIGHT: u16 = 480;
+
+/// A full frame sensor cropped to the 4:3 of the frames.
+const SENSOR_SIZE_MM: (f32, f32) = (36.0, 27.0);
+
+/// Size of each card of the simulated camera.
+const CAPACITY_BYTES: u64 = 32 * 1024 * 1024 * 1024;
+
+/// The card slots of the simulated camera by name and folder.
+const SLOTS: [(&str, &str); 2] = [("SD1", "/store_00010001"), ("SD2", "/store_00020001")];
+
+/// Folder of the captures on a simulated card, below the slot's.
+const CAPTURE_FOLDER: &str = "DCIM/100SIMCA";
+
+/// Space each capture takes on the simulated card, about one RAW file.
+const IMAGE_BYTES: u64 = 24 * 1024 * 1024;
+
+/// Most frames of a simulated movie, which has one per second recorded.
+const MAX_MOVIE_FRAMES: i64 = 60;
+
+/// How long the simulated battery lasts per percent.
+const SECONDS_PER_BATTERY_PERCENT: u64 = 180;
+
+/// Body temperature in °C of the simulated camera when idle and at most.
+const BODY_TEMPERATURE: (f32, f32) = (35.0, 45.0);
+
+/// Zoom range of the simulated lens.
+const ZOOM_RANGE: RangeInclusive<f32> = 0.0..=100.0;
+
+/// Focal lengths in mm of the simulated lens at the ends of its zoom range.
+const FOCAL_LENGTHS: RangeInclusive<f32> = 24.0..=70.0;
+
+/// The simulated settings with choices as gphoto2 key, initial value and
+/// choices.
+const SETTING_CHOICES: [(&str, &str, &[&str]); 8] = [
+    ("imageformat", "JPEG", &["JPEG", "RAW", "RAW + JPEG"]),
+    ("expprogram", "M", &["M", "P", "A", "S"]),
+    (
+        "f-number",
+        "f/5.6",
+        &["f/2.8", "f/4", "f/5.6", "f/8", "f/11", "f/16"],
+    ),
+    (
+        "shutterspeed",
+        "1/1000",
+        &["1/4000", "1/2000", "1/1000", "1/500", "1/250", "1/125"],
+    ),
+    ("iso", "100", &["100", "200", "400", "800", "1600", "3200"]),
+    (
+        "exposurecompensation",
+        "0",
+        &[
+            "-3", "-2.666", "-2.333", "-2", "-1.666", "-1.333", "-1", "-0.666", "-0.333", "0",
+            "0.333", "0.666", "1", "1.333", "1.666", "2", "2.333", "2.666", "3",
+        ],
+    ),
+    (
+        "whitebalance",
+        "Auto",
+        &[
+            "Auto",
+            "Daylight",
+            "Shade",
+            "Cloudy",
+            "Tungsten",
+            "Fluorescent",
+            "Flash",
+            "Color Temperature",
+        ],
+    ),
+    (
+        "capturetarget",
+        "Memory card",
+        &["Internal RAM", "Memory card"],
+    ),
+];
+
+/// The simulated colour temperature in Kelvin: initial value, range and step.
+const COLOR_TEMPERATURE: (f32, RangeInclusive<f32>, f32) = (5500.0, 2500.0..=10000.0, 100.0);
+
+/// How long the simulated lens takes to focus.
+const FOCUS_TIME: Duration = Duration::from_millis(100);
+
+/// Side length in pixels of one dot of the timestamp font.
+const FONT_SCALE: usize = 8;
+
+/// A camera that doesn't need hardware, for exercising the MAVLink side in CI
+/// and SITL.
+///
+/// Each capture writes a synthetic JPEG with its sequence number and UTC time
+/// burnt in, and with `imageformat` set to RAW a `.dng` holding the same
+/// pixels. It has two card slots, which fill up unless `capturetarget` is
+/// the RAM, and the battery drains as if a real camera was used. Movies are
+/// MJPEG with a frame per second recorded.
+pub struct SimCamera {
+    image_dir: PathBuf,
+    captures: u64,
+    /// Captures saved to each simulated card rather than only to RAM.
+    stored: [u64; SLOTS.len()],
+    /// The slots photos and videos go to.
+    photo_slot: usize,
+    video_slot: usize,
+    /// Files of the captures and movies on the cards or in RAM by name.
+    card: BTreeMap<String, SimFile>,
+    movies: u64,
+    /// When the movie being recorded started.
+    recording: Option<DateTime<Utc>>,
+    powered_on: Instant,
+    config: BTreeMap<String, String>,
+    zoom: f32,
+    focus_locked: bool,
+    /// Manual focus steps driven since the camera was opened.
+    focus_position: i32,
+    /// How far the simulated clock is ahead of the companion's.
+    clock_offset: TimeDelta,
+    download: DownloadFormat,
+    delete_after_download: bool,
+}
+
+impl SimCamera {
+    /// Creates a simulated camera that writes its captures to `image_dir`.
+    pub fn new(image_dir: impl Into<PathBuf>) -> Result<Self> {
+        let image_dir = image_dir.into();
+        std::fs::create_dir_all(&image_dir)
+            .with_context(|| format!("Failed to create image directory {}", image_dir.display()))?;
+
+        info!(target: "backend", "Opened simulated camera");
+
+        Ok(Self {
+            image_dir,
+            captures: 0,
+            stored: [0; SLOTS.len()],
+            photo_slot: 0,
+            video_slot: 0,
+            card: BTreeMap::new(),
+            movies: 0,
+            recording: None,
+            powered_on: Instant::now(),
+            config: BTreeMap::new(),
+            zoom: 0.0,
+            focus_locked: false,
+            focus_position: 0,
+            clock_offset: TimeDelta::zero(),
+            download: DownloadFormat::All,
+            delete_after_download: false,
+        })
+    }
+
+    /// Only writes these files of each shot.
+    pub fn with_download_format(mut self, download: DownloadFormat) -> Self {
+        self.download = download;
+        self
+    }
+
+    /// Frees the simulated card of every capture once it's written.
+    pub fn with_delete_after_download(mut self, delete: bool) -> Self {
+        self.delete_after_download = delete;
+        self
+    }
+
+    fn captures_to_ram(&self) -> bool {
+        self.config.get("capturetarget").map(String::as_str) == Some("Internal RAM")
+    }
+}
+
+impl CameraBackend for SimCamera {
+    fn capture_image(&mut self) -> Result<CapturedImage> {
+        let shot = self.trigger()?;
+        self.download(shot)
+    }
+
+    fn trigger(&mut self) -> Result<PendingShot> {
+        let format = self
+            .config
+            .get("imageformat")
+            .map_or("JPEG", String::as_str);
+        let mut names = Vec::new();
+        if format.contains("JPEG") {
+            names.push(format!("SIM_{:05}.jpg", self.captures));
+        }
+        if format.contains("RAW") {
+            names.push(format!("SIM_{:05}.dng", self.captures));
+        }
+
+        let (slot, taken) = (self.photo_slot, Utc::now() + self.clock_offset);
+        for name in &names {
+            let file = SimFile {
+                slot,
+                number: self.captures,
+                taken,
+                length: None,
+            };
+            self.card.insert(name.clone(), file);
+        }
+        self.captures += 1;
+        if !self.captures_to_ram() {
+            self.stored[slot] += 1;
+        }
+
+        let (_, root) = SLOTS[slot];
+        Ok(PendingShot::OnCamera(
+            names
+                .into_iter()
+                .map(|name| CameraFile {
+                    folder: format!("{root}/{CAPTURE_FOLDER}"),
+                    name,
+                })
+                .collect(),
+        ))
+    }
+
+    fn download(&mut self, shot: PendingShot) -> Result<CapturedImage> {
+        let files = match shot {
+            PendingShot::OnCamera(files) => files,
+            PendingShot::Downloaded(image) => return Ok(image),
+        };
+
+        let (to_ram, delete) = (self.captures_to_ram(), self.delete_after_download);
+        let (mut paths, mut slot) = (Vec::new(), None);
+        for file in self.download.select(files, |file| file.name.clone()) {
+            let sim_file = *self
+                .card
+                .get(&file.name)
+                .with_context(|| format!("No {} on the simulated card", file.name))?;
+            // A movie is a frame per second, as MJPEG.
+            let frames = sim_file
+                .length
+                .map_or(1, |length| length.num_seconds().clamp(1, MAX_MOVIE_FRAMES));
+            let mut data = Vec::new();
+            for second in 0..frames {
+                let taken = sim_file.taken + TimeDelta::seconds(second);
+                Encoder::new(&mut data, 85).encode(
+                    &frame(sim_file.number, taken),
+                    WIDTH,
+                    HEIGHT,
+                    ColorType::Rgb,
+                )?;
+            }
+
+            let path = self.image_dir.join(&file.name);
+            std::fs::write(&path, data)?;
+            debug!(target: "backend", path = %path.display(), "Wrote simulated capture");
+            paths.push(path);
+            slot = Some(sim_file.slot);
+            if to_ram || delete {
+                self.card.remove(&file.name);
+            }
+        }
+        if let Some(slot) = slot.filter(|_| delete && !to_ram) {
+            self.stored[slot] = self.stored[slot].saturating_sub(1);
+        }
+
+        let path = paths.remove(0);
+        Ok(CapturedImage {
+            path,
+            companions: paths,
+        })
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn capture_bulb(&mut self, exposure: Duration) -> Result<CapturedImage> {
+        debug!(target: "backend", ?exposure, "Simulating bulb exposure");
+        std::thread::sleep(exposure);
+        self.capture_image()
+    }
+
+    fn start_recording(&mut self) -> Result<()> {
+        if self.recording.is_some() {
+            bail!("The simulated camera is recording already");
+        }
+        self.recording = Some(Utc::now() + self.clock_offset);
+        debug!(target: "backend", "Started simulated recording");
+        Ok(())
+    }
+
+    fn stop_recording(&mut self) -> Result<Option<CameraFile>> {
+        let Some(started) = self.recording.take() else {
+            bail!("The simulated camera isn't recording");
+        };
+        let name = format!("SIM_{:05}.mjpg", self.movies);
+        let file = SimFile {
+            slot: self.video_slot,
+            number: self.movies,
+            taken: started,
+            length: Some(Utc::now() + self.clock_offset - started),
+        };
+        self.card.insert(name.clone(), file);
+        self.movies += 1;
+        self.stored[self.video_slot] += 1;
+        debug!(target: "backend", %name, "Stopped simulated recording");
+
+        let (_, root) = SLOTS[self.video_slot];
+        Ok(Some(CameraFile {
+            folder: format!("{root}/{CAPTURE_FOLDER}"),
+            name,
+        }))
+    }
+
+    fn set_config(&mut self, key: &str, value: &str) -> Result<()> {
+        debug!(target: "backend", key, value, "Writing simulated camera setting");
+        self.config.insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    fn capture_settings(&mut self) -> Result<BTreeMap<String, String>> {
+        let mut settings = self.config.clone();
+        settings.insert("zoom".to_owned(), self.zoom.to_string());
+        settings.insert("focuslock".to_owned(), self.focus_locked.to_string());
+        settings.insert("focusposition".to_owned(), self.focus_position.to_string());
+        Ok(settings)
+    }
+
+    fn setting_choices(&mut self, key: &str) -> Result<Option<SettingChoices>> {
+        let Some((_, initial, choices)) = SETTING_CHOICES
+            .iter()
+            .find(|(setting, _, _)| *setting == key)
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(SettingChoices {
+            current: self
+                .config
+                .get(key)
+                .cloned()
+                .unwrap_or_else(|| (*initial).to_owned()),
+            choices: choices.iter().map(|&choice| choice.to_owned()).collect(),
+        }))
+    }
+
+    fn setting_range(&mut self, key: &str) -> Result<Option<SettingRange>> {
+        if key != "colortemperature" {
+            return Ok(None);
+        }
+
+        let (initial, range, step) = COLOR_TEMPERATURE;
+        Ok(Some(SettingRange {
+            current: self
+                .config
+                .get(key)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(initial),
+            range,
+            step,
+        }))
+    }
+
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        Ok(Capabilities {
+            zoom: true,
+            focus: true,
+            autofocus: true,
+            video: true,
+            track_point: false,
+            track_rectangle: false,
+        })
+    }
+
+    fn model(&mut self) -> Result<Option<CameraModel>> {
+        Ok(Some(CameraModel {
+            vendor: "Simulated".to_owned(),
+            model: "SITL camera".to_owned(),
+        }))
+    }
+
+    fn sensor(&mut self) -> Result<Option<Sensor>> {
+        Ok(Some(Sensor {
+            size_mm: SENSOR_SIZE_MM,
+            resolution: (WIDTH, HEIGHT),
+        }))
+    }
+
+    fn lens(&mut self) -> Result<Option<Lens>> {
+        let share = (self.zoom - ZOOM_RANGE.start()) / (ZOOM_RANGE.end() - ZOOM_RANGE.start());
+        Ok(Some(Lens {
+            model: "Simulated 24-70mm F2.8".to_owned(),
+            focal_length_mm: Some(
+                FOCAL_LENGTHS.start() + share * (FOCAL_LENGTHS.end() - FOCAL_LENGTHS.start()),
+            ),
+        }))
+    }
+
+    fn shutter_count(&mut self) -> Result<Option<u64>> {
+        Ok(Some(self.captures))
+    }
+
+    fn temperature(&mut self) -> Result<Option<f32>> {
+        // Warms up a little with every capture.
+        let (idle, warmest) = BODY_TEMPERATURE;
+        Ok(Some((idle + self.captures as f32 * 0.1).min(warmest)))
+    }
+
+    fn storage_info(&mut self) -> Result<Vec<StorageInfo>> {
+        Ok(SLOTS
+            .iter()
+            .zip(self.stored)
+            .enumerate()
+            .map(|(slot, ((name, root), stored))| {
+                let used_bytes = (stored * IMAGE_BYTES).min(CAPACITY_BYTES);
+                StorageInfo {
+                    total_bytes: CAPACITY_BYTES,
+                    available_bytes: CAPACITY_BYTES - used_bytes,
+                    name: Some(name.to_string()),
+                    root: Some(root.to_string()),
+                    usage: Some(StorageUsage {
+                        photos: slot == self.photo_slot,
+                        videos: slot == self.video_slot,
+                    }),
+                }
+            })
+            .collect())
+    }
+
+    fn format_storage(&mut self, storage: usize) -> Result<()> {
+        if storage >= SLOTS.len() {
+            bail!("The simulated camera has no storage {}", storage + 1);
+        }
+        self.card.retain(|_, file| file.slot != storage);
+        self.stored[storage] = 0;
+        debug!(target: "backend", storage, "Formatted simulated card");
+        Ok(())
+    }
+
+    fn set_storage_usage(&mut self, storage: usize, usage: StorageUsage) -> Result<()> {
+        if storage >= SLOTS.len() {
+            bail!("The simulated camera has no storage {}", storage + 1);
+        }
+        // Photos and videos always go to one of the slots, so taking them
+        // off one moves them to the other.
+        let other = (storage + 1) % SLOTS.len();
+        for (slot, wanted) in [
+            (&mut self.photo_slot, usage.photos),
+            (&mut self.video_slot, usage.videos),
+        ] {
+            if wanted {
+                *slot = storage;
+            } else if *slot == storage {
+                *slot = other;
+            }
+        }
+        debug!(target: "backend", storage, ?usage, "Set simulated card usage");
+        Ok(())
+    }
+
+    fn battery_level(&mut self) -> Result<Option<u8>> {
+        let drained = self.powered_on.elapsed().as_secs() / SECONDS_PER_BATTERY_PERCENT;
+        Ok(Some(100 - drained.min(100) as u8))
+    }
+
+    fn preview_frame(&mut self) -> Result<Vec<u8>> {
+        let mut pixels = background(self.captures);
+        let now = (Utc::now() + self.clock_offset)
+            .format("%H:%M:%S")
+            .to_string();
+        draw_text(&mut pixels, 16, 16, &now);
+
+        let mut frame = Vec::new();
+        Encoder::new(&mut frame, 70).encode(&pixels, WIDTH, HEIGHT, ColorType::Rgb)?;
+        Ok(frame)
+    }
+
+    fn zoom(&mut self, zoom: Zoom) -> Result<()> {
+        let position = match zoom {
+            Zoom::Absolute(position) => position,
+            Zoom::Step(step) => self.zoom + step,
+            Zoom::Range(share) => {
+                ZOOM_RANGE.start() + share * (ZOOM_RANGE.end() - ZOOM_RANGE.start())
+            }
+        };
+        self.zoom = position.clamp(*ZOOM_RANGE.start(), *ZOOM_RANGE.end());
+        debug!(target: "backend", position = self.zoom, "Zooming simulated lens");
+        Ok(())
+    }
+
+    fn set_focus_lock(&mut self, locked: bool) -> Result<()> {
+        debug!(target: "backend", locked, "Setting simulated focus lock");
+        self.focus_locked = locked;
+        Ok(())
+    }
+
+    fn drive_focus(&mut self, steps: i32) -> Result<()> {
+        self.focus_position += steps;
+        debug!(target: "backend", steps, position = self.focus_position, "Driving simulated focus");
+        Ok(())
+    }
+
+    fn set_clock(&mut self, time: DateTime<Utc>) -> Result<()> {
+        self.clock_offset = time - Utc::now();
+        debug!(target: "backend", %time, "Setting simulated clock");
+        Ok(())
+    }
+
+    fn autofocus(&mut self, timeout: Duration) -> Result<()> {
+        if timeout < FOCUS_TIME {
+            std::thread::sleep(timeout);
+            bail!("Simulated lens didn't focus within {timeout:?}");
+        }
+        std::thread::sleep(FOCUS_TIME);
+        debug!(target: "backend", "Focused simulated lens");
+        Ok(())
+    }
+}
+
+/// A file on a simulated card or in RAM.
+#[derive(Debug, Clone, Copy)]
+struct SimFile {
+    slot: usize,
+    /// Number of the shot or movie.
+    number: u64,
+    taken: DateTime<Utc>,
+    /// How long a movie is, `None` for pictures.
+    length: Option<TimeDelta>,
+}
+
+/// The pixels of capture `number` taken at `taken`, with both burnt in.
+fn frame(number: u64, taken: DateTime<Utc>) -> Vec<u8> {
+    let mut pixels = background(number);
+    draw_text(&mut pixels, 16, 16, &format!("#{number:05}"));
+    let taken = taken.format("%Y-%m-%d %H:%M:%S").to_string();
+    draw_text(&mut pixels, 16, 72, &taken);
+    pixels
+}
+
+/// A gradient whose colour changes with every capture so consecutive images
+/// are easy to tell apart.
+fn background(capture: u64) -> Vec<u8> {
+    let shift = (capture * 37 % 256) as usize;
+    let mut pixels = Vec::with_capacity(usize::from(WIDTH) * usize::from(HEIGHT) * 3);
+
+    for y in 0..usize::from(HEIGHT) {
+        for x in 0..usize::from(WIDTH) {
+            pixels.extend([
+                ((x * 255 / usize::from(WIDTH) + shift) % 256) as u8,
+                (y * 255 / usize::from(HEIGHT)) as u8,
+                (255 - shift) as u8,
+            ]);
+        }
+    }
+
+    pixels
+}
+
+/// Draws `text` in white with its top left corner at `x`, `y`. Characters
+/// without a glyph are left blank.
+fn draw_text(pixels: &mut [u8], x: usize, y: usize, text: &str) {
+    for (index, character) in text.chars().enumerate() {
+        let Some(rows) = glyph(character) else {
+            continue;
+        };
+        let left = x + index * 4 * FONT_SCALE;
+
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+
+                for dy in 0..FONT_SCALE {
+                    for dx in 0..FONT_SCALE {
+                        let px = left + column * FONT_SCALE + dx;
+                        let py = y + row * FONT_SCALE + dy;
+                        if px < usize::from(WIDTH) && py < usize::from(HEIGHT) {
+                            let offset = (py * usize::from(WIDTH) + px) * 3;
+                            pixels[offset..offset + 3].fill(255);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 3x5 dot glyphs, one row per byte with the leftmost dot in bit 2.
+fn glyph(character: char) -> Option<[u8; 5]> {
+    Some(match character {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        _ => return None,
+    })
+}
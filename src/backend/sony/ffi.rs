@@ -0,0 +1,91 @@
+//! The C interface `shim.cpp` puts over Sony's Camera Remote SDK.
+
+use std::ffi::c_char;
+
+/// Errors of the shim itself, the SDK's are positive.
+pub const NOT_FOUND: i32 = -1;
+pub const NOT_AVAILABLE: i32 = -2;
+pub const TIMEOUT: i32 = -3;
+pub const READ_ONLY: i32 = -4;
+pub const INIT_FAILED: i32 = -5;
+
+pub const MAX_VALUES: usize = 256;
+
+/// A connected camera, owned by the shim.
+#[repr(C)]
+pub struct Camera {
+    _private: [u8; 0],
+}
+
+/// Mirrors `Setting` in shim.cpp.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Iso,
+    ShutterSpeed,
+    FNumber,
+    ExposureBias,
+    WhiteBalance,
+    ColorTemperature,
+    ExposureProgram,
+    FileType,
+    BatteryRemain,
+}
+
+/// Mirrors `sony_setting_values` in shim.cpp.
+#[repr(C)]
+pub struct SettingValues {
+    pub current: i64,
+    pub values: [i64; MAX_VALUES],
+    pub count: usize,
+    /// `values` are the minimum, maximum and step rather than a list.
+    pub range: bool,
+    pub writable: bool,
+}
+
+impl Default for SettingValues {
+    fn default() -> Self {
+        Self {
+            current: 0,
+            values: [0; MAX_VALUES],
+            count: 0,
+            range: false,
+            writable: false,
+        }
+    }
+}
+
+impl SettingValues {
+    pub fn list(&self) -> &[i64] {
+        &self.values[..self.count.min(MAX_VALUES)]
+    }
+}
+
+// Linked by build.rs, along with the SDK.
+extern "C" {
+    pub fn sony_init() -> i32;
+    pub fn sony_open(
+        id: *const c_char,
+        save_dir: *const c_char,
+        timeout_ms: u32,
+        camera: *mut *mut Camera,
+    ) -> i32;
+    pub fn sony_close(camera: *mut Camera);
+    pub fn sony_model(camera: *const Camera) -> *const c_char;
+    pub fn sony_connected(camera: *mut Camera) -> bool;
+    pub fn sony_release_shutter(camera: *mut Camera) -> i32;
+    pub fn sony_next_download(
+        camera: *mut Camera,
+        timeout_ms: u32,
+        path: *mut c_char,
+        len: usize,
+    ) -> i32;
+    pub fn sony_get_setting(
+        camera: *mut Camera,
+        setting: Setting,
+        values: *mut SettingValues,
+    ) -> i32;
+    pub fn sony_set_setting(camera: *mut Camera, setting: Setting, value: i64) -> i32;
+    pub fn sony_live_view(camera: *mut Camera, data: *mut *mut u8, len: *mut usize) -> i32;
+    pub fn sony_free(data: *mut u8);
+}
@@ -0,0 +1,483 @@
+//! Sony bodies through Sony's Camera Remote SDK, for models such as the a7R IV
+//! and V whose libgphoto2 support lacks settings or drops transfers.
+//!
+//! The SDK transfers every file of a shot to the image directory and keeps a
+//! copy on the card, which the shim can't delete. Settings go by the keys of their gphoto2 counterparts,
+//! e.g. `shutterspeed`, with values in the same form, e.g. `1/1000`, so the
+//! camera definition and parameter overrides work with either backend.
+
+mod ffi;
+
+use super::{
+    verify_download, CameraBackend, CameraModel, CapturedImage, DownloadFormat, RetryOptions,
+    SettingChoices, SettingRange,
+};
+use anyhow::{anyhow, bail, Context as _, Result};
+use ffi::{Setting, SettingValues};
+use std::collections::BTreeMap;
+use std::ffi::{c_char, CStr, CString, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr::{self, NonNull};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// The settings the backend has, by gphoto2 key.
+const SETTINGS: [(&str, Setting); 8] = [
+    ("iso", Setting::Iso),
+    ("shutterspeed", Setting::ShutterSpeed),
+    ("f-number", Setting::FNumber),
+    ("exposurecompensation", Setting::ExposureBias),
+    ("whitebalance", Setting::WhiteBalance),
+    ("colortemperature", Setting::ColorTemperature),
+    ("expprogram", Setting::ExposureProgram),
+    ("imageformat", Setting::FileType),
+];
+
+/// Settings recorded in the capture log.
+const CAPTURE_SETTINGS: [&str; 5] = [
+    "iso",
+    "shutterspeed",
+    "f-number",
+    "exposurecompensation",
+    "whitebalance",
+];
+
+/// How long to wait for the camera to connect after it was found.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for the first file of a shot, which includes the
+/// exposure and the transfer.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for the other file of a RAW+JPEG shot once one arrived.
+/// The SDK transfers them one after the other.
+const SHOT_FILE_WAIT: Duration = Duration::from_secs(5);
+
+/// Longest path the SDK reports a transferred file with.
+const MAX_PATH: usize = 4096;
+
+/// Result of initialising the SDK, which is done once for all cameras.
+static SDK_INIT: OnceLock<i32> = OnceLock::new();
+
+/// Backend for Sony bodies supported by the Camera Remote SDK.
+pub struct SonyBackend {
+    camera: Handle,
+    id: Option<String>,
+    model: String,
+    image_dir: PathBuf,
+    download: DownloadFormat,
+    retry: RetryOptions,
+}
+
+/// A camera connected through the shim, disconnected when dropped.
+struct Handle(NonNull<ffi::Camera>);
+
+// SAFETY: the SDK can be called from any thread and the shim guards what its
+// callbacks share. The handle is only used through `&mut SonyBackend`.
+unsafe impl Send for Handle {}
+
+impl Handle {
+    fn ptr(&self) -> *mut ffi::Camera {
+        self.0.as_ptr()
+    }
+
+    fn model(&self) -> String {
+        // SAFETY: the shim returns a string that lives as long as the camera.
+        unsafe { CStr::from_ptr(ffi::sony_model(self.ptr())) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // SAFETY: the camera was opened by `sony_open` and is closed once.
+        unsafe { ffi::sony_close(self.ptr()) }
+    }
+}
+
+impl SonyBackend {
+    /// Connects to the camera with the id `id`, the serial number of a USB
+    /// camera, or of the model `id`, e.g. `ILCE-7RM4`. The first camera found
+    /// is taken when no id is given. Shots are transferred to `image_dir`.
+    pub fn open(id: Option<&str>, image_dir: impl Into<PathBuf>) -> Result<Self> {
+        let image_dir = image_dir.into();
+        std::fs::create_dir_all(&image_dir)
+            .with_context(|| format!("Failed to create image directory {}", image_dir.display()))?;
+
+        let camera = connect(id, &image_dir)?;
+        let model = camera.model();
+        info!(target: "backend", %model, "Opened Sony camera");
+
+        Ok(Self {
+            camera,
+            id: id.map(str::to_owned),
+            model,
+            image_dir,
+            download: DownloadFormat::All,
+            retry: RetryOptions::NONE,
+        })
+    }
+
+    /// Only keeps these files of each shot. The others are still
+    /// transferred, as the SDK takes every file, and deleted right away.
+    pub fn with_download_format(mut self, download: DownloadFormat) -> Self {
+        self.download = download;
+        self
+    }
+
+    /// Waits for late transfers again, rather than losing the shot. A
+    /// transfer that doesn't check out can't be asked for again.
+    pub fn with_download_retry(mut self, retry: RetryOptions) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Reads `setting`, `None` if the camera doesn't have it.
+    fn read(&self, setting: Setting) -> Result<Option<SettingValues>> {
+        let mut values = SettingValues::default();
+        // SAFETY: `values` is valid for the duration of the call.
+        let result = unsafe { ffi::sony_get_setting(self.camera.ptr(), setting, &mut values) };
+        if result == ffi::NOT_AVAILABLE {
+            return Ok(None);
+        }
+        check(result, "read a camera setting")?;
+        Ok(Some(values))
+    }
+
+    /// Waits up to `timeout` for the next transferred file, `None` if none
+    /// came.
+    fn next_download(&self, timeout: Duration) -> Result<Option<PathBuf>> {
+        let mut path = vec![0u8; MAX_PATH];
+        // SAFETY: `path` has room for `MAX_PATH` bytes, which the shim ends with a nul.
+        let result = unsafe {
+            ffi::sony_next_download(
+                self.camera.ptr(),
+                timeout.as_millis().try_into().unwrap_or(u32::MAX),
+                path.as_mut_ptr().cast::<c_char>(),
+                path.len(),
+            )
+        };
+        if result == ffi::TIMEOUT {
+            return Ok(None);
+        }
+        check(result, "transfer the shot")?;
+
+        let path = CStr::from_bytes_until_nul(&path)?;
+        Ok(Some(PathBuf::from(OsStr::from_bytes(path.to_bytes()))))
+    }
+
+    /// Waits for the first file of a shot, again as `retry` says when it's
+    /// late.
+    fn first_download(&self) -> Result<PathBuf> {
+        let mut retries = 0;
+        loop {
+            if let Some(file) = self.next_download(DOWNLOAD_TIMEOUT)? {
+                return Ok(file);
+            }
+            if retries == self.retry.retries {
+                bail!("No image from the camera after the capture");
+            }
+            let delay = self.retry.delay(retries);
+            warn!(target: "backend", retry = retries + 1, "No image from the camera yet, waiting again in {delay:?}");
+            std::thread::sleep(delay);
+            retries += 1;
+        }
+    }
+
+    /// How many files a shot has, 2 for RAW+JPEG.
+    fn files_per_shot(&self) -> usize {
+        match self.read(Setting::FileType) {
+            Ok(Some(values)) => match format(Setting::FileType, values.current).as_str() {
+                "RAW+JPEG" | "RAW+HEIF" => 2,
+                _ => 1,
+            },
+            _ => 1,
+        }
+    }
+
+    /// Keeps the wanted `files` of one shot, the JPEG first.
+    fn keep_shot(&self, files: Vec<PathBuf>) -> Result<CapturedImage> {
+        let mut kept = self.download.select(files.clone(), |path| file_name(path));
+        for path in files.iter().filter(|path| !kept.contains(path)) {
+            debug!(target: "backend", path = %path.display(), "Removing unwanted file");
+            if let Err(error) = std::fs::remove_file(path) {
+                warn!(target: "backend", path = %path.display(), "Failed to remove unwanted file: {error}");
+            }
+        }
+
+        let path = kept.remove(0);
+        Ok(CapturedImage {
+            path,
+            companions: kept,
+        })
+    }
+}
+
+/// Initialises the SDK if needed and connects to the camera `id`.
+fn connect(id: Option<&str>, image_dir: &Path) -> Result<Handle> {
+    // SAFETY: the SDK is initialised only once.
+    check(
+        *SDK_INIT.get_or_init(|| unsafe { ffi::sony_init() }),
+        "initialise the Camera Remote SDK",
+    )?;
+
+    let id = id.map(CString::new).transpose()?;
+    let image_dir = CString::new(image_dir.as_os_str().as_bytes())?;
+    let mut camera = ptr::null_mut();
+    // SAFETY: the strings outlive the call, which sets `camera` on success.
+    let result = unsafe {
+        ffi::sony_open(
+            id.as_ref().map_or(ptr::null(), |id| id.as_ptr()),
+            image_dir.as_ptr(),
+            CONNECT_TIMEOUT.as_millis().try_into().unwrap_or(u32::MAX),
+            &mut camera,
+        )
+    };
+    match result {
+        ffi::NOT_FOUND => match id {
+            Some(id) => bail!("No Sony camera {} found", id.to_string_lossy()),
+            None => bail!("No Sony camera found"),
+        },
+        result => check(result, "connect to the camera")?,
+    }
+
+    NonNull::new(camera)
+        .map(Handle)
+        .ok_or_else(|| anyhow!("The Camera Remote SDK returned no camera"))
+}
+
+/// Fails for any `result` of the shim but 0, saying what `action` failed.
+fn check(result: i32, action: &str) -> Result<()> {
+    match result {
+        0 => Ok(()),
+        ffi::NOT_FOUND | ffi::NOT_AVAILABLE => bail!("Failed to {action}: not available"),
+        ffi::TIMEOUT => bail!("Failed to {action}: timed out"),
+        ffi::READ_ONLY => bail!("Failed to {action}: the camera doesn't take it now"),
+        ffi::INIT_FAILED => bail!("Failed to {action}"),
+        error => bail!("Failed to {action}: Camera Remote SDK error {error:#x}"),
+    }
+}
+
+fn setting(key: &str) -> Option<Setting> {
+    SETTINGS
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, setting)| *setting)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// `value` of `setting` the way gphoto2 shows it, e.g. `1/1000` for a
+/// shutter speed.
+fn format(setting: Setting, value: i64) -> String {
+    match setting {
+        // The low 24 bits are the ISO, the others the extended ISO mode.
+        Setting::Iso => match value & 0xff_ffff {
+            0xff_ffff => "Auto".to_owned(),
+            iso => iso.to_string(),
+        },
+        // The numerator in the high 16 bits, the denominator in the low.
+        Setting::ShutterSpeed => match ((value >> 16) & 0xffff, value & 0xffff) {
+            (0, 0) => "Bulb".to_owned(),
+            (1, denominator) if denominator > 1 => format!("1/{denominator}"),
+            (numerator, 0 | 1) => numerator.to_string(),
+            (numerator, denominator) => decimal(numerator as f64 / denominator as f64),
+        },
+        // In hundredths, with the highest values for no aperture.
+        Setting::FNumber if value >= 0xfffd => "--".to_owned(),
+        Setting::FNumber => decimal(value as f64 / 100.0),
+        // In thousandths of a stop.
+        Setting::ExposureBias => decimal(value as f64 / 1000.0),
+        Setting::WhiteBalance => match value {
+            0x0000 => "Auto".to_owned(),
+            0x0011 => "Daylight".to_owned(),
+            0x0012 => "Shade".to_owned(),
+            0x0013 => "Cloudy".to_owned(),
+            0x0014 => "Tungsten".to_owned(),
+            0x0020 => "Fluorescent".to_owned(),
+            0x0021 => "Fluorescent: Warm White".to_owned(),
+            0x0022 => "Fluorescent: Cool White".to_owned(),
+            0x0023 => "Fluorescent: Day White".to_owned(),
+            0x0024 => "Fluorescent: Daylight".to_owned(),
+            0x0030 => "Flash".to_owned(),
+            0x0100 => "Color Temperature".to_owned(),
+            0x0101..=0x0104 => format!("Custom {}", value - 0x0100),
+            _ => format!("{value:#06x}"),
+        },
+        // The PTP exposure program in the low 16 bits.
+        Setting::ExposureProgram => match value & 0xffff {
+            1 => "M".to_owned(),
+            2 => "P".to_owned(),
+            3 => "A".to_owned(),
+            4 => "S".to_owned(),
+            _ => format!("{value:#x}"),
+        },
+        Setting::FileType => match value {
+            1 => "JPEG".to_owned(),
+            2 => "RAW".to_owned(),
+            3 => "RAW+JPEG".to_owned(),
+            4 => "RAW+HEIF".to_owned(),
+            5 => "HEIF".to_owned(),
+            _ => format!("{value:#x}"),
+        },
+        Setting::ColorTemperature | Setting::BatteryRemain => value.to_string(),
+    }
+}
+
+/// `value` with at most one decimal, e.g. `5.6` or `8`.
+fn decimal(value: f64) -> String {
+    let value = format!("{value:.1}");
+    value
+        .strip_suffix(".0")
+        .map_or_else(|| value.clone(), str::to_owned)
+}
+
+impl CameraBackend for SonyBackend {
+    fn capture_image(&mut self) -> Result<CapturedImage> {
+        // SAFETY: the camera is open.
+        check(
+            unsafe { ffi::sony_release_shutter(self.camera.ptr()) },
+            "release the shutter",
+        )?;
+
+        let mut files = vec![self.first_download()?];
+        for _ in 1..self.files_per_shot() {
+            match self.next_download(SHOT_FILE_WAIT)? {
+                Some(file) => files.push(file),
+                None => break,
+            }
+        }
+        for file in &files {
+            verify_download(file, None)?;
+        }
+        self.keep_shot(files)
+    }
+
+    fn check_connection(&mut self) -> Result<()> {
+        // SAFETY: the camera is open.
+        if !unsafe { ffi::sony_connected(self.camera.ptr()) } {
+            bail!("The camera disconnected");
+        }
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        let id = self.id.as_deref().unwrap_or(&self.model);
+        let camera = connect(Some(id), &self.image_dir)?;
+
+        // Replacing the old handle closes it.
+        self.camera = camera;
+        info!(target: "backend", model = %self.model, "Reconnected Sony camera");
+        Ok(())
+    }
+
+    fn set_config(&mut self, key: &str, value: &str) -> Result<()> {
+        let setting = setting(key).ok_or_else(|| anyhow!("The camera has no setting {key}"))?;
+        let values = self
+            .read(setting)?
+            .ok_or_else(|| anyhow!("The camera has no setting {key} now"))?;
+        let raw = if values.range {
+            value.trim().parse::<f64>()?.round() as i64
+        } else {
+            values
+                .list()
+                .iter()
+                .copied()
+                .find(|raw| format(setting, *raw) == value)
+                .ok_or_else(|| anyhow!("{value} is not a choice of {key}"))?
+        };
+
+        debug!(target: "backend", key, value, "Writing camera setting");
+        // SAFETY: the camera is open.
+        check(
+            unsafe { ffi::sony_set_setting(self.camera.ptr(), setting, raw) },
+            "write a camera setting",
+        )
+    }
+
+    fn capture_settings(&mut self) -> Result<BTreeMap<String, String>> {
+        let mut settings = BTreeMap::new();
+        for key in CAPTURE_SETTINGS {
+            let Some(setting) = setting(key) else {
+                continue;
+            };
+            if let Some(values) = self.read(setting)? {
+                settings.insert(key.to_owned(), format(setting, values.current));
+            }
+        }
+        Ok(settings)
+    }
+
+    fn setting_choices(&mut self, key: &str) -> Result<Option<SettingChoices>> {
+        let Some(setting) = setting(key) else {
+            return Ok(None);
+        };
+        let Some(values) = self.read(setting)?.filter(|values| !values.range) else {
+            return Ok(None);
+        };
+
+        Ok(Some(SettingChoices {
+            current: format(setting, values.current),
+            choices: values
+                .list()
+                .iter()
+                .map(|value| format(setting, *value))
+                .collect(),
+        }))
+    }
+
+    fn setting_range(&mut self, key: &str) -> Result<Option<SettingRange>> {
+        let Some(setting) = setting(key) else {
+            return Ok(None);
+        };
+        let Some(values) = self.read(setting)?.filter(|values| values.range) else {
+            return Ok(None);
+        };
+        let &[min, max, step, ..] = values.list() else {
+            return Ok(None);
+        };
+
+        Ok(Some(SettingRange {
+            current: values.current as f32,
+            range: min as f32..=max as f32,
+            step: step as f32,
+        }))
+    }
+
+    fn model(&mut self) -> Result<Option<CameraModel>> {
+        Ok(Some(CameraModel {
+            vendor: "Sony".to_owned(),
+            model: self.model.clone(),
+        }))
+    }
+
+    fn battery_level(&mut self) -> Result<Option<u8>> {
+        let level = self
+            .read(Setting::BatteryRemain)?
+            .and_then(|values| u8::try_from(values.current).ok())
+            .filter(|level| *level <= 100);
+        Ok(level)
+    }
+
+    fn preview_frame(&mut self) -> Result<Vec<u8>> {
+        let mut data = ptr::null_mut();
+        let mut len = 0;
+        // SAFETY: the shim sets `data` to `len` bytes it allocated on success.
+        check(
+            unsafe { ffi::sony_live_view(self.camera.ptr(), &mut data, &mut len) },
+            "read the live view",
+        )?;
+
+        // SAFETY: as above, freed once copied.
+        let frame = unsafe { std::slice::from_raw_parts(data, len) }.to_vec();
+        unsafe { ffi::sony_free(data) };
+        Ok(frame)
+    }
+}
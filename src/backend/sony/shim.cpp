@@ -0,0 +1,384 @@
+// C interface to Sony's Camera Remote SDK for ffi.rs, as Rust can't call its
+// C++ API. Built with the `sony` feature, see build.rs.
+//
+// The SDK calls back from threads of its own, so everything the callbacks
+// touch is guarded by the camera's mutex.
+
+#include "CRSDK/CameraRemote_SDK.h"
+#include "CRSDK/IDeviceCallback.h"
+
+#include <chrono>
+#include <condition_variable>
+#include <cstdint>
+#include <cstdlib>
+#include <cstring>
+#include <deque>
+#include <mutex>
+#include <string>
+#include <thread>
+#include <vector>
+
+namespace SDK = SCRSDK;
+
+namespace {
+
+// Errors of the shim itself, the SDK's are positive.
+constexpr int32_t NOT_FOUND = -1;
+constexpr int32_t NOT_AVAILABLE = -2;
+constexpr int32_t TIMEOUT = -3;
+constexpr int32_t READ_ONLY = -4;
+constexpr int32_t INIT_FAILED = -5;
+
+constexpr size_t MAX_VALUES = 256;
+
+// Has the SDK number the files it saves on its own.
+constexpr CrInt32 SAVE_AUTO_NUMBER = -1;
+
+// How long the shutter button is held down for a shot.
+constexpr auto RELEASE_HOLD = std::chrono::milliseconds(35);
+
+// Mirrors `Setting` in ffi.rs.
+enum Setting : int32_t {
+    ISO,
+    SHUTTER_SPEED,
+    F_NUMBER,
+    EXPOSURE_BIAS,
+    WHITE_BALANCE,
+    COLOR_TEMPERATURE,
+    EXPOSURE_PROGRAM,
+    FILE_TYPE,
+    BATTERY_REMAIN,
+};
+
+CrInt32u property_code(int32_t setting) {
+    switch (setting) {
+    case ISO:
+        return SDK::CrDeviceProperty_IsoSensitivity;
+    case SHUTTER_SPEED:
+        return SDK::CrDeviceProperty_ShutterSpeed;
+    case F_NUMBER:
+        return SDK::CrDeviceProperty_FNumber;
+    case EXPOSURE_BIAS:
+        return SDK::CrDeviceProperty_ExposureBiasCompensation;
+    case WHITE_BALANCE:
+        return SDK::CrDeviceProperty_WhiteBalance;
+    case COLOR_TEMPERATURE:
+        return SDK::CrDeviceProperty_Colortemp;
+    case EXPOSURE_PROGRAM:
+        return SDK::CrDeviceProperty_ExposureProgramMode;
+    case FILE_TYPE:
+        return SDK::CrDeviceProperty_FileType;
+    case BATTERY_REMAIN:
+        return SDK::CrDeviceProperty_BatteryRemain;
+    default:
+        return SDK::CrDeviceProperty_Undefined;
+    }
+}
+
+// Bytes per value of a property of `type`.
+size_t value_size(CrInt32u type) {
+    switch (type & ~(SDK::CrDataType_SignBit | SDK::CrDataType_ArrayBit | SDK::CrDataType_RangeBit)) {
+    case SDK::CrDataType_UInt8:
+        return 1;
+    case SDK::CrDataType_UInt16:
+        return 2;
+    case SDK::CrDataType_UInt32:
+        return 4;
+    default:
+        return 8;
+    }
+}
+
+// `raw` as the signed or unsigned number of `size` bytes it holds.
+int64_t widen(uint64_t raw, size_t size, bool is_signed) {
+    if (size >= 8) {
+        return static_cast<int64_t>(raw);
+    }
+    const unsigned bits = static_cast<unsigned>(size * 8);
+    raw &= (uint64_t{1} << bits) - 1;
+    if (is_signed && (raw >> (bits - 1)) != 0) {
+        return static_cast<int64_t>(raw | ~((uint64_t{1} << bits) - 1));
+    }
+    return static_cast<int64_t>(raw);
+}
+
+} // namespace
+
+extern "C" {
+
+// Mirrors `SettingValues` in ffi.rs.
+struct sony_setting_values {
+    int64_t current;
+    int64_t values[MAX_VALUES];
+    size_t count;
+    // `values` are the minimum, maximum and step rather than a list.
+    bool range;
+    bool writable;
+};
+
+} // extern "C"
+
+struct sony_camera final : public SDK::IDeviceCallback {
+    SDK::ICrEnumCameraObjectInfo* list = nullptr;
+    SDK::CrDeviceHandle handle = 0;
+    std::string model;
+
+    std::mutex mutex;
+    std::condition_variable changed;
+    bool connected = false;
+    // The last error the SDK reported, 0 for none.
+    CrInt32u error = 0;
+    // Paths of the files transferred and not yet taken by sony_next_download.
+    std::deque<std::string> downloads;
+
+    void OnConnected(SDK::DeviceConnectionVersioin) override {
+        std::lock_guard<std::mutex> lock(mutex);
+        connected = true;
+        changed.notify_all();
+    }
+
+    void OnDisconnected(CrInt32u reason) override {
+        std::lock_guard<std::mutex> lock(mutex);
+        connected = false;
+        error = reason;
+        changed.notify_all();
+    }
+
+    void OnPropertyChanged() override {}
+
+    void OnLvPropertyChanged() override {}
+
+    void OnCompleteDownload(CrChar* filename, CrInt32u) override {
+        std::lock_guard<std::mutex> lock(mutex);
+        downloads.emplace_back(filename);
+        changed.notify_all();
+    }
+
+    void OnWarning(CrInt32u) override {}
+
+    void OnError(CrInt32u reason) override {
+        std::lock_guard<std::mutex> lock(mutex);
+        error = reason;
+        changed.notify_all();
+    }
+
+    // Copies the property `code` out of the SDK's list into `out`.
+    int32_t read(CrInt32u code, sony_setting_values* out, CrInt32u* type) {
+        SDK::CrDeviceProperty* properties = nullptr;
+        CrInt32 count = 0;
+        const auto error = SDK::GetDeviceProperties(handle, &properties, &count);
+        if (CR_FAILED(error)) {
+            return static_cast<int32_t>(error);
+        }
+
+        int32_t result = NOT_AVAILABLE;
+        for (CrInt32 index = 0; index < count; ++index) {
+            const auto& property = properties[index];
+            if (property.GetCode() != code) {
+                continue;
+            }
+
+            *type = property.GetValueType();
+            const size_t size = value_size(*type);
+            const bool is_signed = (*type & SDK::CrDataType_SignBit) != 0;
+            const CrInt8u* values = property.GetValues();
+            const size_t len = values ? property.GetValueSize() / size : 0;
+
+            out->current = widen(property.GetCurrentValue(), size, is_signed);
+            out->count = len < MAX_VALUES ? len : MAX_VALUES;
+            for (size_t value = 0; value < out->count; ++value) {
+                uint64_t raw = 0;
+                std::memcpy(&raw, values + value * size, size);
+                out->values[value] = widen(raw, size, is_signed);
+            }
+            out->range = (*type & SDK::CrDataType_RangeBit) != 0;
+            out->writable = property.IsSetEnableCurrentValue();
+            result = 0;
+            break;
+        }
+
+        SDK::ReleaseDeviceProperties(handle, properties);
+        return result;
+    }
+
+    int32_t write(CrInt32u code, int64_t value) {
+        sony_setting_values current{};
+        CrInt32u type = 0;
+        const auto result = read(code, &current, &type);
+        if (result != 0) {
+            return result;
+        }
+        if (!current.writable) {
+            return READ_ONLY;
+        }
+
+        SDK::CrDeviceProperty property;
+        property.SetCode(code);
+        property.SetCurrentValue(static_cast<CrInt64u>(value));
+        property.SetValueType(static_cast<SDK::CrDataType>(type));
+        return static_cast<int32_t>(SDK::SetDeviceProperty(handle, &property));
+    }
+};
+
+extern "C" {
+
+int32_t sony_init(void) {
+    return SDK::Init() ? 0 : INIT_FAILED;
+}
+
+void sony_close(sony_camera* camera) {
+    if (camera->handle) {
+        SDK::Disconnect(camera->handle);
+        SDK::ReleaseDevice(camera->handle);
+    }
+    if (camera->list) {
+        camera->list->Release();
+    }
+    delete camera;
+}
+
+int32_t sony_open(const char* id, const char* save_dir, uint32_t timeout_ms, sony_camera** out) {
+    SDK::ICrEnumCameraObjectInfo* list = nullptr;
+    const auto error = SDK::EnumCameraObjects(&list);
+    if (CR_FAILED(error)) {
+        return static_cast<int32_t>(error);
+    }
+    if (!list) {
+        return NOT_FOUND;
+    }
+
+    const SDK::ICrCameraObjectInfo* info = nullptr;
+    for (CrInt32u index = 0; index < list->GetCount(); ++index) {
+        const auto* candidate = list->GetCameraObjectInfo(index);
+        const std::string model(candidate->GetModel());
+        const std::string serial(reinterpret_cast<const char*>(candidate->GetId()), candidate->GetIdSize());
+        if (!id || model == id || serial == id) {
+            info = candidate;
+            break;
+        }
+    }
+    if (!info) {
+        list->Release();
+        return NOT_FOUND;
+    }
+
+    auto* camera = new sony_camera();
+    camera->list = list;
+    camera->model = info->GetModel();
+
+    int32_t result = static_cast<int32_t>(
+        SDK::Connect(const_cast<SDK::ICrCameraObjectInfo*>(info), camera, &camera->handle));
+    if (result == 0) {
+        std::unique_lock<std::mutex> lock(camera->mutex);
+        const bool done = camera->changed.wait_for(lock, std::chrono::milliseconds(timeout_ms),
+                                                   [camera] { return camera->connected || camera->error != 0; });
+        if (!done) {
+            result = TIMEOUT;
+        } else if (!camera->connected) {
+            result = static_cast<int32_t>(camera->error);
+        }
+    }
+    if (result == 0) {
+        result = static_cast<int32_t>(SDK::SetSaveInfo(camera->handle, const_cast<CrChar*>(save_dir),
+                                                       const_cast<CrChar*>("DSC"), SAVE_AUTO_NUMBER));
+    }
+    // Settings made over USB win over the dials. Bodies without the choice
+    // take them anyway.
+    if (result == 0) {
+        camera->write(SDK::CrDeviceProperty_PriorityKeySettings, SDK::CrPriorityKey_PCRemote);
+    }
+    // Shots are transferred as well as written to the card.
+    if (result == 0) {
+        result = camera->write(SDK::CrDeviceProperty_StillImageStoreDestination,
+                               SDK::CrStillImageStoreDestination_HostPCAndMemoryCard);
+    }
+
+    if (result != 0) {
+        sony_close(camera);
+        return result;
+    }
+    *out = camera;
+    return 0;
+}
+
+const char* sony_model(const sony_camera* camera) {
+    return camera->model.c_str();
+}
+
+bool sony_connected(sony_camera* camera) {
+    std::lock_guard<std::mutex> lock(camera->mutex);
+    return camera->connected;
+}
+
+int32_t sony_release_shutter(sony_camera* camera) {
+    {
+        // Files left from an earlier shot don't belong to this one.
+        std::lock_guard<std::mutex> lock(camera->mutex);
+        camera->downloads.clear();
+    }
+
+    auto error = SDK::SendCommand(camera->handle, SDK::CrCommandId_Release, SDK::CrCommandParam_Down);
+    if (CR_FAILED(error)) {
+        return static_cast<int32_t>(error);
+    }
+    std::this_thread::sleep_for(RELEASE_HOLD);
+    error = SDK::SendCommand(camera->handle, SDK::CrCommandId_Release, SDK::CrCommandParam_Up);
+    return static_cast<int32_t>(error);
+}
+
+int32_t sony_next_download(sony_camera* camera, uint32_t timeout_ms, char* path, size_t len) {
+    std::unique_lock<std::mutex> lock(camera->mutex);
+    const bool transferred = camera->changed.wait_for(lock, std::chrono::milliseconds(timeout_ms),
+                                                      [camera] { return !camera->downloads.empty() || !camera->connected; });
+    if (!transferred || camera->downloads.empty()) {
+        return camera->connected ? TIMEOUT : static_cast<int32_t>(camera->error);
+    }
+
+    const std::string file = std::move(camera->downloads.front());
+    camera->downloads.pop_front();
+    if (file.size() >= len) {
+        return NOT_AVAILABLE;
+    }
+    std::memcpy(path, file.c_str(), file.size() + 1);
+    return 0;
+}
+
+int32_t sony_get_setting(sony_camera* camera, int32_t setting, sony_setting_values* values) {
+    CrInt32u type = 0;
+    return camera->read(property_code(setting), values, &type);
+}
+
+int32_t sony_set_setting(sony_camera* camera, int32_t setting, int64_t value) {
+    return camera->write(property_code(setting), value);
+}
+
+int32_t sony_live_view(sony_camera* camera, uint8_t** data, size_t* len) {
+    SDK::CrImageInfo info;
+    auto error = SDK::GetLiveViewImageInfo(camera->handle, &info);
+    if (CR_FAILED(error)) {
+        return static_cast<int32_t>(error);
+    }
+
+    std::vector<CrInt8u> buffer(info.GetBufferSize());
+    SDK::CrImageDataBlock block;
+    block.SetSize(static_cast<CrInt32u>(buffer.size()));
+    block.SetData(buffer.data());
+    error = SDK::GetLiveViewImage(camera->handle, &block);
+    if (CR_FAILED(error)) {
+        return static_cast<int32_t>(error);
+    }
+
+    *len = block.GetImageSize();
+    *data = static_cast<uint8_t*>(std::malloc(*len));
+    if (!*data) {
+        return NOT_AVAILABLE;
+    }
+    std::memcpy(*data, block.GetImageData(), *len);
+    return 0;
+}
+
+void sony_free(uint8_t* data) {
+    std::free(data);
+}
+
+} // extern "C"
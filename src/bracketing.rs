@@ -0,0 +1,33 @@
+//! Auto exposure bracketing: one trigger takes a series of shots with the
+//! exposure compensation stepped around the camera's setting, for HDR merges
+//! of high contrast scenes.
+
+/// Number of shots and their spacing in a bracket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BracketingOptions {
+    /// Shots per trigger, at least 2.
+    pub shots: u8,
+    /// Exposure compensation between shots in EV.
+    pub ev_step: f32,
+}
+
+impl Default for BracketingOptions {
+    /// The common -1, 0, +1 EV bracket.
+    fn default() -> Self {
+        Self {
+            shots: 3,
+            ev_step: 1.0,
+        }
+    }
+}
+
+impl BracketingOptions {
+    /// Exposure compensation of every shot relative to the camera's setting,
+    /// darkest first and centred on it.
+    pub(crate) fn offsets(&self) -> Vec<f32> {
+        let centre = f32::from(self.shots.saturating_sub(1)) / 2.0;
+        (0..self.shots)
+            .map(|shot| (f32::from(shot) - centre) * self.ev_step)
+            .collect()
+    }
+}
@@ -0,0 +1,19 @@
+//! Bulb exposures longer than the camera's shutter speeds go, for night
+//! surveys and astrophotography.
+
+use mavlink::common::MavCmd;
+use std::time::Duration;
+
+/// Takes one bulb exposure of param 1 seconds.
+pub const BULB_COMMAND: MavCmd = MavCmd::MAV_CMD_USER_2;
+
+/// Longest exposure taken, so a typo doesn't tie the camera up for days.
+const MAX_EXPOSURE: Duration = Duration::from_secs(60 * 60);
+
+/// The exposure asked for with `seconds`, `None` unless it's positive and
+/// at most an hour.
+pub(crate) fn exposure(seconds: f32) -> Option<Duration> {
+    Duration::try_from_secs_f32(seconds)
+        .ok()
+        .filter(|exposure| !exposure.is_zero() && *exposure <= MAX_EXPOSURE)
+}
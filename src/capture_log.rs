@@ -0,0 +1,255 @@
+//! Append-only log of every capture with where the vehicle was and how the
+//! camera was set, for survey processing tools like Pix4D or ODM that can
+//! import image positions from a CSV or JSON file.
+
+use crate::clock::UtcSource;
+use crate::error::{CameraError, Result};
+use crate::geotag::Geotag;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+const CSV_HEADER: &str = concat!(
+    "camera,seq,time_utc,lat,lon,alt,relative_alt,roll,pitch,yaw,",
+    "result,path,settings,burst,time_source,retries"
+);
+
+/// Where and in which format captures are logged.
+#[derive(Debug, Clone)]
+pub struct CaptureLogOptions {
+    /// The log file, appended to if it already exists.
+    pub path: PathBuf,
+    pub format: CaptureLogFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureLogFormat {
+    /// One row per capture below a header row. Positions are in degrees and
+    /// metres, attitude in degrees and settings as `key=value;...`.
+    #[default]
+    Csv,
+    /// One JSON object per line with the same fields as the CSV columns.
+    Json,
+}
+
+/// How a capture turned out, the `result` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CaptureOutcome {
+    Ok,
+    Failed,
+    /// Failed as `path` didn't download whole, the area needs flying again.
+    Corrupt,
+    /// Taken, but left on the camera at `path` as the download queue was full.
+    OnCamera,
+}
+
+impl CaptureOutcome {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Failed => "failed",
+            Self::Corrupt => "corrupt",
+            Self::OnCamera => "on_camera",
+        }
+    }
+}
+
+/// One capture, successful or not.
+pub(crate) struct CaptureRecord {
+    pub camera: u8,
+    pub seq: i32,
+    pub taken: DateTime<Utc>,
+    /// Whose clock `taken` is from.
+    pub time_source: UtcSource,
+    pub geotag: Option<Geotag>,
+    /// Exposure settings the image was taken with, as far as the backend knows.
+    pub settings: BTreeMap<String, String>,
+    /// The downloaded image, `None` if the capture failed.
+    pub path: Option<PathBuf>,
+    pub outcome: CaptureOutcome,
+    /// Why the capture failed.
+    pub error: Option<String>,
+    /// Shared by the shots of one burst, exposure bracket or focus stack: the
+    /// image index of its first shot.
+    pub burst: Option<i32>,
+    /// How often the shot was taken again after failing.
+    pub retries: u32,
+}
+
+/// The open capture log, shared by all cameras.
+#[derive(Clone)]
+pub(crate) struct CaptureLog {
+    format: CaptureLogFormat,
+    file: Arc<Mutex<File>>,
+}
+
+impl CaptureLog {
+    /// Opens the log for appending, writing the CSV header to a new file.
+    pub fn open(options: CaptureLogOptions) -> Result<Self> {
+        let path = options.path;
+        let open = || -> io::Result<File> {
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            if options.format == CaptureLogFormat::Csv && file.metadata()?.len() == 0 {
+                writeln!(file, "{CSV_HEADER}")?;
+            }
+            Ok(file)
+        };
+        let file = open().map_err(|source| CameraError::CaptureLog {
+            path: path.clone(),
+            source,
+        })?;
+        info!(target: "rx", path = %path.display(), "Logging captures");
+
+        Ok(Self {
+            format: options.format,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Appends `record` as a single write so lines from several cameras can't
+    /// interleave. Blocks on the disk.
+    pub fn record(&self, record: &CaptureRecord) -> Result<()> {
+        let line = match self.format {
+            CaptureLogFormat::Csv => csv_line(record),
+            CaptureLogFormat::Json => json_line(record),
+        };
+
+        self.file.lock()?.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// The geotag in the units survey tools expect: lat, lon, alt, relative alt,
+/// roll, pitch and yaw, each `None` if unknown.
+fn position_fields(geotag: Option<&Geotag>) -> [Option<String>; 7] {
+    let Some(geotag) = geotag else {
+        return Default::default();
+    };
+    let attitude = |axis: usize| {
+        geotag
+            .attitude
+            .map(|attitude| format!("{:.2}", attitude[axis].to_degrees()))
+    };
+
+    [
+        Some(format!("{:.7}", f64::from(geotag.lat) / 1e7)),
+        Some(format!("{:.7}", f64::from(geotag.lon) / 1e7)),
+        Some(format!("{:.3}", f64::from(geotag.alt) / 1e3)),
+        Some(format!("{:.3}", f64::from(geotag.relative_alt) / 1e3)),
+        attitude(0),
+        attitude(1),
+        attitude(2),
+    ]
+}
+
+fn time_utc(record: &CaptureRecord) -> String {
+    record.taken.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn csv_line(record: &CaptureRecord) -> String {
+    let settings = record
+        .settings
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join(";");
+    let path = record
+        .path
+        .as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+
+    let mut line = format!("{},{},{}", record.camera, record.seq, time_utc(record));
+    for field in position_fields(record.geotag.as_ref()) {
+        line.push(',');
+        line.push_str(field.as_deref().unwrap_or_default());
+    }
+    for field in [record.outcome.name(), &path, &settings] {
+        line.push(',');
+        line.push_str(&csv_field(field));
+    }
+    line.push(',');
+    if let Some(burst) = record.burst {
+        let _ = write!(line, "{burst}");
+    }
+    let _ = writeln!(line, ",{},{}", record.time_source.name(), record.retries);
+    line
+}
+
+/// Quotes `value` if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+fn json_line(record: &CaptureRecord) -> String {
+    let mut line = format!(
+        r#"{{"camera":{},"seq":{},"time_utc":{}"#,
+        record.camera,
+        record.seq,
+        json_string(&time_utc(record))
+    );
+
+    let names = ["lat", "lon", "alt", "relative_alt", "roll", "pitch", "yaw"];
+    for (name, field) in names
+        .into_iter()
+        .zip(position_fields(record.geotag.as_ref()))
+    {
+        let _ = write!(line, r#","{name}":{}"#, field.as_deref().unwrap_or("null"));
+    }
+
+    let _ = write!(line, r#","result":{}"#, json_string(record.outcome.name()));
+    if let Some(error) = &record.error {
+        let _ = write!(line, r#","error":{}"#, json_string(error));
+    }
+    let path = record.path.as_ref().map_or("null".to_owned(), |path| {
+        json_string(&path.display().to_string())
+    });
+    let _ = write!(line, r#","path":{path},"settings":{{"#);
+    for (index, (key, value)) in record.settings.iter().enumerate() {
+        if index > 0 {
+            line.push(',');
+        }
+        let _ = write!(line, "{}:{}", json_string(key), json_string(value));
+    }
+    let burst = record
+        .burst
+        .map_or("null".to_owned(), |burst| burst.to_string());
+    let _ = writeln!(
+        line,
+        r#"}},"burst":{burst},"time_source":{},"retries":{}}}"#,
+        json_string(record.time_source.name()),
+        record.retries
+    );
+    line
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            control if control.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", control as u32);
+            }
+            other => escaped.push(other),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
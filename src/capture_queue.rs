@@ -0,0 +1,114 @@
+//! Pictures asked for faster than the camera takes them, e.g. by a distance
+//! trigger at high speed, held back, merged or turned down by policy rather
+//! than fired back to back at a camera that is still busy.
+
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// What happens to a picture asked for while another one waits or the last
+/// one was taken less than the minimum interval ago.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueuePolicy {
+    /// Waits its turn, up to the queue's depth.
+    #[default]
+    Queue,
+    /// Merges with the picture waiting, so a burst of requests takes one.
+    Coalesce,
+    /// Is turned down.
+    Reject,
+}
+
+/// How pictures asked for in quick succession are spaced out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureQueueOptions {
+    pub policy: QueuePolicy,
+    /// Most pictures waiting with [`QueuePolicy::Queue`], at least 1.
+    pub max_depth: usize,
+    /// Shortest time from the start of one capture to the next.
+    pub min_interval: Duration,
+}
+
+impl Default for CaptureQueueOptions {
+    fn default() -> Self {
+        Self {
+            policy: QueuePolicy::Queue,
+            max_depth: 4,
+            min_interval: Duration::ZERO,
+        }
+    }
+}
+
+/// What became of a picture asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Admission {
+    Queued,
+    /// Taken along with the one already waiting.
+    Merged,
+    Rejected,
+}
+
+/// The pictures one camera was asked for and hasn't started yet.
+#[derive(Debug)]
+pub(crate) struct CaptureQueue {
+    options: CaptureQueueOptions,
+    pending: usize,
+    last_capture: Option<Instant>,
+}
+
+impl CaptureQueue {
+    pub fn new(options: CaptureQueueOptions) -> Self {
+        Self {
+            options,
+            pending: 0,
+            last_capture: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending == 0
+    }
+
+    /// What would become of a picture asked for now.
+    pub fn admission(&self) -> Admission {
+        let busy = self.pending > 0 || Instant::now() < self.next_start();
+        match self.options.policy {
+            _ if !busy => Admission::Queued,
+            QueuePolicy::Queue if self.pending < self.options.max_depth => Admission::Queued,
+            QueuePolicy::Coalesce if self.pending > 0 => Admission::Merged,
+            QueuePolicy::Coalesce => Admission::Queued,
+            QueuePolicy::Queue | QueuePolicy::Reject => Admission::Rejected,
+        }
+    }
+
+    /// Asks for a picture.
+    pub fn push(&mut self) -> Admission {
+        let admission = self.admission();
+        if admission == Admission::Queued {
+            self.pending += 1;
+        }
+        admission
+    }
+
+    /// Takes the next picture off the queue once it's due.
+    pub fn pop(&mut self) {
+        self.pending = self.pending.saturating_sub(1);
+    }
+
+    /// Records that a capture starts now, queued or not, e.g. of a
+    /// time-lapse.
+    pub fn started(&mut self) {
+        self.last_capture = Some(Instant::now());
+    }
+
+    /// Waits until the next picture may start. Cancel safe.
+    pub async fn due(&self) {
+        tokio::time::sleep_until(self.next_start()).await;
+    }
+
+    fn next_start(&self) -> Instant {
+        self.last_capture
+            .map_or_else(Instant::now, |last| last + self.options.min_interval)
+    }
+}
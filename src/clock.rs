@@ -0,0 +1,54 @@
+//! Timestamps of the messages the component sends, and the camera's own
+//! clock.
+
+use mavlink::common::MavCmd;
+use std::time::Instant;
+
+/// Sets the camera body's clock to the vehicle's UTC time, usually from GPS,
+/// so the times in the camera's EXIF match the flight log. Temporarily
+/// rejected until the autopilot has sent `SYSTEM_TIME`.
+pub const SET_CLOCK_COMMAND: MavCmd = MavCmd::MAV_CMD_USER_4;
+
+/// Milliseconds since the component started, for the `time_boot_ms` of
+/// outgoing messages. Monotonic, so it doesn't jump with the wall clock.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TimeSource {
+    started: Instant,
+}
+
+impl TimeSource {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+        }
+    }
+
+    /// Wraps around after 49 days, like an autopilot's.
+    pub fn boot_ms(&self) -> u32 {
+        self.started.elapsed().as_millis() as u32
+    }
+
+    /// Nanoseconds since the component started, as `TIMESYNC` wants.
+    pub fn boot_ns(&self) -> i64 {
+        self.started.elapsed().as_nanos() as i64
+    }
+}
+
+/// Where the UTC time of a capture came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UtcSource {
+    /// The autopilot's `SYSTEM_TIME`, usually GPS time.
+    Vehicle,
+    /// The companion computer's clock, which may be off without a network
+    /// or a real-time clock.
+    Companion,
+}
+
+impl UtcSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Vehicle => "vehicle",
+            Self::Companion => "companion",
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! The component's own commands beyond the camera protocol, such as
+//! [`crate::FOCUS_STACK_COMMAND`], which take MAVLink's user command ids by
+//! default. Applications that need those ids for their own
+//! [`crate::MavLinkCameraHandle::on_command`] handlers move the commands to
+//! other ids or turn them off.
+
+use crate::bulb::BULB_COMMAND;
+use crate::clock::SET_CLOCK_COMMAND;
+use crate::focus_stack::FOCUS_STACK_COMMAND;
+use crate::offload::OFFLOAD_COMMAND;
+use crate::selftest::SELF_TEST_COMMAND;
+use crate::video::STREAM_SETTINGS_COMMAND;
+use mavlink::common::MavCmd;
+
+/// The command id of each of the component's own commands, `None` to turn
+/// it off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CustomCommands {
+    /// [`crate::FOCUS_STACK_COMMAND`] by default.
+    pub focus_stack: Option<u32>,
+    /// [`crate::BULB_COMMAND`] by default.
+    pub bulb: Option<u32>,
+    /// [`crate::SELF_TEST_COMMAND`] by default.
+    pub self_test: Option<u32>,
+    /// [`crate::SET_CLOCK_COMMAND`] by default.
+    pub set_clock: Option<u32>,
+    /// [`crate::OFFLOAD_COMMAND`] by default.
+    pub offload: Option<u32>,
+    /// [`crate::STREAM_SETTINGS_COMMAND`] by default.
+    pub stream_settings: Option<u32>,
+}
+
+impl Default for CustomCommands {
+    /// Every command on its documented id.
+    fn default() -> Self {
+        Self {
+            focus_stack: Some(FOCUS_STACK_COMMAND as u32),
+            bulb: Some(BULB_COMMAND as u32),
+            self_test: Some(SELF_TEST_COMMAND as u32),
+            set_clock: Some(SET_CLOCK_COMMAND as u32),
+            offload: Some(OFFLOAD_COMMAND as u32),
+            stream_settings: Some(STREAM_SETTINGS_COMMAND as u32),
+        }
+    }
+}
+
+/// One of the component's own commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CustomCommand {
+    FocusStack,
+    Bulb,
+    SelfTest,
+    SetClock,
+    Offload,
+    StreamSettings,
+}
+
+impl CustomCommands {
+    fn ids(&self) -> [(Option<u32>, CustomCommand); 6] {
+        [
+            (self.focus_stack, CustomCommand::FocusStack),
+            (self.bulb, CustomCommand::Bulb),
+            (self.self_test, CustomCommand::SelfTest),
+            (self.set_clock, CustomCommand::SetClock),
+            (self.offload, CustomCommand::Offload),
+            (self.stream_settings, CustomCommand::StreamSettings),
+        ]
+    }
+
+    /// Which of the commands `command` is, if any.
+    pub(crate) fn get(&self, command: MavCmd) -> Option<CustomCommand> {
+        self.ids()
+            .into_iter()
+            .find(|(id, _)| *id == Some(command as u32))
+            .map(|(_, custom)| custom)
+    }
+
+    /// Whether one of the commands has the id `command`.
+    pub(crate) fn contains(&self, command: u32) -> bool {
+        self.ids().iter().any(|(id, _)| *id == Some(command))
+    }
+
+    /// An id given to more than one command, if any.
+    pub(crate) fn duplicate(&self) -> Option<u32> {
+        let ids = self.ids();
+        ids.iter().enumerate().find_map(|(index, (id, _))| {
+            id.filter(|id| {
+                ids[index + 1..]
+                    .iter()
+                    .any(|(other, _)| *other == Some(*id))
+            })
+        })
+    }
+}
@@ -0,0 +1,1527 @@
+//! TOML configuration for headless deployments.
+//!
+//! Every section is optional; missing values fall back to the defaults below and
+//! command line flags take precedence over anything loaded from the file.
+
+use crate::backend::{CaptureTarget, DownloadFormat, RetryOptions, Sensor};
+#[cfg(feature = "grpc")]
+use crate::GrpcServerOptions;
+#[cfg(feature = "rtsp")]
+use crate::H264Encoder;
+#[cfg(feature = "mqtt")]
+use crate::MqttOptions;
+#[cfg(feature = "ros2")]
+use crate::Ros2Options;
+use crate::{
+    AutofocusOptions, BracketingOptions, CaptureLogFormat, CaptureLogOptions, CaptureQueueOptions,
+    ComponentOptions, CustomCommands, DownloadQueueOptions, FilenameTemplate, FocusStackOptions,
+    FootprintOptions, HotShoeOptions, HttpServerOptions, IdConflict, IdConflictCheck,
+    ImageTransmissionOptions, LiveViewServer, MavlinkCameraComponent, PcapOptions, QueuePolicy,
+    StorageOptions, StreamRates, ThumbnailOptions, TlogOptions, VideoEncoding, VideoStreamOptions,
+    WatchdogOptions, CAMERA_COMPONENT_IDS,
+};
+use anyhow::{bail, Context, Result};
+use mavlink::common::VideoStreamType;
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Connection string schemes the component can open. `tlog` replays a recorded
+/// telemetry log, see the `replay` subcommand.
+pub const CONNECTION_SCHEMES: [&str; 8] = [
+    "tcpin", "tcpout", "udpin", "udpout", "udpbcast", "serial", "file", "tlog",
+];
+
+/// MAVLink's command ids left for users: `MAV_CMD_WAYPOINT_USER_1` to
+/// `MAV_CMD_USER_5`. Other ids are taken by commands with their own meaning.
+const USER_COMMANDS: std::ops::RangeInclusive<u32> = 31000..=31014;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub mavlink: MavlinkConfig,
+    pub camera: CameraConfig,
+    /// Further camera bodies, each run as its own MAVLink component.
+    pub extra_cameras: Vec<ExtraCameraConfig>,
+    pub capture: CaptureConfig,
+    pub streaming: StreamingConfig,
+    pub logging: LoggingConfig,
+    pub daemon: DaemonConfig,
+    pub tlog: TlogConfig,
+    pub pcap: PcapConfig,
+    pub capture_log: CaptureLogConfig,
+    pub footprints: FootprintConfig,
+    pub image_transmission: ImageTransmissionConfig,
+    pub thumbnails: ThumbnailConfig,
+    pub http: HttpConfig,
+    #[cfg(feature = "grpc")]
+    pub grpc: GrpcConfig,
+    #[cfg(feature = "ros2")]
+    pub ros2: Ros2Config,
+    pub storage: StorageConfig,
+    pub bracketing: BracketingConfig,
+    pub focus_stack: FocusStackConfig,
+    pub autofocus: AutofocusConfig,
+    pub watchdog: WatchdogConfig,
+    pub stream_rates: StreamRatesConfig,
+    pub commands: CommandsConfig,
+    #[cfg(feature = "mqtt")]
+    pub mqtt: MqttConfig,
+    /// Camera settings applied through the backend at startup, e.g. `iso = "100"`.
+    pub parameters: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MavlinkConfig {
+    pub connection: String,
+    /// Further endpoints that get the same messages, e.g. `udpout:gcs:14550`.
+    pub extra_connections: Vec<String>,
+    pub system_id: u8,
+    /// A number or a name from `camera` to `camera6`, see
+    /// [`parse_component_id`].
+    #[serde(deserialize_with = "component_id")]
+    pub component_id: u8,
+    /// Most bytes per second sent over `connection`, unlimited when unset.
+    pub max_bytes_per_second: Option<u32>,
+    /// Most bytes per second sent over those of the `extra_connections` it
+    /// names, by connection string.
+    pub extra_max_bytes_per_second: BTreeMap<String, u32>,
+    /// How long to listen for components already using a camera's ids before
+    /// starting, not at all when 0.
+    pub id_conflict_check_ms: u64,
+    pub id_conflict: IdConflict,
+    /// Take the autopilot's system id instead of `system_id`, which is kept
+    /// when no autopilot is heard within `autopilot_wait_s`.
+    pub adopt_autopilot_system_id: bool,
+    pub autopilot_wait_s: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    /// Backend driving every camera body.
+    pub backend: BackendKind,
+    /// gphoto2 port of the camera, autodetected when unset, or
+    /// `ptpip:<address>` for a camera on the network. With the `sony`
+    /// backend, the camera's serial number or model, e.g. `ILCE-7RM4`, and
+    /// with the `canon` backend its CCAPI address, e.g. `192.168.1.2:8080`.
+    /// With the `libcamera` backend the camera's index, `0` when unset.
+    pub port: Option<String>,
+    /// Vendor and model ground stations are told, the camera's own when
+    /// unset.
+    pub vendor_name: Option<String>,
+    pub model_name: Option<String>,
+    /// Sensor size in mm and full image resolution in pixels, the long side
+    /// first, set together. The camera's own when unset.
+    pub sensor_width_mm: Option<f32>,
+    pub sensor_height_mm: Option<f32>,
+    pub resolution_h: Option<u16>,
+    pub resolution_v: Option<u16>,
+    /// Camera definition ground stations download instead of the one served
+    /// with `http`, and its version, that of the served one when unset.
+    pub definition_uri: Option<String>,
+    pub definition_version: Option<u16>,
+    /// Sysfs GPIO a hot-shoe adapter is wired to, for exact shutter times.
+    pub hot_shoe_gpio: Option<u32>,
+    /// Actuations the shutter is rated for, warned about when nearly reached.
+    pub rated_shutter_life: Option<u64>,
+    /// Body temperature in °C at which the live view of every camera is
+    /// paused.
+    pub overheat_temperature_c: Option<f32>,
+    /// Sets the clock of every camera to the vehicle's time once known.
+    pub sync_clock: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// Real cameras through libgphoto2.
+    #[default]
+    Gphoto,
+    /// Simulated cameras that need no hardware.
+    #[cfg(feature = "sim")]
+    Sim,
+    /// Sony bodies through Sony's Camera Remote SDK.
+    #[cfg(feature = "sony")]
+    Sony,
+    /// Canon bodies over their CCAPI REST interface.
+    #[cfg(feature = "ccapi")]
+    Canon,
+    /// Raspberry Pi cameras through libcamera.
+    #[cfg(feature = "libcamera")]
+    Libcamera,
+}
+
+/// A further camera body, e.g. an oblique camera next to the nadir one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtraCameraConfig {
+    #[serde(deserialize_with = "component_id")]
+    pub component_id: u8,
+    /// gphoto2 port of the camera, the serial number of a Sony camera, the
+    /// CCAPI address of a Canon one or the index of a libcamera one; required
+    /// to tell the bodies apart.
+    pub port: String,
+    /// Kept separate from `capture.image_dir` so file names can't collide.
+    pub image_dir: PathBuf,
+    pub vendor_name: Option<String>,
+    pub model_name: Option<String>,
+    pub sensor_width_mm: Option<f32>,
+    pub sensor_height_mm: Option<f32>,
+    pub resolution_h: Option<u16>,
+    pub resolution_v: Option<u16>,
+    pub definition_uri: Option<String>,
+    pub definition_version: Option<u16>,
+    pub hot_shoe_gpio: Option<u32>,
+    pub rated_shutter_life: Option<u64>,
+    /// Live view of this camera like `[streaming]` is of the primary one,
+    /// off when unset.
+    pub streaming: Option<StreamingConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    pub image_dir: PathBuf,
+    /// Shortest time between captures when triggering by distance.
+    pub min_trigger_interval_ms: u64,
+    /// Names downloaded images, e.g. `{flight}/{seq:05}_{lat}_{lon}.{ext}`.
+    /// See [`FilenameTemplate`] for the placeholders.
+    pub filename_template: Option<String>,
+    /// Keeps the image counter across restarts.
+    pub state_dir: Option<PathBuf>,
+    /// Which files of each shot are downloaded, whatever the camera saves.
+    pub download: DownloadFormat,
+    /// Where the camera saves shots, left as it is when unset.
+    pub target: Option<CaptureTarget>,
+    /// Deletes each file from the camera once its download is verified.
+    pub delete_after_download: bool,
+    /// Stops time-lapses and distance triggering after this many seconds
+    /// without autopilot heartbeats.
+    pub autopilot_timeout_s: Option<u64>,
+    /// What happens to pictures asked for while the camera is busy.
+    pub queue_policy: QueuePolicy,
+    /// Most pictures waiting with the `queue` policy.
+    pub queue_depth: usize,
+    /// Shortest time from the start of one capture to the next.
+    pub min_capture_interval_ms: u64,
+    /// How often a failed capture or download is tried again.
+    pub retries: u32,
+    /// Pause before the first retry, doubled for every further one.
+    pub retry_backoff_ms: u64,
+    /// Most pictures waiting for their download in the background, 0
+    /// downloads each before the next is taken.
+    pub download_queue_depth: usize,
+    /// Leaves single pictures on the camera until offloaded.
+    pub keep_on_card: bool,
+    /// Downloads movies once recorded.
+    pub download_video: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamingConfig {
+    pub enabled: bool,
+    pub port: u16,
+    /// Advertised stream URI, `rtsp://<hostname>:<port>/live` or
+    /// `http://<hostname>:<port>/live` for `mjpeg` when unset.
+    pub uri: Option<String>,
+    pub width: u16,
+    pub height: u16,
+    pub framerate: f32,
+    /// What serves the stream at `uri`.
+    pub server: StreamServer,
+    /// H.264 encoder of the `rtsp` server.
+    #[cfg(feature = "rtsp")]
+    pub encoder: H264Encoder,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamServer {
+    /// Something outside the component, the stream is only advertised.
+    #[default]
+    External,
+    /// The component's own MJPEG over HTTP server, which needs no GStreamer.
+    Mjpeg,
+    /// The component's own RTSP server.
+    #[cfg(feature = "rtsp")]
+    Rtsp,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// `tracing` filter directives, e.g. `info,rx=debug,heartbeat=warn`.
+    pub filter: String,
+}
+
+/// Running as a system service.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// File the process ID is written to while running, none when unset.
+    pub pid_file: Option<PathBuf>,
+}
+
+/// Recording of the MAVLink traffic for post-flight debugging.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlogConfig {
+    /// Telemetry log to write, recording is off when unset.
+    pub path: Option<PathBuf>,
+    /// Size in MiB after which the log is rotated.
+    pub max_size_mb: u64,
+    /// Number of rotated logs to keep.
+    pub keep: usize,
+}
+
+/// Capture of the raw MAVLink frames for Wireshark, see [`PcapOptions`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PcapConfig {
+    /// Capture to write, off when unset.
+    pub path: Option<PathBuf>,
+    /// Size in MiB after which nothing more is captured.
+    pub max_size_mb: u64,
+}
+
+/// Log of every capture for survey processing.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureLogConfig {
+    /// Capture log to append to, logging is off when unset.
+    pub path: Option<PathBuf>,
+    /// `csv` or `json` (one object per line).
+    pub format: CaptureLogFormat,
+}
+
+/// GeoJSON coverage file with the ground footprint of every capture.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FootprintConfig {
+    /// Coverage file to add to, footprints are off when unset.
+    pub path: Option<PathBuf>,
+    /// Sensor size for cameras whose sensor isn't configured with them or
+    /// told by the camera.
+    pub sensor_width_mm: Option<f32>,
+    pub sensor_height_mm: Option<f32>,
+    /// Used for images that don't record their focal length.
+    pub focal_length_mm: f32,
+}
+
+/// Previews of the captures over MAVLink with `ENCAPSULATED_DATA`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageTransmissionConfig {
+    pub enabled: bool,
+    /// Longest side of the preview in pixels.
+    pub max_size: u32,
+    /// 1-100.
+    pub jpeg_quality: u8,
+    /// Packets of 253 bytes per second.
+    pub packet_rate: f32,
+}
+
+/// Small JPEG thumbnails of the captures.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThumbnailConfig {
+    pub enabled: bool,
+    /// Longest side of the thumbnail in pixels.
+    pub max_size: u32,
+    /// 1-100.
+    pub jpeg_quality: u8,
+}
+
+/// Exposure brackets instead of single pictures.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BracketingConfig {
+    pub enabled: bool,
+    /// Shots per trigger.
+    pub shots: u8,
+    /// Exposure compensation between shots in EV.
+    pub ev_step: f32,
+}
+
+/// Defaults of focus stacks started without shots and range.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FocusStackConfig {
+    pub shots: u8,
+    /// Manual focus steps from the first shot to the last, negative towards
+    /// the camera.
+    pub range: i32,
+}
+
+/// Autofocus before every shot, see [`AutofocusOptions`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutofocusConfig {
+    pub enabled: bool,
+    pub timeout_ms: u64,
+}
+
+/// When internal tasks count as stalled, see [`WatchdogOptions`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchdogConfig {
+    pub stall_timeout_s: u64,
+    /// Exit after a stall this long, for systemd or the like to restart.
+    pub exit_after_s: Option<u64>,
+}
+
+/// Command ids of the component's own commands, 0 turning one off, see
+/// [`CustomCommands`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandsConfig {
+    pub focus_stack: u32,
+    pub bulb: u32,
+    pub self_test: u32,
+    pub set_clock: u32,
+    pub offload: u32,
+    pub stream_settings: u32,
+}
+
+/// How often the messages streamed unasked are sent, in Hz, see
+/// [`StreamRates`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamRatesConfig {
+    pub heartbeat_hz: f32,
+    /// Off when 0, as are the other messages.
+    pub camera_settings_hz: f32,
+    pub storage_information_hz: f32,
+    pub capture_status_hz: f32,
+}
+
+/// gRPC control service, see `proto/camera.proto`.
+#[cfg(feature = "grpc")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+/// ROS 2 node with trigger and status topics and services.
+#[cfg(feature = "ros2")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ros2Config {
+    pub enabled: bool,
+    pub node_name: String,
+    pub namespace: String,
+}
+
+/// MQTT broker the events and camera states are published to.
+#[cfg(feature = "mqtt")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    /// What every topic starts with.
+    pub topic_prefix: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Embedded HTTP server for the captures and their thumbnails.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub enabled: bool,
+    pub port: u16,
+    /// URL ground stations reach the server at, put in `CAMERA_IMAGE_CAPTURED`.
+    /// `http://<hostname>:<port>` when unset.
+    pub url: Option<String>,
+    /// Serve the REST control API under `/api` too.
+    pub api: bool,
+}
+
+/// Free space on the companion computer and cleanup of old images.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Free space below which ground stations are warned.
+    pub low_space_mb: u64,
+    /// Free space below which captures are refused.
+    pub full_space_mb: u64,
+    /// Delete the oldest images once a camera's take more than this.
+    pub quota_mb: Option<u64>,
+    /// Delete images older than this.
+    pub max_age_hours: Option<u64>,
+}
+
+impl Default for MavlinkConfig {
+    fn default() -> Self {
+        Self {
+            connection: "tcpout:localhost:5762".to_owned(),
+            extra_connections: Vec::new(),
+            system_id: 100,
+            component_id: 100,
+            max_bytes_per_second: None,
+            extra_max_bytes_per_second: BTreeMap::new(),
+            id_conflict_check_ms: 2000,
+            id_conflict: IdConflict::Refuse,
+            adopt_autopilot_system_id: false,
+            autopilot_wait_s: 30,
+        }
+    }
+}
+
+/// A component id as a number or a name.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ComponentId {
+    Number(u8),
+    Name(String),
+}
+
+impl CameraConfig {
+    /// Returns the configured sensor, `None` to go by the camera's.
+    pub fn sensor(&self) -> Result<Option<Sensor>> {
+        sensor(
+            "camera",
+            [self.sensor_width_mm, self.sensor_height_mm],
+            [self.resolution_h, self.resolution_v],
+        )
+    }
+}
+
+impl ExtraCameraConfig {
+    /// Returns the configured sensor, `None` to go by the camera's.
+    pub fn sensor(&self) -> Result<Option<Sensor>> {
+        sensor(
+            "extra_cameras",
+            [self.sensor_width_mm, self.sensor_height_mm],
+            [self.resolution_h, self.resolution_v],
+        )
+    }
+}
+
+/// The sensor of a `section` with its size and resolution, which are all set
+/// or none of them.
+fn sensor(
+    section: &str,
+    size_mm: [Option<f32>; 2],
+    resolution: [Option<u16>; 2],
+) -> Result<Option<Sensor>> {
+    match (size_mm, resolution) {
+        ([None, None], [None, None]) => Ok(None),
+        ([Some(width), Some(height)], [Some(h), Some(v)])
+            if width > 0.0 && height > 0.0 && h > 0 && v > 0 =>
+        {
+            Ok(Some(Sensor {
+                size_mm: (width, height),
+                resolution: (h, v),
+            }))
+        }
+        _ => bail!(
+            "{section}.sensor_width_mm, sensor_height_mm, resolution_h and resolution_v must all \
+             be set and positive, or none of them"
+        ),
+    }
+}
+
+fn component_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    match ComponentId::deserialize(deserializer)? {
+        ComponentId::Number(id) => Ok(id),
+        ComponentId::Name(name) => parse_component_id(&name).map_err(serde::de::Error::custom),
+    }
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            image_dir: PathBuf::from("images"),
+            min_trigger_interval_ms: 0,
+            filename_template: None,
+            state_dir: None,
+            download: DownloadFormat::All,
+            target: None,
+            delete_after_download: false,
+            autopilot_timeout_s: None,
+            queue_policy: QueuePolicy::default(),
+            queue_depth: CaptureQueueOptions::default().max_depth,
+            min_capture_interval_ms: 0,
+            retries: RetryOptions::default().retries,
+            retry_backoff_ms: RetryOptions::default().backoff.as_millis() as u64,
+            download_queue_depth: 0,
+            keep_on_card: false,
+            download_video: false,
+        }
+    }
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        let defaults = VideoStreamOptions::new("");
+
+        Self {
+            enabled: false,
+            port: 8554,
+            uri: None,
+            width: defaults.width,
+            height: defaults.height,
+            framerate: defaults.framerate,
+            server: StreamServer::default(),
+            #[cfg(feature = "rtsp")]
+            encoder: H264Encoder::default(),
+        }
+    }
+}
+
+impl StreamingConfig {
+    /// Returns the live-view stream of the camera, `None` when streaming is
+    /// off.
+    pub fn options(&self) -> Option<VideoStreamOptions> {
+        if !self.enabled {
+            return None;
+        }
+
+        let scheme = match self.server {
+            StreamServer::Mjpeg => "http",
+            _ => "rtsp",
+        };
+        let uri = self.uri.clone().unwrap_or_else(|| {
+            let host = sys_info::hostname().unwrap_or_else(|_| "localhost".to_owned());
+            format!("{scheme}://{host}:{}/live", self.port)
+        });
+        let mut stream = VideoStreamOptions {
+            width: self.width,
+            height: self.height,
+            framerate: self.framerate,
+            ..VideoStreamOptions::new(uri)
+        };
+        if self.server == StreamServer::Mjpeg {
+            stream.stream_type = VideoStreamType::VIDEO_STREAM_TYPE_TCP_MPEG;
+            stream.encoding = VideoEncoding::Mjpeg;
+        }
+        Some(stream)
+    }
+
+    /// Returns how the component serves the stream itself, `None` if it
+    /// doesn't.
+    pub fn live_view(&self) -> Option<LiveViewServer> {
+        match (self.enabled, self.server) {
+            (true, StreamServer::Mjpeg) => Some(LiveViewServer::Mjpeg { port: self.port }),
+            #[cfg(feature = "rtsp")]
+            (true, StreamServer::Rtsp) => Some(LiveViewServer::Rtsp {
+                port: self.port,
+                encoder: self.encoder,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_owned(),
+        }
+    }
+}
+
+impl Default for TlogConfig {
+    fn default() -> Self {
+        let defaults = TlogOptions::new("");
+
+        Self {
+            path: None,
+            max_size_mb: defaults.max_bytes / (1024 * 1024),
+            keep: defaults.keep,
+        }
+    }
+}
+
+impl TlogConfig {
+    /// Returns the recording options, `None` when recording is off.
+    pub fn options(&self) -> Option<TlogOptions> {
+        let path = self.path.as_ref()?;
+
+        Some(TlogOptions {
+            max_bytes: self.max_size_mb * 1024 * 1024,
+            keep: self.keep,
+            ..TlogOptions::new(path)
+        })
+    }
+}
+
+impl Default for PcapConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_size_mb: PcapOptions::new("").max_bytes / (1024 * 1024),
+        }
+    }
+}
+
+impl PcapConfig {
+    /// Returns the capture options, `None` when capturing is off.
+    pub fn options(&self) -> Option<PcapOptions> {
+        let path = self.path.as_ref()?;
+
+        Some(PcapOptions {
+            max_bytes: self.max_size_mb * 1024 * 1024,
+            ..PcapOptions::new(path)
+        })
+    }
+}
+
+impl CaptureConfig {
+    /// Returns the parsed file name template, `None` to keep the camera's
+    /// names.
+    pub fn filename_template(&self) -> Result<Option<FilenameTemplate>> {
+        self.filename_template
+            .as_deref()
+            .map(|template| {
+                template
+                    .parse()
+                    .with_context(|| format!("Invalid capture.filename_template {template:?}"))
+            })
+            .transpose()
+    }
+
+    /// Returns how pictures asked for in quick succession are spaced out.
+    pub fn queue(&self) -> CaptureQueueOptions {
+        CaptureQueueOptions {
+            policy: self.queue_policy,
+            max_depth: self.queue_depth,
+            min_interval: Duration::from_millis(self.min_capture_interval_ms),
+        }
+    }
+
+    /// Returns the retries of failed captures and downloads.
+    pub fn retry(&self) -> RetryOptions {
+        RetryOptions {
+            retries: self.retries,
+            backoff: Duration::from_millis(self.retry_backoff_ms),
+        }
+    }
+
+    /// Returns the background downloads, if on.
+    pub fn download_queue(&self) -> Option<DownloadQueueOptions> {
+        (self.download_queue_depth > 0).then_some(DownloadQueueOptions {
+            max_depth: self.download_queue_depth,
+        })
+    }
+}
+
+impl Default for FootprintConfig {
+    fn default() -> Self {
+        let defaults = FootprintOptions::new("");
+
+        Self {
+            path: None,
+            sensor_width_mm: defaults.sensor_size_mm.map(|(width, _)| width),
+            sensor_height_mm: defaults.sensor_size_mm.map(|(_, height)| height),
+            focal_length_mm: defaults.focal_length_mm,
+        }
+    }
+}
+
+impl FootprintConfig {
+    /// Returns the footprint options, `None` when footprints are off.
+    pub fn options(&self) -> Option<FootprintOptions> {
+        let path = self.path.as_ref()?;
+
+        Some(FootprintOptions {
+            sensor_size_mm: self.sensor_width_mm.zip(self.sensor_height_mm),
+            focal_length_mm: self.focal_length_mm,
+            ..FootprintOptions::new(path)
+        })
+    }
+}
+
+impl Default for ImageTransmissionConfig {
+    fn default() -> Self {
+        let defaults = ImageTransmissionOptions::default();
+
+        Self {
+            enabled: false,
+            max_size: defaults.max_size,
+            jpeg_quality: defaults.jpeg_quality,
+            packet_rate: defaults.packet_rate,
+        }
+    }
+}
+
+impl ImageTransmissionConfig {
+    /// Returns the transmission options, `None` when it's off.
+    pub fn options(&self) -> Option<ImageTransmissionOptions> {
+        self.enabled.then_some(ImageTransmissionOptions {
+            max_size: self.max_size,
+            jpeg_quality: self.jpeg_quality,
+            packet_rate: self.packet_rate,
+        })
+    }
+}
+
+impl Default for BracketingConfig {
+    fn default() -> Self {
+        let defaults = BracketingOptions::default();
+
+        Self {
+            enabled: false,
+            shots: defaults.shots,
+            ev_step: defaults.ev_step,
+        }
+    }
+}
+
+impl BracketingConfig {
+    /// Returns the bracketing options, `None` when it's off.
+    pub fn options(&self) -> Option<BracketingOptions> {
+        self.enabled.then_some(BracketingOptions {
+            shots: self.shots,
+            ev_step: self.ev_step,
+        })
+    }
+}
+
+impl Default for FocusStackConfig {
+    fn default() -> Self {
+        let defaults = FocusStackOptions::default();
+
+        Self {
+            shots: defaults.shots,
+            range: defaults.range,
+        }
+    }
+}
+
+impl FocusStackConfig {
+    pub fn options(&self) -> FocusStackOptions {
+        FocusStackOptions {
+            shots: self.shots,
+            range: self.range,
+        }
+    }
+}
+
+impl Default for AutofocusConfig {
+    fn default() -> Self {
+        let defaults = AutofocusOptions::default();
+
+        Self {
+            enabled: defaults.enabled,
+            timeout_ms: defaults.timeout.as_millis() as u64,
+        }
+    }
+}
+
+impl AutofocusConfig {
+    pub fn options(&self) -> AutofocusOptions {
+        AutofocusOptions {
+            enabled: self.enabled,
+            timeout: Duration::from_millis(self.timeout_ms),
+        }
+    }
+}
+
+impl Default for CommandsConfig {
+    fn default() -> Self {
+        let defaults = CustomCommands::default();
+        let id = |command: Option<u32>| command.unwrap_or(0);
+
+        Self {
+            focus_stack: id(defaults.focus_stack),
+            bulb: id(defaults.bulb),
+            self_test: id(defaults.self_test),
+            set_clock: id(defaults.set_clock),
+            offload: id(defaults.offload),
+            stream_settings: id(defaults.stream_settings),
+        }
+    }
+}
+
+impl CommandsConfig {
+    pub fn commands(&self) -> CustomCommands {
+        let id = |command: u32| (command != 0).then_some(command);
+
+        CustomCommands {
+            focus_stack: id(self.focus_stack),
+            bulb: id(self.bulb),
+            self_test: id(self.self_test),
+            set_clock: id(self.set_clock),
+            offload: id(self.offload),
+            stream_settings: id(self.stream_settings),
+        }
+    }
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        let defaults = WatchdogOptions::default();
+
+        Self {
+            stall_timeout_s: defaults.stall_timeout.as_secs(),
+            exit_after_s: None,
+        }
+    }
+}
+
+impl WatchdogConfig {
+    pub fn options(&self) -> WatchdogOptions {
+        WatchdogOptions {
+            stall_timeout: Duration::from_secs(self.stall_timeout_s),
+            exit_after: self.exit_after_s.map(Duration::from_secs),
+        }
+    }
+}
+
+impl Default for StreamRatesConfig {
+    fn default() -> Self {
+        let defaults = StreamRates::default();
+
+        Self {
+            heartbeat_hz: 1.0 / defaults.heartbeat.as_secs_f32(),
+            camera_settings_hz: 0.0,
+            storage_information_hz: 0.0,
+            capture_status_hz: 0.0,
+        }
+    }
+}
+
+impl StreamRatesConfig {
+    pub fn rates(&self) -> StreamRates {
+        let interval = |hz: f32| (hz > 0.0).then(|| Duration::from_secs_f32(1.0 / hz));
+
+        StreamRates {
+            heartbeat: interval(self.heartbeat_hz).unwrap_or_default(),
+            camera_settings: interval(self.camera_settings_hz),
+            storage_information: interval(self.storage_information_hz),
+            capture_status: interval(self.capture_status_hz),
+        }
+    }
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        let defaults = ThumbnailOptions::default();
+
+        Self {
+            enabled: false,
+            max_size: defaults.max_size,
+            jpeg_quality: defaults.jpeg_quality,
+        }
+    }
+}
+
+impl ThumbnailConfig {
+    /// Returns the thumbnail options, `None` when they're off.
+    pub fn options(&self) -> Option<ThumbnailOptions> {
+        self.enabled.then_some(ThumbnailOptions {
+            max_size: self.max_size,
+            jpeg_quality: self.jpeg_quality,
+        })
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8080,
+            url: None,
+            api: false,
+        }
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: GrpcServerOptions::default().port,
+        }
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl GrpcConfig {
+    /// Returns the gRPC server options, `None` when the server is off.
+    pub fn options(&self) -> Option<GrpcServerOptions> {
+        self.enabled
+            .then_some(GrpcServerOptions { port: self.port })
+    }
+}
+
+#[cfg(feature = "ros2")]
+impl Default for Ros2Config {
+    fn default() -> Self {
+        let defaults = Ros2Options::default();
+
+        Self {
+            enabled: false,
+            node_name: defaults.node_name,
+            namespace: defaults.namespace,
+        }
+    }
+}
+
+#[cfg(feature = "ros2")]
+impl Ros2Config {
+    /// Returns the node options, `None` when the node is off.
+    pub fn options(&self) -> Option<Ros2Options> {
+        self.enabled.then(|| Ros2Options {
+            node_name: self.node_name.clone(),
+            namespace: self.namespace.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl Default for MqttConfig {
+    fn default() -> Self {
+        let defaults = MqttOptions::new("localhost");
+
+        Self {
+            enabled: false,
+            host: defaults.host,
+            port: defaults.port,
+            client_id: defaults.client_id,
+            topic_prefix: defaults.topic_prefix,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl MqttConfig {
+    /// Returns the broker to publish to, `None` when the bridge is off.
+    pub fn options(&self) -> Option<MqttOptions> {
+        self.enabled.then(|| MqttOptions {
+            host: self.host.clone(),
+            port: self.port,
+            client_id: self.client_id.clone(),
+            topic_prefix: self.topic_prefix.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+        })
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        let defaults = StorageOptions::default();
+
+        Self {
+            low_space_mb: defaults.low_bytes / (1024 * 1024),
+            full_space_mb: defaults.full_bytes / (1024 * 1024),
+            quota_mb: None,
+            max_age_hours: None,
+        }
+    }
+}
+
+impl StorageConfig {
+    /// Returns the free space thresholds and cleanup settings.
+    pub fn options(&self) -> StorageOptions {
+        StorageOptions {
+            low_bytes: self.low_space_mb * 1024 * 1024,
+            full_bytes: self.full_space_mb * 1024 * 1024,
+            quota_bytes: self.quota_mb.map(|quota| quota * 1024 * 1024),
+            max_age: self
+                .max_age_hours
+                .map(|hours| Duration::from_secs(hours * 60 * 60)),
+        }
+    }
+}
+
+impl CaptureLogConfig {
+    /// Returns the logging options, `None` when logging is off.
+    pub fn options(&self) -> Option<CaptureLogOptions> {
+        Some(CaptureLogOptions {
+            path: self.path.clone()?,
+            format: self.format,
+        })
+    }
+}
+
+impl MavlinkConfig {
+    /// Returns the check for components using a camera's ids, `None` when
+    /// it's off.
+    pub fn id_conflict_check(&self) -> Option<IdConflictCheck> {
+        (self.id_conflict_check_ms > 0).then(|| IdConflictCheck {
+            listen: Duration::from_millis(self.id_conflict_check_ms),
+            resolution: self.id_conflict,
+        })
+    }
+
+    /// Returns how long to wait for the autopilot's system id, `None` to use
+    /// the configured one.
+    pub fn adopt_system_id(&self) -> Option<Duration> {
+        self.adopt_autopilot_system_id
+            .then(|| Duration::from_secs(self.autopilot_wait_s))
+    }
+
+    /// Returns the most bytes per second to send over each connection that
+    /// has a limit, by connection string.
+    pub fn bandwidth_limits(&self) -> HashMap<String, u32> {
+        let primary = self
+            .max_bytes_per_second
+            .map(|limit| (self.connection.clone(), limit));
+        primary
+            .into_iter()
+            .chain(
+                self.extra_max_bytes_per_second
+                    .iter()
+                    .map(|(connection, limit)| (connection.clone(), *limit)),
+            )
+            .collect()
+    }
+
+    /// Returns the primary connection followed by the extra ones.
+    pub fn endpoints(&self) -> Vec<String> {
+        std::iter::once(&self.connection)
+            .chain(&self.extra_connections)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Config {
+    /// Reads and validates the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+        let config: Config = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Checks values that the TOML types alone can't express.
+    pub fn validate(&self) -> Result<()> {
+        for connection in self.mavlink.endpoints() {
+            validate_connection(&connection)?;
+        }
+
+        if self.mavlink.system_id == 0 || self.mavlink.component_id == 0 {
+            bail!("mavlink.system_id and mavlink.component_id must be between 1 and 255");
+        }
+
+        if self.mavlink.max_bytes_per_second == Some(0) {
+            bail!("mavlink.max_bytes_per_second must be positive");
+        }
+        for (connection, limit) in &self.mavlink.extra_max_bytes_per_second {
+            if !self.mavlink.extra_connections.contains(connection) {
+                bail!(
+                    "mavlink.extra_max_bytes_per_second names {connection}, which isn't one of \
+                     mavlink.extra_connections"
+                );
+            }
+            if *limit == 0 {
+                bail!("mavlink.extra_max_bytes_per_second must be positive");
+            }
+        }
+
+        if self.mavlink.adopt_autopilot_system_id && self.mavlink.autopilot_wait_s == 0 {
+            bail!("mavlink.autopilot_wait_s must be at least 1");
+        }
+
+        self.capture.filename_template()?;
+
+        let footprints = &self.footprints;
+        let sensor = [footprints.sensor_width_mm, footprints.sensor_height_mm];
+        if sensor.iter().any(Option::is_none) && sensor.iter().any(Option::is_some) {
+            bail!("footprints.sensor_width_mm and sensor_height_mm must be set together");
+        }
+        if sensor
+            .iter()
+            .flatten()
+            .chain([&footprints.focal_length_mm])
+            .any(|value| *value <= 0.0)
+        {
+            bail!(
+                "footprints.sensor_width_mm, sensor_height_mm and focal_length_mm must be positive"
+            );
+        }
+
+        let transmission = &self.image_transmission;
+        if transmission.max_size == 0
+            || !(1..=100).contains(&transmission.jpeg_quality)
+            || transmission.packet_rate <= 0.0
+        {
+            bail!(
+                "image_transmission.max_size and packet_rate must be positive and jpeg_quality between 1 and 100"
+            );
+        }
+
+        let thumbnails = &self.thumbnails;
+        if thumbnails.max_size == 0 || !(1..=100).contains(&thumbnails.jpeg_quality) {
+            bail!("thumbnails.max_size must be positive and jpeg_quality between 1 and 100");
+        }
+
+        if self.bracketing.shots < 2 || self.bracketing.ev_step <= 0.0 {
+            bail!("bracketing.shots must be at least 2 and ev_step positive");
+        }
+
+        if self.focus_stack.shots == 0 {
+            bail!("focus_stack.shots must be at least 1");
+        }
+
+        if self.autofocus.timeout_ms == 0 {
+            bail!("autofocus.timeout_ms must be positive");
+        }
+
+        if self.capture.queue_depth == 0 {
+            bail!("capture.queue_depth must be at least 1");
+        }
+
+        let target = self
+            .parameter_overrides()
+            .filter(|(key, _)| *key == "capturetarget")
+            .last()
+            .and_then(|(_, setting)| CaptureTarget::from_setting(&setting));
+        if target == Some(CaptureTarget::Ram)
+            && (self.capture.keep_on_card || self.capture.download_queue_depth > 0)
+        {
+            bail!(
+                "capture.target = \"ram\" can't be combined with capture.keep_on_card or capture.download_queue_depth, shots in the camera's RAM are lost unless downloaded at once"
+            );
+        }
+
+        #[cfg(feature = "sony")]
+        if self.capture.delete_after_download && self.camera.backend == BackendKind::Sony {
+            bail!("capture.delete_after_download isn't supported by the sony backend, the Camera Remote SDK can't delete the copy on the card");
+        }
+
+        #[cfg(feature = "libcamera")]
+        if self.capture.delete_after_download && self.camera.backend == BackendKind::Libcamera {
+            bail!("capture.delete_after_download isn't supported by the libcamera backend, which writes shots straight to capture.image_dir");
+        }
+
+        if self.capture.autopilot_timeout_s == Some(0) {
+            bail!("capture.autopilot_timeout_s must be at least 1");
+        }
+
+        if self.watchdog.stall_timeout_s == 0 {
+            bail!("watchdog.stall_timeout_s must be at least 1");
+        }
+
+        let commands = &self.commands;
+        if [
+            commands.focus_stack,
+            commands.bulb,
+            commands.self_test,
+            commands.set_clock,
+            commands.offload,
+            commands.stream_settings,
+        ]
+        .iter()
+        .any(|id| *id != 0 && !USER_COMMANDS.contains(id))
+        {
+            bail!("the ids in commands must be MAVLink user commands from 31000 to 31014, or 0");
+        }
+        if let Some(id) = commands.commands().duplicate() {
+            bail!("command {id} is given to more than one command in commands");
+        }
+
+        let rates = &self.stream_rates;
+        if !(rates.heartbeat_hz > 0.0 && rates.heartbeat_hz <= 1000.0)
+            || [
+                rates.camera_settings_hz,
+                rates.storage_information_hz,
+                rates.capture_status_hz,
+            ]
+            .iter()
+            .any(|hz| !(0.0..=1000.0).contains(hz))
+        {
+            bail!("stream_rates.heartbeat_hz must be positive and the other rates 0 or more, all at most 1000");
+        }
+
+        #[cfg(feature = "ros2")]
+        if self.ros2.enabled && self.ros2.node_name.is_empty() {
+            bail!("ros2.node_name must be set");
+        }
+
+        #[cfg(feature = "mqtt")]
+        if self.mqtt.enabled
+            && (self.mqtt.host.is_empty()
+                || self.mqtt.client_id.is_empty()
+                || self.mqtt.topic_prefix.is_empty()
+                || ["#", "+"]
+                    .iter()
+                    .any(|wildcard| self.mqtt.topic_prefix.contains(wildcard)))
+        {
+            bail!(
+                "mqtt.host, client_id and topic_prefix must be set, topic_prefix without wildcards"
+            );
+        }
+
+        if self.storage.full_space_mb > self.storage.low_space_mb {
+            bail!("storage.full_space_mb must not be above low_space_mb");
+        }
+
+        if self.pcap.max_size_mb == 0 {
+            bail!("pcap.max_size_mb must be at least 1");
+        }
+
+        if self.tlog.max_size_mb == 0 {
+            bail!("tlog.max_size_mb must be at least 1");
+        }
+
+        if self
+            .camera
+            .overheat_temperature_c
+            .is_some_and(|temperature| !temperature.is_finite())
+        {
+            bail!("camera.overheat_temperature_c must be a number");
+        }
+
+        self.camera.sensor()?;
+        for camera in &self.extra_cameras {
+            camera.sensor()?;
+        }
+
+        let mut rated_shutter_lives = std::iter::once(self.camera.rated_shutter_life).chain(
+            self.extra_cameras
+                .iter()
+                .map(|camera| camera.rated_shutter_life),
+        );
+        if rated_shutter_lives.any(|life| life == Some(0)) {
+            bail!("rated_shutter_life must be at least 1");
+        }
+
+        let mut live_view_ports = Vec::new();
+        for (_, streaming) in
+            self.per_camera(Some(&self.streaming), |camera| camera.streaming.as_ref())
+        {
+            let Some(streaming) = streaming.filter(|streaming| streaming.live_view().is_some())
+            else {
+                continue;
+            };
+            if live_view_ports.contains(&streaming.port) {
+                bail!(
+                    "streaming.port {} is taken by the live view of another camera",
+                    streaming.port
+                );
+            }
+            live_view_ports.push(streaming.port);
+        }
+
+        let mut component_ids = vec![self.mavlink.component_id];
+        for camera in &self.extra_cameras {
+            if camera.component_id == 0 || component_ids.contains(&camera.component_id) {
+                bail!(
+                    "extra_cameras.component_id {} must be between 1 and 255 and unique",
+                    camera.component_id
+                );
+            }
+            component_ids.push(camera.component_id);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the MAVLink identity of every camera with its port and image
+    /// directory, the primary camera first.
+    pub fn cameras(&self) -> Result<Vec<(MavlinkCameraComponent, Option<&str>, &Path)>> {
+        let mut cameras = vec![(
+            MavlinkCameraComponent {
+                system_id: self.mavlink.system_id,
+                component_id: self.mavlink.component_id,
+                vendor_name: self.camera.vendor_name.clone(),
+                model_name: self.camera.model_name.clone(),
+                sensor: self.camera.sensor()?,
+                definition_uri: self.camera.definition_uri.clone(),
+                definition_version: self.camera.definition_version,
+            },
+            self.camera.port.as_deref(),
+            self.capture.image_dir.as_path(),
+        )];
+        for camera in &self.extra_cameras {
+            cameras.push((
+                MavlinkCameraComponent {
+                    system_id: self.mavlink.system_id,
+                    component_id: camera.component_id,
+                    vendor_name: camera.vendor_name.clone(),
+                    model_name: camera.model_name.clone(),
+                    sensor: camera.sensor()?,
+                    definition_uri: camera.definition_uri.clone(),
+                    definition_version: camera.definition_version,
+                },
+                Some(camera.port.as_str()),
+                camera.image_dir.as_path(),
+            ));
+        }
+        Ok(cameras)
+    }
+
+    /// Returns the options of the component running the cameras.
+    pub fn component_options(&self) -> Result<ComponentOptions> {
+        let mut options = ComponentOptions::default();
+        options.tlog = self.tlog.options();
+        options.pcap = self.pcap.options();
+        options.capture_log = self.capture_log.options();
+        options.footprints = self.footprints.options();
+        options.image_transmission = self.image_transmission.options();
+        options.thumbnails = self.thumbnails.options();
+        options.http = self.http_options();
+        options.filename_template = self.capture.filename_template()?;
+        options.storage = self.storage.options();
+        options.state_dir = self.capture.state_dir.clone();
+        options.bracketing = self.bracketing.options();
+        options.focus_stack = self.focus_stack.options();
+        options.commands = self.commands.commands();
+        options.autofocus = self.autofocus.options();
+        options.watchdog = self.watchdog.options();
+        options.stream_rates = self.stream_rates.rates();
+        options.id_conflict = self.mavlink.id_conflict_check();
+        options.adopt_system_id = self.mavlink.adopt_system_id();
+        #[cfg(feature = "grpc")]
+        {
+            options.grpc = self.grpc.options();
+        }
+        #[cfg(feature = "ros2")]
+        {
+            options.ros2 = self.ros2.options();
+        }
+        #[cfg(feature = "mqtt")]
+        {
+            options.mqtt = self.mqtt.options();
+        }
+        options.min_trigger_interval = Duration::from_millis(self.capture.min_trigger_interval_ms);
+        options.autopilot_timeout = self.capture.autopilot_timeout_s.map(Duration::from_secs);
+        options.bandwidth_limits = self.mavlink.bandwidth_limits();
+        for (component_id, streaming) in
+            self.per_camera(Some(&self.streaming), |camera| camera.streaming.as_ref())
+        {
+            let Some(streaming) = streaming else {
+                continue;
+            };
+            if let Some(stream) = streaming.options() {
+                options.video_streams.insert(component_id, stream);
+            }
+            if let Some(server) = streaming.live_view() {
+                options.live_views.insert(component_id, server);
+            }
+        }
+        options.hot_shoes = self
+            .per_camera(self.camera.hot_shoe_gpio, |camera| camera.hot_shoe_gpio)
+            .filter_map(|(component_id, gpio)| Some((component_id, HotShoeOptions::new(gpio?))))
+            .collect();
+        options.overheat_temperature = self.camera.overheat_temperature_c;
+        options.sync_camera_clock = self.camera.sync_clock;
+        options.capture_queue = self.capture.queue();
+        options.capture_retry = self.capture.retry();
+        options.download_queue = self.capture.download_queue();
+        options.keep_on_card = self.capture.keep_on_card;
+        options.download_video = self.capture.download_video;
+        options.rated_shutter_lives = self
+            .per_camera(self.camera.rated_shutter_life, |camera| {
+                camera.rated_shutter_life
+            })
+            .filter_map(|(component_id, life)| Some((component_id, life?)))
+            .collect();
+
+        Ok(options)
+    }
+
+    /// `primary` for the primary camera followed by `extra` of each further
+    /// one, by component id.
+    fn per_camera<'a, T: 'a>(
+        &'a self,
+        primary: T,
+        extra: impl Fn(&'a ExtraCameraConfig) -> T + 'a,
+    ) -> impl Iterator<Item = (u8, T)> + 'a {
+        std::iter::once((self.mavlink.component_id, primary)).chain(
+            self.extra_cameras
+                .iter()
+                .map(move |camera| (camera.component_id, extra(camera))),
+        )
+    }
+
+    /// Returns the HTTP server options serving every camera's image
+    /// directory, `None` when the server is off.
+    pub fn http_options(&self) -> Option<HttpServerOptions> {
+        if !self.http.enabled {
+            return None;
+        }
+
+        let mut options = HttpServerOptions::new(self.http.port);
+        options.url = self.http.url.clone();
+        options.api = self.http.api;
+        options
+            .image_dirs
+            .insert(self.mavlink.component_id, self.capture.image_dir.clone());
+        for camera in &self.extra_cameras {
+            options
+                .image_dirs
+                .insert(camera.component_id, camera.image_dir.clone());
+        }
+        Some(options)
+    }
+
+    /// Returns the parameter overrides as `(key, value)` strings for the backend,
+    /// with the capture target unless `[parameters]` sets `capturetarget`.
+    pub fn parameter_overrides(&self) -> impl Iterator<Item = (&str, String)> {
+        let target = self
+            .capture
+            .target
+            .filter(|_| !self.parameters.contains_key("capturetarget"))
+            .map(|target| ("capturetarget", target.setting().to_owned()));
+
+        target
+            .into_iter()
+            .chain(self.parameters.iter().map(|(key, value)| {
+                let value = match value {
+                    toml::Value::String(value) => value.clone(),
+                    other => other.to_string(),
+                };
+
+                (key.as_str(), value)
+            }))
+    }
+}
+
+/// Checks that `connection` looks like `<scheme>:<address>` for a known scheme.
+/// Parses a component id given as a number or as the name of a camera
+/// component, `camera` to `camera6` with or without the `MAV_COMP_ID_`
+/// prefix, in any case.
+pub fn parse_component_id(value: &str) -> Result<u8> {
+    if let Ok(id) = value.parse() {
+        return Ok(id);
+    }
+
+    let name = value.to_ascii_lowercase();
+    let name = name.strip_prefix("mav_comp_id_").unwrap_or(&name);
+    let number = match name.strip_prefix("camera") {
+        Some("") => Some(1),
+        Some(number) => number.parse::<u8>().ok().filter(|number| *number >= 2),
+        None => None,
+    };
+    match number {
+        Some(number) if number <= CAMERA_COMPONENT_IDS.len() as u8 => {
+            Ok(CAMERA_COMPONENT_IDS.start() + number - 1)
+        }
+        _ => bail!("Invalid component id {value:?}, expected a number or camera to camera6"),
+    }
+}
+
+pub fn validate_connection(connection: &str) -> Result<()> {
+    match connection.split_once(':') {
+        Some((scheme, address)) if CONNECTION_SCHEMES.contains(&scheme) && !address.is_empty() => {
+            Ok(())
+        }
+        _ => bail!(
+            "Invalid connection {connection:?}, expected <{}>:<address>",
+            CONNECTION_SCHEMES.join("|")
+        ),
+    }
+}
@@ -0,0 +1,160 @@
+//! Splits a byte stream into MAVLink frames for the async transports.
+
+use super::frame_stats::{Dropped, FrameStats};
+use super::pcap::PcapTap;
+use mavlink::error::{MessageReadError, MessageWriteError};
+use mavlink::{MavHeader, MavlinkVersion, Message};
+
+const MAGIC_V1: u8 = 0xFE;
+const MAGIC_V2: u8 = 0xFD;
+const V1_OVERHEAD: usize = 8;
+const V2_OVERHEAD: usize = 12;
+const V2_SIGNATURE_LEN: usize = 13;
+const V2_FLAG_SIGNED: u8 = 0x01;
+
+/// A received message and the protocol version it was framed with.
+pub(crate) type Decoded<M> = (MavlinkVersion, MavHeader, M);
+
+/// Where the raw frames of an endpoint are reported besides being decoded.
+#[derive(Clone)]
+pub(crate) struct FrameTaps {
+    /// Captures every frame as received, if set.
+    pub pcap: Option<PcapTap>,
+    pub stats: FrameStats,
+}
+
+/// Bytes received from a transport that have not been decoded yet.
+pub(crate) struct FrameBuffer {
+    bytes: Vec<u8>,
+    taps: FrameTaps,
+}
+
+impl FrameBuffer {
+    pub fn new(taps: FrameTaps) -> Self {
+        Self {
+            bytes: Vec::new(),
+            taps,
+        }
+    }
+
+    pub fn extend(&mut self, data: &[u8]) {
+        self.bytes.extend_from_slice(data);
+    }
+
+    /// Decodes the next buffered message, skipping garbage and dropping the
+    /// frames that can't be decoded. Returns `None` once more data is needed.
+    pub fn next_message<M: Message>(&mut self) -> Option<Decoded<M>> {
+        loop {
+            let (version, len) = self.next_frame()?;
+            let frame = &self.bytes[..len];
+            if let Some(tap) = &self.taps.pcap {
+                tap.received(frame);
+            }
+
+            let sender = Some(frame_sender(version, frame));
+            match mavlink::read_versioned_msg(&mut &*frame, version) {
+                Ok((header, message)) => {
+                    self.bytes.drain(..len);
+                    return Some((version, header, message));
+                }
+                // The frame is corrupt, of a message whose checksum can't be
+                // checked, or the magic byte was noise. Resync on the next one.
+                Err(MessageReadError::Io(_)) => {
+                    let id = frame_message_id(version, frame);
+                    if M::default_message_from_id(id).is_ok() {
+                        self.taps
+                            .stats
+                            .dropped(Dropped::BadChecksum, sender, "bad checksum");
+                    } else {
+                        self.taps.stats.dropped(
+                            Dropped::UnknownMessage,
+                            sender,
+                            format_args!("unknown message id {id}"),
+                        );
+                    }
+                    self.bytes.drain(..1);
+                }
+                Err(MessageReadError::Parse(error)) => {
+                    self.taps.stats.dropped(Dropped::of(&error), sender, &error);
+                    self.bytes.drain(..len);
+                }
+            }
+        }
+    }
+
+    /// Drops bytes up to the next magic marker and returns the frame's version and
+    /// length once it has been received completely.
+    fn next_frame(&mut self) -> Option<(MavlinkVersion, usize)> {
+        let Some(start) = self
+            .bytes
+            .iter()
+            .position(|byte| matches!(*byte, MAGIC_V1 | MAGIC_V2))
+        else {
+            self.bytes.clear();
+            return None;
+        };
+        self.bytes.drain(..start);
+
+        let (version, len) = frame_len(&self.bytes)?;
+        (self.bytes.len() >= len).then_some((version, len))
+    }
+}
+
+/// Returns the version and total length of the frame starting at `bytes[0]`,
+/// or `None` if that isn't a magic marker or the header is incomplete.
+pub(crate) fn frame_len(bytes: &[u8]) -> Option<(MavlinkVersion, usize)> {
+    if bytes.len() < 3 {
+        return None;
+    }
+
+    let payload_len = bytes[1] as usize;
+    match bytes[0] {
+        MAGIC_V1 => Some((MavlinkVersion::V1, payload_len + V1_OVERHEAD)),
+        MAGIC_V2 if bytes[2] & V2_FLAG_SIGNED != 0 => Some((
+            MavlinkVersion::V2,
+            payload_len + V2_OVERHEAD + V2_SIGNATURE_LEN,
+        )),
+        MAGIC_V2 => Some((MavlinkVersion::V2, payload_len + V2_OVERHEAD)),
+        _ => None,
+    }
+}
+
+/// The system and component id in the header of `frame`.
+fn frame_sender(version: MavlinkVersion, frame: &[u8]) -> (u8, u8) {
+    match version {
+        MavlinkVersion::V1 => (frame[3], frame[4]),
+        MavlinkVersion::V2 => (frame[5], frame[6]),
+    }
+}
+
+/// The message id in the header of `frame`.
+fn frame_message_id(version: MavlinkVersion, frame: &[u8]) -> u32 {
+    match version {
+        MavlinkVersion::V1 => frame[5].into(),
+        MavlinkVersion::V2 => u32::from_le_bytes([frame[7], frame[8], frame[9], 0]),
+    }
+}
+
+/// Returns the length of the unsigned frame `message` is sent in.
+pub(crate) fn encoded_len<M: Message>(version: MavlinkVersion, message: &M) -> usize {
+    let mut payload = [0; 255];
+    let payload_len = message.ser(version, &mut payload);
+
+    payload_len
+        + match version {
+            MavlinkVersion::V1 => V1_OVERHEAD,
+            MavlinkVersion::V2 => V2_OVERHEAD,
+        }
+}
+
+/// Serializes `message` into a complete frame ready to be written to a transport.
+pub(crate) fn encode<M: Message>(
+    version: MavlinkVersion,
+    header: &MavHeader,
+    message: &M,
+) -> Result<Vec<u8>, MessageWriteError> {
+    let mut frame = Vec::with_capacity(mavlink::MAX_FRAME_SIZE);
+    mavlink::write_versioned_msg(&mut frame, version, *header, message)?;
+
+    Ok(frame)
+}
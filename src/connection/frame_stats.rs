@@ -0,0 +1,116 @@
+//! Counts of the frames each endpoint receives, by endpoint and by sender, in
+//! the component's status. Frames that can't be decoded are dropped and
+//! logged, at most every [`LOG_INTERVAL`] per endpoint so a noisy link can't
+//! flood the log, and the endpoint keeps receiving.
+
+use crate::status::{ComponentStatus, FrameCounts};
+use mavlink::error::ParserError;
+use mavlink::MavHeader;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Shortest time between two warnings about dropped frames on one endpoint.
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Why a received frame was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Dropped {
+    BadChecksum,
+    UnknownMessage,
+    Invalid,
+}
+
+impl Dropped {
+    pub fn of(error: &ParserError) -> Self {
+        match error {
+            ParserError::UnknownMessage { .. } => Self::UnknownMessage,
+            _ => Self::Invalid,
+        }
+    }
+
+    fn count(self, counts: &mut FrameCounts) {
+        match self {
+            Self::BadChecksum => counts.bad_checksum += 1,
+            Self::UnknownMessage => counts.unknown_message += 1,
+            Self::Invalid => counts.invalid += 1,
+        }
+    }
+}
+
+/// Counts the frames of one endpoint.
+#[derive(Clone)]
+pub(crate) struct FrameStats {
+    status: Arc<Mutex<ComponentStatus>>,
+    address: Arc<str>,
+    log: Arc<Mutex<LogLimit>>,
+}
+
+#[derive(Default)]
+struct LogLimit {
+    last: Option<Instant>,
+    /// Frames dropped without a warning since the last one.
+    suppressed: u64,
+}
+
+impl FrameStats {
+    pub fn new(status: &Arc<Mutex<ComponentStatus>>, address: &str) -> Self {
+        Self {
+            status: status.clone(),
+            address: address.into(),
+            log: Arc::default(),
+        }
+    }
+
+    /// Records a frame decoded from the sender of `header`.
+    pub fn received(&self, header: &MavHeader) {
+        self.count(Some((header.system_id, header.component_id)), |counts| {
+            counts.received += 1;
+        });
+    }
+
+    /// Records a frame dropped because of `error`. `sender` is the system
+    /// and component id in its header, if it could be read.
+    pub fn dropped(&self, reason: Dropped, sender: Option<(u8, u8)>, error: impl Display) {
+        self.count(sender, |counts| reason.count(counts));
+
+        let system = sender.map(|(system, _)| system);
+        let component = sender.map(|(_, component)| component);
+        if reason == Dropped::UnknownMessage {
+            // Usual with a dialect smaller than the autopilot's, not worth a warning.
+            debug!(target: "rx", address = %self.address, system, component, "Dropping frame: {error}");
+            return;
+        }
+
+        let Ok(mut log) = self.log.lock() else {
+            return;
+        };
+        if log.last.is_some_and(|last| last.elapsed() < LOG_INTERVAL) {
+            log.suppressed += 1;
+            return;
+        }
+        warn!(
+            target: "rx",
+            address = %self.address,
+            system,
+            component,
+            suppressed = log.suppressed,
+            "Dropping bad frame: {error}"
+        );
+        *log = LogLimit {
+            last: Some(Instant::now()),
+            suppressed: 0,
+        };
+    }
+
+    fn count(&self, sender: Option<(u8, u8)>, update: impl Fn(&mut FrameCounts)) {
+        let Ok(mut status) = self.status.lock() else {
+            return;
+        };
+        update(status.frames.entry(self.address.to_string()).or_default());
+        if let Some(sender) = sender {
+            update(status.frames_by_sender.entry(sender).or_default());
+        }
+    }
+}
@@ -0,0 +1,574 @@
+//! The MAVLink IO tasks.
+//!
+//! Each endpoint's transport is owned by a single task. The rest of the
+//! component hands them outgoing messages through a [`LinkSender`], which fans
+//! out to every endpoint, and receives incoming ones from one merged
+//! [`Incoming`] queue, so no locks are held around socket IO. Each IO task
+//! writes the most urgent messages first, see [`outgoing::Priority`].
+//! Replies to a peer only go to the endpoints it was heard on, see
+//! [`LinkSender::send_to`].
+
+mod frame;
+mod frame_stats;
+mod outgoing;
+pub(crate) mod pcap;
+mod radio;
+mod routes;
+mod serial;
+mod server;
+pub(crate) mod tlog;
+mod transport;
+
+use crate::error::{CameraError, Result};
+use crate::event::{CameraEvent, EventSender};
+use crate::status::{spawn_worker, ComponentStatus, WorkerReporter};
+use crate::CameraDialect;
+use frame::{Decoded, FrameTaps};
+use frame_stats::{Dropped, FrameStats};
+use mavlink::common::MavMessage;
+use mavlink::error::{MessageReadError, MessageWriteError};
+use mavlink::{MavHeader, MavlinkVersion, Message};
+use outgoing::{Bandwidth, OutgoingQueue};
+use pcap::PcapRecorder;
+use radio::Slowdown;
+use routes::Routes;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tlog::TlogRecorder;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, trace, warn};
+use transport::Transport;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const QUEUE_SIZE: usize = 256;
+/// Most outgoing messages written to a transport in one go.
+const MAX_BATCH: usize = 16;
+/// How many recent messages are remembered to drop copies arriving over
+/// another endpoint.
+const DEDUP_WINDOW: usize = 64;
+
+type Outgoing<M> = mpsc::Receiver<(MavHeader, M)>;
+
+/// Connects to every endpoint and starts one IO task for each, failing if any
+/// of the initial connections can't be made. With a `tlog`, every message sent
+/// or received on any endpoint is recorded, and with a `pcap` every raw
+/// frame. Endpoints in `bandwidth_limits` are written at most that many bytes
+/// per second. The frames each endpoint receives are counted in `status`.
+pub(crate) async fn start<M: CameraDialect>(
+    addresses: &[String],
+    events: &EventSender,
+    status: &Arc<Mutex<ComponentStatus>>,
+    tlog: Option<TlogRecorder>,
+    pcap: Option<PcapRecorder>,
+    bandwidth_limits: &HashMap<String, u32>,
+) -> Result<(LinkSender<M>, Incoming<M>, Vec<JoinHandle<()>>)> {
+    let (incoming, receiver) = mpsc::channel(QUEUE_SIZE);
+    let mut endpoints = Vec::with_capacity(addresses.len());
+    let mut tasks = Vec::with_capacity(addresses.len());
+    let slowdown = Slowdown::new();
+    let routes = Routes::default();
+
+    for (index, address) in addresses.iter().enumerate() {
+        let bandwidth = Bandwidth::new(bandwidth_limits.get(address).copied());
+        let taps = FrameTaps {
+            pcap: pcap.as_ref().map(|pcap| pcap.tap(index)),
+            stats: FrameStats::new(status, address),
+        };
+        let link = Link::connect(
+            address,
+            events.clone(),
+            tlog.clone(),
+            taps,
+            bandwidth,
+            slowdown.clone(),
+            (index, routes.clone()),
+        )
+        .await?;
+
+        let key = address.clone();
+        let reporter = WorkerReporter::new(status, move |status| {
+            status.links.entry(key.clone()).or_default()
+        });
+
+        let (outgoing, task) = link.spawn(incoming.clone(), reporter);
+        endpoints.push(outgoing);
+        tasks.push(task);
+    }
+
+    let incoming = Incoming {
+        receiver,
+        recent: (addresses.len() > 1).then(VecDeque::new),
+    };
+
+    let sender = LinkSender {
+        endpoints,
+        slowdown,
+        routes,
+    };
+    Ok((sender, incoming, tasks))
+}
+
+/// Messages received from the vehicle, in arrival order.
+///
+/// With several endpoints, copies of a message that arrive over more than one
+/// of them are only delivered once.
+pub(crate) struct Incoming<M> {
+    receiver: mpsc::Receiver<(MavHeader, M)>,
+    recent: Option<VecDeque<(MavHeader, M)>>,
+}
+
+impl<M: CameraDialect> Incoming<M> {
+    /// Returns the next message, or `None` once every IO task has stopped.
+    /// Cancel safe.
+    pub async fn recv(&mut self) -> Option<(MavHeader, M)> {
+        loop {
+            let message = self.receiver.recv().await?;
+
+            let Some(recent) = &mut self.recent else {
+                return Some(message);
+            };
+
+            if recent.contains(&message) {
+                trace!(target: "rx", "Dropping duplicate {}", message.1.message_name());
+                continue;
+            }
+
+            if recent.len() == DEDUP_WINDOW {
+                recent.pop_front();
+            }
+            recent.push_back(message.clone());
+
+            return Some(message);
+        }
+    }
+}
+
+/// Queues messages for every endpoint's IO task.
+pub(crate) struct LinkSender<M> {
+    endpoints: Vec<mpsc::Sender<(MavHeader, M)>>,
+    slowdown: Slowdown,
+    routes: Routes,
+}
+
+// Not derived, which would require `M: Clone` for no reason.
+impl<M> Clone for LinkSender<M> {
+    fn clone(&self) -> Self {
+        Self {
+            endpoints: self.endpoints.clone(),
+            slowdown: self.slowdown.clone(),
+            routes: self.routes.clone(),
+        }
+    }
+}
+
+impl<M: CameraDialect> LinkSender<M> {
+    /// How many times slower than configured periodic telemetry and image
+    /// previews should be sent, as a congested telemetry radio asks for.
+    pub fn slowdown(&self) -> u32 {
+        self.slowdown.get()
+    }
+
+    /// Queues the `common` `message` on every endpoint, converted to the
+    /// link's dialect. Messages the dialect lacks are dropped.
+    pub fn send(&self, header: &MavHeader, message: MavMessage) -> Result<()> {
+        match M::from_common(&message) {
+            Some(message) => self.send_dialect(header, message),
+            None => {
+                debug!(target: "rx", "The dialect has no {}, not sending it", message.message_name());
+                Ok(())
+            }
+        }
+    }
+
+    /// Queues the `common` `message` meant for the sender of `target` on the
+    /// endpoints it was heard on, or on every endpoint if it wasn't heard
+    /// from yet.
+    pub fn send_to(
+        &self,
+        header: &MavHeader,
+        target: &MavHeader,
+        message: MavMessage,
+    ) -> Result<()> {
+        let Some(message) = M::from_common(&message) else {
+            debug!(target: "rx", "The dialect has no {}, not sending it", message.message_name());
+            return Ok(());
+        };
+
+        let routes = self.routes.endpoints(target.system_id, target.component_id);
+        if routes.is_empty() {
+            return self.send_dialect(header, message);
+        }
+        let endpoints = routes
+            .into_iter()
+            .filter_map(|index| self.endpoints.get(index));
+        Self::queue(endpoints, header, message)
+    }
+
+    /// Queues `message` on every endpoint. An endpoint that can't keep up drops
+    /// it rather than holding up the others.
+    pub fn send_dialect(&self, header: &MavHeader, message: M) -> Result<()> {
+        Self::queue(self.endpoints.iter(), header, message)
+    }
+
+    fn queue<'a>(
+        endpoints: impl Iterator<Item = &'a mpsc::Sender<(MavHeader, M)>>,
+        header: &MavHeader,
+        message: M,
+    ) -> Result<()> {
+        let mut open = false;
+
+        for endpoint in endpoints {
+            match endpoint.try_send((*header, message.clone())) {
+                Ok(()) => open = true,
+                Err(TrySendError::Full(_)) => {
+                    open = true;
+                    warn!(target: "rx", "Send queue full, dropping {}", message.message_name());
+                }
+                Err(TrySendError::Closed(_)) => {}
+            }
+        }
+
+        if open {
+            Ok(())
+        } else {
+            Err(CameraError::LinkClosed)
+        }
+    }
+}
+
+/// Owns the transport of one endpoint and transparently re-establishes it
+/// after fatal IO errors. Outgoing messages are dropped while the link is down.
+struct Link<M> {
+    address: String,
+    transport: Option<Transport<M>>,
+    sequence: u8,
+    version: PeerVersion,
+    events: EventSender,
+    tlog: Option<TlogRecorder>,
+    taps: FrameTaps,
+    queue: OutgoingQueue<M>,
+    bandwidth: Bandwidth,
+    slowdown: Slowdown,
+    /// This endpoint's index and where the peers have been heard.
+    routes: (usize, Routes),
+}
+
+enum Event<M> {
+    Outgoing(Option<(MavHeader, M)>),
+    /// Queued messages may be written.
+    Writable,
+    Incoming(Result<Decoded<M>, MessageReadError>),
+}
+
+/// The protocol version spoken towards one endpoint.
+///
+/// Starts out as MAVLink 2 and falls back to MAVLink 1 for peers that have
+/// only sent MAVLink 1 frames, e.g. old radios. The first MAVLink 2 frame
+/// switches back for good.
+#[derive(Default)]
+enum PeerVersion {
+    #[default]
+    Unknown,
+    V1,
+    V2,
+}
+
+impl PeerVersion {
+    fn current(&self) -> MavlinkVersion {
+        match self {
+            Self::V1 => MavlinkVersion::V1,
+            Self::Unknown | Self::V2 => MavlinkVersion::V2,
+        }
+    }
+
+    /// Updates the negotiated version from a frame the peer sent.
+    fn observe(&mut self, received: MavlinkVersion, address: &str) {
+        match (&self, received) {
+            (Self::V2, _) | (Self::V1, MavlinkVersion::V1) => {}
+            (Self::Unknown, MavlinkVersion::V1) => {
+                warn!(
+                    target: "rx",
+                    address,
+                    "Peer speaks MAVLink 1, falling back; camera protocol messages with ids above 255 can't be sent"
+                );
+                *self = Self::V1;
+            }
+            (Self::V1, MavlinkVersion::V2) => {
+                info!(target: "rx", address, "Peer switched to MAVLink 2");
+                *self = Self::V2;
+            }
+            (Self::Unknown, MavlinkVersion::V2) => *self = Self::V2,
+        }
+    }
+}
+
+impl<M: CameraDialect> Link<M> {
+    /// Opens the initial connection, failing immediately if it can't be made.
+    async fn connect(
+        address: &str,
+        events: EventSender,
+        tlog: Option<TlogRecorder>,
+        taps: FrameTaps,
+        bandwidth: Bandwidth,
+        slowdown: Slowdown,
+        routes: (usize, Routes),
+    ) -> Result<Self> {
+        let transport = Transport::open(address, taps.clone())
+            .await
+            .map_err(|source| CameraError::Connection {
+                address: address.to_owned(),
+                source,
+            })?;
+
+        Ok(Self {
+            address: address.to_owned(),
+            transport: Some(transport),
+            sequence: 0,
+            version: PeerVersion::default(),
+            events,
+            tlog,
+            taps,
+            queue: OutgoingQueue::new(QUEUE_SIZE),
+            bandwidth,
+            slowdown,
+            routes,
+        })
+    }
+
+    /// Starts the IO task, forwarding received messages to `incoming`. It
+    /// stops once every [`LinkSender`] or the [`Incoming`] queue has been
+    /// dropped.
+    fn spawn(
+        self,
+        incoming: mpsc::Sender<(MavHeader, M)>,
+        reporter: WorkerReporter,
+    ) -> (mpsc::Sender<(MavHeader, M)>, JoinHandle<()>) {
+        let (sender, outgoing) = mpsc::channel(QUEUE_SIZE);
+
+        let task = spawn_worker("link", reporter, move |reporter| {
+            self.run(outgoing, incoming, reporter)
+        });
+
+        (sender, task)
+    }
+
+    async fn run(
+        mut self,
+        mut outgoing: Outgoing<M>,
+        incoming: mpsc::Sender<(MavHeader, M)>,
+        reporter: WorkerReporter,
+    ) -> Result<()> {
+        loop {
+            let Some(transport) = self.transport.as_mut() else {
+                self.queue.clear();
+                match self.reconnect(&mut outgoing).await {
+                    Some(transport) => {
+                        self.transport = Some(transport);
+                        self.version = PeerVersion::default();
+                    }
+                    None => return Ok(()),
+                }
+                self.events.emit(CameraEvent::Reconnected {
+                    address: self.address.clone(),
+                });
+                reporter.running();
+                continue;
+            };
+
+            let event = tokio::select! {
+                message = outgoing.recv() => Event::Outgoing(message),
+                () = self.bandwidth.ready(), if !self.queue.is_empty() => Event::Writable,
+                received = transport.recv() => Event::Incoming(received),
+            };
+
+            match event {
+                Event::Outgoing(None) => return Ok(()),
+                Event::Outgoing(Some(message)) => {
+                    // Take whatever else is waiting so the most urgent goes first.
+                    self.enqueue(message);
+                    while let Ok(message) = outgoing.try_recv() {
+                        self.enqueue(message);
+                    }
+                }
+                Event::Writable => {
+                    // Take what the bandwidth allows so buffering transports write it in one go.
+                    let version = self.version.current();
+                    let mut batch = Vec::new();
+                    while batch.len() < MAX_BATCH && self.bandwidth.has_room() {
+                        let Some(message) = self.queue.pop() else {
+                            break;
+                        };
+                        self.bandwidth
+                            .spend(frame::encoded_len(version, &message.1));
+                        batch.push(message);
+                    }
+
+                    if let Err(error) = self.send(&batch).await {
+                        let error = CameraError::Send(error);
+                        warn!(target: "rx", "Failed to send {} messages: {error}", batch.len());
+                        reporter.degraded(&error);
+                    }
+                }
+                Event::Incoming(Ok((version, header, message))) => {
+                    reporter.running();
+                    self.taps.stats.received(&header);
+                    self.version.observe(version, &self.address);
+                    let (index, routes) = &self.routes;
+                    routes.learn(&header, *index);
+                    if let Some(txbuf) = message.radio_txbuf() {
+                        self.slowdown.observe(txbuf, &self.address);
+                    }
+                    let message = (header, message);
+                    if let Some(tlog) = &self.tlog {
+                        tlog.record(&message.0, &message.1);
+                    }
+
+                    match incoming.try_send(message) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full((_, message))) => {
+                            warn!(target: "rx", "Receive queue full, dropping {}", message.message_name());
+                        }
+                        Err(TrySendError::Closed(_)) => return Ok(()),
+                    }
+                }
+                // Frames from `mavlink::connect`, the others are dropped while framing.
+                Event::Incoming(Err(MessageReadError::Parse(error))) => {
+                    self.taps.stats.dropped(Dropped::of(&error), None, &error);
+                }
+                Event::Incoming(Err(MessageReadError::Io(error)))
+                    if error.kind() == ErrorKind::UnexpectedEof
+                        && self.transport.as_ref().is_some_and(Transport::is_replay) =>
+                {
+                    // Replays end instead of reconnecting, which lets the component shut down.
+                    return Ok(());
+                }
+                Event::Incoming(Err(MessageReadError::Io(error))) => {
+                    self.check_fatal(&error);
+                    let error = CameraError::Receive(MessageReadError::Io(error));
+                    debug!(target: "rx", "Failed to receive message: {error}");
+                    reporter.degraded(&error);
+                }
+            }
+        }
+    }
+
+    fn enqueue(&mut self, message: (MavHeader, M)) {
+        if let Some((_, dropped)) = self.queue.push(message) {
+            warn!(target: "rx", "Send queue full, dropping {}", dropped.message_name());
+        }
+    }
+
+    async fn send(&mut self, batch: &[(MavHeader, M)]) -> Result<(), MessageWriteError> {
+        let Some(transport) = self.transport.as_mut() else {
+            return Ok(());
+        };
+
+        let version = self.version.current();
+        let mut result = Ok(());
+        for (header, message) in batch {
+            // MAVLink 1 frames only have room for 8 bit message ids.
+            if version == MavlinkVersion::V1 && message.message_id() > 255 {
+                trace!(target: "rx", "Can't send {} over MAVLink 1", message.message_name());
+                continue;
+            }
+
+            let header = MavHeader {
+                sequence: self.sequence,
+                ..*header
+            };
+            self.sequence = self.sequence.wrapping_add(1);
+
+            result = transport.send(version, &header, message).await;
+            if result.is_err() {
+                break;
+            }
+            if let Some(tlog) = &self.tlog {
+                tlog.record(&header, message);
+            }
+            if let Some(pcap) = &self.taps.pcap {
+                if let Ok(frame) = frame::encode(version, &header, message) {
+                    pcap.sent(&frame);
+                }
+            }
+        }
+        if result.is_ok() {
+            result = transport.flush().await;
+        }
+
+        if let Err(MessageWriteError::Io(error)) = &result {
+            self.check_fatal(error);
+        }
+
+        result
+    }
+
+    /// Drops the transport after errors that leave it unusable.
+    fn check_fatal(&mut self, error: &std::io::Error) {
+        if is_fatal(error) {
+            warn!(target: "rx", address = %self.address, "Lost MAVLink connection: {error}");
+            self.transport = None;
+            self.events.emit(CameraEvent::ConnectionLost {
+                address: self.address.clone(),
+                error: error.to_string(),
+            });
+        }
+    }
+
+    /// Reopens the transport with exponential backoff. Returns `None` if the
+    /// component shut down in the meantime.
+    async fn reconnect(&self, outgoing: &mut Outgoing<M>) -> Option<Transport<M>> {
+        let mut backoff = INITIAL_BACKOFF;
+
+        loop {
+            match discard_until(outgoing, Transport::open(&self.address, self.taps.clone())).await?
+            {
+                Ok(transport) => {
+                    info!(target: "rx", address = %self.address, "Reconnected");
+                    return Some(transport);
+                }
+                Err(error) => {
+                    warn!(target: "rx", address = %self.address, "Reconnect failed: {error}, retrying in {backoff:?}");
+                }
+            }
+
+            discard_until(outgoing, tokio::time::sleep(backoff)).await?;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+/// Awaits `future` while dropping outgoing messages so senders never block on a
+/// dead link. Returns `None` once all senders are gone.
+async fn discard_until<M: Message, T>(
+    outgoing: &mut Outgoing<M>,
+    future: impl Future<Output = T>,
+) -> Option<T> {
+    tokio::pin!(future);
+
+    loop {
+        tokio::select! {
+            output = &mut future => return Some(output),
+            message = outgoing.recv() => {
+                let (_, message) = message?;
+                trace!(target: "rx", "Dropping {} while disconnected", message.message_name());
+            }
+        }
+    }
+}
+
+/// Errors after which the connection can't be used anymore.
+fn is_fatal(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::BrokenPipe
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::UnexpectedEof
+    )
+}
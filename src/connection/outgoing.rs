@@ -0,0 +1,156 @@
+//! The order and pace outgoing messages are written in, so a burst of
+//! captures or previews doesn't starve the heartbeats on a slow telemetry
+//! radio.
+
+use mavlink::{MavHeader, Message};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How long the link may write at full speed after being idle, when its
+/// bandwidth is limited.
+const BURST: Duration = Duration::from_millis(250);
+
+/// How urgent an outgoing message is, most urgent first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Priority {
+    /// Ground stations drop components whose heartbeats stop.
+    Heartbeat,
+    /// Answers to commands and parameter requests, retried by the sender
+    /// when they don't come in time.
+    Reply,
+    /// Something that happened, e.g. a capture.
+    Event,
+    /// Status, information and image previews.
+    Telemetry,
+}
+
+impl Priority {
+    const COUNT: usize = 4;
+
+    pub fn of<M: Message>(message: &M) -> Self {
+        match message.message_name() {
+            "HEARTBEAT" => Self::Heartbeat,
+            "COMMAND_ACK" | "COMMAND_LONG" | "COMMAND_INT" | "PARAM_EXT_ACK"
+            | "PARAM_EXT_VALUE" | "TIMESYNC" | "PING" => Self::Reply,
+            "CAMERA_IMAGE_CAPTURED" | "CAMERA_FEEDBACK" | "STATUSTEXT" => Self::Event,
+            _ => Self::Telemetry,
+        }
+    }
+}
+
+/// Messages waiting to be written to one endpoint, taken most urgent first
+/// and in order within a [`Priority`].
+pub(crate) struct OutgoingQueue<M> {
+    queues: [VecDeque<(MavHeader, M)>; Priority::COUNT],
+    capacity: usize,
+}
+
+impl<M: Message> OutgoingQueue<M> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queues: Default::default(),
+            capacity,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    /// Queues `message`. When full, the newest of the least urgent messages
+    /// makes room if it is less urgent than `message`, otherwise `message`
+    /// is dropped. Returns what was dropped.
+    pub fn push(&mut self, message: (MavHeader, M)) -> Option<(MavHeader, M)> {
+        let priority = Priority::of(&message.1) as usize;
+
+        let mut dropped = None;
+        if self.queues.iter().map(VecDeque::len).sum::<usize>() >= self.capacity {
+            let least_urgent = self.queues.iter().rposition(|queue| !queue.is_empty());
+            match least_urgent {
+                Some(least_urgent) if least_urgent > priority => {
+                    dropped = self.queues[least_urgent].pop_back();
+                }
+                _ => return Some(message),
+            }
+        }
+        self.queues[priority].push_back(message);
+
+        dropped
+    }
+
+    /// Takes the most urgent message.
+    pub fn pop(&mut self) -> Option<(MavHeader, M)> {
+        self.queues.iter_mut().find_map(VecDeque::pop_front)
+    }
+
+    pub fn clear(&mut self) {
+        self.queues.iter_mut().for_each(VecDeque::clear);
+    }
+}
+
+/// Keeps the bytes written to an endpoint under its limit, if it has one.
+pub(crate) struct Bandwidth {
+    /// Most bytes per second, unlimited when `None`.
+    limit: Option<u32>,
+    /// Bytes that may be written right away. Negative after writing a frame
+    /// larger than what was left, which then has to be made up for.
+    available: f64,
+    updated: Instant,
+}
+
+impl Bandwidth {
+    pub fn new(limit: Option<u32>) -> Self {
+        let mut bandwidth = Self {
+            limit,
+            available: 0.0,
+            updated: Instant::now(),
+        };
+        bandwidth.available = bandwidth.burst();
+
+        bandwidth
+    }
+
+    /// Whether anything may be written right away.
+    pub fn has_room(&mut self) -> bool {
+        self.refill();
+        self.available >= 0.0
+    }
+
+    /// Waits until anything may be written. Cancel safe.
+    pub async fn ready(&mut self) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+
+        if !self.has_room() {
+            let wait = -self.available / f64::from(limit);
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+            self.refill();
+        }
+    }
+
+    /// Counts `bytes` written against the limit.
+    pub fn spend(&mut self, bytes: usize) {
+        if self.limit.is_some() {
+            self.available -= bytes as f64;
+        }
+    }
+
+    fn burst(&self) -> f64 {
+        self.limit
+            .map_or(0.0, |limit| f64::from(limit) * BURST.as_secs_f64())
+            .max(mavlink::MAX_FRAME_SIZE as f64)
+    }
+
+    fn refill(&mut self) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+
+        let now = Instant::now();
+        let earned = (now - self.updated).as_secs_f64() * f64::from(limit);
+        self.available = (self.available + earned).min(self.burst());
+        self.updated = now;
+    }
+}
@@ -0,0 +1,236 @@
+//! Debug captures of the raw MAVLink frames in the pcap format, for Wireshark
+//! and its MAVLink dissector. Frames are recorded byte for byte as received,
+//! signatures and frames failing their checksum included, to look into
+//! framing and signing problems seen in the field.
+//!
+//! Every frame is wrapped in an IPv4 UDP packet on port 14550, which the
+//! dissector listens on. The component is 10.0.0.1 and the peer on endpoint
+//! `n` is 10.0.1.`n + 1`, so the direction shows as source and destination.
+
+use crate::error::{CameraError, Result};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{info, warn};
+
+/// Records waiting to be written before new ones are dropped.
+const RECORD_QUEUE: usize = 1024;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+/// `LINKTYPE_IPV4`, packets start with their IPv4 header.
+const LINKTYPE_IPV4: u32 = 228;
+const SNAPLEN: u32 = 65535;
+const MAVLINK_PORT: u16 = 14550;
+const COMPONENT_ADDRESS: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+
+/// Where raw frames are captured to.
+#[derive(Debug, Clone)]
+pub struct PcapOptions {
+    /// The capture, overwritten at startup.
+    pub path: PathBuf,
+    /// Size after which nothing more is captured.
+    pub max_bytes: u64,
+}
+
+impl PcapOptions {
+    /// Captures to `path`, up to 256 MiB.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Received,
+    Sent,
+}
+
+struct Record {
+    time: SystemTime,
+    endpoint: usize,
+    direction: Direction,
+    frame: Vec<u8>,
+}
+
+/// Queues frames for the writer thread, so the IO tasks never wait on the disk.
+#[derive(Clone)]
+pub(crate) struct PcapRecorder {
+    records: mpsc::Sender<Record>,
+}
+
+impl PcapRecorder {
+    /// Creates the capture and starts its writer thread, which runs until
+    /// every recorder and tap has been dropped.
+    pub fn start(options: PcapOptions) -> Result<Self> {
+        let path = options.path.clone();
+        let writer =
+            PcapWriter::create(options).map_err(|source| CameraError::Pcap { path, source })?;
+        info!(target: "rx", path = %writer.options.path.display(), "Capturing raw MAVLink frames");
+
+        let (records, receiver) = mpsc::channel(RECORD_QUEUE);
+        std::thread::Builder::new()
+            .name("pcap".to_owned())
+            .spawn(move || writer.run(receiver))?;
+
+        Ok(Self { records })
+    }
+
+    /// The frames of endpoint `endpoint`.
+    pub fn tap(&self, endpoint: usize) -> PcapTap {
+        PcapTap {
+            recorder: self.clone(),
+            endpoint,
+        }
+    }
+
+    fn record(&self, endpoint: usize, direction: Direction, frame: &[u8]) {
+        let record = Record {
+            time: SystemTime::now(),
+            endpoint,
+            direction,
+            frame: frame.to_vec(),
+        };
+        if let Err(TrySendError::Full(_)) = self.records.try_send(record) {
+            warn!(target: "rx", "Frame capture can't keep up, dropping a frame");
+        }
+    }
+}
+
+/// Captures the frames of one endpoint.
+#[derive(Clone)]
+pub(crate) struct PcapTap {
+    recorder: PcapRecorder,
+    endpoint: usize,
+}
+
+impl PcapTap {
+    pub fn received(&self, frame: &[u8]) {
+        self.recorder
+            .record(self.endpoint, Direction::Received, frame);
+    }
+
+    pub fn sent(&self, frame: &[u8]) {
+        self.recorder.record(self.endpoint, Direction::Sent, frame);
+    }
+}
+
+struct PcapWriter {
+    options: PcapOptions,
+    file: BufWriter<File>,
+    written: u64,
+    /// Whether `max_bytes` was reached.
+    full: bool,
+}
+
+impl PcapWriter {
+    fn create(options: PcapOptions) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(&options.path)?);
+        for field in [PCAP_MAGIC.to_le_bytes(), [2, 0, 4, 0]] {
+            file.write_all(&field)?;
+        }
+        // Time zone offset and timestamp accuracy, both 0.
+        file.write_all(&[0; 8])?;
+        file.write_all(&SNAPLEN.to_le_bytes())?;
+        file.write_all(&LINKTYPE_IPV4.to_le_bytes())?;
+        file.flush()?;
+
+        Ok(Self {
+            options,
+            file,
+            written: 24,
+            full: false,
+        })
+    }
+
+    fn run(mut self, mut records: mpsc::Receiver<Record>) {
+        while let Some(record) = records.blocking_recv() {
+            let mut result = self.write(&record);
+
+            // Flush once the queue is drained rather than after every frame.
+            while result.is_ok() {
+                let Ok(record) = records.try_recv() else {
+                    break;
+                };
+                result = self.write(&record);
+            }
+            if result.is_ok() {
+                result = self.file.flush();
+            }
+
+            if let Err(error) = result {
+                warn!(target: "rx", path = %self.options.path.display(), "Failed to write frame capture: {error}");
+            }
+        }
+    }
+
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        if self.full {
+            return Ok(());
+        }
+        let packet = udp_packet(record);
+        let len = 16 + packet.len() as u64;
+        if self.written + len > self.options.max_bytes {
+            warn!(target: "rx", path = %self.options.path.display(), "Frame capture full, stopping it");
+            self.full = true;
+            return Ok(());
+        }
+
+        let time = record.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.file
+            .write_all(&(time.as_secs() as u32).to_le_bytes())?;
+        self.file.write_all(&time.subsec_micros().to_le_bytes())?;
+        for _ in 0..2 {
+            self.file.write_all(&(packet.len() as u32).to_le_bytes())?;
+        }
+        self.file.write_all(&packet)?;
+        self.written += len;
+        Ok(())
+    }
+}
+
+/// `record` as an IPv4 UDP packet between the component and the peer.
+fn udp_packet(record: &Record) -> Vec<u8> {
+    let peer = Ipv4Addr::new(10, 0, 1, (record.endpoint % 254) as u8 + 1);
+    let (source, destination) = match record.direction {
+        Direction::Received => (peer, COMPONENT_ADDRESS),
+        Direction::Sent => (COMPONENT_ADDRESS, peer),
+    };
+
+    let udp_len = (UDP_HEADER_LEN + record.frame.len()) as u16;
+    let total_len = IPV4_HEADER_LEN as u16 + udp_len;
+    let mut packet = Vec::with_capacity(total_len.into());
+    // Version 4, 5 words of header, no options, don't fragment, TTL 64, UDP.
+    packet.extend([0x45, 0]);
+    packet.extend(total_len.to_be_bytes());
+    packet.extend([0, 0, 0x40, 0, 64, 17, 0, 0]);
+    packet.extend(source.octets());
+    packet.extend(destination.octets());
+    let checksum = ipv4_checksum(&packet);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+    packet.extend(MAVLINK_PORT.to_be_bytes());
+    packet.extend(MAVLINK_PORT.to_be_bytes());
+    packet.extend(udp_len.to_be_bytes());
+    // The UDP checksum is optional over IPv4.
+    packet.extend([0, 0]);
+    packet.extend(&record.frame);
+
+    packet
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let sum = header
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+        .sum::<u32>();
+    let folded = (sum & 0xffff) + (sum >> 16);
+    !((folded & 0xffff) + (folded >> 16)) as u16
+}
@@ -0,0 +1,53 @@
+//! Backing off when a telemetry radio runs out of buffer, as autopilots do:
+//! SiK and similar radios report the free space of their transmit buffer in
+//! `RADIO_STATUS` about once a second.
+
+use tokio::sync::watch;
+use tracing::info;
+
+/// Free transmit buffer in percent below which telemetry slows down further.
+const CONGESTED: u8 = 20;
+
+/// Free transmit buffer in percent above which telemetry speeds up again.
+const RECOVERED: u8 = 80;
+
+/// The most telemetry is slowed down by.
+const MAX_SLOWDOWN: u32 = 16;
+
+/// How many times slower than configured telemetry and previews are sent,
+/// shared by the IO tasks that hear from a radio and the senders.
+#[derive(Debug, Clone)]
+pub(crate) struct Slowdown(watch::Sender<u32>);
+
+impl Slowdown {
+    pub fn new() -> Self {
+        Self(watch::channel(1).0)
+    }
+
+    /// 1 while the link keeps up.
+    pub fn get(&self) -> u32 {
+        *self.0.borrow()
+    }
+
+    /// Doubles the slowdown while the radio's buffer is nearly full and
+    /// halves it once the buffer has drained again.
+    pub fn observe(&self, txbuf: u8, address: &str) {
+        let slowdown = self.get();
+        let next = if txbuf < CONGESTED {
+            (slowdown * 2).min(MAX_SLOWDOWN)
+        } else if txbuf > RECOVERED {
+            (slowdown / 2).max(1)
+        } else {
+            slowdown
+        };
+
+        if next != slowdown {
+            if next == 1 {
+                info!(target: "rx", address, "Radio link recovered, telemetry back to full rate");
+            } else {
+                info!(target: "rx", address, txbuf, "Radio link congested, telemetry {next}x slower");
+            }
+            self.0.send_replace(next);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Which endpoints each peer has been heard on, so a reply to one ground
+//! station isn't written to every endpoint, as MAVLink routers do.
+
+use mavlink::MavHeader;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Indices of the endpoints a peer was heard on.
+type Endpoints = BTreeSet<usize>;
+
+/// The endpoints of the peers by system and component id, shared by the IO
+/// tasks and the senders.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Routes(Arc<Mutex<HashMap<(u8, u8), Endpoints>>>);
+
+impl Routes {
+    /// Takes note that the sender of `header` is reachable over `endpoint`.
+    pub fn learn(&self, header: &MavHeader, endpoint: usize) {
+        if let Ok(mut routes) = self.0.lock() {
+            routes
+                .entry((header.system_id, header.component_id))
+                .or_default()
+                .insert(endpoint);
+        }
+    }
+
+    /// The endpoints `component` of `system` was heard on, or those of any
+    /// component of `system` when `component` is 0 or hasn't sent anything
+    /// yet. Empty for systems never heard from.
+    pub fn endpoints(&self, system: u8, component: u8) -> Endpoints {
+        let Ok(routes) = self.0.lock() else {
+            return Endpoints::new();
+        };
+
+        match routes.get(&(system, component)) {
+            Some(endpoints) if component != 0 => endpoints.clone(),
+            _ => routes
+                .iter()
+                .filter(|((known, _), _)| *known == system)
+                .flat_map(|(_, endpoints)| endpoints.iter().copied())
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! `serial:<device>:<baud>[:<flow control>]` endpoints, e.g. a UART to the
+//! flight controller.
+
+use std::io::{self, ErrorKind};
+use tokio_serial::{FlowControl, SerialPortBuilderExt, SerialStream};
+use tracing::info;
+
+/// Parsed serial connection string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SerialSettings {
+    device: String,
+    baud_rate: u32,
+    flow_control: FlowControl,
+}
+
+impl SerialSettings {
+    /// Parses the part after `serial:`. Flow control is `none` (the default),
+    /// `software` (XON/XOFF) or `hardware` (RTS/CTS).
+    pub fn parse(target: &str) -> io::Result<Self> {
+        let invalid = || {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "expected serial:<device>:<baud>[:none|software|hardware], got serial:{target}"
+                ),
+            )
+        };
+
+        let (rest, flow_control) = match target.rsplit_once(':') {
+            Some((rest, "none")) => (rest, FlowControl::None),
+            Some((rest, "software")) => (rest, FlowControl::Software),
+            Some((rest, "hardware")) => (rest, FlowControl::Hardware),
+            _ => (target, FlowControl::None),
+        };
+
+        let (device, baud_rate) = rest.rsplit_once(':').ok_or_else(invalid)?;
+        let baud_rate = baud_rate.parse().map_err(|_| invalid())?;
+        if device.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            device: device.to_owned(),
+            baud_rate,
+            flow_control,
+        })
+    }
+
+    pub fn open(&self) -> io::Result<SerialStream> {
+        let port = tokio_serial::new(&self.device, self.baud_rate)
+            .flow_control(self.flow_control)
+            .open_native_async()?;
+
+        info!(
+            target: "rx",
+            device = %self.device,
+            baud_rate = self.baud_rate,
+            flow_control = ?self.flow_control,
+            "Opened serial port"
+        );
+
+        Ok(port)
+    }
+}
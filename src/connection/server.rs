@@ -0,0 +1,172 @@
+//! Server-mode endpoints that accept any number of clients.
+
+use super::frame::{Decoded, FrameBuffer, FrameTaps};
+use mavlink::error::MessageReadError;
+use mavlink::Message;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{debug, info, warn};
+
+const READ_CHUNK: usize = 4096;
+const CLIENT_QUEUE: usize = 64;
+/// UDP clients that stay silent this long stop getting messages.
+const UDP_PEER_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Received<M> = Result<Decoded<M>, MessageReadError>;
+
+/// `tcpin`: accepts clients in the background, merges what they send and
+/// writes every outgoing message to all of them.
+pub(crate) struct TcpServer<M> {
+    incoming: mpsc::Receiver<Received<M>>,
+    new_clients: mpsc::Receiver<(SocketAddr, OwnedWriteHalf)>,
+    clients: Vec<(SocketAddr, OwnedWriteHalf)>,
+    /// Owns the client reader tasks, which stop with it.
+    accept_task: JoinHandle<()>,
+}
+
+impl<M: Message + Send + 'static> TcpServer<M> {
+    pub async fn bind(address: &str, taps: FrameTaps) -> io::Result<Self> {
+        let listener = TcpListener::bind(address).await?;
+        info!(target: "rx", address, "Listening for MAVLink clients");
+
+        let (incoming_sender, incoming) = mpsc::channel(CLIENT_QUEUE);
+        let (client_sender, new_clients) = mpsc::channel(CLIENT_QUEUE);
+        let accept_task = tokio::spawn(accept(listener, incoming_sender, client_sender, taps));
+
+        Ok(Self {
+            incoming,
+            new_clients,
+            clients: Vec::new(),
+            accept_task,
+        })
+    }
+
+    /// Returns the next message from any client. Cancel safe.
+    pub async fn recv(&mut self) -> Received<M> {
+        self.incoming
+            .recv()
+            .await
+            .unwrap_or_else(|| Err(MessageReadError::Io(ErrorKind::UnexpectedEof.into())))
+    }
+
+    /// Writes `frame` to every connected client, dropping the ones that fail.
+    pub async fn send(&mut self, frame: &[u8]) {
+        while let Ok(client) = self.new_clients.try_recv() {
+            self.clients.push(client);
+        }
+
+        let mut index = 0;
+        while index < self.clients.len() {
+            let (peer, writer) = &mut self.clients[index];
+
+            match writer.write_all(frame).await {
+                Ok(()) => index += 1,
+                Err(error) => {
+                    info!(target: "rx", %peer, "MAVLink client disconnected: {error}");
+                    self.clients.swap_remove(index);
+                }
+            }
+        }
+    }
+}
+
+impl<M> Drop for TcpServer<M> {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+async fn accept<M: Message + Send + 'static>(
+    listener: TcpListener,
+    incoming: mpsc::Sender<Received<M>>,
+    new_clients: mpsc::Sender<(SocketAddr, OwnedWriteHalf)>,
+    taps: FrameTaps,
+) {
+    let mut readers = JoinSet::new();
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(client) => client,
+            Err(error) => {
+                warn!(target: "rx", "Failed to accept MAVLink client: {error}");
+                continue;
+            }
+        };
+
+        info!(target: "rx", %peer, "Accepted MAVLink client");
+        let (reader, writer) = stream.into_split();
+
+        if new_clients.send((peer, writer)).await.is_err() {
+            return;
+        }
+        readers.spawn(read_client(peer, reader, incoming.clone(), taps.clone()));
+
+        // Reap readers of clients that have gone away.
+        while readers.try_join_next().is_some() {}
+    }
+}
+
+/// Forwards the messages of one client until it disconnects.
+async fn read_client<M: Message>(
+    peer: SocketAddr,
+    mut reader: OwnedReadHalf,
+    incoming: mpsc::Sender<Received<M>>,
+    taps: FrameTaps,
+) {
+    let mut buffer = FrameBuffer::new(taps);
+    let mut chunk = [0u8; READ_CHUNK];
+
+    loop {
+        while let Some(message) = buffer.next_message() {
+            if incoming.send(Ok(message)).await.is_err() {
+                return;
+            }
+        }
+
+        match reader.read(&mut chunk).await {
+            Ok(0) => {
+                info!(target: "rx", %peer, "MAVLink client disconnected");
+                return;
+            }
+            Ok(read) => buffer.extend(&chunk[..read]),
+            Err(error) => {
+                info!(target: "rx", %peer, "MAVLink client disconnected: {error}");
+                return;
+            }
+        }
+    }
+}
+
+/// `udpin` clients, i.e. everyone who sent us a datagram recently.
+#[derive(Default)]
+pub(crate) struct UdpPeers {
+    last_seen: HashMap<SocketAddr, Instant>,
+}
+
+impl UdpPeers {
+    pub fn seen(&mut self, peer: SocketAddr) {
+        if self.last_seen.insert(peer, Instant::now()).is_none() {
+            info!(target: "rx", %peer, "New MAVLink UDP client");
+        }
+    }
+
+    /// Returns the peers that are still active, forgetting the others.
+    pub fn active(&mut self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.last_seen.retain(|peer, last_seen| {
+            let active = last_seen.elapsed() < UDP_PEER_TIMEOUT;
+            if !active {
+                debug!(target: "rx", %peer, "MAVLink UDP client timed out");
+            }
+            active
+        });
+
+        self.last_seen.keys().copied()
+    }
+}
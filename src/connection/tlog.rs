@@ -0,0 +1,251 @@
+//! Telemetry logs (`.tlog`) of the MAVLink traffic, in the format ground
+//! stations write: each record is the time it was logged in microseconds since
+//! the Unix epoch, big endian, followed by the raw frame.
+//!
+//! Logs are recorded by [`TlogRecorder`] and fed back to the component by the
+//! `tlog:` transport, see [`TlogReplay`].
+
+use super::frame::{self, Decoded};
+use crate::error::{CameraError, Result};
+use mavlink::error::MessageReadError;
+use mavlink::{MavHeader, MavlinkVersion, Message};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Records waiting to be written before new ones are dropped.
+const RECORD_QUEUE: usize = 1024;
+
+const TIMESTAMP_LEN: usize = 8;
+
+/// Where and how much MAVLink traffic is recorded.
+#[derive(Debug, Clone)]
+pub struct TlogOptions {
+    /// The log being written. Rotated logs are kept next to it as
+    /// `<name>.1.tlog`, `<name>.2.tlog`, ... with `1` the most recent.
+    pub path: PathBuf,
+    /// Size after which the log is rotated.
+    pub max_bytes: u64,
+    /// How many rotated logs are kept besides the current one.
+    pub keep: usize,
+}
+
+impl TlogOptions {
+    /// Records to `path`, rotating every 64 MiB and keeping 5 old logs.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes: 64 * 1024 * 1024,
+            keep: 5,
+        }
+    }
+}
+
+/// Queues frames for the writer thread, so the IO tasks never wait on the disk.
+#[derive(Clone)]
+pub(crate) struct TlogRecorder {
+    records: mpsc::Sender<Vec<u8>>,
+}
+
+impl TlogRecorder {
+    /// Opens the log for appending and starts its writer thread, which runs
+    /// until every recorder has been dropped.
+    pub fn start(options: TlogOptions) -> Result<Self> {
+        let path = options.path.clone();
+        let writer =
+            TlogWriter::open(options).map_err(|source| CameraError::Tlog { path, source })?;
+        info!(target: "rx", path = %writer.options.path.display(), "Recording telemetry log");
+
+        let (records, receiver) = mpsc::channel(RECORD_QUEUE);
+        std::thread::Builder::new()
+            .name("tlog".to_owned())
+            .spawn(move || writer.run(receiver))?;
+
+        Ok(Self { records })
+    }
+
+    /// Logs `message` as it was sent or received with `header`.
+    pub fn record<M: Message>(&self, header: &MavHeader, message: &M) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_micros() as u64);
+
+        let mut record = timestamp.to_be_bytes().to_vec();
+        match frame::encode(MavlinkVersion::V2, header, message) {
+            Ok(frame) => record.extend(frame),
+            Err(error) => {
+                debug!(target: "rx", "Can't log {}: {error}", message.message_name());
+                return;
+            }
+        }
+
+        if let Err(TrySendError::Full(_)) = self.records.try_send(record) {
+            warn!(target: "rx", "Telemetry log can't keep up, dropping {}", message.message_name());
+        }
+    }
+}
+
+struct TlogWriter {
+    options: TlogOptions,
+    file: BufWriter<File>,
+    written: u64,
+}
+
+impl TlogWriter {
+    fn open(options: TlogOptions) -> io::Result<Self> {
+        let file = append(&options.path)?;
+        let written = file.metadata()?.len();
+
+        Ok(Self {
+            options,
+            file: BufWriter::new(file),
+            written,
+        })
+    }
+
+    fn run(mut self, mut records: mpsc::Receiver<Vec<u8>>) {
+        while let Some(record) = records.blocking_recv() {
+            let mut result = self.write(&record);
+
+            // Flush once the queue is drained rather than after every frame.
+            while result.is_ok() {
+                let Ok(record) = records.try_recv() else {
+                    break;
+                };
+                result = self.write(&record);
+            }
+            if result.is_ok() {
+                result = self.file.flush();
+            }
+
+            if let Err(error) = result {
+                warn!(target: "rx", path = %self.options.path.display(), "Failed to write telemetry log: {error}");
+            }
+        }
+    }
+
+    fn write(&mut self, record: &[u8]) -> io::Result<()> {
+        if self.written > 0 && self.written + record.len() as u64 > self.options.max_bytes {
+            self.rotate()?;
+        }
+
+        self.file.write_all(record)?;
+        self.written += record.len() as u64;
+        Ok(())
+    }
+
+    /// Shifts the old logs up by one, dropping the oldest, and starts a new one.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        let path = &self.options.path;
+        if self.options.keep == 0 {
+            std::fs::remove_file(path)?;
+        } else {
+            for index in (1..self.options.keep).rev() {
+                let from = rotated_path(path, index);
+                if from.exists() {
+                    std::fs::rename(from, rotated_path(path, index + 1))?;
+                }
+            }
+            std::fs::rename(path, rotated_path(path, 1))?;
+        }
+
+        self.file = BufWriter::new(append(path)?);
+        self.written = 0;
+        debug!(target: "rx", path = %path.display(), "Rotated telemetry log");
+        Ok(())
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// `flight.tlog` becomes `flight.<index>.tlog`.
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}.{index}.{}", extension.to_string_lossy()),
+        None => format!("{stem}.{index}"),
+    };
+
+    path.with_file_name(name)
+}
+
+/// Plays back a telemetry log as if its messages were arriving now, keeping
+/// their original spacing divided by `speed`. Messages sent to it are dropped.
+///
+/// Opened from `tlog:<path>[:<speed>]`; a speed of `0` replays as fast as
+/// possible. [`TlogReplay::recv`] fails with [`ErrorKind::UnexpectedEof`] at the
+/// end of the log.
+pub(crate) struct TlogReplay {
+    data: Vec<u8>,
+    position: usize,
+    speed: f64,
+    /// When the first record was replayed and the time it was logged at.
+    start: Option<(Instant, u64)>,
+}
+
+impl TlogReplay {
+    pub fn open(target: &str) -> io::Result<Self> {
+        let (path, speed) = target
+            .rsplit_once(':')
+            .and_then(|(path, speed)| Some((path, speed.parse::<f64>().ok()?)))
+            .unwrap_or((target, 1.0));
+        if !(speed >= 0.0 && f64::is_finite(speed)) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid replay speed {speed}"),
+            ));
+        }
+
+        let data = std::fs::read(path)?;
+        info!(target: "rx", path, speed, "Replaying telemetry log");
+
+        Ok(Self {
+            data,
+            position: 0,
+            speed,
+            start: None,
+        })
+    }
+
+    /// Waits until the next message is due and returns it. Cancel safe.
+    pub async fn recv<M: Message>(&mut self) -> Result<Decoded<M>, MessageReadError> {
+        let Some((timestamp, version, len)) = self.next_record() else {
+            if self.position < self.data.len() {
+                warn!(target: "rx", offset = self.position, "Stopping replay at corrupt telemetry log record");
+            }
+            info!(target: "rx", "Telemetry log replay finished");
+            return Err(MessageReadError::Io(ErrorKind::UnexpectedEof.into()));
+        };
+
+        let (started, first) = *self.start.get_or_insert((Instant::now(), timestamp));
+        if self.speed > 0.0 {
+            let offset = Duration::from_micros(timestamp.saturating_sub(first));
+            tokio::time::sleep_until(started + offset.div_f64(self.speed)).await;
+        }
+
+        let frame = self.position + TIMESTAMP_LEN;
+        self.position = frame + len;
+        let (header, message) =
+            mavlink::read_versioned_msg(&mut &self.data[frame..self.position], version)?;
+        Ok((version, header, message))
+    }
+
+    /// Returns the timestamp, version and frame length of the next record if
+    /// it's complete.
+    fn next_record(&self) -> Option<(u64, MavlinkVersion, usize)> {
+        let record = self.data.get(self.position..)?;
+        let timestamp = u64::from_be_bytes(record.get(..TIMESTAMP_LEN)?.try_into().ok()?);
+
+        let frame = &record[TIMESTAMP_LEN..];
+        let (version, len) = frame::frame_len(frame)?;
+        (frame.len() >= len).then_some((timestamp, version, len))
+    }
+}
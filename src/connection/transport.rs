@@ -0,0 +1,286 @@
+//! Async MAVLink transports selected by connection string scheme.
+
+use super::frame::{self, Decoded, FrameBuffer, FrameTaps};
+use super::serial::SerialSettings;
+use super::server::{TcpServer, UdpPeers};
+use super::tlog::TlogReplay;
+use crate::CameraDialect;
+use mavlink::error::{MessageReadError, MessageWriteError};
+use mavlink::{MavConnection, MavHeader, MavlinkVersion};
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tokio_serial::SerialStream;
+use tracing::debug;
+
+const READ_CHUNK: usize = 4096;
+const READ_QUEUE: usize = 64;
+
+/// A connected MAVLink link, owned by the IO task.
+///
+/// TCP, UDP and serial ports are handled natively on the tokio reactor; other
+/// schemes such as `file:` fall back to `mavlink::connect` with a reader thread.
+/// [`Transport::recv`] is cancel safe so it can be raced against outgoing
+/// messages.
+pub(crate) enum Transport<M> {
+    Tcp {
+        reader: OwnedReadHalf,
+        writer: OwnedWriteHalf,
+        buffer: FrameBuffer,
+    },
+    /// `tcpin`, serving any number of clients.
+    TcpServer(TcpServer<M>),
+    Udp {
+        socket: UdpSocket,
+        buffer: FrameBuffer,
+        target: UdpTarget,
+    },
+    Serial {
+        port: SerialStream,
+        buffer: FrameBuffer,
+        /// Frames queued by [`Transport::send`] until the next [`Transport::flush`].
+        pending: Vec<u8>,
+    },
+    /// `tlog`, replaying a recorded telemetry log.
+    Replay(TlogReplay),
+    Blocking {
+        connection: Arc<BlockingConnection<M>>,
+        incoming: mpsc::Receiver<Result<Decoded<M>, MessageReadError>>,
+    },
+}
+
+/// Where UDP messages are sent to.
+pub(crate) enum UdpTarget {
+    /// `udpout` and `udpbcast`.
+    Fixed(SocketAddr),
+    /// `udpin`: every client that sent us something recently.
+    Peers(UdpPeers),
+}
+
+type BlockingConnection<M> = dyn MavConnection<M> + Sync + Send;
+
+impl<M: CameraDialect> Transport<M> {
+    /// Opens `address`, reporting the frames received natively to `taps`.
+    pub async fn open(address: &str, taps: FrameTaps) -> io::Result<Self> {
+        let (scheme, target) = address.split_once(':').ok_or_else(|| {
+            io::Error::new(ErrorKind::InvalidInput, "expected <scheme>:<address>")
+        })?;
+
+        match scheme {
+            "tcpout" => Ok(Self::tcp(TcpStream::connect(target).await?, taps)),
+            "tcpin" => Ok(Self::TcpServer(TcpServer::bind(target, taps).await?)),
+            "udpin" => Ok(Self::udp(
+                UdpSocket::bind(target).await?,
+                UdpTarget::Peers(UdpPeers::default()),
+                taps,
+            )),
+            "udpout" | "udpbcast" => {
+                let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                socket.set_broadcast(scheme == "udpbcast")?;
+
+                let peer = tokio::net::lookup_host(target)
+                    .await?
+                    .next()
+                    .ok_or_else(|| {
+                        io::Error::new(ErrorKind::InvalidInput, format!("can't resolve {target}"))
+                    })?;
+
+                Ok(Self::udp(socket, UdpTarget::Fixed(peer), taps))
+            }
+            "serial" => Ok(Self::Serial {
+                port: SerialSettings::parse(target)?.open()?,
+                buffer: FrameBuffer::new(taps),
+                pending: Vec::new(),
+            }),
+            "tlog" => Ok(Self::Replay(TlogReplay::open(target)?)),
+            _ => {
+                let address = address.to_owned();
+                let connection =
+                    tokio::task::spawn_blocking(move || mavlink::connect::<M>(&address))
+                        .await
+                        .map_err(io::Error::other)??;
+
+                Ok(Self::blocking(Arc::from(connection)))
+            }
+        }
+    }
+
+    fn tcp(stream: TcpStream, taps: FrameTaps) -> Self {
+        let (reader, writer) = stream.into_split();
+
+        Self::Tcp {
+            reader,
+            writer,
+            buffer: FrameBuffer::new(taps),
+        }
+    }
+
+    fn udp(socket: UdpSocket, target: UdpTarget, taps: FrameTaps) -> Self {
+        Self::Udp {
+            socket,
+            buffer: FrameBuffer::new(taps),
+            target,
+        }
+    }
+
+    /// Reads from `connection` on a dedicated thread. The thread exits once the
+    /// transport has been dropped and the next message arrives.
+    fn blocking(connection: Arc<BlockingConnection<M>>) -> Self {
+        let (sender, incoming) = mpsc::channel(READ_QUEUE);
+        let reader = connection.clone();
+
+        std::thread::spawn(move || {
+            let recv = || {
+                let frame = reader.recv_frame()?;
+                Ok((frame.protocol_version, frame.header, frame.msg))
+            };
+            while sender.blocking_send(recv()).is_ok() {}
+        });
+
+        Self::Blocking {
+            connection,
+            incoming,
+        }
+    }
+
+    /// Whether this plays back a recorded log rather than talking to a vehicle.
+    pub fn is_replay(&self) -> bool {
+        matches!(self, Self::Replay(_))
+    }
+
+    pub async fn recv(&mut self) -> Result<Decoded<M>, MessageReadError> {
+        let mut chunk = [0u8; READ_CHUNK];
+
+        match self {
+            Self::Tcp { reader, buffer, .. } => loop {
+                if let Some(message) = buffer.next_message() {
+                    return Ok(message);
+                }
+
+                let read = reader
+                    .read(&mut chunk)
+                    .await
+                    .map_err(MessageReadError::Io)?;
+                if read == 0 {
+                    return Err(MessageReadError::Io(ErrorKind::UnexpectedEof.into()));
+                }
+                buffer.extend(&chunk[..read]);
+            },
+            Self::TcpServer(server) => server.recv().await,
+            Self::Udp {
+                socket,
+                buffer,
+                target,
+            } => loop {
+                if let Some(message) = buffer.next_message() {
+                    return Ok(message);
+                }
+
+                let (read, from) = socket
+                    .recv_from(&mut chunk)
+                    .await
+                    .map_err(MessageReadError::Io)?;
+                if let UdpTarget::Peers(peers) = target {
+                    peers.seen(from);
+                }
+                buffer.extend(&chunk[..read]);
+            },
+            Self::Serial { port, buffer, .. } => loop {
+                if let Some(message) = buffer.next_message() {
+                    return Ok(message);
+                }
+
+                let read = port.read(&mut chunk).await.map_err(MessageReadError::Io)?;
+                if read == 0 {
+                    return Err(MessageReadError::Io(ErrorKind::UnexpectedEof.into()));
+                }
+                buffer.extend(&chunk[..read]);
+            },
+            Self::Replay(replay) => replay.recv().await,
+            Self::Blocking { incoming, .. } => incoming
+                .recv()
+                .await
+                .unwrap_or_else(|| Err(MessageReadError::Io(ErrorKind::UnexpectedEof.into()))),
+        }
+    }
+
+    /// Sends `message` framed as `version`. The `file:` fallback always uses
+    /// the version `mavlink::connect` picked.
+    pub async fn send(
+        &mut self,
+        version: MavlinkVersion,
+        header: &MavHeader,
+        message: &M,
+    ) -> Result<(), MessageWriteError> {
+        match self {
+            Self::Tcp { writer, .. } => {
+                let frame = frame::encode(version, header, message)?;
+                writer
+                    .write_all(&frame)
+                    .await
+                    .map_err(MessageWriteError::Io)?;
+            }
+            Self::TcpServer(server) => {
+                let frame = frame::encode(version, header, message)?;
+                server.send(&frame).await;
+            }
+            Self::Udp {
+                socket,
+                target: UdpTarget::Fixed(peer),
+                ..
+            } => {
+                let frame = frame::encode(version, header, message)?;
+                socket
+                    .send_to(&frame, *peer)
+                    .await
+                    .map_err(MessageWriteError::Io)?;
+            }
+            Self::Udp {
+                socket,
+                target: UdpTarget::Peers(peers),
+                ..
+            } => {
+                // Like mavlink's udpin, nothing can be sent before a peer has talked to us.
+                let frame = frame::encode(version, header, message)?;
+
+                for peer in peers.active() {
+                    if let Err(error) = socket.send_to(&frame, peer).await {
+                        debug!(target: "rx", %peer, "Failed to send to MAVLink UDP client: {error}");
+                    }
+                }
+            }
+            Self::Serial { pending, .. } => {
+                pending.extend(frame::encode(version, header, message)?);
+            }
+            Self::Replay(_) => {}
+            Self::Blocking { connection, .. } => {
+                let connection = connection.clone();
+                let header = *header;
+                let message = message.clone();
+
+                tokio::task::spawn_blocking(move || connection.send(&header, &message))
+                    .await
+                    .map_err(|error| MessageWriteError::Io(io::Error::other(error)))??;
+            }
+        }
+
+        Ok(())
+    }
+    /// Writes out anything [`Transport::send`] buffered. Serial ports batch
+    /// frames so slow radios see fewer, larger writes.
+    pub async fn flush(&mut self) -> Result<(), MessageWriteError> {
+        if let Self::Serial { port, pending, .. } = self {
+            if !pending.is_empty() {
+                let result = port.write_all(pending).await;
+                pending.clear();
+                result.map_err(MessageWriteError::Io)?;
+            }
+        }
+
+        Ok(())
+    }
+}
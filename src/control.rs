@@ -0,0 +1,269 @@
+//! Control of the cameras from on-board software outside of MAVLink, shared
+//! by the REST API, the gRPC service, the ROS 2 node and the MQTT bridge so
+//! they all behave the same.
+
+use crate::capture_log::json_string;
+use crate::dispatcher::Request;
+use crate::error::Result;
+use crate::event::{CameraEvent, EventSender};
+use crate::state::{CameraState, StateChanges};
+use crate::status::{ComponentStatus, FrameCounts, WorkerStatus};
+use mavlink::MavHeader;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+/// Outcome of a change of a setting.
+#[derive(Debug)]
+pub(crate) enum SettingChange {
+    /// The camera took the setting, possibly snapped to one of its steps.
+    Changed(String),
+    UnknownParameter,
+    /// The parameter can't be set to that.
+    Unsupported,
+    /// Writing the setting to the camera failed.
+    Failed(String),
+}
+
+/// Why a camera didn't take a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Refused {
+    NoSuchCamera,
+    /// The camera has too many requests waiting already.
+    Busy,
+    /// The camera's command task has exited.
+    Stopped,
+}
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoSuchCamera => "no such camera",
+            Self::Busy => "camera busy",
+            Self::Stopped => "camera stopped",
+        })
+    }
+}
+
+/// What is controlled of one camera.
+pub(crate) struct ControlledCamera {
+    pub inbox: mpsc::Sender<(MavHeader, Request)>,
+    pub state: watch::Receiver<CameraState>,
+}
+
+/// The cameras by component id and the health of the component.
+#[derive(Clone)]
+pub(crate) struct Control {
+    status: Arc<Mutex<ComponentStatus>>,
+    cameras: Arc<BTreeMap<u8, ControlledCamera>>,
+    events: EventSender,
+}
+
+impl Control {
+    pub fn new(
+        status: Arc<Mutex<ComponentStatus>>,
+        cameras: BTreeMap<u8, ControlledCamera>,
+        events: EventSender,
+    ) -> Self {
+        Self {
+            status,
+            cameras: Arc::new(cameras),
+            events,
+        }
+    }
+
+    pub fn status(&self) -> Result<ComponentStatus> {
+        Ok(self.status.lock()?.clone())
+    }
+
+    /// The health of the component and the state of every camera as a JSON
+    /// object.
+    pub fn status_json(&self) -> Result<String> {
+        let status = self.status()?;
+
+        let mut json = String::from(r#"{"links":{"#);
+        for (index, (address, link)) in status.links.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            let _ = write!(json, "{}:{}", json_string(address), worker_json(link));
+        }
+        let _ = write!(
+            json,
+            r#"}},"receiver":{},"http":{},"grpc":{},"ros2":{},"watchdog":{},"mqtt":{},"cameras":["#,
+            worker_json(&status.receiver),
+            worker_json(&status.http),
+            worker_json(&status.grpc),
+            worker_json(&status.ros2),
+            worker_json(&status.watchdog),
+            worker_json(&status.mqtt)
+        );
+        for (index, (id, state)) in self.states().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            let tasks = status.cameras.get(&id).cloned().unwrap_or_default();
+            let _ = write!(
+                json,
+                r#"{{"camera":{id},{},"heartbeat":{},"commands":{},"live_view":{},"image_transmission":{}}}"#,
+                state.json_fields(),
+                worker_json(&tasks.heartbeat),
+                worker_json(&tasks.commands),
+                worker_json(&tasks.live_view),
+                worker_json(&tasks.image_transmission)
+            );
+        }
+        json.push_str(r#"],"peers":["#);
+        for (index, ((system, component), peer)) in status.peers.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                r#"{{"system":{system},"component":{component},"kind":"{}","last_heartbeat_ms":{}}}"#,
+                peer.kind.name(),
+                peer.last_heartbeat.elapsed().as_millis()
+            );
+        }
+        json.push_str(r#"],"frames":{"#);
+        for (index, (address, counts)) in status.frames.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{}:{{{}}}",
+                json_string(address),
+                frame_counts_json(counts)
+            );
+        }
+        json.push_str(r#"},"frames_by_sender":["#);
+        for (index, ((system, component), counts)) in status.frames_by_sender.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                r#"{{"system":{system},"component":{component},{}}}"#,
+                frame_counts_json(counts)
+            );
+        }
+        json.push_str("]}");
+
+        Ok(json)
+    }
+
+    pub fn has_camera(&self, camera: u8) -> bool {
+        self.cameras.contains_key(&camera)
+    }
+
+    /// The current state of every camera, by component id.
+    pub fn states(&self) -> impl Iterator<Item = (u8, CameraState)> + '_ {
+        self.cameras
+            .iter()
+            .map(|(&id, camera)| (id, *camera.state.borrow()))
+    }
+
+    /// Every camera's state from now on, starting with the current ones.
+    pub fn state_changes(&self) -> StateChanges {
+        StateChanges::new(
+            self.cameras
+                .iter()
+                .map(|(&id, camera)| (id, camera.state.clone())),
+        )
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<CameraEvent> {
+        self.events.subscribe()
+    }
+
+    /// Queues a picture like a trigger from the autopilot, without waiting
+    /// for it. It shows up as an event once downloaded.
+    pub fn capture(&self, camera: u8) -> Result<(), Refused> {
+        let camera = self.cameras.get(&camera).ok_or(Refused::NoSuchCamera)?;
+
+        camera
+            .inbox
+            .try_send((MavHeader::default(), Request::Capture))
+            .map_err(|error| match error {
+                TrySendError::Full(_) => Refused::Busy,
+                TrySendError::Closed(_) => Refused::Stopped,
+            })
+    }
+
+    /// Downloads the pictures left on the camera, without waiting for it.
+    /// They show up as events once downloaded.
+    pub fn offload(&self, camera: u8) -> Result<(), Refused> {
+        let camera = self.cameras.get(&camera).ok_or(Refused::NoSuchCamera)?;
+
+        camera
+            .inbox
+            .try_send((MavHeader::default(), Request::Offload))
+            .map_err(|error| match error {
+                TrySendError::Full(_) => Refused::Busy,
+                TrySendError::Closed(_) => Refused::Stopped,
+            })
+    }
+
+    /// The camera's settings by parameter name.
+    pub async fn settings(&self, camera: u8) -> Result<Vec<(&'static str, String)>, Refused> {
+        let (sender, settings) = oneshot::channel();
+        self.request(camera, Request::Settings(sender)).await?;
+        settings.await.map_err(|_| Refused::Stopped)
+    }
+
+    /// Changes parameter `name` to `setting` as the camera names it, e.g.
+    /// `1/1000` for `CAM_SHUTTERSPD`.
+    pub async fn set_setting(
+        &self,
+        camera: u8,
+        name: String,
+        setting: String,
+    ) -> Result<SettingChange, Refused> {
+        let (sender, result) = oneshot::channel();
+        let request = Request::SetSetting {
+            name,
+            setting,
+            result: sender,
+        };
+        self.request(camera, request).await?;
+        result.await.map_err(|_| Refused::Stopped)
+    }
+
+    /// Waits for room in the camera's inbox for `request`.
+    async fn request(&self, camera: u8, request: Request) -> Result<(), Refused> {
+        let camera = self.cameras.get(&camera).ok_or(Refused::NoSuchCamera)?;
+
+        camera
+            .inbox
+            .send((MavHeader::default(), request))
+            .await
+            .map_err(|_| Refused::Stopped)
+    }
+}
+
+/// The fields of `counts`, without the braces.
+fn frame_counts_json(counts: &FrameCounts) -> String {
+    format!(
+        r#""received":{},"dropped":{},"bad_checksum":{},"unknown_message":{},"invalid":{}"#,
+        counts.received,
+        counts.dropped(),
+        counts.bad_checksum,
+        counts.unknown_message,
+        counts.invalid
+    )
+}
+
+fn worker_json(status: &WorkerStatus) -> String {
+    match status {
+        WorkerStatus::Running => r#"{"status":"running"}"#.to_owned(),
+        WorkerStatus::Degraded(error) => {
+            format!(r#"{{"status":"degraded","error":{}}}"#, json_string(error))
+        }
+        WorkerStatus::Failed(error) => {
+            format!(r#"{{"status":"failed","error":{}}}"#, json_string(error))
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Running as a system service: a PID file for init scripts and readiness
+//! notifications for systemd units with `Type=notify`.
+
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// A file holding the ID of this process, removed again when dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the process ID to `path`, replacing what an earlier run may
+    /// have left behind.
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        writeln!(file, "{}", std::process::id())?;
+
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            warn!(path = %self.path.display(), "Failed to remove PID file: {error}");
+        }
+    }
+}
+
+/// Tells systemd about the state of the service, e.g. `READY=1` once it is
+/// up or `STOPPING=1` when it shuts down. Does nothing unless started by
+/// systemd with `NOTIFY_SOCKET` set.
+pub fn notify(state: &str) {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    match send_notification(&socket, state) {
+        Ok(()) => debug!(state, "Notified systemd"),
+        Err(error) => warn!(state, "Failed to notify systemd: {error}"),
+    }
+}
+
+fn send_notification(socket: &OsStr, state: &str) -> io::Result<()> {
+    let datagram = UnixDatagram::unbound()?;
+    // Sockets in the abstract namespace are given with a leading `@`.
+    match socket.as_bytes().strip_prefix(b"@") {
+        Some(name) => {
+            let address = SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &address)?;
+        }
+        None => {
+            datagram.send_to(state.as_bytes(), socket)?;
+        }
+    }
+
+    Ok(())
+}
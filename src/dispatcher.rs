@@ -0,0 +1,2922 @@
+//! Executes camera commands for one camera body.
+
+use crate::autofocus::AutofocusOptions;
+use crate::backend::{
+    CameraBackend, CameraFile, CameraModel, Capabilities, CaptureTarget, CapturedImage,
+    CorruptDownload, Lens, PendingShot, RetryOptions, Sensor, StorageInfo, StorageUsage,
+    TrackingTarget, Unsupported, Zoom,
+};
+use crate::bracketing::BracketingOptions;
+use crate::bulb;
+use crate::capture_log::{CaptureLog, CaptureOutcome, CaptureRecord};
+use crate::capture_queue::{Admission, CaptureQueue};
+use crate::clock::{TimeSource, UtcSource};
+use crate::commands::{CustomCommand, CustomCommands};
+use crate::connection::LinkSender;
+use crate::control::SettingChange;
+use crate::downloader::{self, Download, Downloader};
+use crate::error::{CameraError, Result};
+use crate::event::{CameraEvent, EventSender};
+use crate::focus_stack::FocusStackOptions;
+use crate::footprint::FootprintLog;
+use crate::geotag::{self, Geotag};
+use crate::hotshoe::ShutterFeedback;
+use crate::http;
+use crate::lens;
+use crate::mavlink_camera::{camera_information, str_to_fixed_arr, string_to_uri};
+use crate::message::{CameraDialect, CaptureFeedback};
+use crate::naming::{FilenameTemplate, NameContext};
+use crate::offload;
+use crate::parameters::{CameraDefinition, ParameterChange, Parameters};
+use crate::selftest::{self, CheckResult};
+use crate::sequence::{CaptureReport, CaptureSequence, Commanded, SequenceFile};
+use crate::shutter_count::{self, ShutterCount};
+use crate::state::{camera_mode, CameraState};
+use crate::status::{WorkerReporter, WorkerStatus};
+use crate::statustext::StatusTexts;
+use crate::storage::{self, SpaceLevel, StorageOptions};
+use crate::stream_rates::{StreamRates, StreamSchedule, Streamed};
+use crate::streaming::LiveViewServer;
+use crate::thumbnail::{self, ThumbnailOptions};
+use crate::timelapse::{self, TimeLapse};
+use crate::transmission::ImageTransmitter;
+use crate::trigger::DistanceTrigger;
+use crate::vehicle::VehicleState;
+use crate::video::{self, StreamChange, VideoStreamOptions};
+use crate::watchdog::Pulse;
+use chrono::{DateTime, Utc};
+use mavlink::common::{
+    CameraCapFlags, CameraMode, MavCmd, MavMessage, MavResult, MavSeverity, ParamAck,
+    StorageStatus, StorageUsageFlag, COMMAND_LONG_DATA, NAMED_VALUE_FLOAT_DATA,
+};
+use mavlink::MavHeader;
+use std::collections::BTreeMap;
+use std::mem::{replace, take};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, watch};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+pub(crate) type Backend = Arc<Mutex<Box<dyn CameraBackend>>>;
+
+/// Requests routed to one camera, with the header of their sender.
+pub(crate) type Inbox = mpsc::Receiver<(MavHeader, Request)>;
+
+/// Something a camera was asked to do.
+pub(crate) enum Request {
+    /// A command, acked before it runs.
+    Command(COMMAND_LONG_DATA),
+    /// A picture asked for outside of the camera protocol, see
+    /// [`CameraDialect::capture_request`]. It isn't acked.
+    Capture,
+    /// A `PARAM_EXT` request for the camera's parameters.
+    Parameter(Box<MavMessage>),
+    /// The camera's settings by parameter name, for the REST API or gRPC.
+    Settings(oneshot::Sender<Vec<(&'static str, String)>>),
+    /// A change of parameter `name` to `setting` as the camera names it, for
+    /// the REST API or gRPC.
+    SetSetting {
+        name: String,
+        setting: String,
+        result: oneshot::Sender<SettingChange>,
+    },
+    /// Downloads the pictures left on the camera like
+    /// [`crate::OFFLOAD_COMMAND`], for the REST API. It isn't acked.
+    Offload,
+}
+
+/// When, where and how a picture was taken, for reporting it once it's
+/// downloaded.
+pub(crate) struct TakenShot {
+    geotag: Option<Geotag>,
+    taken: DateTime<Utc>,
+    time_source: UtcSource,
+    /// Whether `taken` is from the hot-shoe.
+    closed_loop: bool,
+    burst: Option<i32>,
+    /// How often the shot was taken again after failing.
+    retries: u32,
+    /// The exposure settings when it was taken, if it's downloaded later.
+    settings: Option<BTreeMap<String, String>>,
+}
+
+/// A picture reported but left on the camera, until it's offloaded.
+pub(crate) struct LeftOnCamera {
+    files: Vec<CameraFile>,
+    shot: TakenShot,
+    /// The image index it was reported with.
+    seq: i32,
+}
+
+/// How the camera is fired for a capture.
+#[derive(Debug, Clone, Copy)]
+enum Shot {
+    Single,
+    /// This many pictures as fast as the camera takes them.
+    Burst(u32),
+    /// One picture with the shutter held open this long.
+    Bulb(Duration),
+}
+
+impl Shot {
+    /// How much longer than a single picture the shot may take.
+    fn duration(self) -> Duration {
+        match self {
+            Self::Single => Duration::ZERO,
+            // A second per picture is slow even for RAW.
+            Self::Burst(count) => Duration::from_secs(count.into()),
+            Self::Bulb(exposure) => exposure,
+        }
+    }
+}
+
+/// How often each camera is checked to still respond.
+const CAMERA_CHECK_PERIOD: Duration = Duration::from_secs(5);
+
+/// How often the autopilot's heartbeats are checked while capturing on
+/// their own.
+const AUTOPILOT_CHECK_PERIOD: Duration = Duration::from_secs(1);
+
+/// How many °C below the overheating temperature a camera has to cool down
+/// before its live view resumes.
+const COOL_DOWN: f32 = 5.0;
+
+/// How often `VIDEO_STREAM_STATUS` is sent while the stream runs.
+const VIDEO_STATUS_PERIOD: Duration = Duration::from_secs(1);
+
+/// Vendor and model of a camera that's neither configured nor tells.
+const UNKNOWN: &str = "Unknown";
+
+/// State owned by one camera's command task.
+pub(crate) struct Dispatcher<M> {
+    pub link: LinkSender<M>,
+    pub header: MavHeader,
+    pub backend: Backend,
+    pub events: EventSender,
+    pub state: watch::Sender<CameraState>,
+    pub vehicle: watch::Receiver<VehicleState>,
+    pub capture_log: Option<CaptureLog>,
+    pub footprints: Option<FootprintLog>,
+    /// Sends previews of the captures over MAVLink, if enabled.
+    pub transmitter: Option<ImageTransmitter>,
+    pub thumbnails: Option<ThumbnailOptions>,
+    /// URL of the HTTP server's directory with this camera's images, if it
+    /// serves them.
+    pub image_url: Option<String>,
+    /// Names downloaded images, they keep the camera's name when unset.
+    pub filename_template: Option<FilenameTemplate>,
+    /// When the component started, the `{flight}` of captures before the
+    /// vehicle armed.
+    pub started: DateTime<Utc>,
+    pub storage: StorageOptions,
+    /// How full the image directory was at the last check.
+    pub space: SpaceLevel,
+    /// Where the backend downloads to, known after the first capture.
+    pub image_dir: Option<PathBuf>,
+    /// Hot-shoe input of this camera, if it has one.
+    pub shutter: Option<ShutterFeedback>,
+    /// Index of the next capture, also the number of images taken.
+    pub image_index: i32,
+    /// The last successful capture, sent again when a ground station missed it.
+    pub last_capture: Option<CaptureReport>,
+    /// Keeps `image_index`, `last_capture`, the local shutter count and what
+    /// was commanded across restarts, if set.
+    pub sequence_file: Option<SequenceFile>,
+    /// What was commanded before the restart, restored at startup. Only its
+    /// parameters are kept up to date, the rest is saved as it is then.
+    pub commanded: Commanded,
+    /// How often the shutter fired, for the status, `CAM_SHUTTERCNT` and the
+    /// warning before its rated life is reached.
+    pub shutter_count: ShutterCount,
+    /// Pictures asked for that haven't been taken yet.
+    pub captures: CaptureQueue,
+    /// Tries again after captures that failed outright.
+    pub capture_retry: RetryOptions,
+    /// Downloads single pictures in the background when set.
+    pub downloads: Option<Downloader<TakenShot>>,
+    /// Leaves single pictures on the camera instead of downloading them.
+    pub keep_on_card: bool,
+    /// The pictures to download once [`crate::OFFLOAD_COMMAND`] asks for them.
+    pub left_on_camera: Vec<LeftOnCamera>,
+    /// Downloads and logs movies once they're recorded, otherwise they stay
+    /// on the camera.
+    pub download_video: bool,
+    /// When and where the movie being recorded was started.
+    pub recording: Option<(Instant, TakenShot)>,
+    /// Whether `MAV_CMD_DO_DIGICAM_CONTROL` locked the focus.
+    pub focus_locked: bool,
+    pub trigger: DistanceTrigger,
+    /// The lens last seen on the camera, for `CAMERA_INFORMATION`.
+    pub lens: Option<Lens>,
+    /// Whether the backend names the lens, otherwise it's taken from the
+    /// EXIF of every capture.
+    pub camera_names_lens: bool,
+    /// Stops `timelapse` and `trigger` once the autopilot has been silent
+    /// this long, if set.
+    pub autopilot_timeout: Option<Duration>,
+    /// Live-view stream of this camera, if it has one.
+    pub video_stream: Option<VideoStreamOptions>,
+    /// The server of the live view if the component serves it, with the
+    /// stream's settings it follows.
+    pub live_view: Option<(LiveViewServer, watch::Sender<VideoStreamOptions>)>,
+    /// Pauses the live view once the body is this warm in °C, if set.
+    pub overheat_temperature: Option<f32>,
+    /// Whether the live view was paused because the camera overheated.
+    pub paused_for_heat: bool,
+    /// Sets the camera's clock to the vehicle's once the autopilot has sent
+    /// its time, and again whenever the camera reconnects.
+    pub sync_clock: bool,
+    /// Whether the camera's clock was set since it last connected.
+    pub clock_synced: bool,
+    /// Sends `CAMERA_INFORMATION` unasked at startup, for ground stations
+    /// that already know the vehicle the camera took the system id of.
+    pub announce_information: bool,
+    /// The configured stream rates, which `MAV_CMD_SET_MESSAGE_INTERVAL`
+    /// goes back to with an interval of 0.
+    pub default_rates: StreamRates,
+    /// When the messages streamed by this task are due next.
+    pub streams: StreamSchedule,
+    /// Vendor and model the camera goes by, configured or as the camera tells
+    /// them.
+    pub vendor_name: Option<String>,
+    pub model_name: Option<String>,
+    /// Sensor size and resolution, configured or as the camera tells them.
+    pub sensor: Option<Sensor>,
+    /// Camera settings exposed as `PARAM_EXT` parameters, read from the
+    /// camera at startup.
+    pub parameters: Parameters,
+    /// The definition generated from `parameters`, for the HTTP server.
+    pub definition: watch::Sender<Option<CameraDefinition>>,
+    /// Where ground stations download the definition, if it's configured or
+    /// served.
+    pub definition_url: Option<String>,
+    /// Version of the definition at `definition_url` when configured, that of
+    /// `definition` otherwise.
+    pub definition_version: Option<u16>,
+    /// Takes an exposure bracket instead of a single picture when set.
+    pub bracketing: Option<BracketingOptions>,
+    /// Focus stacks taken without the command giving shots and range.
+    pub focus_stack: FocusStackOptions,
+    /// Focuses before every shot when enabled, switched with `CAM_AFSHOT`.
+    pub autofocus: AutofocusOptions,
+    /// Ids of the component's own commands.
+    pub commands: CustomCommands,
+    /// Started by `MAV_CMD_IMAGE_START_CAPTURE` with an interval.
+    pub timelapse: Option<TimeLapse>,
+    pub status_texts: StatusTexts,
+    /// Timestamps outgoing messages.
+    pub time: TimeSource,
+    /// Tells the watchdog the task still makes progress.
+    pub pulse: Pulse,
+}
+
+/// Handles the requests routed to one camera until the router goes away or
+/// `shutdown` turns true. A request being handled, e.g. a capture still
+/// downloading, is finished first, as are the queued captures.
+pub(crate) async fn run<M: CameraDialect>(
+    mut inbox: Inbox,
+    mut dispatcher: Dispatcher<M>,
+    mut shutdown: watch::Receiver<bool>,
+    reporter: WorkerReporter,
+) -> Result<()> {
+    let mut camera_check = tokio::time::interval(CAMERA_CHECK_PERIOD);
+    let mut video_status = tokio::time::interval(VIDEO_STATUS_PERIOD);
+    let mut autopilot_check = tokio::time::interval(AUTOPILOT_CHECK_PERIOD);
+    dispatcher.pulse.beat();
+    dispatcher.read_model().await;
+    dispatcher.read_sensor().await;
+    dispatcher.read_parameters().await;
+    dispatcher.read_lens().await;
+    dispatcher.read_shutter_count().await?;
+    dispatcher.restore_commanded().await;
+    if dispatcher.announce_information {
+        dispatcher.send_camera_information().await?;
+    }
+
+    loop {
+        if dispatcher.pulse.take_stalled() {
+            // Whatever hung may have left the camera wedged, re-attach it
+            // rather than wait for the next check.
+            warn!(target: "backend", camera = dispatcher.header.component_id, "Recovering from a stall");
+            dispatcher.check_camera().await?;
+        }
+        dispatcher.pulse.idle();
+
+        let (recv_header, request) = tokio::select! {
+            _ = camera_check.tick() => {
+                dispatcher.pulse.beat();
+                dispatcher.check_storage()?;
+                if dispatcher.check_camera().await? {
+                    dispatcher.check_temperature().await?;
+                    if dispatcher.sync_clock && !dispatcher.clock_synced {
+                        dispatcher.sync_camera_clock().await;
+                    }
+                    reporter.running();
+                } else {
+                    reporter.set(WorkerStatus::Degraded("camera disconnected".to_owned()));
+                }
+                continue;
+            }
+            _ = video_status.tick(), if dispatcher.state.borrow().streaming => {
+                dispatcher.pulse.beat();
+                if let Some(stream) = &dispatcher.video_stream {
+                    let status = video::stream_status(stream, true);
+                    dispatcher.link.send(&dispatcher.header, status)?;
+                }
+                video_status.reset_after(VIDEO_STATUS_PERIOD * dispatcher.link.slowdown());
+                continue;
+            }
+            message = dispatcher.streams.next(), if !dispatcher.streams.is_empty() => {
+                dispatcher.pulse.beat();
+                dispatcher.send_streamed(message).await?;
+                continue;
+            }
+            _ = autopilot_check.tick(), if dispatcher.autopilot_timeout.is_some() && dispatcher.is_capturing_unattended() => {
+                dispatcher.pulse.beat();
+                dispatcher.check_autopilot().await?;
+                continue;
+            }
+            _ = dispatcher.captures.due(), if !dispatcher.captures.is_empty() => {
+                dispatcher.pulse.beat();
+                dispatcher.captures.pop();
+                dispatcher.capture_image().await?;
+                continue;
+            }
+            (download, shot) = downloader::next(dispatcher.downloads.as_mut()) => {
+                dispatcher.pulse.beat();
+                dispatcher.report_download(download, shot).await?;
+                continue;
+            }
+            _ = timelapse::due(dispatcher.timelapse.as_ref()) => {
+                dispatcher.pulse.beat();
+                dispatcher.timelapse_capture().await?;
+                continue;
+            }
+            changed = dispatcher.vehicle.changed(), if dispatcher.trigger.is_active() => {
+                changed.map_err(|_| CameraError::Stopped)?;
+                dispatcher.pulse.beat();
+                if dispatcher.distance_reached() {
+                    debug!(target: "rx", camera = dispatcher.header.component_id, "Trigger distance reached");
+                    // Claims the distance, so updates until the picture is
+                    // taken don't queue more.
+                    dispatcher.trigger.captured();
+                    dispatcher.queue_capture();
+                }
+                continue;
+            }
+            request = inbox.recv() => request.ok_or(CameraError::Stopped)?,
+            Ok(()) = shutdown.changed() => {
+                debug!(target: "backend", camera = dispatcher.header.component_id, "Shutting down");
+                // Pictures already acked are still taken, and downloaded.
+                while !dispatcher.captures.is_empty() {
+                    dispatcher.captures.due().await;
+                    dispatcher.captures.pop();
+                    dispatcher.capture_image().await?;
+                }
+                dispatcher.finish_downloads().await?;
+                if dispatcher.recording.is_some() {
+                    dispatcher.stop_recording().await;
+                }
+                return Ok(());
+            }
+        };
+        dispatcher.pulse.beat();
+
+        match request {
+            Request::Command(command_long) => {
+                let span = info_span!(
+                    target: "rx",
+                    "command",
+                    camera = dispatcher.header.component_id,
+                    command = ?command_long.command,
+                    from_system = recv_header.system_id,
+                    from_component = recv_header.component_id,
+                );
+
+                dispatcher
+                    .handle_command_long(&recv_header, command_long)
+                    .instrument(span)
+                    .await?;
+            }
+            Request::Capture => {
+                debug!(target: "rx", camera = dispatcher.header.component_id, "Capture requested");
+                // Nothing to ack, so a refused picture is reported as failed.
+                if dispatcher.capture_admission(true) == MavResult::MAV_RESULT_DENIED {
+                    dispatcher.refuse_capture(None).await?;
+                } else {
+                    dispatcher.queue_capture();
+                }
+            }
+            Request::Parameter(message) => {
+                dispatcher.handle_parameter(&recv_header, *message).await?;
+            }
+            Request::Settings(settings) => dispatcher.send_settings(settings).await,
+            Request::SetSetting {
+                name,
+                setting,
+                result,
+            } => dispatcher.set_setting(&name, &setting, result).await?,
+            Request::Offload => {
+                debug!(target: "rx", camera = dispatcher.header.component_id, "Offload requested");
+                dispatcher.offload(None).await?;
+            }
+        }
+        reporter.running();
+    }
+}
+
+impl<M: CameraDialect> Dispatcher<M> {
+    async fn handle_command_long(
+        &mut self,
+        recv_header: &MavHeader,
+        command_long: COMMAND_LONG_DATA,
+    ) -> Result<()> {
+        info!(target: "rx", "Received command");
+
+        let custom = self.commands.get(command_long.command);
+        if command_long.command == MavCmd::MAV_CMD_DO_DIGICAM_CONTROL {
+            return self.digicam_control(recv_header, &command_long).await;
+        }
+        if custom == Some(CustomCommand::SelfTest) {
+            return self.self_test(recv_header, command_long.command).await;
+        }
+        if custom == Some(CustomCommand::Offload) {
+            return self
+                .offload(Some((recv_header, command_long.command)))
+                .await;
+        }
+        if custom == Some(CustomCommand::SetClock) {
+            let result = match self.set_camera_clock().await {
+                None => MavResult::MAV_RESULT_TEMPORARILY_REJECTED,
+                Some(Ok(())) => MavResult::MAV_RESULT_ACCEPTED,
+                Some(Err(CameraError::Backend(error))) if error.is::<Unsupported>() => {
+                    info!(target: "backend", "{error}");
+                    MavResult::MAV_RESULT_UNSUPPORTED
+                }
+                Some(Err(error)) => {
+                    warn!(target: "backend", "Failed to set the camera clock: {error}");
+                    MavResult::MAV_RESULT_FAILED
+                }
+            };
+            return send_command_ack(
+                &self.link,
+                &self.header,
+                recv_header,
+                command_long.command,
+                result,
+            );
+        }
+        if matches!(
+            command_long.command,
+            MavCmd::MAV_CMD_SET_CAMERA_ZOOM | MavCmd::MAV_CMD_SET_CAMERA_FOCUS
+        ) {
+            return self.set_lens(recv_header, &command_long).await;
+        }
+        if command_long.command == MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL {
+            return self.set_message_interval(recv_header, &command_long);
+        }
+        if matches!(
+            command_long.command,
+            MavCmd::MAV_CMD_STORAGE_FORMAT | MavCmd::MAV_CMD_SET_STORAGE_USAGE
+        ) {
+            return self.manage_storage(recv_header, &command_long).await;
+        }
+        if matches!(
+            command_long.command,
+            MavCmd::MAV_CMD_CAMERA_TRACK_POINT
+                | MavCmd::MAV_CMD_CAMERA_TRACK_RECTANGLE
+                | MavCmd::MAV_CMD_CAMERA_STOP_TRACKING
+        ) {
+            return self.track(recv_header, &command_long).await;
+        }
+        if custom == Some(CustomCommand::StreamSettings) {
+            return self.change_video_stream(recv_header, &command_long);
+        }
+        if matches!(
+            command_long.command,
+            MavCmd::MAV_CMD_VIDEO_START_CAPTURE | MavCmd::MAV_CMD_VIDEO_STOP_CAPTURE
+        ) {
+            return self.record_video(recv_header, &command_long).await;
+        }
+
+        let captures = command_long.command == MavCmd::MAV_CMD_IMAGE_START_CAPTURE
+            || matches!(
+                custom,
+                Some(CustomCommand::FocusStack | CustomCommand::Bulb)
+            );
+        let invalid_exposure =
+            custom == Some(CustomCommand::Bulb) && bulb::exposure(command_long.param1).is_none();
+        let invalid_mode = command_long.command == MavCmd::MAV_CMD_SET_CAMERA_MODE
+            && requested_mode(&command_long).is_none();
+        let result = if is_video_stream_command(&command_long) && self.video_stream.is_none() {
+            MavResult::MAV_RESULT_UNSUPPORTED
+        } else if invalid_exposure || invalid_mode {
+            MavResult::MAV_RESULT_DENIED
+        } else if captures {
+            self.capture_admission(is_single_capture(&command_long))
+        } else {
+            MavResult::MAV_RESULT_ACCEPTED
+        };
+        send_command_ack(
+            &self.link,
+            &self.header,
+            recv_header,
+            command_long.command,
+            result,
+        )?;
+        if result != MavResult::MAV_RESULT_ACCEPTED {
+            return Ok(());
+        }
+
+        match command_long.command {
+            _ if custom == Some(CustomCommand::Bulb) => {
+                if let Some(exposure) = bulb::exposure(command_long.param1) {
+                    self.capture_shot(Shot::Bulb(exposure), None).await?;
+                }
+            }
+            _ if custom == Some(CustomCommand::FocusStack) => {
+                let mut options = self.focus_stack;
+                if command_long.param1 >= 1.0 {
+                    options.shots = command_long.param1 as u8;
+                }
+                if command_long.param2 != 0.0 {
+                    options.range = command_long.param2 as i32;
+                }
+                self.capture_focus_stack(options).await?;
+            }
+            MavCmd::MAV_CMD_REQUEST_MESSAGE if command_long.param1 == 259.0 => {
+                debug!(target: "rx", ?command_long, "Camera information requested");
+                self.read_lens().await;
+                self.send_camera_information().await?;
+            }
+            MavCmd::MAV_CMD_REQUEST_MESSAGE if command_long.param1 == 269.0 => {
+                self.send_video_stream(video::stream_information)?;
+            }
+            MavCmd::MAV_CMD_REQUEST_VIDEO_STREAM_INFORMATION => {
+                self.send_video_stream(video::stream_information)?;
+            }
+            MavCmd::MAV_CMD_REQUEST_MESSAGE if command_long.param1 == 270.0 => {
+                self.send_video_stream(video::stream_status)?;
+            }
+            MavCmd::MAV_CMD_REQUEST_VIDEO_STREAM_STATUS => {
+                self.send_video_stream(video::stream_status)?;
+            }
+            MavCmd::MAV_CMD_VIDEO_START_STREAMING => {
+                self.paused_for_heat = false;
+                self.set_streaming(true);
+            }
+            MavCmd::MAV_CMD_VIDEO_STOP_STREAMING => {
+                self.paused_for_heat = false;
+                self.set_streaming(false);
+            }
+            MavCmd::MAV_CMD_REQUEST_MESSAGE if command_long.param1 == 260.0 => {
+                self.link.send(&self.header, self.camera_settings())?;
+            }
+            MavCmd::MAV_CMD_REQUEST_CAMERA_SETTINGS => {
+                self.link.send(&self.header, self.camera_settings())?;
+            }
+            MavCmd::MAV_CMD_REQUEST_MESSAGE if command_long.param1 == 262.0 => {
+                self.link.send(&self.header, self.capture_status())?;
+            }
+            MavCmd::MAV_CMD_REQUEST_CAMERA_CAPTURE_STATUS => {
+                self.link.send(&self.header, self.capture_status())?;
+            }
+            // Param 2 is the index of the capture asked for, -1 for the last.
+            MavCmd::MAV_CMD_REQUEST_MESSAGE if command_long.param1 == 263.0 => {
+                let requested = command_long.param2 as i32;
+                match &self.last_capture {
+                    Some(last) if requested == -1 || requested == last.image_index => {
+                        self.link.send(&self.header, image_captured(last, 1))?;
+                    }
+                    _ => debug!(target: "rx", requested, "Capture isn't known anymore"),
+                }
+            }
+            MavCmd::MAV_CMD_REQUEST_MESSAGE if command_long.param1 == 261.0 => {
+                self.send_storage_information().await?;
+            }
+            MavCmd::MAV_CMD_REQUEST_STORAGE_INFORMATION => {
+                self.send_storage_information().await?;
+            }
+            MavCmd::MAV_CMD_GET_MESSAGE_INTERVAL => {
+                self.send_message_interval(command_long.param1)?;
+            }
+            // Param 2 is the id of the message whose interval is asked for.
+            MavCmd::MAV_CMD_REQUEST_MESSAGE if command_long.param1 == 244.0 => {
+                self.send_message_interval(command_long.param2)?;
+            }
+            MavCmd::MAV_CMD_REQUEST_MESSAGE if command_long.param1 == 147.0 => {
+                match with_backend(&self.backend, |backend| backend.battery_level()).await {
+                    Ok(level) => self.link.send(&self.header, battery_status(level))?,
+                    Err(error) => warn!(target: "backend", "Failed to read battery: {error}"),
+                }
+            }
+            // Param 2 is the interval and 3 the number of images, several
+            // without an interval make a burst.
+            MavCmd::MAV_CMD_IMAGE_START_CAPTURE
+                if command_long.param2 == 0.0 && command_long.param3 > 1.0 =>
+            {
+                self.capture_shot(Shot::Burst(command_long.param3 as u32), None)
+                    .await?;
+            }
+            MavCmd::MAV_CMD_IMAGE_START_CAPTURE if command_long.param2 > 0.0 => {
+                let total = (command_long.param3 >= 1.0).then_some(command_long.param3 as u32);
+                match Duration::try_from_secs_f32(command_long.param2) {
+                    Ok(interval) => {
+                        info!(target: "rx", ?interval, total, "Starting time-lapse");
+                        self.timelapse = Some(TimeLapse::new(interval, total));
+                        self.save_sequence().await;
+                    }
+                    Err(error) => warn!(target: "rx", "Invalid time-lapse interval: {error}"),
+                }
+            }
+            MavCmd::MAV_CMD_IMAGE_START_CAPTURE => self.queue_capture(),
+            MavCmd::MAV_CMD_IMAGE_STOP_CAPTURE => {
+                let running = self.timelapse.take().is_some();
+                info!(target: "rx", running, "Stopping time-lapse");
+                self.save_sequence().await;
+            }
+            MavCmd::MAV_CMD_SET_CAMERA_MODE => {
+                if let Some(mode) = requested_mode(&command_long) {
+                    info!(target: "rx", ?mode, "Setting camera mode");
+                    self.state.send_modify(|state| state.mode = mode);
+                    self.save_sequence().await;
+                    self.link.send(&self.header, self.camera_settings())?;
+                }
+            }
+            MavCmd::MAV_CMD_DO_SET_CAM_TRIGG_DIST => {
+                self.trigger.set_spacing(command_long.param1);
+                info!(target: "rx", spacing = command_long.param1, "Set trigger distance");
+                if command_long.param3 == 1.0 {
+                    self.queue_capture();
+                }
+            }
+            MavCmd::MAV_CMD_DO_TRIGGER_CONTROL => self.trigger_control(&command_long).await?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Answers a `PARAM_EXT` request to whoever sent it. A change is written
+    /// to the camera before it's acked.
+    async fn handle_parameter(
+        &mut self,
+        recv_header: &MavHeader,
+        message: MavMessage,
+    ) -> Result<()> {
+        if self.parameters.is_empty() {
+            // The camera may not have been connected at startup.
+            self.read_parameters().await;
+        }
+
+        match message {
+            MavMessage::PARAM_EXT_REQUEST_LIST(_) => {
+                debug!(target: "rx", camera = self.header.component_id, "Parameters requested");
+                for value in self.parameters.values() {
+                    self.link.send_to(&self.header, recv_header, value)?;
+                }
+            }
+            MavMessage::PARAM_EXT_REQUEST_READ(request) => {
+                match self
+                    .parameters
+                    .read_value(&request.param_id, request.param_index)
+                {
+                    Some(value) => self.link.send_to(&self.header, recv_header, value)?,
+                    None => debug!(target: "rx", index = request.param_index, "Unknown parameter"),
+                }
+            }
+            MavMessage::PARAM_EXT_SET(set) => {
+                let result = match self.parameters.change(
+                    &set.param_id,
+                    &set.param_value,
+                    set.param_type,
+                ) {
+                    Ok(change) => {
+                        let key = change.key;
+                        info!(target: "rx", key, setting = %change.setting, "Setting parameter");
+                        match self.set_parameter(&change).await {
+                            Ok(()) => {
+                                self.remember_parameter(&change).await;
+                                ParamAck::PARAM_ACK_ACCEPTED
+                            }
+                            Err(error) => {
+                                warn!(target: "backend", key, "Failed to set parameter: {error}");
+                                if let CameraError::CaptureToRam = error {
+                                    // The ack can't tell why.
+                                    self.send_status_text(
+                                        MavSeverity::MAV_SEVERITY_WARNING,
+                                        "Can't capture to RAM: shots stay on the camera",
+                                    )?;
+                                }
+                                ParamAck::PARAM_ACK_FAILED
+                            }
+                        }
+                    }
+                    Err(result) => result,
+                };
+                let ack = self.parameters.ack(&set.param_id, result);
+                self.link.send_to(&self.header, recv_header, ack)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Answers the REST API or gRPC with the current settings.
+    async fn send_settings(&mut self, settings: oneshot::Sender<Vec<(&'static str, String)>>) {
+        if self.parameters.is_empty() {
+            self.read_parameters().await;
+        }
+        // The client may have given up waiting.
+        let _ = settings.send(self.parameters.settings());
+    }
+
+    /// Changes a setting for the REST API or gRPC and tells ground stations
+    /// its new value, as they don't expect changes they didn't make.
+    async fn set_setting(
+        &mut self,
+        name: &str,
+        setting: &str,
+        result: oneshot::Sender<SettingChange>,
+    ) -> Result<()> {
+        if self.parameters.is_empty() {
+            self.read_parameters().await;
+        }
+
+        let change = match self.parameters.change_setting(name, setting) {
+            _ if !self.parameters.contains(name) => SettingChange::UnknownParameter,
+            Err(_) => SettingChange::Unsupported,
+            Ok(change) => {
+                let key = change.key;
+                info!(target: "rx", key, setting = %change.setting, "Setting parameter from the API");
+                match self.set_parameter(&change).await {
+                    Ok(()) => {
+                        self.remember_parameter(&change).await;
+                        self.link
+                            .send(&self.header, self.parameters.changed_value(&change))?;
+                        SettingChange::Changed(change.setting)
+                    }
+                    Err(error) => {
+                        warn!(target: "backend", key, "Failed to set parameter: {error}");
+                        SettingChange::Failed(error.to_string())
+                    }
+                }
+            }
+        };
+        let _ = result.send(change);
+        Ok(())
+    }
+
+    /// Keeps `change` to restore after a restart, unless it's a one-off like
+    /// driving the focus.
+    async fn remember_parameter(&mut self, change: &ParameterChange) {
+        if change.focus_drive().is_some() {
+            return;
+        }
+        self.commanded
+            .parameters
+            .insert(change.name.to_owned(), change.setting.clone());
+        self.save_sequence().await;
+    }
+
+    /// Restores what was commanded before a restart: the mode, the parameters
+    /// set and a running time-lapse, which takes the pictures it had left.
+    /// Parameters the camera doesn't offer now are kept for the next start.
+    async fn restore_commanded(&mut self) {
+        let commanded = self.commanded.clone();
+        if commanded == Commanded::default() {
+            return;
+        }
+        info!(target: "rx", ?commanded, "Restoring what was commanded before the restart");
+
+        if let Some(mode) = camera_mode(commanded.mode) {
+            self.state.send_modify(|state| state.mode = mode);
+        }
+        for (name, setting) in &commanded.parameters {
+            let Ok(change) = self.parameters.change_setting(name, setting) else {
+                warn!(target: "rx", name, setting, "Can't restore parameter");
+                continue;
+            };
+            if let Err(error) = self.set_parameter(&change).await {
+                warn!(target: "backend", name, "Failed to restore parameter: {error}");
+            }
+        }
+        let interval = commanded
+            .timelapse_interval_s
+            .and_then(|interval| Duration::try_from_secs_f32(interval).ok());
+        if let Some(interval) = interval.filter(|_| commanded.timelapse_left != Some(0)) {
+            info!(target: "rx", ?interval, left = commanded.timelapse_left, "Resuming time-lapse");
+            self.timelapse = Some(TimeLapse::new(interval, commanded.timelapse_left));
+        }
+    }
+
+    /// Reads the camera's settings for the parameters and publishes their
+    /// definition. Without a camera they stay empty until asked for again.
+    async fn read_parameters(&mut self) {
+        match with_backend(&self.backend, |backend| Parameters::read(backend)).await {
+            Ok(parameters) => {
+                self.parameters = parameters;
+                self.parameters
+                    .set_shutter_count(self.shutter_count.count());
+                self.parameters.set_autofocus(self.autofocus.enabled);
+            }
+            Err(error) => {
+                warn!(target: "backend", "Failed to read the camera settings: {error}");
+                return;
+            }
+        }
+
+        let (vendor, model) = self.names();
+        let definition = self.parameters.definition(vendor, model);
+        debug!(target: "backend", version = definition.version, "Generated camera definition");
+        self.definition.send_replace(Some(definition));
+    }
+
+    /// The legacy `MAV_CMD_DO_DIGICAM_CONTROL` ArduPilot missions and the RC
+    /// camera switch still send. Params follow `DIGICAM_CONTROL`: 2 is the
+    /// absolute zoom position and 3 a zoom step, both 0 to leave the zoom
+    /// alone, 4 unlocks (0), locks (1) or re-locks (3) the focus and 5 takes a
+    /// picture if 1.
+    ///
+    /// The lens is moved before acking so the ack tells whether it could be.
+    /// A picture the camera can't take turns down the whole command, the
+    /// lens included.
+    async fn digicam_control(
+        &mut self,
+        recv_header: &MavHeader,
+        command_long: &COMMAND_LONG_DATA,
+    ) -> Result<()> {
+        let zoom = match (command_long.param2, command_long.param3) {
+            (position, _) if position > 0.0 => Some(Zoom::Absolute(position)),
+            (_, step) if step != 0.0 => Some(Zoom::Step(step)),
+            _ => None,
+        };
+        // Most missions leave the focus param at 0, so only unlock what was
+        // locked here to not fail every shot on cameras without a focus lock.
+        let focus_lock: &'static [bool] = match command_long.param4 as u8 {
+            0 if self.focus_locked => &[false],
+            1 => &[true],
+            3 => &[false, true],
+            _ => &[],
+        };
+        let shot = command_long.param5 == 1.0;
+        if shot {
+            let admission = self.capture_admission(true);
+            if admission != MavResult::MAV_RESULT_ACCEPTED {
+                return send_command_ack(
+                    &self.link,
+                    &self.header,
+                    recv_header,
+                    command_long.command,
+                    admission,
+                );
+            }
+        }
+
+        let lens = with_backend(&self.backend, move |backend| {
+            if let Some(zoom) = zoom {
+                backend.zoom(zoom)?;
+            }
+            for &locked in focus_lock {
+                backend.set_focus_lock(locked)?;
+            }
+            Ok(())
+        })
+        .await;
+
+        let result = match lens {
+            Ok(()) => {
+                if let Some(&locked) = focus_lock.last() {
+                    self.focus_locked = locked;
+                }
+                MavResult::MAV_RESULT_ACCEPTED
+            }
+            // The picture is still worth taking without the zoom or focus.
+            Err(CameraError::Backend(error)) if error.is::<Unsupported>() => {
+                info!(target: "backend", "{error}");
+                if shot {
+                    MavResult::MAV_RESULT_ACCEPTED
+                } else {
+                    MavResult::MAV_RESULT_UNSUPPORTED
+                }
+            }
+            Err(error) => {
+                warn!(target: "backend", "Failed to move the lens: {error}");
+                MavResult::MAV_RESULT_FAILED
+            }
+        };
+        send_command_ack(
+            &self.link,
+            &self.header,
+            recv_header,
+            command_long.command,
+            result,
+        )?;
+
+        if shot {
+            self.queue_capture();
+        }
+
+        Ok(())
+    }
+
+    /// Runs the self-test for [`crate::SELF_TEST_COMMAND`], acked as
+    /// `command`. The test capture isn't counted or reported as a capture.
+    async fn self_test(&mut self, recv_header: &MavHeader, command: MavCmd) -> Result<()> {
+        send_command_ack(
+            &self.link,
+            &self.header,
+            recv_header,
+            command,
+            MavResult::MAV_RESULT_IN_PROGRESS,
+        )?;
+        info!(target: "rx", "Running self-test");
+
+        let camera = if self.check_camera().await? {
+            Ok("responding".to_owned())
+        } else {
+            Err("not responding".to_owned())
+        };
+        let capture = self.test_capture().await;
+        let storage = self.test_storage();
+
+        let mut passed = true;
+        for (check, result) in [
+            ("camera", camera),
+            ("capture", capture),
+            ("storage", storage),
+        ] {
+            if let Err(reason) = &result {
+                warn!(target: "rx", check, "Self-test failed: {reason}");
+                passed = false;
+            }
+            let (severity, text) = selftest::report(check, &result);
+            self.send_status_text(severity, &text)?;
+        }
+
+        let (severity, text, result) = if passed {
+            (
+                MavSeverity::MAV_SEVERITY_NOTICE,
+                "Camera self-test passed",
+                MavResult::MAV_RESULT_ACCEPTED,
+            )
+        } else {
+            (
+                MavSeverity::MAV_SEVERITY_CRITICAL,
+                "Camera self-test failed",
+                MavResult::MAV_RESULT_FAILED,
+            )
+        };
+        info!(target: "rx", passed, "Self-test done");
+        self.send_status_text(severity, text)?;
+        send_command_ack(&self.link, &self.header, recv_header, command, result)
+    }
+
+    /// Takes a picture and checks it downloads, then deletes it.
+    async fn test_capture(&mut self) -> CheckResult {
+        self.state.send_modify(|state| state.capturing = true);
+        let capture = with_backend(&self.backend, |backend| backend.capture_image()).await;
+        self.state.send_modify(|state| state.capturing = false);
+
+        let image = capture.map_err(|error| error.to_string())?;
+        if let Some(directory) = image.path.parent() {
+            self.image_dir = Some(directory.to_path_buf());
+        }
+        tokio::task::spawn_blocking(move || selftest::check_download(&image))
+            .await
+            .map_err(|error| error.to_string())?
+    }
+
+    /// Checks there's room for more images.
+    fn test_storage(&self) -> CheckResult {
+        let directory = self
+            .image_dir
+            .as_deref()
+            .ok_or("image directory unknown without a capture")?;
+        let available = storage::available_space(directory).map_err(|error| error.to_string())?;
+        let free = format!("{} MiB free", available / (1024 * 1024));
+
+        match self.storage.level(available) {
+            SpaceLevel::Full => Err(format!("full, {free}")),
+            SpaceLevel::Low => Ok(format!("low, {free}")),
+            SpaceLevel::Ok => Ok(free),
+        }
+    }
+
+    /// Takes the time-lapse picture that's due, ending the time-lapse after
+    /// the last one.
+    async fn timelapse_capture(&mut self) -> Result<()> {
+        self.capture_image().await?;
+        if let Some(timelapse) = &mut self.timelapse {
+            if !timelapse.captured() {
+                info!(target: "rx", "Time-lapse finished");
+                self.timelapse = None;
+            }
+            // With the picture counted, so a restart doesn't take it again.
+            self.save_sequence().await;
+        }
+        Ok(())
+    }
+
+    /// What to ack a request for pictures with: denied while the image
+    /// storage is full and, for a picture that would be `queued`, rejected
+    /// for now if the capture queue turns it down.
+    fn capture_admission(&self, queued: bool) -> MavResult {
+        if self.state.borrow().storage_full {
+            MavResult::MAV_RESULT_DENIED
+        } else if queued && self.captures.admission() == Admission::Rejected {
+            MavResult::MAV_RESULT_TEMPORARILY_REJECTED
+        } else {
+            MavResult::MAV_RESULT_ACCEPTED
+        }
+    }
+
+    /// Reports a picture that isn't taken because the image storage is full,
+    /// one of `burst` if it's part of one. Pictures still downloading are
+    /// reported first, so reports keep the order pictures were asked for in.
+    async fn refuse_capture(&mut self, burst: Option<i32>) -> Result<()> {
+        self.finish_downloads().await?;
+        let (taken, time_source) = self.vehicle.borrow().utc(Utc::now());
+        let shot = TakenShot {
+            geotag: self.vehicle.borrow().geotag(),
+            taken,
+            time_source,
+            closed_loop: false,
+            burst,
+            retries: 0,
+            settings: None,
+        };
+        self.report_capture(Err(CameraError::StorageFull), shot)
+            .await
+    }
+
+    /// Asks for a picture, taken once the capture queue gets to it.
+    fn queue_capture(&mut self) {
+        match self.captures.push() {
+            Admission::Queued => {}
+            Admission::Merged => debug!(target: "rx", "Merging the capture with the one waiting"),
+            Admission::Rejected => warn!(target: "rx", "Camera busy, turning down the capture"),
+        }
+    }
+
+    /// Takes a picture, or an exposure bracket if bracketing is on.
+    async fn capture_image(&mut self) -> Result<()> {
+        self.captures.started();
+        let Some(bracketing) = self.bracketing else {
+            return self.capture_shot(Shot::Single, None).await;
+        };
+        let Some((shots, restore)) = self.parameters.exposure_bracket(&bracketing.offsets()) else {
+            warn!(target: "rx", "Camera has no exposure compensation to bracket with, taking a single picture");
+            return self.capture_shot(Shot::Single, None).await;
+        };
+
+        self.finish_downloads().await?;
+        let burst = self.image_index;
+        info!(target: "rx", burst, shots = shots.len(), "Capturing exposure bracket");
+        for shot in &shots {
+            match self.set_parameter(shot).await {
+                Ok(()) => self.capture_shot(Shot::Single, Some(burst)).await?,
+                Err(error) => {
+                    warn!(target: "backend", setting = %shot.setting, "Skipping bracket shot: {error}");
+                }
+            }
+        }
+        if let Err(error) = self.set_parameter(&restore).await {
+            warn!(target: "backend", "Failed to restore exposure compensation: {error}");
+        }
+        Ok(())
+    }
+
+    /// Takes a focus stack, driving the focus back to where it started
+    /// afterwards. The stack stops early if the camera can't drive the focus.
+    async fn capture_focus_stack(&mut self, options: FocusStackOptions) -> Result<()> {
+        self.finish_downloads().await?;
+        let burst = self.image_index;
+        info!(target: "rx", burst, shots = options.shots, range = options.range, "Capturing focus stack");
+
+        let mut driven = 0;
+        self.capture_shot(Shot::Single, Some(burst)).await?;
+        for steps in options.steps() {
+            if let Err(error) =
+                with_backend(&self.backend, move |backend| backend.drive_focus(steps)).await
+            {
+                warn!(target: "backend", "Stopping focus stack: {error}");
+                break;
+            }
+            driven += steps;
+            self.capture_shot(Shot::Single, Some(burst)).await?;
+        }
+
+        if driven != 0 {
+            if let Err(error) =
+                with_backend(&self.backend, move |backend| backend.drive_focus(-driven)).await
+            {
+                warn!(target: "backend", "Failed to drive the focus back: {error}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a parameter change to the camera and records it if it took it.
+    async fn set_parameter(&mut self, change: &ParameterChange) -> Result<()> {
+        if change.key == "capturetarget"
+            && CaptureTarget::from_setting(&change.setting) == Some(CaptureTarget::Ram)
+            && (self.keep_on_card || self.downloads.is_some())
+        {
+            return Err(CameraError::CaptureToRam);
+        }
+        if let Some(enabled) = change.autofocus() {
+            // Kept by the component, the camera has nothing to write.
+            self.autofocus.enabled = enabled;
+            self.parameters.apply(change);
+            return Ok(());
+        }
+        if let Some(steps) = change.focus_drive() {
+            // Goes back to `Hold` once driven, so isn't applied.
+            if steps != 0 {
+                debug!(target: "backend", steps, "Driving focus for CAM_FOCUSDRV");
+                with_backend(&self.backend, move |backend| backend.drive_focus(steps)).await?;
+            }
+            return Ok(());
+        }
+        let (key, setting) = (change.key, change.setting.clone());
+        with_backend(&self.backend, move |backend| {
+            backend.set_config(key, &setting)
+        })
+        .await?;
+        self.parameters.apply(change);
+        Ok(())
+    }
+
+    /// Fires the camera and reports every picture with
+    /// `CAMERA_IMAGE_CAPTURED`. The pictures of a burst share its geotag and
+    /// time. Shots of a burst, bracket or focus stack share `burst` in the
+    /// capture log, a burst without one gets the index of its first picture.
+    ///
+    /// A shot that failed outright is taken again as `capture_retry` says
+    /// and only reported as failed once the retries are used up. Bursts that
+    /// got some of their frames aren't.
+    ///
+    /// Single pictures outside of a burst are downloaded in the background
+    /// if there's a downloader, other shots wait for its queue to empty first
+    /// so pictures keep their order, as do failed shots before they're
+    /// reported. With `keep_on_card` single pictures, also those of brackets
+    /// and focus stacks, stay on the camera.
+    async fn capture_shot(&mut self, shot: Shot, burst: Option<i32>) -> Result<()> {
+        if self.state.borrow().storage_full {
+            return self.refuse_capture(burst).await;
+        }
+        let deferred = matches!(shot, Shot::Single)
+            && (self.keep_on_card || self.downloads.is_some() && burst.is_none());
+        if !deferred {
+            self.finish_downloads().await?;
+        }
+
+        let mut retries = 0;
+        let (geotag, triggered, captures) = loop {
+            let (geotag, triggered, captures) = self.fire(shot, deferred).await;
+            match &captures[..] {
+                [Err(CameraError::Backend(error))]
+                    if retries < self.capture_retry.retries
+                        && error.downcast_ref::<Unsupported>().is_none() =>
+                {
+                    let delay = self.capture_retry.delay(retries);
+                    warn!(target: "backend", retry = retries + 1, "Capture failed, retrying in {delay:?}: {error}");
+                    self.pulse.busy_for(delay);
+                    tokio::time::sleep(delay).await;
+                    retries += 1;
+                }
+                _ => break (geotag, triggered, captures),
+            }
+        };
+
+        let fired = self
+            .shutter
+            .as_ref()
+            .and_then(|shutter| *shutter.borrow())
+            .filter(|fired| *fired >= triggered);
+        if self.shutter.is_some() && fired.is_none() {
+            warn!(target: "backend", "No hot-shoe feedback, using the trigger time");
+        }
+        let (taken, time_source) = self.vehicle.borrow().utc(fired.unwrap_or(triggered));
+
+        let burst = burst.or(matches!(shot, Shot::Burst(_)).then_some(self.image_index));
+        for capture in captures {
+            let shot = TakenShot {
+                geotag,
+                taken,
+                time_source,
+                closed_loop: fired.is_some(),
+                burst,
+                retries,
+                settings: None,
+            };
+            match capture {
+                Ok(PendingShot::OnCamera(files)) if self.keep_on_card => {
+                    debug!(target: "backend", "Leaving the picture on the camera");
+                    self.report_on_camera(files, shot).await?;
+                }
+                Ok(PendingShot::OnCamera(files)) => self.queue_download(files, shot).await,
+                Ok(PendingShot::Downloaded(image)) => self.report_capture(Ok(image), shot).await?,
+                Err(error) => {
+                    self.finish_downloads().await?;
+                    self.report_capture(Err(error), shot).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Leaves the download of the picture of `files` to the downloader.
+    async fn queue_download(&mut self, files: Vec<CameraFile>, mut shot: TakenShot) {
+        // The settings may have changed by the time it's downloaded.
+        if self.capture_log.is_some() {
+            shot.settings = Some(self.capture_settings().await);
+        }
+        if let Some(downloads) = &mut self.downloads {
+            downloads.queue(files, shot);
+        }
+        self.update_pending_downloads();
+    }
+
+    /// Reports the picture the downloader got to.
+    async fn report_download(&mut self, download: Download, shot: TakenShot) -> Result<()> {
+        self.update_pending_downloads();
+        match download {
+            Download::Done(capture) => self.report_capture(capture, shot).await,
+            Download::OnCamera(files) => {
+                warn!(target: "backend", "Download queue full, leaving the picture on the camera");
+                self.report_on_camera(files, shot).await
+            }
+        }
+    }
+
+    /// Waits for the downloader to get through its queue and reports the
+    /// pictures.
+    async fn finish_downloads(&mut self) -> Result<()> {
+        while self
+            .downloads
+            .as_ref()
+            .is_some_and(|downloads| downloads.pending() > 0)
+        {
+            let (download, shot) = downloader::next(self.downloads.as_mut()).await;
+            self.pulse.beat();
+            self.report_download(download, shot).await?;
+        }
+        Ok(())
+    }
+
+    /// Publishes how many pictures wait for their download.
+    fn update_pending_downloads(&self) {
+        let pending = self.downloads.as_ref().map_or(0, Downloader::downloading);
+        self.state
+            .send_if_modified(|state| replace(&mut state.pending_downloads, pending) != pending);
+    }
+
+    /// Takes `shot` once, returning the geotag and time it was triggered at
+    /// with the pictures. A `deferred` single picture is left on the camera
+    /// if the backend can.
+    async fn fire(
+        &mut self,
+        shot: Shot,
+        deferred: bool,
+    ) -> (Option<Geotag>, DateTime<Utc>, Vec<Result<PendingShot>>) {
+        if self.autofocus.enabled && !self.focus_locked {
+            self.autofocus().await;
+        }
+
+        // Geotag with where the vehicle was when the shutter fired, not
+        // after the slow download.
+        let geotag = self.vehicle.borrow().geotag();
+        if geotag.is_none() {
+            warn!(target: "rx", "No recent vehicle position, image won't be geotagged");
+        }
+        let triggered = Utc::now();
+
+        self.pulse.busy_for(shot.duration());
+        self.state.send_modify(|state| state.capturing = true);
+        let captures = match shot {
+            Shot::Single => vec![
+                with_backend(&self.backend, move |backend| {
+                    if deferred {
+                        backend.trigger()
+                    } else {
+                        backend.capture_image().map(PendingShot::Downloaded)
+                    }
+                })
+                .await,
+            ],
+            Shot::Burst(count) => {
+                info!(target: "rx", count, "Capturing burst");
+                match with_backend(&self.backend, move |backend| {
+                    Ok(backend.capture_burst(count))
+                })
+                .await
+                {
+                    Ok(frames) => frames
+                        .into_iter()
+                        .map(|frame| {
+                            frame
+                                .map(PendingShot::Downloaded)
+                                .map_err(CameraError::Backend)
+                        })
+                        .collect(),
+                    Err(error) => vec![Err(error)],
+                }
+            }
+            Shot::Bulb(exposure) => {
+                info!(target: "rx", ?exposure, "Capturing bulb exposure");
+                vec![
+                    with_backend(&self.backend, move |backend| {
+                        backend.capture_bulb(exposure).map(PendingShot::Downloaded)
+                    })
+                    .await,
+                ]
+            }
+        };
+        self.state.send_modify(|state| state.capturing = false);
+
+        (geotag, triggered, captures)
+    }
+
+    /// Focuses as a half-press would before a shot. The shot is taken
+    /// anyway if the camera can't focus in time.
+    async fn autofocus(&mut self) {
+        let timeout = self.autofocus.timeout;
+        self.pulse.busy_for(timeout);
+        match with_backend(&self.backend, move |backend| backend.autofocus(timeout)).await {
+            Ok(()) => debug!(target: "backend", "Focused before the shot"),
+            Err(CameraError::Backend(error)) if error.downcast_ref::<Unsupported>().is_some() => {
+                debug!(target: "backend", "Camera can't autofocus on command, capturing as focused");
+            }
+            Err(error) => warn!(target: "backend", "Autofocus failed, capturing anyway: {error}"),
+        }
+    }
+
+    /// Names, geotags, logs and reports one downloaded picture.
+    async fn report_capture(
+        &mut self,
+        mut capture: Result<CapturedImage>,
+        shot: TakenShot,
+    ) -> Result<()> {
+        // Where the image ended up below the camera's image directory.
+        let mut relative_path = None;
+        let capture_result = match &mut capture {
+            Ok(image) => {
+                relative_path = self.store_image(image, &shot, self.image_index).await?;
+                info!(target: "rx", path = %image.path.display(), companions = image.companions.len(), "Captured image");
+                1
+            }
+            Err(error) => {
+                error!(target: "rx", "Failed to capture image: {error}");
+                self.events.emit(CameraEvent::CaptureFailed {
+                    camera: self.header.component_id,
+                    seq: self.image_index,
+                    error: error.to_string(),
+                });
+                self.notify(
+                    MavSeverity::MAV_SEVERITY_ERROR,
+                    &format!("Capture failed: {error}"),
+                )?;
+                if !matches!(error, CameraError::StorageFull) {
+                    self.check_camera().await?;
+                }
+                0
+            }
+        };
+
+        let file_url = relative_path.as_deref().and_then(|path| {
+            let url = self.image_url.as_ref()?;
+            Some(format!("{url}/{}", http::encode_path(path)?))
+        });
+        if let Some(capture_log) = &self.capture_log {
+            let (path, outcome) = match &capture {
+                Ok(image) => (Some(image.path.clone()), CaptureOutcome::Ok),
+                Err(CameraError::Backend(error)) => match error.downcast_ref::<CorruptDownload>() {
+                    Some(corrupt) => (Some(corrupt.path.clone()), CaptureOutcome::Corrupt),
+                    None => (None, CaptureOutcome::Failed),
+                },
+                Err(_) => (None, CaptureOutcome::Failed),
+            };
+            let error = capture.as_ref().err().map(ToString::to_string);
+            self.log_capture(
+                capture_log.clone(),
+                &shot,
+                self.image_index,
+                path,
+                outcome,
+                error,
+            )
+            .await;
+        }
+        self.send_capture_report(file_url.unwrap_or_default(), capture_result, &shot)
+            .await
+    }
+
+    /// Names and geotags picture `seq` once downloaded and passes it on to
+    /// whatever else wants it. Returns where it ended up below the camera's
+    /// image directory.
+    async fn store_image(
+        &mut self,
+        image: &mut CapturedImage,
+        shot: &TakenShot,
+        seq: i32,
+    ) -> Result<Option<PathBuf>> {
+        let TakenShot { geotag, taken, .. } = *shot;
+        let directory = image.path.parent().map(Path::to_path_buf);
+        self.image_dir.clone_from(&directory);
+        if let Some(template) = &self.filename_template {
+            let context = NameContext {
+                flight: self.vehicle.borrow().armed_at().unwrap_or(self.started),
+                taken,
+                seq,
+                camera: self.header.component_id,
+                geotag,
+            };
+            image.path =
+                rename_capture(template.clone(), image.path.clone(), context.clone()).await;
+            for companion in &mut image.companions {
+                *companion =
+                    rename_capture(template.clone(), companion.clone(), context.clone()).await;
+            }
+        }
+
+        self.set_camera_connected(true)?;
+        if let Some(geotag) = geotag {
+            write_geotag(image.path.clone(), geotag, taken).await;
+            if let Some(footprints) = &self.footprints {
+                record_footprint(
+                    footprints.clone(),
+                    self.header.component_id,
+                    self.sensor,
+                    seq,
+                    image.path.clone(),
+                    geotag,
+                    taken,
+                )
+                .await;
+            }
+        }
+        if let Some(options) = self.thumbnails {
+            thumbnail::spawn_write(
+                image.path.clone(),
+                options,
+                self.header.component_id,
+                self.events.clone(),
+            );
+        }
+        if let Some(transmitter) = &self.transmitter {
+            transmitter.queue(image.path.clone());
+        }
+        self.check_lens(image.path.clone()).await?;
+        self.count_shutter(image.path.clone()).await?;
+        self.clean_up_storage(image.path.clone()).await;
+        self.check_storage()?;
+        self.events.emit(CameraEvent::ImageCaptured {
+            camera: self.header.component_id,
+            path: image.path.clone(),
+            seq,
+        });
+
+        Ok(directory
+            .and_then(|directory| image.path.strip_prefix(directory).ok())
+            .map(Path::to_path_buf))
+    }
+
+    /// Reports a picture left on the camera and keeps it for the offload.
+    async fn report_on_camera(&mut self, files: Vec<CameraFile>, shot: TakenShot) -> Result<()> {
+        let path = files.first().map(|file| PathBuf::from(file.path()));
+        self.set_camera_connected(true)?;
+        if let Some(capture_log) = &self.capture_log {
+            self.log_capture(
+                capture_log.clone(),
+                &shot,
+                self.image_index,
+                path,
+                CaptureOutcome::OnCamera,
+                None,
+            )
+            .await;
+        }
+        let seq = self.image_index;
+        self.send_capture_report(String::new(), 1, &shot).await?;
+        self.left_on_camera.push(LeftOnCamera { files, shot, seq });
+        Ok(())
+    }
+
+    /// Downloads the pictures left on the camera for
+    /// [`crate::OFFLOAD_COMMAND`], acked to the `requester` as the command it
+    /// sent, or for the REST API without a `requester` to ack to.
+    async fn offload(&mut self, requester: Option<(&MavHeader, MavCmd)>) -> Result<()> {
+        self.finish_downloads().await?;
+        let shots = take(&mut self.left_on_camera);
+        let total = shots.len();
+        if let Some((requester, command)) = requester {
+            send_command_progress(&self.link, &self.header, requester, command, 0)?;
+        }
+        info!(target: "rx", total, "Offloading pictures left on the camera");
+        self.send_status_text(
+            MavSeverity::MAV_SEVERITY_INFO,
+            &format!("Offloading {total} pictures"),
+        )?;
+
+        let mut failed = 0;
+        for (done, left) in shots.into_iter().enumerate() {
+            self.pulse.beat();
+            let files = left.files.clone();
+            let download = with_backend(&self.backend, move |backend| {
+                backend.download(PendingShot::OnCamera(files))
+            })
+            .await;
+            match download {
+                Ok(mut image) => {
+                    self.store_image(&mut image, &left.shot, left.seq).await?;
+                    info!(target: "rx", path = %image.path.display(), seq = left.seq, "Offloaded image");
+                    if let Some(capture_log) = &self.capture_log {
+                        self.log_capture(
+                            capture_log.clone(),
+                            &left.shot,
+                            left.seq,
+                            Some(image.path),
+                            CaptureOutcome::Ok,
+                            None,
+                        )
+                        .await;
+                    }
+                }
+                Err(error) => {
+                    warn!(target: "backend", seq = left.seq, "Failed to offload image: {error}");
+                    failed += 1;
+                    self.left_on_camera.push(left);
+                }
+            }
+            if let Some((requester, command)) = requester {
+                let progress = offload::progress(done + 1, total);
+                send_command_progress(&self.link, &self.header, requester, command, progress)?;
+            }
+        }
+
+        info!(target: "rx", total, failed, "Offload done");
+        let (severity, text) = offload::report(total, failed);
+        self.send_status_text(severity, &text)?;
+        match requester {
+            Some((requester, command)) => {
+                let result = if failed == 0 {
+                    MavResult::MAV_RESULT_ACCEPTED
+                } else {
+                    MavResult::MAV_RESULT_FAILED
+                };
+                send_command_ack(&self.link, &self.header, requester, command, result)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Sends `CAMERA_IMAGE_CAPTURED` for the picture at `file_url` and moves
+    /// on to the next image index if it was taken.
+    async fn send_capture_report(
+        &mut self,
+        file_url: String,
+        capture_result: i8,
+        shot: &TakenShot,
+    ) -> Result<()> {
+        let report = CaptureReport {
+            image_index: self.image_index,
+            time_utc: shot.taken.timestamp_micros() as u64,
+            time_boot_ms: self.time.boot_ms(),
+            file_url,
+            geotag: shot.geotag,
+        };
+        self.link
+            .send(&self.header, image_captured(&report, capture_result))?;
+
+        // Feedback such as ArduPilot's only makes sense for captures it can geotag.
+        if let (1, Some(geotag)) = (capture_result, shot.geotag) {
+            let feedback = M::capture_feedback(&CaptureFeedback {
+                time_usec: shot.taken.timestamp_micros() as u64,
+                image_index: self.image_index,
+                camera: self.header.component_id,
+                geotag,
+                closed_loop: shot.closed_loop,
+            });
+            if let Some(feedback) = feedback {
+                self.link.send_dialect(&self.header, feedback)?;
+            }
+        }
+
+        if capture_result == 1 {
+            self.image_index += 1;
+            self.last_capture = Some(report);
+            self.trigger.captured();
+            self.save_sequence().await;
+        }
+
+        Ok(())
+    }
+
+    /// `MAV_CMD_DO_TRIGGER_CONTROL` for the distance trigger: param 1 disables
+    /// (0) or enables (1) it, 2 restarts the distance count if 1 and 3 pauses
+    /// (1) or resumes (0) it. -1 leaves a setting alone. Like with
+    /// `MAV_CMD_DO_SET_CAM_TRIGG_DIST`'s "trigger once", enabling takes the
+    /// first picture right away.
+    async fn trigger_control(&mut self, command_long: &COMMAND_LONG_DATA) -> Result<()> {
+        match command_long.param3 {
+            0.0 => self.trigger.set_paused(false),
+            1.0 => self.trigger.set_paused(true),
+            _ => {}
+        }
+        if command_long.param2 == 1.0 {
+            self.trigger.reset();
+        }
+        let capture = match command_long.param1 {
+            0.0 => self.trigger.set_enabled(false),
+            1.0 => self.trigger.set_enabled(true),
+            _ => false,
+        };
+        info!(target: "rx", active = self.trigger.is_active(), "Changed distance trigger");
+
+        if capture {
+            self.queue_capture();
+        }
+        Ok(())
+    }
+
+    /// `MAV_CMD_SET_CAMERA_ZOOM` and `MAV_CMD_SET_CAMERA_FOCUS`, acked once
+    /// the lens has moved. Param 1 is the type of movement: a zoom step (0)
+    /// or a zoom position in percent of the range (2) in param 2, or focus
+    /// steps (0) in param 2, negative towards the camera. Continuous moves
+    /// and autofocus aren't supported.
+    async fn set_lens(
+        &mut self,
+        recv_header: &MavHeader,
+        command_long: &COMMAND_LONG_DATA,
+    ) -> Result<()> {
+        let value = command_long.param2;
+        let movement = match (command_long.command, command_long.param1 as u8) {
+            (MavCmd::MAV_CMD_SET_CAMERA_ZOOM, 0) => Some(LensMovement::Zoom(Zoom::Step(value))),
+            (MavCmd::MAV_CMD_SET_CAMERA_ZOOM, 2) => {
+                let share = (value / 100.0).clamp(0.0, 1.0);
+                Some(LensMovement::Zoom(Zoom::Range(share)))
+            }
+            (MavCmd::MAV_CMD_SET_CAMERA_FOCUS, 0) => Some(LensMovement::Focus(value as i32)),
+            _ => None,
+        };
+
+        let moved = match movement {
+            None => Err(CameraError::Backend(
+                Unsupported("This lens movement").into(),
+            )),
+            Some(movement) => {
+                with_backend(&self.backend, move |backend| match movement {
+                    LensMovement::Zoom(zoom) => backend.zoom(zoom),
+                    LensMovement::Focus(steps) => backend.drive_focus(steps),
+                })
+                .await
+            }
+        };
+
+        let result = match moved {
+            Ok(()) => MavResult::MAV_RESULT_ACCEPTED,
+            Err(CameraError::Backend(error)) if error.is::<Unsupported>() => {
+                info!(target: "backend", "{error}");
+                MavResult::MAV_RESULT_UNSUPPORTED
+            }
+            Err(error) => {
+                warn!(target: "backend", "Failed to move the lens: {error}");
+                MavResult::MAV_RESULT_FAILED
+            }
+        };
+        send_command_ack(
+            &self.link,
+            &self.header,
+            recv_header,
+            command_long.command,
+            result,
+        )
+    }
+
+    /// `MAV_CMD_CAMERA_TRACK_POINT` (x, y and radius in params 1 to 3),
+    /// `MAV_CMD_CAMERA_TRACK_RECTANGLE` (left, top, right and bottom in params
+    /// 1 to 4) and `MAV_CMD_CAMERA_STOP_TRACKING`, acked once the backend
+    /// follows the target or stopped. Unsupported unless the backend tracks,
+    /// denied for targets outside the image.
+    async fn track(
+        &mut self,
+        recv_header: &MavHeader,
+        command_long: &COMMAND_LONG_DATA,
+    ) -> Result<()> {
+        let command = command_long.command;
+        let target = match command {
+            MavCmd::MAV_CMD_CAMERA_TRACK_POINT => Some(TrackingTarget::Point {
+                x: command_long.param1,
+                y: command_long.param2,
+                radius: command_long.param3,
+            }),
+            MavCmd::MAV_CMD_CAMERA_TRACK_RECTANGLE => Some(TrackingTarget::Rectangle {
+                left: command_long.param1,
+                top: command_long.param2,
+                right: command_long.param3,
+                bottom: command_long.param4,
+            }),
+            _ => None,
+        };
+        if target.is_some_and(|target| !is_in_image(&target)) {
+            return send_command_ack(
+                &self.link,
+                &self.header,
+                recv_header,
+                command,
+                MavResult::MAV_RESULT_DENIED,
+            );
+        }
+
+        let tracked = with_backend(&self.backend, move |backend| match target {
+            Some(target) => backend.track(target),
+            None => backend.stop_tracking(),
+        })
+        .await;
+        let result = match tracked {
+            Ok(()) => {
+                info!(target: "backend", ?target, "Changed the tracking target");
+                MavResult::MAV_RESULT_ACCEPTED
+            }
+            Err(CameraError::Backend(error)) if error.is::<Unsupported>() => {
+                info!(target: "backend", "{error}");
+                MavResult::MAV_RESULT_UNSUPPORTED
+            }
+            Err(error) => {
+                warn!(target: "backend", "Failed to track: {error}");
+                MavResult::MAV_RESULT_FAILED
+            }
+        };
+        send_command_ack(&self.link, &self.header, recv_header, command, result)
+    }
+
+    /// `MAV_CMD_STORAGE_FORMAT` and `MAV_CMD_SET_STORAGE_USAGE` for the
+    /// storage with the id in param 1, as in `STORAGE_INFORMATION`. Param 2
+    /// is 1 to format or the usage flags, a format's param 3 is 1 to restart
+    /// the image index too. The storages are sent again once changed.
+    async fn manage_storage(
+        &mut self,
+        recv_header: &MavHeader,
+        command_long: &COMMAND_LONG_DATA,
+    ) -> Result<()> {
+        let command = command_long.command;
+        let Some(storage) = (command_long.param1 as usize).checked_sub(1) else {
+            return send_command_ack(
+                &self.link,
+                &self.header,
+                recv_header,
+                command,
+                MavResult::MAV_RESULT_DENIED,
+            );
+        };
+        let formatting = command == MavCmd::MAV_CMD_STORAGE_FORMAT;
+        let format = formatting && command_long.param2 == 1.0;
+        let restart_index = formatting && command_long.param3 == 1.0;
+        let usage = (!formatting).then(|| {
+            let flags = StorageUsageFlag::from_bits_truncate(command_long.param2 as _);
+            StorageUsage {
+                photos: flags.contains(StorageUsageFlag::STORAGE_USAGE_FLAG_PHOTO),
+                videos: flags.contains(StorageUsageFlag::STORAGE_USAGE_FLAG_VIDEO),
+            }
+        });
+
+        let changed = with_backend(&self.backend, move |backend| {
+            let mut root = None;
+            if format {
+                root = backend
+                    .storage_info()?
+                    .get(storage)
+                    .and_then(|info| info.root.clone());
+                backend.format_storage(storage)?;
+            }
+            if let Some(usage) = usage {
+                backend.set_storage_usage(storage, usage)?;
+            }
+            Ok(root)
+        })
+        .await;
+
+        let result = match changed {
+            Ok(root) => {
+                info!(target: "backend", storage, format, ?usage, "Changed camera storage");
+                if let Some(root) = root {
+                    // Formatted away, there's nothing left to offload.
+                    self.left_on_camera.retain(|left| {
+                        !left.files.iter().any(|file| file.folder.starts_with(&root))
+                    });
+                }
+                if restart_index {
+                    info!(target: "rx", "Restarting the image index");
+                    self.image_index = 0;
+                    self.last_capture = None;
+                    self.save_sequence().await;
+                }
+                MavResult::MAV_RESULT_ACCEPTED
+            }
+            Err(CameraError::Backend(error)) if error.is::<Unsupported>() => {
+                info!(target: "backend", "{error}");
+                MavResult::MAV_RESULT_UNSUPPORTED
+            }
+            Err(error) => {
+                warn!(target: "backend", storage, "Failed to change camera storage: {error}");
+                MavResult::MAV_RESULT_FAILED
+            }
+        };
+        send_command_ack(&self.link, &self.header, recv_header, command, result)?;
+        if result == MavResult::MAV_RESULT_ACCEPTED {
+            self.send_storage_information().await?;
+        }
+        Ok(())
+    }
+
+    /// `MAV_CMD_VIDEO_START_CAPTURE` and `MAV_CMD_VIDEO_STOP_CAPTURE`, acked
+    /// once the camera started or stopped recording. Starting again while
+    /// recording or with the storage full is denied, stopping while not
+    /// recording does nothing.
+    async fn record_video(
+        &mut self,
+        recv_header: &MavHeader,
+        command_long: &COMMAND_LONG_DATA,
+    ) -> Result<()> {
+        let command = command_long.command;
+        let start = command == MavCmd::MAV_CMD_VIDEO_START_CAPTURE;
+        if start && (self.recording.is_some() || self.state.borrow().storage_full) {
+            return send_command_ack(
+                &self.link,
+                &self.header,
+                recv_header,
+                command,
+                MavResult::MAV_RESULT_DENIED,
+            );
+        }
+        if !start && self.recording.is_none() {
+            return send_command_ack(
+                &self.link,
+                &self.header,
+                recv_header,
+                command,
+                MavResult::MAV_RESULT_ACCEPTED,
+            );
+        }
+
+        if start {
+            let result = match self.start_recording().await {
+                Ok(()) => MavResult::MAV_RESULT_ACCEPTED,
+                Err(CameraError::Backend(error)) if error.is::<Unsupported>() => {
+                    info!(target: "backend", "{error}");
+                    MavResult::MAV_RESULT_UNSUPPORTED
+                }
+                Err(error) => {
+                    warn!(target: "backend", "Failed to start recording: {error}");
+                    MavResult::MAV_RESULT_FAILED
+                }
+            };
+            send_command_ack(&self.link, &self.header, recv_header, command, result)?;
+        } else {
+            // Acked before the movie downloads, which takes a while.
+            let movie = self.stop_recording_movie().await;
+            let result = if movie.is_ok() {
+                MavResult::MAV_RESULT_ACCEPTED
+            } else {
+                MavResult::MAV_RESULT_FAILED
+            };
+            send_command_ack(&self.link, &self.header, recv_header, command, result)?;
+            if let Ok(Some((file, shot))) = movie {
+                self.download_movie(file, shot).await;
+            }
+        }
+        self.link.send(&self.header, self.capture_status())?;
+        Ok(())
+    }
+
+    /// Starts recording a movie, taken as shot where the vehicle is now.
+    async fn start_recording(&mut self) -> Result<()> {
+        with_backend(&self.backend, |backend| backend.start_recording()).await?;
+        let geotag = self.vehicle.borrow().geotag();
+        let (taken, time_source) = self.vehicle.borrow().utc(Utc::now());
+        let shot = TakenShot {
+            geotag,
+            taken,
+            time_source,
+            closed_loop: false,
+            burst: None,
+            retries: 0,
+            settings: None,
+        };
+        info!(target: "backend", "Started recording");
+        self.recording = Some((Instant::now(), shot));
+        self.state.send_modify(|state| state.recording = true);
+        Ok(())
+    }
+
+    /// Stops the recording, and downloads the movie if it's to be.
+    async fn stop_recording(&mut self) {
+        if let Ok(Some((file, shot))) = self.stop_recording_movie().await {
+            self.download_movie(file, shot).await;
+        }
+    }
+
+    /// Stops the recording, returning the movie's file with when and where it
+    /// was started if it's to be downloaded. The recording goes on if the
+    /// camera fails to stop.
+    async fn stop_recording_movie(&mut self) -> Result<Option<(CameraFile, TakenShot)>> {
+        let movie = with_backend(&self.backend, |backend| backend.stop_recording())
+            .await
+            .inspect_err(|error| warn!(target: "backend", "Failed to stop recording: {error}"))?;
+        let Some((started, shot)) = self.recording.take() else {
+            return Ok(None);
+        };
+        info!(target: "backend", length = ?started.elapsed(), "Stopped recording");
+        self.state.send_modify(|state| state.recording = false);
+        Ok(movie
+            .filter(|_| self.download_video)
+            .map(|file| (file, shot)))
+    }
+
+    /// Downloads the movie `file` and logs it as a capture taken as `shot`.
+    /// Movies aren't images, they're logged with seq -1.
+    async fn download_movie(&mut self, file: CameraFile, shot: TakenShot) {
+        debug!(target: "backend", name = %file.name, "Downloading movie");
+        let downloaded = with_backend(&self.backend, move |backend| {
+            backend.download(PendingShot::OnCamera(vec![file]))
+        })
+        .await;
+        let (path, outcome, error) = match downloaded {
+            Ok(movie) => {
+                info!(target: "backend", path = %movie.path.display(), "Downloaded movie");
+                (Some(movie.path), CaptureOutcome::Ok, None)
+            }
+            Err(error) => {
+                warn!(target: "backend", "Failed to download movie: {error}");
+                (None, CaptureOutcome::Failed, Some(error.to_string()))
+            }
+        };
+        if let Some(capture_log) = self.capture_log.clone() {
+            self.log_capture(capture_log, &shot, -1, path, outcome, error)
+                .await;
+        }
+    }
+
+    /// Sets the camera's clock to the vehicle's time, `None` if the autopilot
+    /// hasn't sent it yet.
+    async fn set_camera_clock(&mut self) -> Option<Result<()>> {
+        self.vehicle.borrow().time()?;
+        // Read again on the blocking pool, right before it's written.
+        let vehicle = self.vehicle.clone();
+        let result = with_backend(&self.backend, move |backend| {
+            let time = vehicle.borrow().time().unwrap_or_else(Utc::now);
+            backend.set_clock(time)
+        })
+        .await;
+        if result.is_ok() {
+            info!(target: "backend", "Set the camera clock to the vehicle's time");
+            self.clock_synced = true;
+        }
+        Some(result)
+    }
+
+    /// Sets the camera's clock for `sync_clock` once the vehicle's time is
+    /// known. Cameras that can't are left alone from then on.
+    async fn sync_camera_clock(&mut self) {
+        match self.set_camera_clock().await {
+            None | Some(Ok(())) => {}
+            Some(Err(CameraError::Backend(error))) if error.is::<Unsupported>() => {
+                info!(target: "backend", "{error}, not syncing the camera clock");
+                self.clock_synced = true;
+            }
+            Some(Err(error)) => warn!(target: "backend", "Failed to set the camera clock: {error}"),
+        }
+    }
+
+    /// Sends `CAMERA_INFORMATION` with what the camera can do and the lens
+    /// on it.
+    async fn send_camera_information(&mut self) -> Result<()> {
+        let flags = self.capability_flags().await;
+        let version = self
+            .definition_version
+            .or_else(|| Some(self.definition.borrow().as_ref()?.version));
+        let definition = version.zip(self.definition_url.as_deref());
+        let (vendor, model) = self.names();
+        let mut information = camera_information(vendor, model, self.sensor, definition);
+        if let MavMessage::CAMERA_INFORMATION(data) = &mut information {
+            data.time_boot_ms = self.time.boot_ms();
+            data.flags |= flags;
+            if let Some(lens) = &self.lens {
+                data.lens_id = lens::lens_id(&lens.model);
+                data.focal_length = lens.focal_length_mm.unwrap_or(0.0);
+            }
+        }
+        self.link.send(&self.header, information)
+    }
+
+    /// Asks the backend which camera it is, for the vendor and model that
+    /// aren't configured.
+    async fn read_model(&mut self) {
+        if self.vendor_name.is_some() && self.model_name.is_some() {
+            return;
+        }
+        match with_backend(&self.backend, |backend| backend.model()).await {
+            Ok(Some(CameraModel { vendor, model })) => {
+                debug!(target: "backend", %vendor, %model, "Read the camera model");
+                self.vendor_name.get_or_insert(vendor);
+                self.model_name.get_or_insert(model);
+            }
+            Ok(None) => {}
+            Err(error) => {
+                debug!(target: "backend", "Failed to read the camera model: {error}");
+            }
+        }
+    }
+
+    /// Asks the backend for the sensor, unless it's configured.
+    async fn read_sensor(&mut self) {
+        if self.sensor.is_some() {
+            return;
+        }
+        match with_backend(&self.backend, |backend| backend.sensor()).await {
+            Ok(sensor) => self.sensor = sensor,
+            Err(error) => debug!(target: "backend", "Failed to read the sensor: {error}"),
+        }
+    }
+
+    /// The vendor and model the camera goes by.
+    fn names(&self) -> (&str, &str) {
+        (
+            self.vendor_name.as_deref().unwrap_or(UNKNOWN),
+            self.model_name.as_deref().unwrap_or(UNKNOWN),
+        )
+    }
+
+    /// Asks the backend for the lens, if it can tell. Returns whether it was
+    /// swapped.
+    async fn read_lens(&mut self) -> bool {
+        match with_backend(&self.backend, |backend| backend.lens()).await {
+            Ok(Some(lens)) => {
+                self.camera_names_lens = true;
+                self.update_lens(lens)
+            }
+            Ok(None) => false,
+            Err(error) => {
+                debug!(target: "backend", "Failed to read the lens: {error}");
+                false
+            }
+        }
+    }
+
+    /// Takes the lens from the EXIF of `image` for backends that can't tell,
+    /// sending a fresh `CAMERA_INFORMATION` when it was swapped.
+    async fn check_lens(&mut self, image: PathBuf) -> Result<()> {
+        if self.camera_names_lens {
+            return Ok(());
+        }
+        let Ok(Some(lens)) = tokio::task::spawn_blocking(move || lens::from_exif(&image)).await
+        else {
+            return Ok(());
+        };
+
+        if self.update_lens(lens) {
+            self.send_camera_information().await?;
+        }
+        Ok(())
+    }
+
+    /// Remembers `lens`. Returns whether it replaced a different one.
+    fn update_lens(&mut self, lens: Lens) -> bool {
+        let swapped = match &self.lens {
+            Some(known) => known.model != lens.model,
+            None => {
+                info!(target: "backend", lens = lens.model, "Found lens");
+                false
+            }
+        };
+        if swapped {
+            info!(target: "backend", lens = lens.model, "Lens changed");
+        }
+
+        self.lens = Some(lens);
+        swapped
+    }
+
+    /// Asks the camera how often its shutter fired, at startup and after it
+    /// was re-attached, possibly as another body.
+    async fn read_shutter_count(&mut self) -> Result<()> {
+        match with_backend(&self.backend, |backend| backend.shutter_count()).await {
+            Ok(Some(count)) => self.shutter_count.set_from_camera(count),
+            Ok(None) => {}
+            Err(error) => debug!(target: "backend", "Failed to read the shutter count: {error}"),
+        }
+        self.update_shutter_count()
+    }
+
+    /// Counts the capture of `image`, taking the count from the camera or
+    /// the image where they tell.
+    async fn count_shutter(&mut self, image: PathBuf) -> Result<()> {
+        self.shutter_count.fired();
+        if self.shutter_count.is_from_camera() {
+            return self.read_shutter_count().await;
+        }
+
+        if let Ok(Some(count)) =
+            tokio::task::spawn_blocking(move || shutter_count::from_exif(&image)).await
+        {
+            self.shutter_count.set_from_exif(count);
+        }
+        self.update_shutter_count()
+    }
+
+    /// Publishes the shutter count and warns ground stations once it nears
+    /// the rated life.
+    fn update_shutter_count(&mut self) -> Result<()> {
+        let count = self.shutter_count.count();
+        self.state
+            .send_if_modified(|state| replace(&mut state.shutter_count, count) != count);
+        // Added along with the settings, once they could be read.
+        if !self.parameters.is_empty() {
+            self.parameters.set_shutter_count(count);
+        }
+
+        if let Some(warning) = self.shutter_count.take_warning() {
+            warn!(target: "backend", count, "{warning}");
+            self.send_status_text(MavSeverity::MAV_SEVERITY_WARNING, &warning)?;
+        }
+        Ok(())
+    }
+
+    /// The `CAMERA_INFORMATION` flags for what the camera can do beyond
+    /// taking pictures, asked from the backend so ground stations don't
+    /// offer zoom, focus or tracking controls for a camera without them.
+    async fn capability_flags(&self) -> CameraCapFlags {
+        let capabilities = with_backend(&self.backend, |backend| backend.capabilities())
+            .await
+            .unwrap_or_else(|error| {
+                warn!(target: "backend", "Failed to read the camera's capabilities: {error}");
+                Capabilities::default()
+            });
+
+        let mut flags = CameraCapFlags::empty();
+        if capabilities.zoom {
+            flags |= CameraCapFlags::CAMERA_CAP_FLAGS_HAS_BASIC_ZOOM;
+        }
+        if capabilities.focus {
+            flags |= CameraCapFlags::CAMERA_CAP_FLAGS_HAS_BASIC_FOCUS;
+        }
+        if capabilities.video {
+            flags |= CameraCapFlags::CAMERA_CAP_FLAGS_CAPTURE_VIDEO
+                | CameraCapFlags::CAMERA_CAP_FLAGS_HAS_MODES;
+        }
+        if capabilities.track_point {
+            flags |= CameraCapFlags::CAMERA_CAP_FLAGS_HAS_TRACKING_POINT;
+        }
+        if capabilities.track_rectangle {
+            flags |= CameraCapFlags::CAMERA_CAP_FLAGS_HAS_TRACKING_RECTANGLE;
+        }
+        if self.video_stream.is_some() {
+            flags |= CameraCapFlags::CAMERA_CAP_FLAGS_HAS_VIDEO_STREAM;
+        }
+        flags
+    }
+
+    /// Whether captures keep being taken without further commands.
+    fn is_capturing_unattended(&self) -> bool {
+        self.timelapse.is_some() || self.trigger.is_active()
+    }
+
+    /// Stops the time-lapse and distance triggering when the autopilot's
+    /// heartbeats have been missing for longer than `autopilot_timeout`.
+    async fn check_autopilot(&mut self) -> Result<()> {
+        let Some(timeout) = self.autopilot_timeout else {
+            return Ok(());
+        };
+        if !self.vehicle.borrow().is_lost(timeout) {
+            return Ok(());
+        }
+
+        let timelapse = self.timelapse.take().is_some();
+        self.trigger.set_enabled(false);
+        if timelapse {
+            self.save_sequence().await;
+        }
+        warn!(target: "rx", camera = self.header.component_id, timelapse, ?timeout, "Autopilot lost, stopped capturing");
+        self.send_status_text(
+            MavSeverity::MAV_SEVERITY_WARNING,
+            "Autopilot lost, camera stopped capturing",
+        )
+    }
+
+    /// Changes how often a message is streamed: param 1 is its id and param 2
+    /// the interval in µs, -1 to stop it and 0 to go back to the configured
+    /// rate. Only the heartbeat, `CAMERA_SETTINGS`, `STORAGE_INFORMATION` and
+    /// `CAMERA_CAPTURE_STATUS` are streamed, and the heartbeat can't be
+    /// stopped.
+    fn set_message_interval(
+        &mut self,
+        recv_header: &MavHeader,
+        command_long: &COMMAND_LONG_DATA,
+    ) -> Result<()> {
+        let interval_us = command_long.param2;
+        let result = match Streamed::from_id(command_long.param1 as u32) {
+            None => MavResult::MAV_RESULT_UNSUPPORTED,
+            Some(message) => {
+                let interval = if interval_us == 0.0 {
+                    Some(self.default_rates.interval(message))
+                } else if interval_us == -1.0 {
+                    Some(None)
+                } else {
+                    Duration::try_from_secs_f32(interval_us / 1e6)
+                        .ok()
+                        .filter(|interval| !interval.is_zero())
+                        .map(Some)
+                };
+
+                let mut rates = self.state.borrow().stream_rates;
+                match interval {
+                    Some(interval) if rates.set(message, interval) => {
+                        info!(target: "rx", ?message, ?interval, "Changed message interval");
+                        self.state.send_modify(|state| state.stream_rates = rates);
+                        self.streams = StreamSchedule::new(&rates);
+                        MavResult::MAV_RESULT_ACCEPTED
+                    }
+                    _ => MavResult::MAV_RESULT_DENIED,
+                }
+            }
+        };
+        send_command_ack(
+            &self.link,
+            &self.header,
+            recv_header,
+            command_long.command,
+            result,
+        )
+    }
+
+    /// Answers with `MESSAGE_INTERVAL` for the message with id `message_id`,
+    /// an interval of 0 for messages that aren't streamed.
+    fn send_message_interval(&self, message_id: f32) -> Result<()> {
+        let interval_us = Streamed::from_id(message_id as u32).map_or(0, |message| {
+            self.state.borrow().stream_rates.interval_us(message)
+        });
+        self.link.send(
+            &self.header,
+            MavMessage::MESSAGE_INTERVAL(mavlink::common::MESSAGE_INTERVAL_DATA {
+                interval_us,
+                message_id: message_id as u16,
+            }),
+        )
+    }
+
+    /// Sends a streamed message that is due.
+    async fn send_streamed(&mut self, message: Streamed) -> Result<()> {
+        match message {
+            // Sent by the heartbeat task.
+            Streamed::Heartbeat => {}
+            Streamed::CameraSettings => self.link.send(&self.header, self.camera_settings())?,
+            Streamed::StorageInformation if self.state.borrow().camera_connected => {
+                self.send_storage_information().await?;
+            }
+            Streamed::StorageInformation => {}
+            Streamed::CaptureStatus => self.link.send(&self.header, self.capture_status())?,
+        }
+        let rates = self.state.borrow().stream_rates;
+        self.streams.sent(message, &rates, self.link.slowdown());
+
+        Ok(())
+    }
+
+    /// Sends `STORAGE_INFORMATION` for every storage medium of the camera.
+    async fn send_storage_information(&self) -> Result<()> {
+        match with_backend(&self.backend, |backend| backend.storage_info()).await {
+            Ok(storages) => {
+                for message in storage_information(&storages, self.time.boot_ms()) {
+                    self.link.send(&self.header, message)?;
+                }
+            }
+            Err(error) => warn!(target: "backend", "Failed to read storage: {error}"),
+        }
+        Ok(())
+    }
+
+    /// Sends `message` built from the camera's stream, if it has one.
+    fn send_video_stream(
+        &self,
+        message: fn(&VideoStreamOptions, bool) -> MavMessage,
+    ) -> Result<()> {
+        if let Some(stream) = &self.video_stream {
+            let running = self.state.borrow().streaming;
+            self.link.send(&self.header, message(stream, running))?;
+        }
+        Ok(())
+    }
+
+    /// [`crate::STREAM_SETTINGS_COMMAND`] for the live view the component
+    /// serves, sent again in `VIDEO_STREAM_INFORMATION` once changed. Changes
+    /// the server can't make, e.g. MJPEG's bitrate, are unsupported.
+    fn change_video_stream(
+        &mut self,
+        recv_header: &MavHeader,
+        command_long: &COMMAND_LONG_DATA,
+    ) -> Result<()> {
+        let change = StreamChange::from_command(command_long);
+        let result = match (&self.live_view, change) {
+            (None, _) => MavResult::MAV_RESULT_UNSUPPORTED,
+            (Some(_), None) => MavResult::MAV_RESULT_DENIED,
+            (Some((server, _)), Some(change)) if !server.can_change(&change) => {
+                MavResult::MAV_RESULT_UNSUPPORTED
+            }
+            (Some((_, settings)), Some(change)) => {
+                info!(target: "rx", ?change, "Changing the live view");
+                settings.send_modify(|stream| change.apply(stream));
+                if let Some(stream) = &mut self.video_stream {
+                    change.apply(stream);
+                }
+                MavResult::MAV_RESULT_ACCEPTED
+            }
+        };
+        send_command_ack(
+            &self.link,
+            &self.header,
+            recv_header,
+            command_long.command,
+            result,
+        )?;
+        if result == MavResult::MAV_RESULT_ACCEPTED {
+            self.send_video_stream(video::stream_information)?;
+        }
+        Ok(())
+    }
+
+    fn set_streaming(&self, streaming: bool) {
+        info!(target: "rx", streaming, "Switching live view");
+        self.state
+            .send_if_modified(|state| replace(&mut state.streaming, streaming) != streaming);
+    }
+
+    /// Reports the body temperature with a `CAM_TEMP` `NAMED_VALUE_FLOAT` and
+    /// pauses the live view while the camera overheats, as some bodies shut
+    /// down rather than throttle. It resumes once the camera cooled down.
+    async fn check_temperature(&mut self) -> Result<()> {
+        let temperature = with_backend(&self.backend, |backend| backend.temperature())
+            .await
+            .unwrap_or_else(|error| {
+                debug!(target: "backend", "Failed to read the temperature: {error}");
+                None
+            });
+        self.state
+            .send_if_modified(|state| replace(&mut state.temperature, temperature) != temperature);
+        let Some(temperature) = temperature else {
+            return Ok(());
+        };
+        self.link.send(
+            &self.header,
+            MavMessage::NAMED_VALUE_FLOAT(NAMED_VALUE_FLOAT_DATA {
+                time_boot_ms: self.time.boot_ms(),
+                value: temperature,
+                name: str_to_fixed_arr("CAM_TEMP"),
+            }),
+        )?;
+
+        let Some(limit) = self.overheat_temperature else {
+            return Ok(());
+        };
+        if temperature >= limit && self.state.borrow().streaming {
+            warn!(target: "backend", temperature, "Camera overheating, pausing the live view");
+            self.paused_for_heat = true;
+            self.set_streaming(false);
+            self.notify(
+                MavSeverity::MAV_SEVERITY_WARNING,
+                "Camera overheating, live view paused",
+            )?;
+        } else if self.paused_for_heat && temperature <= limit - COOL_DOWN {
+            info!(target: "backend", temperature, "Camera cooled down, resuming the live view");
+            self.paused_for_heat = false;
+            self.set_streaming(true);
+            self.notify(
+                MavSeverity::MAV_SEVERITY_INFO,
+                "Camera cooled down, live view resumed",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// `CAMERA_SETTINGS` with the current mode. Zoom and focus aren't known.
+    fn camera_settings(&self) -> MavMessage {
+        MavMessage::CAMERA_SETTINGS(mavlink::common::CAMERA_SETTINGS_DATA {
+            time_boot_ms: self.time.boot_ms(),
+            mode_id: self.state.borrow().mode,
+            zoomLevel: f32::NAN,
+            focusLevel: f32::NAN,
+        })
+    }
+
+    /// `CAMERA_CAPTURE_STATUS` with the number of images taken so far and
+    /// how long the camera has been recording.
+    fn capture_status(&self) -> MavMessage {
+        const MIB: f32 = 1024.0 * 1024.0;
+
+        let available_capacity = self
+            .image_dir
+            .as_deref()
+            .and_then(|directory| storage::available_space(directory).ok())
+            .map_or(0.0, |available| available as f32 / MIB);
+        // Bit 0 is a capture in progress, bit 1 a time-lapse.
+        let image_status =
+            u8::from(self.state.borrow().capturing) | u8::from(self.timelapse.is_some()) << 1;
+        MavMessage::CAMERA_CAPTURE_STATUS(mavlink::common::CAMERA_CAPTURE_STATUS_DATA {
+            time_boot_ms: self.time.boot_ms(),
+            image_status,
+            image_interval: self
+                .timelapse
+                .as_ref()
+                .map_or(0.0, |timelapse| timelapse.interval().as_secs_f32()),
+            image_count: self.image_index,
+            available_capacity,
+            recording_time_ms: self
+                .recording
+                .as_ref()
+                .map_or(0, |(started, _)| started.elapsed().as_millis() as u32),
+            video_status: u8::from(self.recording.is_some()),
+        })
+    }
+
+    /// Writes the image counter and the last capture to the sequence file.
+    /// Failures are only logged since the capture itself succeeded.
+    async fn save_sequence(&self) {
+        let Some(sequence_file) = self.sequence_file.clone() else {
+            return;
+        };
+        let sequence = CaptureSequence {
+            image_index: self.image_index,
+            shutter_count: self.shutter_count.count(),
+            last_capture: self.last_capture.clone(),
+            commanded: Commanded {
+                mode: self.state.borrow().mode as u32,
+                timelapse_interval_s: self
+                    .timelapse
+                    .as_ref()
+                    .map(|timelapse| timelapse.interval().as_secs_f32()),
+                timelapse_left: self.timelapse.as_ref().and_then(TimeLapse::left),
+                parameters: self.commanded.parameters.clone(),
+            },
+        };
+        let result = tokio::task::spawn_blocking(move || sequence_file.save(&sequence)).await;
+
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => warn!(target: "rx", "Failed to save the image counter: {error}"),
+            Err(error) => warn!(target: "rx", "Failed to save the image counter: {error}"),
+        }
+    }
+
+    /// Deletes the images the storage options say to, except `keep`.
+    async fn clean_up_storage(&self, keep: PathBuf) {
+        let (Some(directory), storage) = (self.image_dir.clone(), self.storage) else {
+            return;
+        };
+        let result =
+            tokio::task::spawn_blocking(move || storage::clean_up(&directory, &storage, &keep))
+                .await;
+
+        match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(error)) => warn!(target: "backend", "Failed to clean up images: {error}"),
+            Err(error) => warn!(target: "backend", "Failed to clean up images: {error}"),
+        }
+    }
+
+    /// Checks the free space in the image directory, tells ground stations
+    /// with `STATUSTEXT` when it gets low or full and stops captures while
+    /// it's full.
+    fn check_storage(&mut self) -> Result<()> {
+        let Some(directory) = &self.image_dir else {
+            return Ok(());
+        };
+        let available_bytes = match storage::available_space(directory) {
+            Ok(available_bytes) => available_bytes,
+            Err(error) => {
+                debug!(target: "rx", "Can't check free space in {}: {error}", directory.display());
+                return Ok(());
+            }
+        };
+
+        let level = self.storage.level(available_bytes);
+        let previous = replace(&mut self.space, level);
+        if level > previous {
+            warn!(target: "rx", available_bytes, ?level, "Image storage is running low");
+            self.events.emit(CameraEvent::StorageLow {
+                camera: self.header.component_id,
+                available_bytes,
+            });
+            let (severity, text) = match level {
+                SpaceLevel::Full => (
+                    MavSeverity::MAV_SEVERITY_CRITICAL,
+                    "Camera storage full, not capturing".to_owned(),
+                ),
+                _ => (
+                    MavSeverity::MAV_SEVERITY_WARNING,
+                    format!(
+                        "Camera storage low: {} MiB free",
+                        available_bytes / (1024 * 1024)
+                    ),
+                ),
+            };
+            self.notify(severity, &text)?;
+        } else if level < previous {
+            info!(target: "rx", available_bytes, ?level, "Image storage has space again");
+        }
+
+        let storage_full = level == SpaceLevel::Full;
+        self.state.send_if_modified(|state| {
+            replace(&mut state.storage_full, storage_full) != storage_full
+        });
+        Ok(())
+    }
+
+    /// Feeds the vehicle's latest position to the distance trigger and returns
+    /// whether it wants a capture.
+    fn distance_reached(&mut self) -> bool {
+        let vehicle = self.vehicle.borrow_and_update();
+        vehicle
+            .position()
+            .is_some_and(|position| self.trigger.update(position.lat, position.lon))
+    }
+
+    /// Appends capture `seq` to the capture log along with the settings it
+    /// was taken with.
+    async fn log_capture(
+        &self,
+        capture_log: CaptureLog,
+        shot: &TakenShot,
+        seq: i32,
+        path: Option<PathBuf>,
+        outcome: CaptureOutcome,
+        error: Option<String>,
+    ) {
+        let settings = match (&shot.settings, outcome) {
+            (Some(settings), _) => settings.clone(),
+            (None, CaptureOutcome::Ok | CaptureOutcome::OnCamera) => self.capture_settings().await,
+            // Don't wait on a camera that just failed to capture.
+            (None, _) => Default::default(),
+        };
+
+        let record = CaptureRecord {
+            camera: self.header.component_id,
+            seq,
+            taken: shot.taken,
+            time_source: shot.time_source,
+            geotag: shot.geotag,
+            settings,
+            path,
+            outcome,
+            error,
+            burst: shot.burst,
+            retries: shot.retries,
+        };
+        let result = tokio::task::spawn_blocking(move || capture_log.record(&record)).await;
+
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => warn!(target: "rx", "Failed to write capture log: {error}"),
+            Err(error) => warn!(target: "rx", "Failed to write capture log: {error}"),
+        }
+    }
+
+    /// The exposure settings as far as the backend knows, for the capture log.
+    async fn capture_settings(&self) -> BTreeMap<String, String> {
+        with_backend(&self.backend, |backend| backend.capture_settings())
+            .await
+            .unwrap_or_else(|error| {
+                debug!(target: "backend", "Failed to read capture settings: {error}");
+                Default::default()
+            })
+    }
+
+    /// Updates whether the camera responds, logging transitions.
+    fn set_camera_connected(&mut self, connected: bool) -> Result<()> {
+        let changed = self
+            .state
+            .send_if_modified(|state| replace(&mut state.camera_connected, connected) != connected);
+        if !changed {
+            return Ok(());
+        }
+
+        let camera = self.header.component_id;
+        if connected {
+            info!(target: "backend", "Camera is responding again");
+            self.events.emit(CameraEvent::CameraReconnected { camera });
+            self.notify(MavSeverity::MAV_SEVERITY_INFO, "Camera responding again")
+        } else {
+            warn!(target: "backend", "Camera stopped responding");
+            self.events.emit(CameraEvent::CameraDisconnected { camera });
+            self.notify(MavSeverity::MAV_SEVERITY_CRITICAL, "Camera not responding")
+        }
+    }
+
+    /// Tells ground stations with `STATUSTEXT`, unless the same text went
+    /// out just now.
+    fn notify(&mut self, severity: MavSeverity, text: &str) -> Result<()> {
+        for message in self.status_texts.unless_repeated(severity, text) {
+            self.link.send(&self.header, message)?;
+        }
+        Ok(())
+    }
+
+    /// Sends `text` with `STATUSTEXT` even if it was just sent.
+    fn send_status_text(&mut self, severity: MavSeverity, text: &str) -> Result<()> {
+        for message in self.status_texts.messages(severity, text) {
+            self.link.send(&self.header, message)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the camera still responds and tries to re-attach it if not,
+    /// e.g. after it was power cycled or its USB cable was bumped. Returns
+    /// whether the camera is usable.
+    async fn check_camera(&mut self) -> Result<bool> {
+        let Err(error) = with_backend(&self.backend, |backend| backend.check_connection()).await
+        else {
+            self.set_camera_connected(true)?;
+            return Ok(true);
+        };
+
+        debug!(target: "backend", "Camera check failed: {error}");
+        self.set_camera_connected(false)?;
+
+        match with_backend(&self.backend, |backend| backend.reconnect()).await {
+            Ok(()) => {
+                self.set_camera_connected(true)?;
+                // The clock may have been reset with the battery.
+                self.clock_synced = false;
+                // A camera that wasn't there at startup is only known now.
+                let was_unknown = self.vendor_name.is_none() || self.parameters.is_empty();
+                self.read_model().await;
+                self.read_sensor().await;
+                if self.parameters.is_empty() {
+                    self.read_parameters().await;
+                }
+                // The lens may have been swapped while the camera was off.
+                if self.read_lens().await || was_unknown {
+                    self.send_camera_information().await?;
+                }
+                self.read_shutter_count().await?;
+                Ok(true)
+            }
+            Err(error) => {
+                debug!(target: "backend", "Re-attaching the camera failed: {error}");
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// `MAV_CMD_IMAGE_START_CAPTURE` for one picture, which goes through the
+/// capture queue.
+fn is_single_capture(command_long: &COMMAND_LONG_DATA) -> bool {
+    let burst = command_long.param2 == 0.0 && command_long.param3 > 1.0;
+    let timelapse = command_long.param2 > 0.0;
+    command_long.command == MavCmd::MAV_CMD_IMAGE_START_CAPTURE && !burst && !timelapse
+}
+
+/// Commands only cameras with a live-view stream support.
+fn is_video_stream_command(command_long: &COMMAND_LONG_DATA) -> bool {
+    match command_long.command {
+        MavCmd::MAV_CMD_REQUEST_MESSAGE => matches!(command_long.param1 as u32, 269 | 270),
+        MavCmd::MAV_CMD_REQUEST_VIDEO_STREAM_INFORMATION
+        | MavCmd::MAV_CMD_REQUEST_VIDEO_STREAM_STATUS
+        | MavCmd::MAV_CMD_VIDEO_START_STREAMING
+        | MavCmd::MAV_CMD_VIDEO_STOP_STREAMING => true,
+        _ => false,
+    }
+}
+
+/// The mode `MAV_CMD_SET_CAMERA_MODE` asks for in param 2, `None` for one
+/// that doesn't exist.
+fn requested_mode(command_long: &COMMAND_LONG_DATA) -> Option<CameraMode> {
+    let id = command_long.param2;
+    if id < 0.0 || id.fract() != 0.0 {
+        return None;
+    }
+    camera_mode(id as u32)
+}
+
+/// Whether `target` lies within the image, with a rectangle's corners in
+/// order.
+fn is_in_image(target: &TrackingTarget) -> bool {
+    let inside = |value: f32| (0.0..=1.0).contains(&value);
+    match *target {
+        TrackingTarget::Point { x, y, radius } => inside(x) && inside(y) && inside(radius),
+        TrackingTarget::Rectangle {
+            left,
+            top,
+            right,
+            bottom,
+        } => [left, top, right, bottom].into_iter().all(inside) && left < right && top < bottom,
+    }
+}
+
+/// What `MAV_CMD_SET_CAMERA_ZOOM` or `MAV_CMD_SET_CAMERA_FOCUS` asked for.
+#[derive(Debug, Clone, Copy)]
+enum LensMovement {
+    Zoom(Zoom),
+    /// Steps of the camera's smallest manual focus step.
+    Focus(i32),
+}
+
+pub(crate) fn send_command_ack<M: CameraDialect>(
+    link: &LinkSender<M>,
+    our_header: &MavHeader,
+    their_header: &MavHeader,
+    command: MavCmd,
+    result: MavResult,
+) -> Result<()> {
+    link.send_to(
+        our_header,
+        their_header,
+        MavMessage::COMMAND_ACK(mavlink::common::COMMAND_ACK_DATA {
+            command,
+            result,
+            target_system: their_header.system_id,
+            target_component: their_header.component_id,
+            ..Default::default()
+        }),
+    )
+}
+
+/// Acks `command` as in progress, `progress` percent done.
+pub(crate) fn send_command_progress<M: CameraDialect>(
+    link: &LinkSender<M>,
+    our_header: &MavHeader,
+    their_header: &MavHeader,
+    command: MavCmd,
+    progress: u8,
+) -> Result<()> {
+    link.send_to(
+        our_header,
+        their_header,
+        MavMessage::COMMAND_ACK(mavlink::common::COMMAND_ACK_DATA {
+            command,
+            result: MavResult::MAV_RESULT_IN_PROGRESS,
+            progress,
+            target_system: their_header.system_id,
+            target_component: their_header.component_id,
+            ..Default::default()
+        }),
+    )
+}
+
+/// Runs `operation` on the blocking pool so slow cameras don't stall the runtime.
+pub(crate) async fn with_backend<T, F>(backend: &Backend, operation: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut dyn CameraBackend) -> anyhow::Result<T> + Send + 'static,
+{
+    let backend = backend.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let mut backend = backend.lock()?;
+        operation(backend.as_mut()).map_err(CameraError::Backend)
+    })
+    .await
+    .map_err(|error| CameraError::Backend(error.into()))?
+}
+
+/// Embeds `geotag` in the downloaded image. Failures are only logged since the
+/// capture itself succeeded.
+async fn write_geotag(path: PathBuf, geotag: Geotag, taken: DateTime<Utc>) {
+    let result =
+        tokio::task::spawn_blocking(move || geotag::write_geotag(&path, &geotag, taken)).await;
+
+    match result {
+        Ok(Ok(true)) => debug!(target: "backend", "Wrote geotag"),
+        Ok(Ok(false)) => debug!(target: "backend", "Not a JPEG, skipping geotag"),
+        Ok(Err(error)) => warn!(target: "backend", "Failed to write geotag: {error:#}"),
+        Err(error) => warn!(target: "backend", "Failed to write geotag: {error}"),
+    }
+}
+
+/// Moves the downloaded image at `path` to its name from `template` and
+/// returns where it is now. It stays where it is if that fails.
+async fn rename_capture(
+    template: FilenameTemplate,
+    path: PathBuf,
+    context: NameContext,
+) -> PathBuf {
+    let original = path.clone();
+    let result = tokio::task::spawn_blocking(move || template.rename(&path, &context)).await;
+
+    match result {
+        Ok(Ok(renamed)) => {
+            debug!(target: "backend", path = %renamed.display(), "Renamed capture");
+            renamed
+        }
+        Ok(Err(error)) => {
+            warn!(target: "backend", "Failed to rename capture: {error}");
+            original
+        }
+        Err(error) => {
+            warn!(target: "backend", "Failed to rename capture: {error}");
+            original
+        }
+    }
+}
+
+/// Adds the footprint of capture `seq` of `camera` to the coverage file.
+/// Failures are only logged since the capture itself succeeded.
+async fn record_footprint(
+    footprints: FootprintLog,
+    camera: u8,
+    sensor: Option<Sensor>,
+    seq: i32,
+    path: PathBuf,
+    geotag: Geotag,
+    taken: DateTime<Utc>,
+) {
+    let result = tokio::task::spawn_blocking(move || {
+        footprints.record(camera, sensor, seq, taken, &geotag, &path)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(true)) => debug!(target: "rx", "Wrote footprint"),
+        Ok(Ok(false)) => {}
+        Ok(Err(error)) => warn!(target: "rx", "Failed to write footprint: {error}"),
+        Err(error) => warn!(target: "rx", "Failed to write footprint: {error}"),
+    }
+}
+
+/// One STORAGE_INFORMATION per storage medium, or a single one saying storage
+/// isn't supported if the backend doesn't report any.
+fn storage_information(storages: &[StorageInfo], time_boot_ms: u32) -> Vec<MavMessage> {
+    const MIB: f32 = 1024.0 * 1024.0;
+
+    if storages.is_empty() {
+        return vec![MavMessage::STORAGE_INFORMATION(
+            mavlink::common::STORAGE_INFORMATION_DATA {
+                time_boot_ms,
+                status: StorageStatus::STORAGE_STATUS_NOT_SUPPORTED,
+                ..Default::default()
+            },
+        )];
+    }
+
+    storages
+        .iter()
+        .zip(1..)
+        .map(|(storage, storage_id)| {
+            let storage_usage = storage.usage.map_or_else(StorageUsageFlag::empty, |usage| {
+                let mut flags = StorageUsageFlag::STORAGE_USAGE_FLAG_SET;
+                if usage.photos {
+                    flags |= StorageUsageFlag::STORAGE_USAGE_FLAG_PHOTO;
+                }
+                if usage.videos {
+                    flags |= StorageUsageFlag::STORAGE_USAGE_FLAG_VIDEO;
+                }
+                flags
+            });
+            MavMessage::STORAGE_INFORMATION(mavlink::common::STORAGE_INFORMATION_DATA {
+                time_boot_ms,
+                total_capacity: storage.total_bytes as f32 / MIB,
+                used_capacity: storage.total_bytes.saturating_sub(storage.available_bytes) as f32
+                    / MIB,
+                available_capacity: storage.available_bytes as f32 / MIB,
+                storage_id,
+                storage_count: storages.len() as u8,
+                status: StorageStatus::STORAGE_STATUS_READY,
+                name: str_to_fixed_arr(storage.name.as_deref().unwrap_or_default()),
+                storage_usage,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+fn battery_status(level: Option<u8>) -> MavMessage {
+    MavMessage::BATTERY_STATUS(mavlink::common::BATTERY_STATUS_DATA {
+        // Unknown values as defined by the message.
+        battery_remaining: level.map_or(-1, |level| level as i8),
+        current_battery: -1,
+        temperature: i16::MAX,
+        voltages: [u16::MAX; 10],
+        ..Default::default()
+    })
+}
+
+fn image_captured(report: &CaptureReport, capture_result: i8) -> MavMessage {
+    let geotag = report.geotag.unwrap_or_default();
+
+    MavMessage::CAMERA_IMAGE_CAPTURED(mavlink::common::CAMERA_IMAGE_CAPTURED_DATA {
+        time_utc: report.time_utc,
+        time_boot_ms: report.time_boot_ms,
+        lat: geotag.lat,
+        lon: geotag.lon,
+        alt: geotag.alt,
+        relative_alt: geotag.relative_alt,
+        q: geotag.quaternion(),
+        image_index: report.image_index,
+        capture_result,
+        file_url: string_to_uri(&report.file_url),
+        ..Default::default()
+    })
+}
@@ -0,0 +1,122 @@
+//! Downloads captures in the background, so the command task fires the next
+//! picture as soon as the camera is free instead of after the download and
+//! its reporting, and keeps answering ground stations meanwhile. The camera
+//! does one thing at a time, so a picture still waits for the file being
+//! downloaded. The queue is bounded: once it's full, pictures are still taken
+//! but stay on the camera's card, rather than the survey waiting on the
+//! downloads.
+
+use crate::backend::{CameraFile, CapturedImage, PendingShot};
+use crate::dispatcher::{with_backend, Backend};
+use crate::error::Result;
+use tokio::sync::mpsc;
+
+/// How many pictures may wait for their download.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadQueueOptions {
+    /// Most pictures taken but not downloaded yet, at least 1. Pictures
+    /// beyond stay on the camera.
+    pub max_depth: usize,
+}
+
+impl Default for DownloadQueueOptions {
+    /// Eight pictures, a few seconds of distance triggering.
+    fn default() -> Self {
+        Self { max_depth: 8 }
+    }
+}
+
+/// What became of a queued picture.
+pub(crate) enum Download {
+    Done(Result<CapturedImage>),
+    /// Left on the camera as the queue was full.
+    OnCamera(Vec<CameraFile>),
+}
+
+/// Downloads the pictures of one camera in the order they were taken, each
+/// with `T` telling how to report it. Pictures left on the camera keep their
+/// place, so they're reported in order too.
+pub(crate) struct Downloader<T> {
+    queue: mpsc::UnboundedSender<(Vec<CameraFile>, bool, T)>,
+    done: mpsc::UnboundedReceiver<(Download, T)>,
+    max_depth: usize,
+    /// Pictures queued to be downloaded that `next` didn't return yet.
+    downloading: usize,
+    /// Pictures queued that `next` didn't return yet.
+    pending: usize,
+}
+
+impl<T: Send + 'static> Downloader<T> {
+    /// Starts downloading from `backend`, which captures get in between the
+    /// downloads of two pictures.
+    pub fn spawn(backend: Backend, options: DownloadQueueOptions) -> Self {
+        let (queue, mut waiting) = mpsc::unbounded_channel::<(Vec<CameraFile>, bool, T)>();
+        let (finished, done) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some((files, download, info)) = waiting.recv().await {
+                let result = if download {
+                    Download::Done(
+                        with_backend(&backend, move |backend| {
+                            backend.download(PendingShot::OnCamera(files))
+                        })
+                        .await,
+                    )
+                } else {
+                    Download::OnCamera(files)
+                };
+                if finished.send((result, info)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            queue,
+            done,
+            max_depth: options.max_depth.max(1),
+            downloading: 0,
+            pending: 0,
+        }
+    }
+
+    /// Queues the picture of `files`, to be downloaded unless the queue is
+    /// full. Returns whether it will be.
+    pub fn queue(&mut self, files: Vec<CameraFile>, info: T) -> bool {
+        let download = self.downloading < self.max_depth;
+        if self.queue.send((files, download, info)).is_err() {
+            return false;
+        }
+        self.pending += 1;
+        self.downloading += usize::from(download);
+        download
+    }
+
+    /// Pictures waiting for their download.
+    pub fn downloading(&self) -> usize {
+        self.downloading
+    }
+
+    /// Pictures waiting for their download or to be reported.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+}
+
+/// Waits for the next queued picture, forever without a downloader or
+/// anything queued.
+pub(crate) async fn next<T>(downloader: Option<&mut Downloader<T>>) -> (Download, T) {
+    let Some(downloader) = downloader.filter(|downloader| downloader.pending > 0) else {
+        return std::future::pending().await;
+    };
+    match downloader.done.recv().await {
+        Some((download, info)) => {
+            downloader.pending -= 1;
+            if let Download::Done(_) = download {
+                downloader.downloading -= 1;
+            }
+            (download, info)
+        }
+        // The task only ends once the downloader is dropped.
+        None => std::future::pending().await,
+    }
+}
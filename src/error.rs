@@ -0,0 +1,155 @@
+//! Error type returned by the camera component.
+
+use mavlink::error::{MessageReadError, MessageWriteError};
+use std::sync::PoisonError;
+use thiserror::Error;
+
+pub type Result<T, E = CameraError> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum CameraError {
+    /// Opening the MAVLink connection failed.
+    #[error("failed to connect to {address}: {source}")]
+    Connection {
+        address: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The component was started without any MAVLink endpoint.
+    #[error("no MAVLink endpoints configured")]
+    NoEndpoints,
+
+    /// The component was started without any camera.
+    #[error("no cameras configured")]
+    NoCameras,
+
+    /// The cameras were given different system ids, when they're all on the
+    /// vehicle whose autopilot they follow.
+    #[error("cameras with system ids {0} and {1}, they have to share the vehicle's")]
+    MixedSystemIds(u8, u8),
+
+    /// The MAVLink IO task has stopped and can't send anymore.
+    #[error("the MAVLink link has shut down")]
+    LinkClosed,
+
+    /// Another component on the link already uses the MAVLink ids of a
+    /// camera, see [`crate::IdConflictCheck`].
+    #[error("component {component} of system {system} is already on the link")]
+    IdConflict { system: u8, component: u8 },
+
+    /// A command id is given to two of the component's own commands, or an
+    /// application handler was registered for one of them, see
+    /// [`crate::CustomCommands`].
+    #[error("command {0} is already one of the component's own commands")]
+    CommandTaken(u32),
+
+    /// The component's worker tasks have exited.
+    #[error("the camera component has stopped")]
+    Stopped,
+
+    /// A MAVLink message could not be received or decoded.
+    #[error("failed to receive MAVLink message: {0}")]
+    Receive(#[from] MessageReadError),
+
+    /// A MAVLink message could not be sent.
+    #[error("failed to send MAVLink message: {0}")]
+    Send(#[from] MessageWriteError),
+
+    /// The camera backend reported an error.
+    #[error("camera backend error: {0:#}")]
+    Backend(anyhow::Error),
+
+    /// The telemetry log could not be opened.
+    #[error("failed to open telemetry log {path}: {source}")]
+    Tlog {
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The raw frame capture could not be created.
+    #[error("failed to create frame capture {path}: {source}")]
+    Pcap {
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The capture log could not be opened.
+    #[error("failed to open capture log {path}: {source}")]
+    CaptureLog {
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The footprint coverage file could not be opened.
+    #[error("failed to open footprint file {path}: {source}")]
+    Footprint {
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The live-view stream could not be started.
+    #[error("failed to start live view: {0:#}")]
+    LiveView(anyhow::Error),
+
+    /// The HTTP server could not listen on its port.
+    #[error("failed to start HTTP server on port {port}: {source}")]
+    HttpServer {
+        port: u16,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The gRPC server could not listen on its port.
+    #[cfg(feature = "grpc")]
+    #[error("failed to start gRPC server on port {port}: {source}")]
+    GrpcServer {
+        port: u16,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The gRPC server stopped serving.
+    #[cfg(feature = "grpc")]
+    #[error("gRPC server failed: {0}")]
+    Grpc(#[from] tonic::transport::Error),
+
+    /// The ROS 2 node could not be created or failed.
+    #[cfg(feature = "ros2")]
+    #[error("ROS 2 error: {0}")]
+    Ros2(#[from] r2r::Error),
+
+    /// The hot-shoe GPIO could not be set up.
+    #[error("failed to set up hot-shoe GPIO {gpio}: {source}")]
+    HotShoe {
+        gpio: u32,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// Shots were to be captured to the camera's RAM while they're left on
+    /// the camera to be downloaded later, where they'd be lost.
+    #[error("shots can't be captured to RAM while they're kept on the camera or downloaded in the background")]
+    CaptureToRam,
+
+    /// A picture was asked for while the image storage is full.
+    #[error("image storage is full")]
+    StorageFull,
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A thread panicked while holding shared component state.
+    #[error("component state was poisoned by a panicked thread")]
+    LockPoisoned,
+}
+
+impl<T> From<PoisonError<T>> for CameraError {
+    fn from(_: PoisonError<T>) -> Self {
+        CameraError::LockPoisoned
+    }
+}
@@ -0,0 +1,161 @@
+//! Events published by a running component.
+
+use crate::capture_log::json_string;
+use mavlink::common::MavCmd;
+use std::path::{Path, PathBuf};
+use tokio::sync::broadcast;
+use tracing::trace;
+
+const EVENT_QUEUE: usize = 64;
+
+/// Something the component did or noticed, see [`crate::MavLinkCameraHandle::subscribe`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum CameraEvent {
+    /// A `COMMAND_LONG` addressed to the camera arrived.
+    CommandReceived {
+        command: MavCmd,
+        from_system: u8,
+        from_component: u8,
+    },
+    /// Camera `camera` (its component id) took and downloaded a photo.
+    ImageCaptured { camera: u8, path: PathBuf, seq: i32 },
+    /// The thumbnail of `image` was written to `thumbnail`.
+    PreviewReady {
+        camera: u8,
+        image: PathBuf,
+        thumbnail: PathBuf,
+    },
+    /// Taking photo `seq` failed.
+    CaptureFailed { camera: u8, seq: i32, error: String },
+    /// The image directory of `camera` is running out of space.
+    StorageLow { camera: u8, available_bytes: u64 },
+    /// Camera `camera` stopped responding, e.g. it was switched off or unplugged.
+    CameraDisconnected { camera: u8 },
+    /// Camera `camera` was re-attached after a [`CameraEvent::CameraDisconnected`].
+    CameraReconnected { camera: u8 },
+    /// The link to one MAVLink endpoint went down and is being re-established.
+    ConnectionLost { address: String, error: String },
+    /// The MAVLink link is back up after a [`CameraEvent::ConnectionLost`].
+    Reconnected { address: String },
+    /// Internal `task` of camera `camera`, or of all of them when `None`,
+    /// stopped making progress, see [`crate::WatchdogOptions`].
+    TaskStalled {
+        task: &'static str,
+        camera: Option<u8>,
+    },
+    /// `task` makes progress again after a [`CameraEvent::TaskStalled`].
+    TaskRecovered {
+        task: &'static str,
+        camera: Option<u8>,
+    },
+}
+
+impl CameraEvent {
+    /// The camera the event is about, `None` for the whole component.
+    #[cfg(feature = "mqtt")]
+    pub(crate) fn camera(&self) -> Option<u8> {
+        match self {
+            Self::ImageCaptured { camera, .. }
+            | Self::PreviewReady { camera, .. }
+            | Self::CaptureFailed { camera, .. }
+            | Self::StorageLow { camera, .. }
+            | Self::CameraDisconnected { camera }
+            | Self::CameraReconnected { camera } => Some(*camera),
+            Self::TaskStalled { camera, .. } | Self::TaskRecovered { camera, .. } => *camera,
+            Self::CommandReceived { .. }
+            | Self::ConnectionLost { .. }
+            | Self::Reconnected { .. } => None,
+        }
+    }
+
+    /// The event as a JSON object with its kind in `type`. `url` gives
+    /// where an image of a camera is served from a route, if it is.
+    pub(crate) fn json(&self, url: impl Fn(&str, u8, &Path) -> Option<String>) -> String {
+        let url_or_null =
+            |url: Option<String>| url.as_deref().map_or("null".to_owned(), json_string);
+        let path = |path: &Path| json_string(&path.display().to_string());
+        let camera_or_null =
+            |camera: &Option<u8>| camera.map_or("null".to_owned(), |camera| camera.to_string());
+
+        match self {
+            CameraEvent::CommandReceived {
+                command,
+                from_system,
+                from_component,
+            } => format!(
+                r#"{{"type":"command_received","command":{},"from_system":{from_system},"from_component":{from_component}}}"#,
+                json_string(&format!("{command:?}"))
+            ),
+            CameraEvent::ImageCaptured {
+                camera,
+                path: image,
+                seq,
+            } => format!(
+                r#"{{"type":"image_captured","camera":{camera},"seq":{seq},"path":{},"url":{}}}"#,
+                path(image),
+                url_or_null(url("images", *camera, image))
+            ),
+            CameraEvent::PreviewReady { camera, image, .. } => format!(
+                r#"{{"type":"preview_ready","camera":{camera},"path":{},"url":{}}}"#,
+                path(image),
+                url_or_null(url("thumbnails", *camera, image))
+            ),
+            CameraEvent::CaptureFailed { camera, seq, error } => format!(
+                r#"{{"type":"capture_failed","camera":{camera},"seq":{seq},"error":{}}}"#,
+                json_string(error)
+            ),
+            CameraEvent::StorageLow {
+                camera,
+                available_bytes,
+            } => format!(
+                r#"{{"type":"storage_low","camera":{camera},"available_bytes":{available_bytes}}}"#
+            ),
+            CameraEvent::CameraDisconnected { camera } => {
+                format!(r#"{{"type":"camera_disconnected","camera":{camera}}}"#)
+            }
+            CameraEvent::CameraReconnected { camera } => {
+                format!(r#"{{"type":"camera_reconnected","camera":{camera}}}"#)
+            }
+            CameraEvent::ConnectionLost { address, error } => format!(
+                r#"{{"type":"connection_lost","address":{},"error":{}}}"#,
+                json_string(address),
+                json_string(error)
+            ),
+            CameraEvent::Reconnected { address } => format!(
+                r#"{{"type":"reconnected","address":{}}}"#,
+                json_string(address)
+            ),
+            CameraEvent::TaskStalled { task, camera } => format!(
+                r#"{{"type":"task_stalled","task":{},"camera":{}}}"#,
+                json_string(task),
+                camera_or_null(camera)
+            ),
+            CameraEvent::TaskRecovered { task, camera } => format!(
+                r#"{{"type":"task_recovered","task":{},"camera":{}}}"#,
+                json_string(task),
+                camera_or_null(camera)
+            ),
+        }
+    }
+}
+
+/// Publishes [`CameraEvent`]s to every current subscriber.
+#[derive(Clone)]
+pub(crate) struct EventSender(broadcast::Sender<CameraEvent>);
+
+impl EventSender {
+    pub fn new() -> Self {
+        Self(broadcast::channel(EVENT_QUEUE).0)
+    }
+
+    pub fn emit(&self, event: CameraEvent) {
+        trace!(?event, "Event");
+        // Nobody listening is fine.
+        let _ = self.0.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<CameraEvent> {
+        self.0.subscribe()
+    }
+}
@@ -0,0 +1,40 @@
+//! Focus stacking for close-range inspection: one command takes a series of
+//! shots with the manual focus driven a little further between them, to be
+//! merged into one image that's sharp throughout.
+
+use mavlink::common::MavCmd;
+
+/// Starts a focus stack. Param 1 is the number of shots and param 2 the range
+/// in focus steps, 0 for the configured ones.
+pub const FOCUS_STACK_COMMAND: MavCmd = MavCmd::MAV_CMD_USER_1;
+
+/// Number of shots and how far the focus moves over them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusStackOptions {
+    pub shots: u8,
+    /// Manual focus steps from the first shot to the last, starting at the
+    /// current focus. Positive moves away from the camera.
+    pub range: i32,
+}
+
+impl Default for FocusStackOptions {
+    /// Five shots over 40 of the camera's smallest focus steps.
+    fn default() -> Self {
+        Self {
+            shots: 5,
+            range: 40,
+        }
+    }
+}
+
+impl FocusStackOptions {
+    /// Focus steps to drive before every shot after the first, spreading the
+    /// range as evenly as whole steps allow.
+    pub(crate) fn steps(&self) -> Vec<i32> {
+        let intervals = i64::from(self.shots.saturating_sub(1));
+        let position = |shot: i64| (i64::from(self.range) * shot / intervals) as i32;
+        (1..=intervals)
+            .map(|shot| position(shot) - position(shot - 1))
+            .collect()
+    }
+}
@@ -0,0 +1,209 @@
+//! Ground footprints of the captures as a GeoJSON coverage file, so surveyors
+//! can check the overlap in e.g. QGIS before leaving the site.
+//!
+//! Footprints are projected from the vehicle position and attitude onto flat
+//! ground at the home altitude, assuming the camera looks straight down with
+//! the top of the image towards the nose. A gimbal or sloped terrain makes
+//! them approximate.
+
+use crate::backend::Sensor;
+use crate::capture_log::json_string;
+use crate::error::{CameraError, Result};
+use crate::geotag::Geotag;
+use crate::lens::exif_focal_length;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info};
+
+/// Mean earth radius in metres.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+const HEADER: &str = "{\"type\":\"FeatureCollection\",\"features\":[\n";
+
+/// Ends the file after the last feature and is replaced by the next one.
+const TRAILER: &str = "]}\n";
+
+/// Corner rays closer to the horizon than this, as the downward share of the
+/// ray, don't hit the ground anywhere useful.
+const MIN_DOWNWARD: f64 = 0.05;
+
+/// Where to write the footprints and the optics to compute them with.
+#[derive(Debug, Clone)]
+pub struct FootprintOptions {
+    /// The GeoJSON file, added to if it already exists.
+    pub path: PathBuf,
+    /// Sensor width and height in mm, the long side of the image first, for
+    /// cameras whose sensor isn't configured or told by the camera.
+    pub sensor_size_mm: Option<(f32, f32)>,
+    /// Focal length in mm, used for images without one in their EXIF.
+    pub focal_length_mm: f32,
+}
+
+impl FootprintOptions {
+    /// Footprints of the cameras' own sensors, behind a 35 mm lens.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            sensor_size_mm: None,
+            focal_length_mm: 35.0,
+        }
+    }
+}
+
+/// The open coverage file, shared by all cameras.
+#[derive(Clone)]
+pub(crate) struct FootprintLog {
+    options: Arc<FootprintOptions>,
+    /// The file and whether it has any features yet.
+    file: Arc<Mutex<(File, bool)>>,
+}
+
+impl FootprintLog {
+    /// Opens the coverage file, starting an empty feature collection in a new
+    /// one.
+    pub fn open(options: FootprintOptions) -> Result<Self> {
+        let open = || -> io::Result<(File, bool)> {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&options.path)?;
+            let len = file.metadata()?.len();
+            if len == 0 {
+                file.write_all(HEADER.as_bytes())?;
+                file.write_all(TRAILER.as_bytes())?;
+            } else if len < (HEADER.len() + TRAILER.len()) as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a coverage file written by this component",
+                ));
+            }
+            let has_features = len > (HEADER.len() + TRAILER.len()) as u64;
+            Ok((file, has_features))
+        };
+        let file = open().map_err(|source| CameraError::Footprint {
+            path: options.path.clone(),
+            source,
+        })?;
+        info!(target: "rx", path = %options.path.display(), "Writing capture footprints");
+
+        Ok(Self {
+            options: Arc::new(options),
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Adds the footprint of the image at `image` taken with the `sensor`,
+    /// keeping the file valid GeoJSON after every capture. Returns `false` if
+    /// the footprint can't be computed, e.g. without attitude, with the camera
+    /// above the horizon or with no sensor size to go by. Blocks on the disk.
+    pub fn record(
+        &self,
+        camera: u8,
+        sensor: Option<Sensor>,
+        seq: i32,
+        taken: DateTime<Utc>,
+        geotag: &Geotag,
+        image: &Path,
+    ) -> Result<bool> {
+        let Some(sensor_size_mm) = sensor
+            .map(|sensor| sensor.size_mm)
+            .or(self.options.sensor_size_mm)
+        else {
+            debug!(target: "rx", camera, "Sensor size unknown, skipping footprint");
+            return Ok(false);
+        };
+        let focal_length_mm = exif_focal_length(image).unwrap_or(self.options.focal_length_mm);
+        let Some(corners) = footprint(geotag, sensor_size_mm, focal_length_mm) else {
+            return Ok(false);
+        };
+
+        let ring = corners
+            .iter()
+            .chain(corners.first())
+            .map(|(lon, lat)| format!("[{lon:.7},{lat:.7}]"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let feature = format!(
+            concat!(
+                r#"{{"type":"Feature","geometry":{{"type":"Polygon","coordinates":[[{}]]}},"#,
+                r#""properties":{{"camera":{},"seq":{},"time_utc":"{}","path":{}}}}}"#,
+            ),
+            ring,
+            camera,
+            seq,
+            taken.to_rfc3339_opts(SecondsFormat::Millis, true),
+            json_string(&image.display().to_string()),
+        );
+
+        let mut guard = self.file.lock()?;
+        let (file, has_features) = &mut *guard;
+        // Replace the trailer so the collection stays closed after each write.
+        file.seek(SeekFrom::End(-(TRAILER.len() as i64)))?;
+        let separator = if *has_features { "," } else { "" };
+        file.write_all(format!("{separator}{feature}\n{TRAILER}").as_bytes())?;
+        *has_features = true;
+
+        Ok(true)
+    }
+}
+
+/// The image corners on the ground as longitude, latitude in degrees,
+/// counter-clockwise starting at the front left.
+fn footprint(
+    geotag: &Geotag,
+    (sensor_width_mm, sensor_height_mm): (f32, f32),
+    focal_length_mm: f32,
+) -> Option<[(f64, f64); 4]> {
+    let [roll, pitch, yaw] = geotag.attitude?.map(f64::from);
+    let height = f64::from(geotag.relative_alt) / 1000.0;
+    if height <= 0.0 {
+        debug!(target: "rx", "Vehicle on the ground, skipping footprint");
+        return None;
+    }
+
+    let forward = f64::from(sensor_height_mm) / 2.0;
+    let right = f64::from(sensor_width_mm) / 2.0;
+    let down = f64::from(focal_length_mm);
+    let lat = f64::from(geotag.lat) / 1e7;
+    let lon = f64::from(geotag.lon) / 1e7;
+
+    let mut corners = [(0.0, 0.0); 4];
+    for (corner, (x, y)) in corners.iter_mut().zip([
+        (forward, -right),
+        (-forward, -right),
+        (-forward, right),
+        (forward, right),
+    ]) {
+        let [north, east, d] = body_to_ned([x, y, down], roll, pitch, yaw);
+        if d < MIN_DOWNWARD * x.hypot(y).hypot(down) {
+            debug!(target: "rx", "Camera sees the horizon, skipping footprint");
+            return None;
+        }
+
+        let scale = height / d;
+        let corner_lat = lat + (north * scale / EARTH_RADIUS_M).to_degrees();
+        let corner_lon =
+            lon + (east * scale / (EARTH_RADIUS_M * lat.to_radians().cos())).to_degrees();
+        *corner = (corner_lon, corner_lat);
+    }
+
+    Some(corners)
+}
+
+/// Rotates a vector from the vehicle's body frame into north, east, down.
+fn body_to_ned([x, y, z]: [f64; 3], roll: f64, pitch: f64, yaw: f64) -> [f64; 3] {
+    let (sin_roll, cos_roll) = roll.sin_cos();
+    let (sin_pitch, cos_pitch) = pitch.sin_cos();
+    let (sin_yaw, cos_yaw) = yaw.sin_cos();
+
+    let (y, z) = (y * cos_roll - z * sin_roll, y * sin_roll + z * cos_roll);
+    let (x, z) = (x * cos_pitch + z * sin_pitch, z * cos_pitch - x * sin_pitch);
+    let (x, y) = (x * cos_yaw - y * sin_yaw, x * sin_yaw + y * cos_yaw);
+
+    [x, y, z]
+}
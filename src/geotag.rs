@@ -0,0 +1,254 @@
+//! Geotags for captures, reported over MAVLink and written into the images so
+//! they can go straight into photogrammetry tools.
+
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use exif::experimental::Writer;
+use exif::{Context, Field, In, Rational, Tag, Value};
+use img_parts::jpeg::{markers, Jpeg, JpegSegment};
+use img_parts::{Bytes, ImageEXIF};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::Path;
+
+const EXIF_PREFIX: &[u8] = b"Exif\0\0";
+const XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Where the vehicle was when an image was taken.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Geotag {
+    /// Latitude in degrees * 1E7.
+    pub lat: i32,
+    /// Longitude in degrees * 1E7.
+    pub lon: i32,
+    /// Altitude above mean sea level in mm.
+    pub alt: i32,
+    /// Altitude above home in mm.
+    pub relative_alt: i32,
+    /// Vehicle roll, pitch and yaw in radians, if known.
+    pub attitude: Option<[f32; 3]>,
+}
+
+impl Geotag {
+    /// The attitude as a `w, x, y, z` quaternion, all zero if unknown.
+    pub fn quaternion(&self) -> [f32; 4] {
+        let Some([roll, pitch, yaw]) = self.attitude else {
+            return [0.0; 4];
+        };
+
+        let (sin_roll, cos_roll) = (roll / 2.0).sin_cos();
+        let (sin_pitch, cos_pitch) = (pitch / 2.0).sin_cos();
+        let (sin_yaw, cos_yaw) = (yaw / 2.0).sin_cos();
+
+        [
+            cos_roll * cos_pitch * cos_yaw + sin_roll * sin_pitch * sin_yaw,
+            sin_roll * cos_pitch * cos_yaw - cos_roll * sin_pitch * sin_yaw,
+            cos_roll * sin_pitch * cos_yaw + sin_roll * cos_pitch * sin_yaw,
+            cos_roll * cos_pitch * sin_yaw - sin_roll * sin_pitch * cos_yaw,
+        ]
+    }
+}
+
+/// Writes `geotag` and the capture time into the EXIF GPS tags of the JPEG at
+/// `path`, replacing any the camera wrote and keeping everything else.
+///
+/// The vehicle attitude goes into XMP using the `drone-dji` flight attitude
+/// tags most processing tools understand, unless the image already has XMP.
+/// Returns `false` without touching the file if it isn't a JPEG, e.g. a RAW.
+pub(crate) fn write_geotag(path: &Path, geotag: &Geotag, taken: DateTime<Utc>) -> Result<bool> {
+    let is_jpeg = path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("jpg") || extension.eq_ignore_ascii_case("jpeg")
+    });
+    if !is_jpeg {
+        return Ok(false);
+    }
+
+    let data = std::fs::read(path)?;
+    let mut jpeg = Jpeg::from_bytes(Bytes::from(data))?;
+
+    let exif = jpeg
+        .exif()
+        .map(|exif| exif::Reader::new().read_raw(exif.to_vec()))
+        .transpose()
+        .context("Failed to parse the existing EXIF")?;
+    let gps = gps_fields(geotag, taken);
+
+    let mut writer = Writer::new();
+    if let Some(exif) = &exif {
+        for field in exif.fields() {
+            if field.tag.context() != Context::Gps {
+                writer.push_field(field);
+            }
+        }
+        if let Some(thumbnail) = thumbnail(exif) {
+            writer.set_jpeg(thumbnail, In::THUMBNAIL);
+        }
+    }
+    for field in &gps {
+        writer.push_field(field);
+    }
+
+    let mut tiff = Cursor::new(Vec::new());
+    let little_endian = exif.as_ref().is_some_and(|exif| exif.little_endian());
+    writer.write(&mut tiff, little_endian)?;
+
+    let has_xmp = jpeg
+        .segments()
+        .iter()
+        .any(|segment| is_app1(segment, XMP_PREFIX));
+
+    // EXIF has to come right after JFIF, which some readers insist on.
+    let segments = jpeg.segments_mut();
+    segments.retain(|segment| !is_app1(segment, EXIF_PREFIX));
+    let position = segments
+        .iter()
+        .take_while(|segment| segment.marker() == markers::APP0)
+        .count();
+    segments.insert(
+        position,
+        JpegSegment::new_with_contents(
+            markers::APP1,
+            [EXIF_PREFIX, &tiff.into_inner()].concat().into(),
+        ),
+    );
+    if let (false, Some(attitude)) = (has_xmp, geotag.attitude) {
+        segments.insert(
+            position + 1,
+            JpegSegment::new_with_contents(
+                markers::APP1,
+                [XMP_PREFIX, attitude_xmp(attitude).as_bytes()]
+                    .concat()
+                    .into(),
+            ),
+        );
+    }
+
+    // Write next to the original and swap it in, so a crash can't lose the image.
+    let partial = path.with_extension("partial");
+    jpeg.encoder().write_to(std::fs::File::create(&partial)?)?;
+    std::fs::rename(&partial, path)?;
+    Ok(true)
+}
+
+fn is_app1(segment: &JpegSegment, prefix: &[u8]) -> bool {
+    segment.marker() == markers::APP1 && segment.contents().starts_with(prefix)
+}
+
+/// The embedded preview, which the writer needs separately from its tags.
+fn thumbnail(exif: &exif::Exif) -> Option<&[u8]> {
+    let offset = exif
+        .get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let len = exif
+        .get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+
+    exif.buf().get(offset..offset.checked_add(len)?)
+}
+
+fn gps_fields(geotag: &Geotag, taken: DateTime<Utc>) -> Vec<Field> {
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let ascii = |text: &str| Value::Ascii(vec![text.as_bytes().to_vec()]);
+    let rational = |num, denom| Rational { num, denom };
+
+    let mut fields = vec![
+        field(Tag::GPSVersionID, Value::Byte(vec![2, 3, 0, 0])),
+        field(
+            Tag::GPSLatitudeRef,
+            ascii(if geotag.lat < 0 { "S" } else { "N" }),
+        ),
+        field(Tag::GPSLatitude, degrees(geotag.lat)),
+        field(
+            Tag::GPSLongitudeRef,
+            ascii(if geotag.lon < 0 { "W" } else { "E" }),
+        ),
+        field(Tag::GPSLongitude, degrees(geotag.lon)),
+        field(
+            Tag::GPSAltitudeRef,
+            Value::Byte(vec![u8::from(geotag.alt < 0)]),
+        ),
+        field(
+            Tag::GPSAltitude,
+            Value::Rational(vec![rational(geotag.alt.unsigned_abs(), 1000)]),
+        ),
+        field(
+            Tag::GPSTimeStamp,
+            Value::Rational(vec![
+                rational(taken.hour(), 1),
+                rational(taken.minute(), 1),
+                rational(
+                    taken.second() * 1000 + taken.timestamp_subsec_millis(),
+                    1000,
+                ),
+            ]),
+        ),
+        field(
+            Tag::GPSDateStamp,
+            ascii(&format!(
+                "{:04}:{:02}:{:02}",
+                taken.year(),
+                taken.month(),
+                taken.day()
+            )),
+        ),
+        field(Tag::GPSMapDatum, ascii("WGS-84")),
+    ];
+
+    if let Some([_, _, yaw]) = geotag.attitude {
+        let heading = yaw.to_degrees().rem_euclid(360.0);
+        fields.push(field(Tag::GPSImgDirectionRef, ascii("T")));
+        fields.push(field(
+            Tag::GPSImgDirection,
+            Value::Rational(vec![rational((heading * 100.0) as u32, 100)]),
+        ));
+    }
+
+    fields
+}
+
+/// Degrees * 1E7 as the degrees, minutes and seconds EXIF wants.
+fn degrees(value: i32) -> Value {
+    const SCALE: u32 = 10_000_000;
+
+    let value = value.unsigned_abs();
+    let minutes = value % SCALE * 60;
+    let seconds = minutes % SCALE * 60;
+
+    Value::Rational(vec![
+        Rational {
+            num: value / SCALE,
+            denom: 1,
+        },
+        Rational {
+            num: minutes / SCALE,
+            denom: 1,
+        },
+        Rational {
+            num: seconds / 1000,
+            denom: SCALE / 1000,
+        },
+    ])
+}
+
+fn attitude_xmp([roll, pitch, yaw]: [f32; 3]) -> String {
+    format!(
+        concat!(
+            r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">"#,
+            r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#,
+            r#"<rdf:Description rdf:about="" xmlns:drone-dji="http://www.dji.com/drone-dji/1.0/""#,
+            r#" drone-dji:FlightRollDegree="{:.2}""#,
+            r#" drone-dji:FlightPitchDegree="{:.2}""#,
+            r#" drone-dji:FlightYawDegree="{:.2}"/>"#,
+            r#"</rdf:RDF></x:xmpmeta>"#,
+        ),
+        roll.to_degrees(),
+        pitch.to_degrees(),
+        yaw.to_degrees()
+    )
+}
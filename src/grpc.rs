@@ -0,0 +1,326 @@
+//! gRPC control service for integrators who want a typed interface on the
+//! companion computer instead of MAVLink, see `proto/camera.proto`. It works
+//! like the REST API: cameras are identified by their component id and there
+//! is no authentication, so only turn it on where the network is trusted.
+
+use crate::control::{Control, Refused, SettingChange};
+use crate::error::{CameraError, Result};
+use crate::event::CameraEvent;
+use crate::state::CameraState;
+use crate::status::{FrameCounts, WorkerReporter, WorkerStatus};
+use proto::camera_control_server::{CameraControl, CameraControlServer};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::{debug, info};
+
+mod proto {
+    tonic::include_proto!("camera");
+}
+
+/// Events that may wait for a slow client before the stream ends.
+const EVENT_QUEUE: usize = 64;
+
+/// Where the gRPC server listens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrpcServerOptions {
+    pub port: u16,
+}
+
+impl Default for GrpcServerOptions {
+    /// The customary gRPC port, 50051.
+    fn default() -> Self {
+        Self { port: 50051 }
+    }
+}
+
+/// The bound server, started by [`GrpcServer::run`].
+pub(crate) struct GrpcServer {
+    listener: TcpListener,
+}
+
+impl GrpcServer {
+    /// Binds the port right away so a taken one fails the startup.
+    pub async fn bind(options: GrpcServerOptions) -> Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", options.port))
+            .await
+            .map_err(|source| CameraError::GrpcServer {
+                port: options.port,
+                source,
+            })?;
+        info!(target: "rx", port = options.port, "Serving gRPC");
+
+        Ok(Self { listener })
+    }
+
+    /// Serves until the listener fails.
+    pub async fn run(self, control: Control, reporter: WorkerReporter) -> Result<()> {
+        reporter.running();
+        Server::builder()
+            .add_service(CameraControlServer::new(Service { control }))
+            .serve_with_incoming(TcpListenerStream::new(self.listener))
+            .await?;
+        Ok(())
+    }
+}
+
+struct Service {
+    control: Control,
+}
+
+#[tonic::async_trait]
+impl CameraControl for Service {
+    async fn capture(
+        &self,
+        request: Request<proto::CaptureRequest>,
+    ) -> Result<Response<proto::CaptureReply>, Status> {
+        let camera = camera_id(request.get_ref().camera)?;
+        self.control.capture(camera).map_err(refused_status)?;
+        Ok(Response::new(proto::CaptureReply {}))
+    }
+
+    async fn get_status(
+        &self,
+        _request: Request<proto::GetStatusRequest>,
+    ) -> Result<Response<proto::Status>, Status> {
+        let status = self
+            .control
+            .status()
+            .map_err(|_| Status::internal("status unavailable"))?;
+        let cameras = self
+            .control
+            .states()
+            .map(|(id, state)| {
+                let tasks = status.cameras.get(&id).cloned().unwrap_or_default();
+                proto::CameraStatus {
+                    state: Some(camera_state(id, &state)),
+                    heartbeat: Some(worker_status(&tasks.heartbeat)),
+                    commands: Some(worker_status(&tasks.commands)),
+                    live_view: Some(worker_status(&tasks.live_view)),
+                    image_transmission: Some(worker_status(&tasks.image_transmission)),
+                }
+            })
+            .collect();
+
+        Ok(Response::new(proto::Status {
+            links: status
+                .links
+                .iter()
+                .map(|(address, link)| (address.clone(), worker_status(link)))
+                .collect(),
+            receiver: Some(worker_status(&status.receiver)),
+            http: Some(worker_status(&status.http)),
+            grpc: Some(worker_status(&status.grpc)),
+            watchdog: Some(worker_status(&status.watchdog)),
+            mqtt: Some(worker_status(&status.mqtt)),
+            cameras,
+            ros2: Some(worker_status(&status.ros2)),
+            peers: status
+                .peers
+                .iter()
+                .map(|(&(system, component), peer)| proto::Peer {
+                    system: system.into(),
+                    component: component.into(),
+                    kind: peer.kind.name().to_owned(),
+                    last_heartbeat_ms: peer.last_heartbeat.elapsed().as_millis() as u64,
+                })
+                .collect(),
+            frames: status
+                .frames
+                .iter()
+                .map(|(address, counts)| (address.clone(), frame_counts(counts)))
+                .collect(),
+            frames_by_sender: status
+                .frames_by_sender
+                .iter()
+                .map(|(&(system, component), counts)| proto::SenderFrames {
+                    system: system.into(),
+                    component: component.into(),
+                    frames: Some(frame_counts(counts)),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn set_param(
+        &self,
+        request: Request<proto::SetParamRequest>,
+    ) -> Result<Response<proto::SetParamReply>, Status> {
+        let request = request.into_inner();
+        let camera = camera_id(request.camera)?;
+        let change = self
+            .control
+            .set_setting(camera, request.name, request.value.trim().to_owned())
+            .await
+            .map_err(refused_status)?;
+
+        match change {
+            SettingChange::Changed(value) => Ok(Response::new(proto::SetParamReply { value })),
+            SettingChange::UnknownParameter => Err(Status::not_found("no such parameter")),
+            SettingChange::Unsupported => Err(Status::invalid_argument(
+                "the camera doesn't take this setting",
+            )),
+            SettingChange::Failed(message) => Err(Status::internal(message)),
+        }
+    }
+
+    type StreamEventsStream = ReceiverStream<Result<proto::Event, Status>>;
+
+    async fn stream_events(
+        &self,
+        _request: Request<proto::StreamEventsRequest>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        let (sender, receiver) = mpsc::channel(EVENT_QUEUE);
+        let mut events = self.control.subscribe();
+        let mut changes = self.control.state_changes();
+
+        debug!(target: "rx", "gRPC client streaming events");
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) => event_message(event),
+                        Err(RecvError::Lagged(skipped)) => proto::event::Event::Lagged(skipped),
+                        Err(RecvError::Closed) => break,
+                    },
+                    Some((camera, state)) = changes.recv() => {
+                        proto::event::Event::State(camera_state(camera, &state))
+                    }
+                    () = sender.closed() => break,
+                };
+                let event = proto::Event { event: Some(event) };
+                if sender.send(Ok(event)).await.is_err() {
+                    break;
+                }
+            }
+            debug!(target: "rx", "gRPC client stopped streaming events");
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+fn camera_id(camera: u32) -> Result<u8, Status> {
+    u8::try_from(camera).map_err(|_| refused_status(Refused::NoSuchCamera))
+}
+
+fn refused_status(refused: Refused) -> Status {
+    match refused {
+        Refused::NoSuchCamera => Status::not_found("no such camera"),
+        Refused::Busy => Status::unavailable("camera busy"),
+        Refused::Stopped => Status::unavailable("camera stopped"),
+    }
+}
+
+fn worker_status(status: &WorkerStatus) -> proto::WorkerStatus {
+    let (health, error) = match status {
+        WorkerStatus::Running => (proto::Health::Running, String::new()),
+        WorkerStatus::Degraded(error) => (proto::Health::Degraded, error.clone()),
+        WorkerStatus::Failed(error) => (proto::Health::Failed, error.clone()),
+    };
+    proto::WorkerStatus {
+        health: health as i32,
+        error,
+    }
+}
+
+fn frame_counts(counts: &FrameCounts) -> proto::FrameCounts {
+    proto::FrameCounts {
+        received: counts.received,
+        bad_checksum: counts.bad_checksum,
+        unknown_message: counts.unknown_message,
+        invalid: counts.invalid,
+    }
+}
+
+fn camera_state(camera: u8, state: &CameraState) -> proto::CameraState {
+    proto::CameraState {
+        camera: camera.into(),
+        mode: format!("{:?}", state.mode),
+        capturing: state.capturing,
+        connected: state.camera_connected,
+        storage_full: state.storage_full,
+        streaming: state.streaming,
+        degraded: state.degraded,
+        shutter_count: state.shutter_count,
+        temperature_c: state.temperature,
+        pending_downloads: state.pending_downloads as u32,
+        recording: state.recording,
+        message_intervals_us: state
+            .stream_rates
+            .intervals_us()
+            .map(|(name, interval_us)| (name.to_owned(), interval_us))
+            .collect(),
+    }
+}
+
+fn event_message(event: CameraEvent) -> proto::event::Event {
+    use proto::event::Event;
+
+    match event {
+        CameraEvent::CommandReceived {
+            command,
+            from_system,
+            from_component,
+        } => Event::CommandReceived(proto::CommandReceived {
+            command: format!("{command:?}"),
+            from_system: from_system.into(),
+            from_component: from_component.into(),
+        }),
+        CameraEvent::ImageCaptured { camera, path, seq } => {
+            Event::ImageCaptured(proto::ImageCaptured {
+                camera: camera.into(),
+                path: path.display().to_string(),
+                seq,
+            })
+        }
+        CameraEvent::PreviewReady {
+            camera,
+            image,
+            thumbnail,
+        } => Event::PreviewReady(proto::PreviewReady {
+            camera: camera.into(),
+            image: image.display().to_string(),
+            thumbnail: thumbnail.display().to_string(),
+        }),
+        CameraEvent::CaptureFailed { camera, seq, error } => {
+            Event::CaptureFailed(proto::CaptureFailed {
+                camera: camera.into(),
+                seq,
+                error,
+            })
+        }
+        CameraEvent::StorageLow {
+            camera,
+            available_bytes,
+        } => Event::StorageLow(proto::StorageLow {
+            camera: camera.into(),
+            available_bytes,
+        }),
+        CameraEvent::CameraDisconnected { camera } => {
+            Event::CameraDisconnected(proto::CameraConnection {
+                camera: camera.into(),
+            })
+        }
+        CameraEvent::CameraReconnected { camera } => {
+            Event::CameraReconnected(proto::CameraConnection {
+                camera: camera.into(),
+            })
+        }
+        CameraEvent::ConnectionLost { address, error } => {
+            Event::ConnectionLost(proto::ConnectionLost { address, error })
+        }
+        CameraEvent::Reconnected { address } => Event::Reconnected(proto::Reconnected { address }),
+        CameraEvent::TaskStalled { task, camera } => Event::TaskStalled(proto::Task {
+            task: task.to_owned(),
+            camera: camera.map(u32::from),
+        }),
+        CameraEvent::TaskRecovered { task, camera } => Event::TaskRecovered(proto::Task {
+            task: task.to_owned(),
+            camera: camera.map(u32::from),
+        }),
+    }
+}
@@ -0,0 +1,150 @@
+//! Shutter feedback from a hot-shoe adapter wired to a GPIO, which tells the
+//! exact moment the shutter fired rather than when the capture was requested.
+//!
+//! Uses the sysfs GPIO interface, so `gpio` is the kernel's global number of
+//! the line. On a Raspberry Pi that's the BCM number plus the base of the
+//! `pinctrl` chip in `/sys/class/gpio`, e.g. 512 on recent kernels.
+
+use crate::error::{CameraError, Result};
+use chrono::{DateTime, Utc};
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+/// Edges this close after one that fired are contact bounce.
+const DEBOUNCE: Duration = Duration::from_millis(20);
+
+/// How often the watcher thread checks whether it's still needed.
+const POLL_TIMEOUT_MS: i32 = 1000;
+
+/// When the shutter last fired, `None` until it first does.
+pub(crate) type ShutterFeedback = watch::Receiver<Option<DateTime<Utc>>>;
+
+/// Which GPIO the hot-shoe adapter is wired to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HotShoeOptions {
+    /// Global sysfs number of the GPIO line.
+    pub gpio: u32,
+    /// Edge the adapter produces when the flash contact closes.
+    pub edge: HotShoeEdge,
+}
+
+impl HotShoeOptions {
+    /// Watches `gpio` for a falling edge, which is what an X-sync contact
+    /// pulling a pulled-up input to ground gives.
+    pub fn new(gpio: u32) -> Self {
+        Self {
+            gpio,
+            edge: HotShoeEdge::Falling,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HotShoeEdge {
+    #[default]
+    Falling,
+    Rising,
+}
+
+impl HotShoeEdge {
+    fn sysfs_name(self) -> &'static str {
+        match self {
+            HotShoeEdge::Falling => "falling",
+            HotShoeEdge::Rising => "rising",
+        }
+    }
+}
+
+/// Exports and configures the GPIO and starts a thread that timestamps every
+/// shutter release until the returned receiver is dropped.
+pub(crate) fn start(options: HotShoeOptions) -> Result<ShutterFeedback> {
+    let directory = PathBuf::from(format!("/sys/class/gpio/gpio{}", options.gpio));
+    let value = configure(&directory, options).map_err(|source| CameraError::HotShoe {
+        gpio: options.gpio,
+        source,
+    })?;
+    info!(target: "backend", gpio = options.gpio, edge = options.edge.sysfs_name(), "Watching hot-shoe");
+
+    let (sender, receiver) = watch::channel(None);
+    std::thread::Builder::new()
+        .name(format!("hotshoe-{}", options.gpio))
+        .spawn(move || watch_edges(value, sender))?;
+
+    Ok(receiver)
+}
+
+fn configure(directory: &Path, options: HotShoeOptions) -> io::Result<File> {
+    if !directory.exists() {
+        std::fs::write("/sys/class/gpio/export", options.gpio.to_string())?;
+    }
+
+    // udev may still be fixing the permissions of a freshly exported line.
+    let mut attempts = 0;
+    loop {
+        let result = std::fs::write(directory.join("direction"), "in")
+            .and_then(|()| std::fs::write(directory.join("edge"), options.edge.sysfs_name()));
+        match result {
+            Err(error) if error.kind() == ErrorKind::PermissionDenied && attempts < 10 => {
+                attempts += 1;
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            result => break result?,
+        }
+    }
+
+    File::open(directory.join("value"))
+}
+
+fn watch_edges(mut value: File, sender: watch::Sender<Option<DateTime<Utc>>>) {
+    let mut last_fired: Option<Instant> = None;
+    // The first read clears the event pending from configuring the line.
+    let _ = read_value(&mut value);
+
+    while !sender.is_closed() {
+        let mut poll = libc::pollfd {
+            fd: value.as_raw_fd(),
+            events: libc::POLLPRI | libc::POLLERR,
+            revents: 0,
+        };
+        // SAFETY: `poll` points to one valid pollfd for the duration of the call.
+        let ready = unsafe { libc::poll(&mut poll, 1, POLL_TIMEOUT_MS) };
+
+        match ready {
+            0 => continue,
+            -1 => {
+                let error = io::Error::last_os_error();
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                warn!(target: "backend", "Stopped watching hot-shoe: {error}");
+                return;
+            }
+            _ => {}
+        }
+
+        let fired = Utc::now();
+        if let Err(error) = read_value(&mut value) {
+            warn!(target: "backend", "Stopped watching hot-shoe: {error}");
+            return;
+        }
+        if last_fired.is_some_and(|last| last.elapsed() < DEBOUNCE) {
+            continue;
+        }
+
+        last_fired = Some(Instant::now());
+        debug!(target: "backend", %fired, "Shutter fired");
+        sender.send_replace(Some(fired));
+    }
+}
+
+/// Reads the line from the start, which re-arms the edge notification.
+fn read_value(value: &mut File) -> io::Result<()> {
+    value.seek(SeekFrom::Start(0))?;
+    value.read_to_end(&mut Vec::new())?;
+    Ok(())
+}
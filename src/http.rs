@@ -0,0 +1,238 @@
+//! Embedded HTTP server that lets ground stations download the captures and
+//! their thumbnails:
+//!
+//! - `GET /images/<camera>/<path>`
+//! - `GET /thumbnails/<camera>/<path>`
+//!
+//! where `<camera>` is the camera's component id and `<path>` the image's
+//! path below the camera's image directory. It also serves the camera
+//! definition files ground stations read the parameters from:
+//!
+//! - `GET /definitions/<camera>.xml`
+//!
+//! and, when turned on, the REST control API, see [`crate::api`].
+
+use crate::api;
+use crate::control::Control;
+use crate::error::{CameraError, Result};
+use crate::parameters::CameraDefinition;
+use crate::status::WorkerReporter;
+use crate::thumbnail::thumbnail_path;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+/// Where the server listens and what it serves.
+#[derive(Debug, Clone)]
+pub struct HttpServerOptions {
+    pub port: u16,
+    /// Image directory by camera component id.
+    pub image_dirs: HashMap<u8, PathBuf>,
+    /// How ground stations reach the server, e.g. `http://192.168.144.10:8080`.
+    /// `http://<hostname>:<port>` when unset.
+    pub url: Option<String>,
+    /// Also serves the REST API to trigger captures, read the status and
+    /// change settings, for on-board scripts and web dashboards.
+    pub api: bool,
+}
+
+impl HttpServerOptions {
+    /// Serves nothing on `port` until image directories are added.
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            image_dirs: HashMap::new(),
+            url: None,
+            api: false,
+        }
+    }
+
+    /// The URL of the directory `camera`'s images are served from, `None` if
+    /// they aren't.
+    pub(crate) fn image_url(&self, camera: u8) -> Option<String> {
+        self.image_dirs.get(&camera)?;
+        Some(format!("{}/images/{camera}", self.base_url()))
+    }
+
+    /// The URL `path` of `camera` is served at from `route`, `None` if it
+    /// isn't.
+    #[cfg(feature = "mqtt")]
+    pub(crate) fn file_url(&self, route: &str, camera: u8, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(self.image_dirs.get(&camera)?).ok()?;
+        Some(format!(
+            "{}/{route}/{camera}/{}",
+            self.base_url(),
+            encode_path(relative)?
+        ))
+    }
+
+    /// The URL of `camera`'s definition file.
+    pub(crate) fn definition_url(&self, camera: u8) -> String {
+        format!("{}/definitions/{camera}.xml", self.base_url())
+    }
+
+    fn base_url(&self) -> String {
+        let url = self.url.clone().unwrap_or_else(|| {
+            let host = sys_info::hostname().unwrap_or_else(|_| "localhost".to_owned());
+            format!("http://{host}:{}", self.port)
+        });
+        url.trim_end_matches('/').to_owned()
+    }
+}
+
+/// The relative `path` as a URL path, `None` if it isn't UTF-8.
+pub(crate) fn encode_path(path: &Path) -> Option<String> {
+    let segments = path
+        .iter()
+        .map(|segment| segment.to_str().map(encode_path_segment))
+        .collect::<Option<Vec<_>>>()?;
+    Some(segments.join("/"))
+}
+
+fn encode_path_segment(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// The latest definition of each camera by component id.
+pub(crate) type Definitions = HashMap<u8, watch::Receiver<Option<CameraDefinition>>>;
+
+/// What the handlers serve from.
+#[derive(Clone)]
+struct Served {
+    image_dirs: Arc<HashMap<u8, PathBuf>>,
+    definitions: Arc<Definitions>,
+}
+
+/// The bound server, started by [`HttpServer::run`].
+pub(crate) struct HttpServer {
+    listener: TcpListener,
+    served: Served,
+    api: bool,
+}
+
+impl HttpServer {
+    /// Binds the port right away so a taken one fails the startup.
+    pub async fn bind(options: HttpServerOptions, definitions: Definitions) -> Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", options.port))
+            .await
+            .map_err(|source| CameraError::HttpServer {
+                port: options.port,
+                source,
+            })?;
+        info!(target: "rx", port = options.port, api = options.api, "Serving images over HTTP");
+
+        Ok(Self {
+            listener,
+            served: Served {
+                image_dirs: Arc::new(options.image_dirs),
+                definitions: Arc::new(definitions),
+            },
+            api: options.api,
+        })
+    }
+
+    /// Serves until the listener fails. `control` is what the REST API, if
+    /// on, works with.
+    pub async fn run(self, control: Control, reporter: WorkerReporter) -> Result<()> {
+        let image_dirs = self.served.image_dirs.clone();
+        let mut app = Router::new()
+            .route("/images/{camera}/{*path}", get(image))
+            .route("/thumbnails/{camera}/{*path}", get(thumbnail))
+            .route("/definitions/{file}", get(definition))
+            .with_state(self.served);
+        if self.api {
+            app = app.merge(api::routes(control, image_dirs));
+        }
+
+        reporter.running();
+        axum::serve(self.listener, app).await?;
+        Ok(())
+    }
+}
+
+async fn image(
+    State(served): State<Served>,
+    UrlPath((camera, path)): UrlPath<(u8, String)>,
+) -> Response {
+    match local_path(&served.image_dirs, camera, &path) {
+        Some(path) => serve_file(path).await,
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// The thumbnail of the image at `path`, which may also be given as the
+/// thumbnail's own name.
+async fn thumbnail(
+    State(served): State<Served>,
+    UrlPath((camera, path)): UrlPath<(u8, String)>,
+) -> Response {
+    match local_path(&served.image_dirs, camera, &path).and_then(|path| thumbnail_path(&path)) {
+        Some(path) => serve_file(path).await,
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// `<camera>.xml`, once the camera's settings were read.
+async fn definition(State(served): State<Served>, UrlPath(file): UrlPath<String>) -> Response {
+    let definition = file
+        .strip_suffix(".xml")
+        .and_then(|camera| camera.parse().ok())
+        .and_then(|camera: u8| served.definitions.get(&camera))
+        .and_then(|definition| definition.borrow().clone());
+
+    match definition {
+        Some(definition) => (
+            [(header::CONTENT_TYPE, "application/xml")],
+            definition.xml.to_string(),
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// `path` below `camera`'s image directory. Hidden files and anything that
+/// could lead out of the directory are refused.
+fn local_path(image_dirs: &HashMap<u8, PathBuf>, camera: u8, path: &str) -> Option<PathBuf> {
+    let directory = image_dirs.get(&camera)?;
+    let safe = path
+        .split('/')
+        .all(|segment| !segment.is_empty() && !segment.starts_with('.') && !segment.contains('\\'));
+
+    safe.then(|| directory.join(path))
+}
+
+async fn serve_file(path: PathBuf) -> Response {
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    match tokio::fs::read(&path).await {
+        Ok(contents) => ([(header::CONTENT_TYPE, content_type(&file))], contents).into_response(),
+        Err(error) => {
+            debug!(target: "rx", path = %path.display(), "Can't serve file: {error}");
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+fn content_type(file: &str) -> &'static str {
+    let extension = file.rsplit_once('.').map(|(_, extension)| extension);
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("tif" | "tiff") => "image/tiff",
+        _ => "application/octet-stream",
+    }
+}
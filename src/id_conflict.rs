@@ -0,0 +1,91 @@
+//! Another component heard on the link with the MAVLink ids of a camera, which
+//! ground stations would show as one camera answering twice. Checked before
+//! the cameras send their first heartbeat.
+
+use crate::connection::Incoming;
+use crate::error::{CameraError, Result};
+use crate::message::CameraDialect;
+use mavlink::common::MavMessage;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// `MAV_COMP_ID_CAMERA` to `MAV_COMP_ID_CAMERA6`, the component ids ground
+/// stations look for cameras at.
+pub const CAMERA_COMPONENT_IDS: RangeInclusive<u8> = 100..=105;
+
+/// What happens to a camera whose ids another component already uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdConflict {
+    /// The component doesn't start.
+    #[default]
+    Refuse,
+    /// The camera takes the next free id of [`CAMERA_COMPONENT_IDS`].
+    Bump,
+}
+
+/// How the link is checked for components using the ids of a camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdConflictCheck {
+    /// How long to listen for heartbeats before starting, long enough to hear
+    /// every component once at 1 Hz.
+    pub listen: Duration,
+    pub resolution: IdConflict,
+}
+
+impl Default for IdConflictCheck {
+    fn default() -> Self {
+        Self {
+            listen: Duration::from_secs(2),
+            resolution: IdConflict::Refuse,
+        }
+    }
+}
+
+/// Listens for heartbeats on `incoming` and returns the new component id of
+/// every camera of `cameras`, given as system and component id, that has to
+/// move. Fails for a conflict that is refused or can't be moved out of.
+pub(crate) async fn resolve<M: CameraDialect>(
+    incoming: &mut Incoming<M>,
+    check: IdConflictCheck,
+    cameras: &[(u8, u8)],
+) -> Result<HashMap<u8, u8>> {
+    info!(?check.listen, "Listening for components with the ids of a camera");
+    let deadline = Instant::now() + check.listen;
+    let mut heard = BTreeSet::new();
+    while let Ok(Some((header, message))) = tokio::time::timeout_at(deadline, incoming.recv()).await
+    {
+        if let Some(MavMessage::HEARTBEAT(_)) = message.to_common() {
+            heard.insert((header.system_id, header.component_id));
+        }
+    }
+
+    let mut taken: BTreeSet<u8> = cameras.iter().map(|(_, component)| *component).collect();
+    let mut moved = HashMap::new();
+    for &(system, component) in cameras {
+        if !heard.contains(&(system, component)) {
+            continue;
+        }
+
+        let free = CAMERA_COMPONENT_IDS
+            .clone()
+            .find(|id| !taken.contains(id) && !heard.contains(&(system, *id)));
+        match (check.resolution, free) {
+            (IdConflict::Bump, Some(free)) => {
+                warn!(
+                    system,
+                    component, free, "Component id already in use, taking the next free one"
+                );
+                taken.insert(free);
+                moved.insert(component, free);
+            }
+            _ => return Err(CameraError::IdConflict { system, component }),
+        }
+    }
+
+    Ok(moved)
+}
@@ -0,0 +1,62 @@
+//! The lens on the camera for `CAMERA_INFORMATION`, asked from the camera or
+//! read from the EXIF of its captures, which also notices a lens swapped
+//! between flights.
+
+use crate::backend::Lens;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// The lens and focal length recorded in the EXIF of `image`, `None` without
+/// a lens model, e.g. for adapted manual lenses.
+pub(crate) fn from_exif(image: &Path) -> Option<Lens> {
+    let exif = read_exif(image)?;
+    let field = exif.get_field(exif::Tag::LensModel, exif::In::PRIMARY)?;
+    let exif::Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let model = String::from_utf8_lossy(values.first()?);
+    let model = model.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    if model.is_empty() {
+        return None;
+    }
+
+    Some(Lens {
+        model: model.to_owned(),
+        focal_length_mm: focal_length(&exif),
+    })
+}
+
+/// The focal length the camera recorded in `image`, which follows a zoom lens.
+pub(crate) fn exif_focal_length(image: &Path) -> Option<f32> {
+    focal_length(&read_exif(image)?)
+}
+
+/// A number from 1 to 255 for the lens `model`, the same on every run, for
+/// the `lens_id` of `CAMERA_INFORMATION`. 0 is left for an unknown lens.
+pub(crate) fn lens_id(model: &str) -> u8 {
+    // FNV-1a, as std's hasher may change between releases.
+    let hash = model.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    (hash % 255) as u8 + 1
+}
+
+pub(crate) fn read_exif(image: &Path) -> Option<exif::Exif> {
+    let file = File::open(image).ok()?;
+    exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()
+}
+
+fn focal_length(exif: &exif::Exif) -> Option<f32> {
+    let field = exif.get_field(exif::Tag::FocalLength, exif::In::PRIMARY)?;
+
+    match &field.value {
+        exif::Value::Rational(values) => values
+            .first()
+            .map(|value| value.to_f64() as f32)
+            .filter(|length| *length > 0.0),
+        _ => None,
+    }
+}
@@ -0,0 +1,129 @@
+//! MAVLink camera protocol component for companion computers.
+//!
+//! [`MavLinkCameraHandle`] connects to a MAVLink endpoint, announces itself with
+//! heartbeats and answers camera protocol requests from the autopilot or ground
+//! station. Embed it in your own application or run the bundled `camera` binary.
+//!
+//! ```no_run
+//! use camera::backend::GPhotoBackend;
+//! use camera::{MavLinkCameraHandle, MavlinkCameraComponent};
+//!
+//! #[tokio::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     let backend = GPhotoBackend::open(None, "images")?;
+//!     let handle = MavLinkCameraHandle::try_new(
+//!         "tcpout:localhost:5762".into(),
+//!         MavlinkCameraComponent::default(),
+//!         Box::new(backend),
+//!     )
+//!     .await?;
+//!
+//!     handle.join().await;
+//!     Ok(())
+//! }
+//! ```
+
+mod api;
+mod autofocus;
+pub mod backend;
+mod bracketing;
+mod bulb;
+mod capture_log;
+mod capture_queue;
+mod clock;
+mod commands;
+pub mod config;
+mod connection;
+mod control;
+pub mod daemon;
+mod dispatcher;
+mod downloader;
+pub mod error;
+mod event;
+mod focus_stack;
+mod footprint;
+mod geotag;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod hotshoe;
+mod http;
+mod id_conflict;
+mod lens;
+pub mod mavlink_camera;
+mod message;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod naming;
+mod offload;
+mod parameters;
+mod preview;
+#[cfg(feature = "ros2")]
+mod ros2;
+mod selftest;
+mod sequence;
+mod shutter_count;
+mod state;
+mod status;
+mod statustext;
+mod storage;
+mod stream_rates;
+mod streaming;
+mod system_id;
+mod thumbnail;
+mod timelapse;
+mod transmission;
+mod trigger;
+mod vehicle;
+mod video;
+mod watchdog;
+
+pub use autofocus::AutofocusOptions;
+pub use bracketing::BracketingOptions;
+pub use bulb::BULB_COMMAND;
+pub use capture_log::{CaptureLogFormat, CaptureLogOptions};
+pub use capture_queue::{CaptureQueueOptions, QueuePolicy};
+pub use clock::SET_CLOCK_COMMAND;
+pub use commands::CustomCommands;
+pub use connection::pcap::PcapOptions;
+pub use connection::tlog::TlogOptions;
+pub use downloader::DownloadQueueOptions;
+pub use error::{CameraError, Result};
+pub use event::CameraEvent;
+pub use focus_stack::{FocusStackOptions, FOCUS_STACK_COMMAND};
+pub use footprint::FootprintOptions;
+pub use geotag::Geotag;
+#[cfg(feature = "grpc")]
+pub use grpc::GrpcServerOptions;
+pub use hotshoe::{HotShoeEdge, HotShoeOptions};
+pub use http::HttpServerOptions;
+pub use id_conflict::{IdConflict, IdConflictCheck, CAMERA_COMPONENT_IDS};
+/// The MAVLink dialect [`MavLinkCameraHandle`] speaks unless another one is
+/// picked with [`MavLinkCameraHandle::try_with_dialect`]: `common`, or
+/// `ardupilotmega` with the `ardupilotmega` feature for ArduPilot's camera
+/// messages.
+#[cfg(feature = "ardupilotmega")]
+pub use mavlink::ardupilotmega as dialect;
+#[cfg(not(feature = "ardupilotmega"))]
+pub use mavlink::common as dialect;
+pub use mavlink_camera::{
+    camera_information, CommandHandler, ComponentOptions, MavLinkCameraHandle,
+    MavlinkCameraComponent,
+};
+pub use message::{CameraDialect, CaptureFeedback};
+#[cfg(feature = "mqtt")]
+pub use mqtt::MqttOptions;
+pub use naming::{FilenameTemplate, TemplateError};
+pub use offload::OFFLOAD_COMMAND;
+#[cfg(feature = "ros2")]
+pub use ros2::Ros2Options;
+pub use selftest::SELF_TEST_COMMAND;
+pub use status::{CameraStatus, ComponentStatus, FrameCounts, Peer, PeerKind, WorkerStatus};
+pub use storage::StorageOptions;
+pub use stream_rates::StreamRates;
+#[cfg(feature = "rtsp")]
+pub use streaming::H264Encoder;
+pub use streaming::{LiveViewServer, LIVE_VIEW_PATH};
+pub use thumbnail::ThumbnailOptions;
+pub use transmission::ImageTransmissionOptions;
+pub use video::{VideoEncoding, VideoStreamOptions, STREAM_SETTINGS_COMMAND};
+pub use watchdog::WatchdogOptions;
@@ -1,9 +1,325 @@
-use mavlink_camera::MavLinkCameraHandle;
-mod mavlink_camera;
+use anyhow::{Context, Result};
+#[cfg(feature = "ccapi")]
+use camera::backend::CanonBackend;
+#[cfg(feature = "libcamera")]
+use camera::backend::LibcameraBackend;
+#[cfg(feature = "sim")]
+use camera::backend::SimCamera;
+#[cfg(feature = "sony")]
+use camera::backend::SonyBackend;
+use camera::backend::{CameraBackend, GPhotoBackend};
+use camera::config::{self, BackendKind, Config};
+use camera::daemon::{self, PidFile};
+use camera::{MavLinkCameraHandle, MavlinkCameraComponent};
+use clap::{Parser, Subcommand};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
 
-const CONNECTION: &str = "tcpout:localhost:5762";
+/// Swaps the log filter of the running process.
+type LogFilter = reload::Handle<EnvFilter, Registry>;
 
-fn main() {
-    let handle = MavLinkCameraHandle::try_new(CONNECTION.into());
-    loop {}
+/// MAVLink camera component for gphoto2 cameras.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// TOML configuration file, see config.example.toml
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// MAVLink connection string [default: tcpout:localhost:5762]
+    #[arg(long, value_parser = parse_connection)]
+    connection: Option<String>,
+
+    /// Additional MAVLink endpoint that gets the same messages; may be repeated
+    #[arg(long, value_parser = parse_connection)]
+    extra_connection: Vec<String>,
+
+    /// MAVLink system id of the camera [default: 100]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..))]
+    system_id: Option<u8>,
+
+    /// MAVLink component id of the camera, a number or camera to camera6 [default: 100]
+    #[arg(long, value_parser = parse_component_id)]
+    component_id: Option<u8>,
+
+    /// Camera backend [default: gphoto]
+    #[arg(long, value_enum)]
+    backend: Option<BackendKind>,
+
+    /// gphoto2 port of the camera, e.g. usb:001,004 (autodetected when omitted)
+    #[arg(long)]
+    camera_port: Option<String>,
+
+    /// Directory captured images are downloaded to [default: images]
+    #[arg(long)]
+    image_dir: Option<PathBuf>,
+
+    /// Record all MAVLink traffic to this telemetry log
+    #[arg(long)]
+    tlog: Option<PathBuf>,
+
+    /// Capture every raw MAVLink frame to this pcap file for Wireshark
+    #[arg(long)]
+    pcap: Option<PathBuf>,
+
+    /// Append every capture with its position to this CSV log, see
+    /// [capture_log] in the config file for JSON
+    #[arg(long)]
+    capture_log: Option<PathBuf>,
+
+    /// Log filter, e.g. info,rx=debug (overridden by RUST_LOG) [default: info]
+    #[arg(long)]
+    log: Option<String>,
+
+    /// Write the process ID to this file while running
+    #[arg(long)]
+    pid_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Feed a recorded telemetry log to the component instead of a vehicle,
+    /// to reproduce problems from the field on a desk
+    Replay {
+        /// Telemetry log to play back, e.g. one written with --tlog
+        tlog: PathBuf,
+
+        /// Playback speed relative to the recording, 0 replays as fast as possible
+        #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+        speed: f64,
+    },
+}
+
+impl Args {
+    /// Overrides the values in `config` with any flags given on the command line.
+    fn merge_into(self, config: &mut Config) {
+        if let Some(Command::Replay { tlog, speed }) = self.command {
+            config.mavlink.connection = format!("tlog:{}:{speed}", tlog.display());
+            config.mavlink.extra_connections.clear();
+            // Don't record the replay into the log that is likely being replayed.
+            config.tlog.path = None;
+        }
+        if let Some(connection) = self.connection {
+            config.mavlink.connection = connection;
+        }
+        if !self.extra_connection.is_empty() {
+            config.mavlink.extra_connections = self.extra_connection;
+        }
+        if let Some(system_id) = self.system_id {
+            config.mavlink.system_id = system_id;
+        }
+        if let Some(component_id) = self.component_id {
+            config.mavlink.component_id = component_id;
+        }
+        if let Some(backend) = self.backend {
+            config.camera.backend = backend;
+        }
+        if let Some(camera_port) = self.camera_port {
+            config.camera.port = Some(camera_port);
+        }
+        if let Some(image_dir) = self.image_dir {
+            config.capture.image_dir = image_dir;
+        }
+        if let Some(tlog) = self.tlog {
+            config.tlog.path = Some(tlog);
+        }
+        if let Some(pcap) = self.pcap {
+            config.pcap.path = Some(pcap);
+        }
+        if let Some(capture_log) = self.capture_log {
+            config.capture_log.path = Some(capture_log);
+        }
+        if let Some(log) = self.log {
+            config.logging.filter = log;
+        }
+        if let Some(pid_file) = self.pid_file {
+            config.daemon.pid_file = Some(pid_file);
+        }
+    }
+}
+
+fn parse_connection(connection: &str) -> Result<String, String> {
+    config::validate_connection(connection)
+        .map(|_| connection.to_owned())
+        .map_err(|error| error.to_string())
+}
+
+fn parse_component_id(component_id: &str) -> Result<u8, String> {
+    match config::parse_component_id(component_id) {
+        Ok(0) => Err("the component id must be between 1 and 255".to_owned()),
+        Ok(component_id) => Ok(component_id),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+fn parse_speed(speed: &str) -> Result<f64, String> {
+    match speed.parse::<f64>() {
+        Ok(speed) if speed >= 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("{speed} is not a positive number")),
+    }
+}
+
+fn init_logging(filter: &str) -> Result<LogFilter> {
+    let filter = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(env) => EnvFilter::try_new(env)?,
+        Err(_) => EnvFilter::try_new(filter)?,
+    };
+    let (filter, handle) = reload::Layer::new(filter);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            fmt::layer()
+                .with_ansi(std::io::stderr().is_terminal())
+                .with_writer(std::io::stderr),
+        )
+        .init();
+
+    Ok(handle)
+}
+
+/// Applies `logging.filter` from the config file at `path` again on every
+/// `SIGHUP`, so log levels change without a restart.
+async fn reload_logging(path: Option<PathBuf>, filter: LogFilter) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(error) => {
+            warn!("Can't handle SIGHUP: {error}");
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        let Some(path) = &path else {
+            warn!("Started without a config file, no log filter to reload");
+            continue;
+        };
+        let reloaded = Config::load(path).and_then(|config| {
+            filter.reload(EnvFilter::try_new(&config.logging.filter)?)?;
+            Ok(config.logging.filter)
+        });
+        match reloaded {
+            Ok(reloaded) => info!(filter = %reloaded, "Reloaded the log filter"),
+            Err(error) => warn!("Failed to reload the log filter: {error:#}"),
+        }
+    }
+}
+
+/// Opens a camera with the configured backend and applies the parameter overrides.
+fn open_camera(
+    config: &Config,
+    port: Option<&str>,
+    image_dir: &Path,
+    component: MavlinkCameraComponent,
+) -> Result<(MavlinkCameraComponent, Box<dyn CameraBackend>)> {
+    let mut backend: Box<dyn CameraBackend> = match config.camera.backend {
+        BackendKind::Gphoto => Box::new(
+            GPhotoBackend::open(port, image_dir)?
+                .with_download_format(config.capture.download)
+                .with_download_retry(config.capture.retry())
+                .with_delete_after_download(config.capture.delete_after_download),
+        ),
+        #[cfg(feature = "sim")]
+        BackendKind::Sim => Box::new(
+            SimCamera::new(image_dir)?
+                .with_download_format(config.capture.download)
+                .with_delete_after_download(config.capture.delete_after_download),
+        ),
+        #[cfg(feature = "sony")]
+        BackendKind::Sony => Box::new(
+            SonyBackend::open(port, image_dir)?
+                .with_download_format(config.capture.download)
+                .with_download_retry(config.capture.retry()),
+        ),
+        #[cfg(feature = "ccapi")]
+        BackendKind::Canon => {
+            let address = port.context("The canon backend needs the camera's address as port")?;
+            Box::new(
+                CanonBackend::open(address, image_dir)?
+                    .with_download_format(config.capture.download)
+                    .with_download_retry(config.capture.retry())
+                    .with_delete_after_download(config.capture.delete_after_download),
+            )
+        }
+        #[cfg(feature = "libcamera")]
+        BackendKind::Libcamera => Box::new(
+            LibcameraBackend::open(port, image_dir)?
+                .with_download_format(config.capture.download)
+                .with_download_retry(config.capture.retry()),
+        ),
+    };
+    for (key, value) in config.parameter_overrides() {
+        if let Err(error) = backend.set_config(key, &value) {
+            warn!(target: "backend", "Failed to apply parameter {key}={value}: {error:#}");
+        }
+    }
+
+    Ok((component, backend))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let config_path = args.config.clone();
+    args.merge_into(&mut config);
+    let log_filter = init_logging(&config.logging.filter)?;
+    tokio::spawn(reload_logging(config_path, log_filter));
+
+    let cameras = config
+        .cameras()?
+        .into_iter()
+        .map(|(component, port, image_dir)| open_camera(&config, port, image_dir, component))
+        .collect::<Result<Vec<_>>>()?;
+    let options = config.component_options()?;
+
+    let _pid_file = config
+        .daemon
+        .pid_file
+        .as_ref()
+        .map(|path| {
+            PidFile::create(path)
+                .with_context(|| format!("Failed to write PID file {}", path.display()))
+        })
+        .transpose()?;
+    let handle =
+        MavLinkCameraHandle::try_with_options(config.mavlink.endpoints(), cameras, options).await?;
+    daemon::notify("READY=1");
+    handle
+        .join_until(async {
+            terminated().await;
+            daemon::notify("STOPPING=1");
+        })
+        .await;
+
+    Ok(())
+}
+
+/// Waits for `SIGINT` or `SIGTERM`.
+async fn terminated() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(error) => {
+            warn!("Can't handle SIGTERM: {error}");
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("Interrupted"),
+        _ = terminate.recv() => info!("Terminated"),
+    }
 }
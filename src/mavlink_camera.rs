@@ -1,180 +1,1171 @@
-use heapless::Vec;
-use mavlink::ardupilotmega::COMMAND_LONG_DATA;
-use mavlink::common::{CameraCapFlags, MavCmd, MavMessage};
-use mavlink::error::MessageReadError;
-use mavlink::MavConnection;
-use std::sync::{Arc, Mutex, RwLock};
-use std::{env, thread, time::Duration};
+use crate::autofocus::AutofocusOptions;
+use crate::backend::{CameraBackend, RetryOptions, Sensor};
+use crate::bracketing::BracketingOptions;
+use crate::capture_log::{CaptureLog, CaptureLogOptions};
+use crate::capture_queue::{CaptureQueue, CaptureQueueOptions};
+use crate::clock::TimeSource;
+use crate::commands::CustomCommands;
+use crate::connection::pcap::{PcapOptions, PcapRecorder};
+use crate::connection::tlog::{TlogOptions, TlogRecorder};
+use crate::connection::{self, Incoming, LinkSender};
+use crate::control::{Control, ControlledCamera};
+use crate::dispatcher::{self, send_command_ack, Dispatcher, Request};
+use crate::downloader::{DownloadQueueOptions, Downloader};
+use crate::error::{CameraError, Result};
+use crate::event::{CameraEvent, EventSender};
+use crate::focus_stack::FocusStackOptions;
+use crate::footprint::{FootprintLog, FootprintOptions};
+#[cfg(feature = "grpc")]
+use crate::grpc::{GrpcServer, GrpcServerOptions};
+use crate::hotshoe::{self, HotShoeOptions};
+use crate::http::{HttpServer, HttpServerOptions};
+use crate::id_conflict::{self, IdConflictCheck};
+use crate::message::CameraDialect;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::{self, MqttOptions};
+use crate::naming::FilenameTemplate;
+use crate::parameters::{parameter_target, Parameters};
+#[cfg(feature = "ros2")]
+use crate::ros2::{Ros2Node, Ros2Options};
+use crate::sequence::{CaptureSequence, SequenceFile};
+use crate::shutter_count::ShutterCount;
+use crate::state::CameraState;
+use crate::status::{spawn_worker, ComponentStatus, Peer, PeerKind, WorkerReporter};
+use crate::statustext::StatusTexts;
+use crate::storage::{SpaceLevel, StorageOptions};
+use crate::stream_rates::{StreamRates, StreamSchedule};
+use crate::streaming::{self, LiveViewServer};
+use crate::system_id;
+use crate::thumbnail::ThumbnailOptions;
+use crate::transmission::{self, ImageTransmissionOptions, ImageTransmitter};
+use crate::trigger::DistanceTrigger;
+use crate::vehicle::VehicleState;
+use crate::video::VideoStreamOptions;
+use crate::watchdog::{self, Pulse, Task, Watchdog, WatchdogOptions};
+use chrono::Utc;
+use mavlink::common::{
+    CameraCapFlags, MavAutopilot, MavCmd, MavMessage, MavResult, MavType, COMMAND_LONG_DATA,
+    HEARTBEAT_DATA, PING_DATA, TIMESYNC_DATA,
+};
+use mavlink::MavHeader;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, trace, warn};
 
-use anyhow::Result;
+/// MAVLink identity of the camera component.
+pub struct MavlinkCameraComponent {
+    pub system_id: u8,
+    pub component_id: u8,
+    /// Vendor named in `CAMERA_INFORMATION` and the camera definition, the
+    /// one the camera tells when unset.
+    pub vendor_name: Option<String>,
+    /// Model named in `CAMERA_INFORMATION` and the camera definition, the
+    /// one the camera tells when unset.
+    pub model_name: Option<String>,
+    /// Sensor size and resolution in `CAMERA_INFORMATION`, also used for the
+    /// capture footprints. The one the camera tells when unset.
+    pub sensor: Option<Sensor>,
+    /// Camera definition ground stations are pointed to instead of the one
+    /// served over HTTP, e.g. a hand-written one.
+    pub definition_uri: Option<String>,
+    /// Version of the camera definition, that of the served one when unset.
+    /// Ground stations download it again when it changes.
+    pub definition_version: Option<u16>,
+}
 
-type Vehicle = Arc<RwLock<Box<dyn MavConnection<MavMessage> + Sync + Send>>>;
+impl Default for MavlinkCameraComponent {
+    fn default() -> Self {
+        Self {
+            system_id: 100,
+            component_id: 100,
+            vendor_name: None,
+            model_name: None,
+            sensor: None,
+            definition_uri: None,
+            definition_version: None,
+        }
+    }
+}
 
-struct MavlinkCameraComponent {
-    system_id: u8,
-    component_id: u8,
-    vendor_name: String,
-    model_name: String,
+impl MavlinkCameraComponent {
+    fn header(&self) -> MavHeader {
+        MavHeader {
+            system_id: self.system_id,
+            component_id: self.component_id,
+            ..Default::default()
+        }
+    }
 }
 
-struct MavlinkCameraInformation {
-    component: MavlinkCameraComponent,
-    mavlink_connection_string: String,
-    vehicle: Vehicle,
+/// Optional behaviour of the component, see
+/// [`MavLinkCameraHandle::try_with_options`].
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ComponentOptions {
+    /// Records all MAVLink traffic to a telemetry log when set.
+    pub tlog: Option<TlogOptions>,
+    /// Captures every raw MAVLink frame sent or received to a pcap file for
+    /// Wireshark when set, for debugging.
+    pub pcap: Option<PcapOptions>,
+    /// Appends every capture to a CSV or JSON log when set.
+    pub capture_log: Option<CaptureLogOptions>,
+    /// Writes the ground footprint of every geotagged capture to a GeoJSON
+    /// file when set.
+    pub footprints: Option<FootprintOptions>,
+    /// Hot-shoe shutter feedback by camera component id. Captures of those
+    /// cameras are timestamped with when the shutter actually fired.
+    pub hot_shoes: HashMap<u8, HotShoeOptions>,
+    /// Shortest time between captures taken for `MAV_CMD_DO_SET_CAM_TRIGG_DIST`,
+    /// e.g. to not outpace the camera when flying fast with a short distance.
+    pub min_trigger_interval: Duration,
+    /// How pictures asked for faster than the camera takes them are spaced
+    /// out, for single captures, distance triggering and `DIGICAM_CONTROL`.
+    pub capture_queue: CaptureQueueOptions,
+    /// How often a capture that failed outright, e.g. as the camera was
+    /// busy, is taken again before it's reported as failed.
+    pub capture_retry: RetryOptions,
+    /// Downloads single pictures in the background while the next ones are
+    /// taken when set, instead of each before the next.
+    pub download_queue: Option<DownloadQueueOptions>,
+    /// Leaves single pictures on the camera's card to capture as fast as it
+    /// can, for [`crate::OFFLOAD_COMMAND`] to download after the flight.
+    pub keep_on_card: bool,
+    /// Downloads each movie once `MAV_CMD_VIDEO_STOP_CAPTURE` stopped it and
+    /// logs it as a capture, rather than leaving it on the camera.
+    pub download_video: bool,
+    /// Stops time-lapses and distance triggering once the autopilot's
+    /// heartbeats have been missing this long, so a lost link or flight
+    /// controller doesn't leave the camera filling its card. Never when
+    /// `None`.
+    pub autopilot_timeout: Option<Duration>,
+    /// Pauses the live view of a camera whose body gets this warm in °C,
+    /// until it cooled down by 5 °C. Only for cameras that report their
+    /// temperature, never when `None`.
+    pub overheat_temperature: Option<f32>,
+    /// Sets the clock of every camera to the vehicle's time, usually from
+    /// GPS, once the autopilot has sent `SYSTEM_TIME`, and again whenever a
+    /// camera reconnects, e.g. after a battery swap.
+    pub sync_camera_clock: bool,
+    /// How often every camera sends its heartbeat and the messages it streams
+    /// unasked, until a ground station changes them with
+    /// `MAV_CMD_SET_MESSAGE_INTERVAL`.
+    pub stream_rates: StreamRates,
+    /// Live-view streams by camera component id, advertised with
+    /// `VIDEO_STREAM_INFORMATION`. They start out running.
+    pub video_streams: HashMap<u8, VideoStreamOptions>,
+    /// Live view served by the component itself, by camera component id.
+    /// Frames go out at the framerate of the camera's entry in
+    /// `video_streams`.
+    pub live_views: HashMap<u8, LiveViewServer>,
+    /// Sends a preview of every capture over MAVLink with
+    /// `ENCAPSULATED_DATA` when set.
+    pub image_transmission: Option<ImageTransmissionOptions>,
+    /// Writes a small thumbnail of every capture when set.
+    pub thumbnails: Option<ThumbnailOptions>,
+    /// Serves the captures and their thumbnails over HTTP when set.
+    pub http: Option<HttpServerOptions>,
+    /// Serves the gRPC control service when set.
+    #[cfg(feature = "grpc")]
+    pub grpc: Option<GrpcServerOptions>,
+    /// Runs a ROS 2 node with trigger and status topics and services when
+    /// set.
+    #[cfg(feature = "ros2")]
+    pub ros2: Option<Ros2Options>,
+    /// Renames every downloaded image after this template when set.
+    pub filename_template: Option<FilenameTemplate>,
+    /// Free space warnings and image cleanup.
+    pub storage: StorageOptions,
+    /// Directory the image counter of every camera is kept in when set, so
+    /// the index continues where it left off after a restart. So is the
+    /// shutter count of cameras that don't tell theirs, and the mode, running
+    /// time-lapse and parameters ground stations last set, which are
+    /// restored at startup.
+    pub state_dir: Option<PathBuf>,
+    /// Actuations the shutter of each camera is rated for, by camera
+    /// component id. Ground stations get a `STATUSTEXT` once the shutter
+    /// count reaches 90% of it.
+    pub rated_shutter_lives: HashMap<u8, u64>,
+    /// Takes an exposure bracket for every trigger when set.
+    pub bracketing: Option<BracketingOptions>,
+    /// Shots and focus range of a [`crate::FOCUS_STACK_COMMAND`] that
+    /// doesn't give them.
+    pub focus_stack: FocusStackOptions,
+    /// Whether cameras focus before every shot, and for how long. Ground
+    /// stations change whether with the `CAM_AFSHOT` parameter.
+    pub autofocus: AutofocusOptions,
+    /// When the internal tasks count as stalled.
+    pub watchdog: WatchdogOptions,
+    /// Publishes the events and camera states to this MQTT broker when set.
+    #[cfg(feature = "mqtt")]
+    pub mqtt: Option<MqttOptions>,
+    /// Most bytes per second written to an endpoint, by connection string,
+    /// e.g. to leave room for the autopilot on a slow telemetry radio.
+    /// Heartbeats go first, then replies, events and other telemetry.
+    pub bandwidth_limits: HashMap<String, u32>,
+    /// Listens for other components with the ids of a camera before
+    /// starting when set, refusing to start or moving the camera.
+    pub id_conflict: Option<IdConflictCheck>,
+    /// Waits up to this long before starting for the autopilot's heartbeat
+    /// and gives every camera its system id, as the camera protocol has
+    /// cameras share the vehicle's, when set. The configured ids are kept
+    /// when no autopilot is heard.
+    pub adopt_system_id: Option<Duration>,
+    /// Command ids of the component's own commands, e.g. to leave MAVLink's
+    /// user commands to handlers of the application.
+    pub commands: CustomCommands,
 }
 
-pub struct MavLinkCameraHandle {
-    camera_information: Arc<Mutex<MavlinkCameraInformation>>,
-    heartbeat_thread: std::thread::JoinHandle<()>,
-    receive_message_thread: std::thread::JoinHandle<()>,
+impl ComponentOptions {
+    /// Moves the per-camera options of camera `from` to `to`.
+    fn move_camera(&mut self, from: u8, to: u8) {
+        fn rekey<T>(options: &mut HashMap<u8, T>, from: u8, to: u8) {
+            if let Some(option) = options.remove(&from) {
+                options.insert(to, option);
+            }
+        }
+
+        rekey(&mut self.hot_shoes, from, to);
+        rekey(&mut self.rated_shutter_lives, from, to);
+        rekey(&mut self.video_streams, from, to);
+        rekey(&mut self.live_views, from, to);
+        if let Some(http) = &mut self.http {
+            rekey(&mut http.image_dirs, from, to);
+        }
+    }
+}
+
+/// Application handler for a `COMMAND_LONG`, see [`MavLinkCameraHandle::on_command`].
+pub type CommandHandler = Box<dyn FnMut(&COMMAND_LONG_DATA) -> MavResult + Send>;
+
+/// How many unconsumed messages a [`MavLinkCameraHandle::messages`] receiver
+/// may fall behind before it starts skipping.
+const MESSAGE_QUEUE: usize = 256;
+
+/// Commands that may wait for a busy camera before new ones are rejected.
+const INBOX_SIZE: usize = 8;
+
+/// How long [`MavLinkCameraHandle::shutdown`] waits for captures and
+/// downloads in progress before giving up on them.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Handle to a running MAVLink camera component.
+///
+/// Creating the handle connects to the vehicle and spawns the link, receive
+/// and per-camera heartbeat and command tasks on the current tokio runtime.
+/// Only the link tasks touch the connections; the others talk to them through
+/// queues. The component keeps running for as long as those tasks do.
+pub struct MavLinkCameraHandle<M = crate::dialect::MavMessage> {
+    endpoints: Vec<String>,
+    status: Arc<Mutex<ComponentStatus>>,
+    events: EventSender,
+    messages: broadcast::Sender<(MavHeader, M)>,
+    registrations: mpsc::UnboundedSender<(MavCmd, CommandHandler)>,
+    /// The component's own commands, which handlers can't take over.
+    commands: CustomCommands,
+    link_tasks: Vec<JoinHandle<()>>,
+    receive_message_task: JoinHandle<()>,
+    dispatcher_tasks: Vec<JoinHandle<()>>,
+    camera_tasks: Vec<JoinHandle<()>>,
+    shutdown: watch::Sender<bool>,
 }
 
 impl MavLinkCameraHandle {
-    pub fn try_new(mavlink_connection_string: String) -> Result<Self> {
-        let component = MavlinkCameraComponent {
-            system_id: 100,
-            component_id: 100,
-            vendor_name: "Davis Vendor".to_owned(),
-            model_name: "Davis Model".to_owned(),
+    /// Connects to `mavlink_connection_string` (e.g. `tcpout:localhost:5762`) and
+    /// starts announcing `component`, taking pictures with `backend`.
+    pub async fn try_new(
+        mavlink_connection_string: String,
+        component: MavlinkCameraComponent,
+        backend: Box<dyn CameraBackend>,
+    ) -> Result<Self> {
+        Self::try_with_endpoints(vec![mavlink_connection_string], component, backend).await
+    }
+
+    /// Like [`MavLinkCameraHandle::try_new`] but talks to several endpoints at
+    /// once, e.g. `tcpout` to SITL plus `udpout` to a ground station.
+    ///
+    /// Everything the component sends goes to all endpoints. Messages that
+    /// reach it over more than one endpoint are only handled once.
+    pub async fn try_with_endpoints(
+        endpoints: Vec<String>,
+        component: MavlinkCameraComponent,
+        backend: Box<dyn CameraBackend>,
+    ) -> Result<Self> {
+        Self::try_with_cameras(endpoints, vec![(component, backend)]).await
+    }
+
+    /// Runs one MAVLink component per camera body over shared endpoints, e.g. a
+    /// nadir and an oblique camera as `MAV_COMP_ID_CAMERA` and
+    /// `MAV_COMP_ID_CAMERA2`.
+    ///
+    /// Each camera sends its own heartbeat and answers the commands addressed
+    /// to its component id with independent capture state. They all take the
+    /// system id of the vehicle they're on, anything else fails with
+    /// [`CameraError::MixedSystemIds`].
+    pub async fn try_with_cameras(
+        endpoints: Vec<String>,
+        cameras: Vec<(MavlinkCameraComponent, Box<dyn CameraBackend>)>,
+    ) -> Result<Self> {
+        Self::try_with_options(endpoints, cameras, ComponentOptions::default()).await
+    }
+
+    /// Like [`MavLinkCameraHandle::try_with_cameras`] with non-default
+    /// [`ComponentOptions`].
+    pub async fn try_with_options(
+        endpoints: Vec<String>,
+        cameras: Vec<(MavlinkCameraComponent, Box<dyn CameraBackend>)>,
+        options: ComponentOptions,
+    ) -> Result<Self> {
+        Self::try_with_dialect(endpoints, cameras, options).await
+    }
+}
+
+impl<M: CameraDialect> MavLinkCameraHandle<M> {
+    /// Like [`MavLinkCameraHandle::try_with_options`] but speaks the dialect
+    /// `M` instead of the one the crate was built with, e.g. a custom dialect
+    /// whose messages the application handles through
+    /// [`MavLinkCameraHandle::messages`].
+    pub async fn try_with_dialect(
+        endpoints: Vec<String>,
+        mut cameras: Vec<(MavlinkCameraComponent, Box<dyn CameraBackend>)>,
+        mut options: ComponentOptions,
+    ) -> Result<Self> {
+        if endpoints.is_empty() {
+            return Err(CameraError::NoEndpoints);
+        }
+        if cameras.is_empty() {
+            return Err(CameraError::NoCameras);
+        }
+        let system_id = cameras[0].0.system_id;
+        if let Some((other, _)) = cameras
+            .iter()
+            .find(|(component, _)| component.system_id != system_id)
+        {
+            return Err(CameraError::MixedSystemIds(system_id, other.system_id));
+        }
+        if let Some(command) = options.commands.duplicate() {
+            return Err(CameraError::CommandTaken(command));
+        }
+
+        let events = EventSender::new();
+        let status = Arc::new(Mutex::new(ComponentStatus::default()));
+        let tlog = options.tlog.take().map(TlogRecorder::start).transpose()?;
+        let pcap = options.pcap.take().map(PcapRecorder::start).transpose()?;
+        let (sender, mut incoming, link_tasks) = connection::start(
+            &endpoints,
+            &events,
+            &status,
+            tlog,
+            pcap,
+            &options.bandwidth_limits,
+        )
+        .await?;
+        let mut adopted_system_id = None;
+        if let Some(timeout) = options.adopt_system_id {
+            adopted_system_id = system_id::autopilot_system_id(&mut incoming, timeout).await;
+            if let Some(system_id) = adopted_system_id {
+                for (camera, _) in &mut cameras {
+                    camera.system_id = system_id;
+                }
+            }
+        }
+        if let Some(check) = options.id_conflict {
+            let ids: Vec<_> = cameras
+                .iter()
+                .map(|(camera, _)| (camera.system_id, camera.component_id))
+                .collect();
+            let moved = id_conflict::resolve(&mut incoming, check, &ids).await?;
+            for (camera, _) in &mut cameras {
+                if let Some(&to) = moved.get(&camera.component_id) {
+                    options.move_camera(camera.component_id, to);
+                    camera.component_id = to;
+                }
+            }
+        }
+        let capture_log = options.capture_log.map(CaptureLog::open).transpose()?;
+        let footprints = options.footprints.map(FootprintLog::open).transpose()?;
+        let image_urls = options.http.as_ref().map_or_else(HashMap::new, |http| {
+            http.image_dirs
+                .keys()
+                .filter_map(|&camera| Some((camera, http.image_url(camera)?)))
+                .collect()
+        });
+        let definition_urls: HashMap<_, _> =
+            options.http.as_ref().map_or_else(HashMap::new, |http| {
+                cameras
+                    .iter()
+                    .map(|(camera, _)| {
+                        (
+                            camera.component_id,
+                            http.definition_url(camera.component_id),
+                        )
+                    })
+                    .collect()
+            });
+        let mut definitions: HashMap<_, _> = cameras
+            .iter()
+            .map(|(camera, _)| (camera.component_id, watch::channel(None).0))
+            .collect();
+        #[cfg(feature = "mqtt")]
+        let served_images = options.http.clone();
+        let http = match options.http {
+            Some(http) => {
+                let served = definitions
+                    .iter()
+                    .map(|(&camera, definition)| (camera, definition.subscribe()))
+                    .collect();
+                Some(HttpServer::bind(http, served).await?)
+            }
+            None => None,
+        };
+        #[cfg(feature = "grpc")]
+        let grpc = match options.grpc {
+            Some(grpc) => Some(GrpcServer::bind(grpc).await?),
+            None => None,
         };
+        #[cfg(feature = "ros2")]
+        let ros2 = options.ros2.as_ref().map(Ros2Node::create).transpose()?;
+        let mut watchdog = Watchdog::new(options.watchdog, sender.clone(), events.clone());
+
+        // The camera protocol has the cameras share the vehicle's system id,
+        // adopted from its autopilot above if asked to.
+        let vehicle = watch::channel(VehicleState::new(cameras[0].0.system_id)).0;
+        let started = Utc::now();
+        let time = TimeSource::new();
+        let mut routes = Vec::with_capacity(cameras.len());
+        let mut camera_tasks = Vec::with_capacity(cameras.len() * 2);
+        let mut dispatcher_tasks = Vec::with_capacity(cameras.len());
+        let shutdown = watch::channel(false).0;
+        let mut controlled = BTreeMap::new();
+
+        for (component, backend) in cameras {
+            let header = component.header();
+            let id = component.component_id;
+            let shutter = options
+                .hot_shoes
+                .get(&id)
+                .map(|hot_shoe| hotshoe::start(*hot_shoe))
+                .transpose()?;
+            let video_stream = options.video_streams.get(&id).cloned();
+            let (state, state_receiver) = watch::channel(CameraState {
+                streaming: video_stream.is_some(),
+                stream_rates: options.stream_rates,
+                ..Default::default()
+            });
+            watchdog.add_camera(header, state.clone());
+
+            let heartbeat_sender = sender.clone();
+            let reporter = WorkerReporter::new(&status, move |status| {
+                &mut status.cameras.entry(id).or_default().heartbeat
+            });
+            let pulse = watchdog.watch(Task::Heartbeat(id), reporter.clone());
+            camera_tasks.push(spawn_worker("heartbeat", reporter, move |reporter| {
+                camera_heartbeat(heartbeat_sender, header, state_receiver, pulse, reporter)
+            }));
+
+            let backend = Arc::new(Mutex::new(backend));
+            let live_view = options.live_views.get(&id).map(|&server| {
+                let (settings, stream) = watch::channel(
+                    video_stream
+                        .clone()
+                        .unwrap_or_else(|| VideoStreamOptions::new("")),
+                );
+                let (backend, state) = (backend.clone(), state.subscribe());
+                camera_tasks.push(spawn_worker(
+                    "live view",
+                    WorkerReporter::new(&status, move |status| {
+                        &mut status.cameras.entry(id).or_default().live_view
+                    }),
+                    move |reporter| streaming::run(server, backend, state, stream, reporter),
+                ));
+                (server, settings)
+            });
+
+            let transmitter = options.image_transmission.map(|transmission| {
+                let (transmitter, images) = ImageTransmitter::new();
+                let link = sender.clone();
+                camera_tasks.push(spawn_worker(
+                    "image transmission",
+                    WorkerReporter::new(&status, move |status| {
+                        &mut status.cameras.entry(id).or_default().image_transmission
+                    }),
+                    move |reporter| transmission::run(link, header, transmission, images, reporter),
+                ));
+                transmitter
+            });
+
+            let sequence_file = options
+                .state_dir
+                .as_deref()
+                .map(|directory| SequenceFile::new(directory, id));
+            let sequence = match &sequence_file {
+                Some(sequence_file) => sequence_file.load().unwrap_or_else(|error| {
+                    warn!(
+                        path = %sequence_file.path().display(),
+                        "Failed to read the image counter, starting from 0: {error}"
+                    );
+                    CaptureSequence::default()
+                }),
+                None => CaptureSequence::default(),
+            };
+            if sequence.image_index > 0 {
+                info!(
+                    camera = id,
+                    image_index = sequence.image_index,
+                    "Continuing image counter"
+                );
+            }
 
-        let vehicle = mavlink::connect(&mavlink_connection_string).unwrap();
+            let downloads = options
+                .download_queue
+                .map(|queue| Downloader::spawn(backend.clone(), queue));
+            let (inbox, inbox_receiver) = mpsc::channel(INBOX_SIZE);
+            controlled.insert(
+                id,
+                ControlledCamera {
+                    inbox: inbox.clone(),
+                    state: state.subscribe(),
+                },
+            );
+            let reporter = WorkerReporter::new(&status, move |status| {
+                &mut status.cameras.entry(id).or_default().commands
+            });
+            let dispatcher = Dispatcher {
+                link: sender.clone(),
+                header,
+                backend,
+                events: events.clone(),
+                state,
+                vehicle: vehicle.subscribe(),
+                capture_log: capture_log.clone(),
+                footprints: footprints.clone(),
+                transmitter,
+                thumbnails: options.thumbnails,
+                image_url: image_urls.get(&id).cloned(),
+                filename_template: options.filename_template.clone(),
+                started,
+                storage: options.storage,
+                space: SpaceLevel::default(),
+                image_dir: None,
+                shutter,
+                image_index: sequence.image_index,
+                last_capture: sequence.last_capture,
+                sequence_file,
+                commanded: sequence.commanded,
+                captures: CaptureQueue::new(options.capture_queue),
+                capture_retry: options.capture_retry,
+                downloads,
+                keep_on_card: options.keep_on_card,
+                left_on_camera: Vec::new(),
+                download_video: options.download_video,
+                recording: None,
+                shutter_count: ShutterCount::new(
+                    sequence.shutter_count,
+                    options.rated_shutter_lives.get(&id).copied(),
+                ),
+                focus_locked: false,
+                trigger: DistanceTrigger::new(options.min_trigger_interval),
+                lens: None,
+                camera_names_lens: false,
+                autopilot_timeout: options.autopilot_timeout,
+                video_stream,
+                live_view,
+                overheat_temperature: options.overheat_temperature,
+                paused_for_heat: false,
+                sync_clock: options.sync_camera_clock,
+                clock_synced: false,
+                announce_information: adopted_system_id.is_some(),
+                default_rates: options.stream_rates,
+                streams: StreamSchedule::new(&options.stream_rates),
+                vendor_name: component.vendor_name.clone(),
+                model_name: component.model_name.clone(),
+                sensor: component.sensor,
+                definition_version: component.definition_version,
+                parameters: Parameters::default(),
+                definition: definitions
+                    .remove(&id)
+                    .unwrap_or_else(|| watch::channel(None).0),
+                definition_url: component
+                    .definition_uri
+                    .clone()
+                    .or_else(|| definition_urls.get(&id).cloned()),
+                bracketing: options.bracketing,
+                focus_stack: options.focus_stack,
+                autofocus: options.autofocus,
+                commands: options.commands,
+                timelapse: None,
+                status_texts: StatusTexts::default(),
+                time,
+                pulse: watchdog.watch(Task::Commands(id), reporter.clone()),
+            };
+            let shutdown = shutdown.subscribe();
+            dispatcher_tasks.push(spawn_worker("camera", reporter, move |reporter| {
+                dispatcher::run(inbox_receiver, dispatcher, shutdown, reporter)
+            }));
 
-        let information = Arc::new(Mutex::new(MavlinkCameraInformation {
-            component,
-            mavlink_connection_string,
-            vehicle: Arc::new(RwLock::new(vehicle)),
-        }));
+            routes.push(CameraRoute { header, inbox });
+        }
 
-        let heartbeat_info = information.clone();
-        let heartbeat_thread = thread::spawn(|| camera_heartbeat(heartbeat_info));
+        let control = Control::new(status.clone(), controlled, events.clone());
+        if let Some(http) = http {
+            let control = control.clone();
+            camera_tasks.push(spawn_worker(
+                "http",
+                WorkerReporter::new(&status, |status| &mut status.http),
+                move |reporter| http.run(control, reporter),
+            ));
+        }
 
-        let receive_message_info = information.clone();
-        let receive_message_thread = thread::spawn(|| receieve_message(receive_message_info));
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = grpc {
+            let control = control.clone();
+            camera_tasks.push(spawn_worker(
+                "grpc",
+                WorkerReporter::new(&status, |status| &mut status.grpc),
+                move |reporter| grpc.run(control, reporter),
+            ));
+        }
+        #[cfg(feature = "ros2")]
+        if let Some(ros2) = ros2 {
+            let control = control.clone();
+            camera_tasks.push(spawn_worker(
+                "ros2",
+                WorkerReporter::new(&status, |status| &mut status.ros2),
+                move |reporter| ros2.run(control, reporter),
+            ));
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = options.mqtt {
+            let control = control.clone();
+            camera_tasks.push(spawn_worker(
+                "mqtt",
+                WorkerReporter::new(&status, |status| &mut status.mqtt),
+                move |reporter| mqtt::run(mqtt, control, served_images, reporter),
+            ));
+        }
+
+        let (registrations, registration_receiver) = mpsc::unbounded_channel();
+        let messages = broadcast::channel(MESSAGE_QUEUE).0;
+        let router = Router {
+            link: sender,
+            cameras: routes,
+            events: events.clone(),
+            messages: messages.clone(),
+            handlers: HashMap::new(),
+            vehicle,
+            time,
+            status: status.clone(),
+        };
+        let reporter = WorkerReporter::new(&status, |status| &mut status.receiver);
+        let pulse = watchdog.watch(Task::Receive, reporter.clone());
+        let receive_message_task = spawn_worker("receive", reporter, move |reporter| {
+            receive_message(incoming, registration_receiver, router, pulse, reporter)
+        });
+        camera_tasks.push(spawn_worker(
+            "watchdog",
+            WorkerReporter::new(&status, |status| &mut status.watchdog),
+            move |reporter| watchdog::run(watchdog, reporter),
+        ));
 
         Ok(MavLinkCameraHandle {
-            camera_information: information,
-            heartbeat_thread,
-            receive_message_thread,
+            endpoints,
+            status,
+            events,
+            messages,
+            registrations,
+            commands: options.commands,
+            link_tasks,
+            receive_message_task,
+            dispatcher_tasks,
+            camera_tasks,
+            shutdown,
         })
     }
+
+    /// Returns the first MAVLink connection string the component was started with.
+    pub fn connection_string(&self) -> &str {
+        &self.endpoints[0]
+    }
+
+    /// Returns every MAVLink connection string the component talks to.
+    pub fn endpoints(&self) -> &[String] {
+        &self.endpoints
+    }
+
+    /// Returns the current health of the component's worker tasks.
+    pub fn status(&self) -> Result<ComponentStatus> {
+        Ok(self.status.lock()?.clone())
+    }
+
+    /// Subscribes to the component's [`CameraEvent`]s.
+    ///
+    /// Events published before subscribing are not delivered. A receiver that
+    /// falls too far behind skips the oldest events and gets
+    /// [`broadcast::error::RecvError::Lagged`].
+    pub fn subscribe(&self) -> broadcast::Receiver<CameraEvent> {
+        self.events.subscribe()
+    }
+
+    /// Subscribes to the messages the component doesn't consume itself, e.g.
+    /// `GLOBAL_POSITION_INT` or `MISSION_ITEM_REACHED` from the autopilot.
+    ///
+    /// This shares the component's connection, so no second link is needed.
+    /// Like [`MavLinkCameraHandle::subscribe`], a lagging receiver skips the
+    /// oldest messages.
+    pub fn messages(&self) -> broadcast::Receiver<(MavHeader, M)> {
+        self.messages.subscribe()
+    }
+
+    /// Calls `callback` for every event from a background task until the
+    /// component shuts down.
+    pub fn on_event(
+        &self,
+        mut callback: impl FnMut(CameraEvent) + Send + 'static,
+    ) -> JoinHandle<()> {
+        let mut events = self.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => callback(event),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Event callback fell behind, skipped {skipped} events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    /// Handles `command` with `handler` instead of the built-in behaviour.
+    ///
+    /// The handler runs on the receive task and the [`MavResult`] it returns is
+    /// sent back in the `COMMAND_ACK` of every camera the command addresses, so
+    /// it should return quickly. Registering the same command again replaces
+    /// the previous handler.
+    ///
+    /// The component's own commands can't be taken over, that fails with
+    /// [`CameraError::CommandTaken`]. Move them to other ids or turn them off
+    /// with [`ComponentOptions::commands`] instead.
+    pub fn on_command(
+        &self,
+        command: MavCmd,
+        handler: impl FnMut(&COMMAND_LONG_DATA) -> MavResult + Send + 'static,
+    ) -> Result<()> {
+        if self.commands.contains(command as u32) {
+            return Err(CameraError::CommandTaken(command as u32));
+        }
+        self.registrations
+            .send((command, Box::new(handler)))
+            .map_err(|_| CameraError::Stopped)
+    }
+
+    /// Waits until the component's worker tasks exit.
+    pub async fn join(mut self) {
+        let _ = (&mut self.receive_message_task).await;
+        self.join_workers().await;
+    }
+
+    /// Like [`MavLinkCameraHandle::join`], but shuts the component down
+    /// cleanly once `signal` completes, e.g. on `SIGTERM`.
+    pub async fn join_until(mut self, signal: impl Future<Output = ()>) {
+        tokio::select! {
+            _ = &mut self.receive_message_task => self.join_workers().await,
+            () = signal => self.shutdown().await,
+        }
+    }
+
+    /// Stops taking requests, lets every camera finish what it is doing,
+    /// e.g. downloading a capture, and then closes the connections.
+    pub async fn shutdown(mut self) {
+        info!("Shutting down");
+        self.receive_message_task.abort();
+        self.shutdown.send_replace(true);
+
+        let finished = async {
+            for task in &mut self.dispatcher_tasks {
+                let _ = task.await;
+            }
+        };
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, finished)
+            .await
+            .is_err()
+        {
+            warn!(timeout = ?SHUTDOWN_TIMEOUT, "Cameras still busy, shutting down anyway");
+        }
+
+        for task in &self.dispatcher_tasks {
+            task.abort();
+        }
+
+        // The servers and links go last so the captures finished above still
+        // reach their subscribers.
+        for task in self.camera_tasks.iter().chain(&self.link_tasks) {
+            task.abort();
+        }
+        for task in self.camera_tasks.into_iter().chain(self.link_tasks) {
+            let _ = task.await;
+        }
+    }
+
+    async fn join_workers(self) {
+        for task in self
+            .dispatcher_tasks
+            .into_iter()
+            .chain(self.camera_tasks)
+            .chain(self.link_tasks)
+        {
+            let _ = task.await;
+        }
+    }
 }
 
-fn heartbeat_message() -> MavMessage {
+pub(crate) fn heartbeat_message(state: &CameraState) -> MavMessage {
     MavMessage::HEARTBEAT(mavlink::common::HEARTBEAT_DATA {
-        custom_mode: 0,
+        custom_mode: state.custom_mode(),
         mavtype: mavlink::common::MavType::MAV_TYPE_CAMERA,
         autopilot: mavlink::common::MavAutopilot::MAV_AUTOPILOT_INVALID,
         base_mode: mavlink::common::MavModeFlag::empty(),
-        system_status: mavlink::common::MavState::MAV_STATE_STANDBY,
+        system_status: state.system_status(),
         mavlink_version: 0x3,
     })
 }
 
-fn camera_heartbeat(mavlink_info: Arc<Mutex<MavlinkCameraInformation>>) {
-    let information = mavlink_info.lock().unwrap();
-    let vehicle = information.vehicle.clone();
-
-    let mut header = mavlink::MavHeader::default();
-    header.system_id = information.component.system_id;
-    header.component_id = information.component.component_id;
-    println!("{header:?}");
+/// Sends a heartbeat at the rate in the camera state, every second unless
+/// changed, and right away when the state changes.
+async fn camera_heartbeat<M: CameraDialect>(
+    link: LinkSender<M>,
+    header: MavHeader,
+    mut state: watch::Receiver<CameraState>,
+    pulse: Pulse,
+    reporter: WorkerReporter,
+) -> Result<()> {
+    info!(target: "heartbeat", ?header, "Starting heartbeat");
 
-    drop(information);
+    let mut interval = tokio::time::interval(state.borrow().stream_rates.heartbeat);
 
     loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
+        tokio::select! {
+            _ = interval.tick() => {}
+            changed = state.changed() => {
+                changed.map_err(|_| CameraError::Stopped)?;
+                interval.reset();
+            }
+        }
 
-        if let Err(error) = vehicle.read().unwrap().send(&header, &heartbeat_message()) {
-            println!("Failed to send heartbeat: {error}");
-        } else {
-            println!("Sent heartbeat!")
+        let current = *state.borrow_and_update();
+        let period = current.stream_rates.heartbeat;
+        if interval.period() != period {
+            debug!(target: "heartbeat", ?period, "Changed heartbeat interval");
+            interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
         }
+        link.send(&header, heartbeat_message(&current))?;
+        trace!(target: "heartbeat", status = ?current.system_status(), "Queued heartbeat");
+        pulse.beat();
+        reporter.running();
     }
 }
 
-fn receieve_message(mavlink_info: Arc<Mutex<MavlinkCameraInformation>>) {
-    let information = mavlink_info.lock().unwrap();
-    let vehicle = information.vehicle.clone();
+/// Routes incoming messages: commands to the cameras they address, everything
+/// else to [`MavLinkCameraHandle::messages`] subscribers.
+struct Router<M> {
+    link: LinkSender<M>,
+    cameras: Vec<CameraRoute>,
+    events: EventSender,
+    messages: broadcast::Sender<(MavHeader, M)>,
+    /// Application handlers keyed by `MavCmd as u32`.
+    handlers: HashMap<u32, CommandHandler>,
+    /// Latest autopilot position for geotagging captures.
+    vehicle: watch::Sender<VehicleState>,
+    time: TimeSource,
+    /// Where the ground stations and autopilots heard from are kept.
+    status: Arc<Mutex<ComponentStatus>>,
+}
 
-    let mut header = mavlink::MavHeader::default();
-    header.system_id = information.component.system_id;
-    header.component_id = information.component.component_id;
+struct CameraRoute {
+    header: MavHeader,
+    inbox: mpsc::Sender<(MavHeader, Request)>,
+}
 
-    drop(information);
+impl CameraRoute {
+    /// Whether a message for `system` and `component` reaches this camera, 0
+    /// addressing everyone.
+    fn is_addressed_by(&self, system: u8, component: u8) -> bool {
+        (system == 0 || system == self.header.system_id)
+            && (component == 0 || component == self.header.component_id)
+    }
+}
 
+/// Routes every received message as soon as an IO task hands it over, so
+/// the time from a trigger command to the shutter is the camera's.
+async fn receive_message<M: CameraDialect>(
+    mut incoming: Incoming<M>,
+    mut registrations: mpsc::UnboundedReceiver<(MavCmd, CommandHandler)>,
+    mut router: Router<M>,
+    pulse: Pulse,
+    reporter: WorkerReporter,
+) -> Result<()> {
     loop {
-        thread::sleep(Duration::from_millis(100));
-
-        match vehicle.read().unwrap().recv() {
-            Ok((recv_header, recv_msg)) => match recv_msg {
-                MavMessage::COMMAND_LONG(command_long) => {
-                    send_command_ack(
-                        &vehicle,
-                        &header,
-                        &recv_header,
-                        command_long.command,
-                        mavlink::common::MavResult::MAV_RESULT_ACCEPTED,
-                    );
+        pulse.idle();
+        let (recv_header, recv_msg) = tokio::select! {
+            // Registrations first so a handler added before a command arrives sees it.
+            biased;
+            Some((command, handler)) = registrations.recv() => {
+                debug!(target: "rx", ?command, "Registered command handler");
+                router.handlers.insert(command as u32, handler);
+                continue;
+            }
+            message = incoming.recv() => message.ok_or(CameraError::LinkClosed)?,
+        };
 
-                    println!("Received Command: {:?}", command_long.command);
+        pulse.beat();
+        reporter.running();
 
-                    match command_long {
-                        cmd @ mavlink::common::COMMAND_LONG_DATA {param1: 259.0, ..} => {
-                            println!("Requesting camera info: {cmd:?}");
-                            vehicle.read().unwrap().send(&header, &camera_information());
-                        },
-                        _ => {}
-                    }
-                },
-                _ => {}
-            },
-            Err(error) => {}
-        }
-    }
-}
-
-fn send_command_ack(
-    vehicle: &Vehicle,
-    our_header: &mavlink::MavHeader,
-    their_header: &mavlink::MavHeader,
-    command: mavlink::common::MavCmd,
-    result: mavlink::common::MavResult,
-) {
-    if let Err(err) = vehicle.read().unwrap().send(
-        our_header,
-        &MavMessage::COMMAND_ACK(mavlink::common::COMMAND_ACK_DATA {
-            command,
-            result,
-            target_system: their_header.system_id,
-            target_component: their_header.component_id,
+        let common = recv_msg.to_common();
+        match (&common, recv_msg.capture_request()) {
+            (Some(MavMessage::COMMAND_LONG(command_long)), _)
+                if router.is_for_us(command_long.target_system, command_long.target_component) =>
+            {
+                router.route_command(&recv_header, command_long.clone())?;
+            }
+            (_, Some((system, component))) if router.is_for_us(system, component) => {
+                router.route_capture_request(&recv_header, system, component);
+            }
+            // Answers have the sender's time in `tc1`.
+            (Some(MavMessage::TIMESYNC(timesync)), _) if timesync.tc1 == 0 => {
+                router.answer_timesync(&recv_header, timesync)?;
+            }
+            // Requests are addressed to everyone, answers to whoever pinged.
+            (Some(MavMessage::PING(ping)), _)
+                if ping.target_system == 0 && ping.target_component == 0 =>
+            {
+                router.answer_ping(&recv_header, ping)?;
+            }
+            (Some(message), _)
+                if parameter_target(message)
+                    .is_some_and(|(system, component)| router.is_for_us(system, component)) =>
+            {
+                router.route_parameter_request(&recv_header, message);
+            }
+            _ => {
+                if let Some(MavMessage::HEARTBEAT(heartbeat)) = &common {
+                    router.track_peer(&recv_header, heartbeat);
+                }
+                if let Some(common) = &common {
+                    router
+                        .vehicle
+                        .send_if_modified(|vehicle| vehicle.update(&recv_header, common));
+                }
+                // Nobody subscribed is fine.
+                let _ = router.messages.send((recv_header, recv_msg));
+            }
+        }
+    }
+}
+
+impl<M: CameraDialect> Router<M> {
+    fn is_for_us(&self, system: u8, component: u8) -> bool {
+        self.cameras
+            .iter()
+            .any(|camera| camera.is_addressed_by(system, component))
+    }
+
+    fn route_command(
+        &mut self,
+        recv_header: &MavHeader,
+        command_long: COMMAND_LONG_DATA,
+    ) -> Result<()> {
+        self.events.emit(CameraEvent::CommandReceived {
+            command: command_long.command,
+            from_system: recv_header.system_id,
+            from_component: recv_header.component_id,
+        });
+
+        let targets = self.cameras.iter().filter(|camera| {
+            camera.is_addressed_by(command_long.target_system, command_long.target_component)
+        });
+
+        if let Some(handler) = self.handlers.get_mut(&(command_long.command as u32)) {
+            let result = handler(&command_long);
+            debug!(target: "rx", command = ?command_long.command, ?result, "Handled by application");
+
+            for camera in targets {
+                send_command_ack(
+                    &self.link,
+                    &camera.header,
+                    recv_header,
+                    command_long.command,
+                    result,
+                )?;
+            }
+            return Ok(());
+        }
+
+        for camera in targets {
+            let request = Request::Command(command_long.clone());
+            let result = match camera.inbox.try_send((*recv_header, request)) {
+                Ok(()) => continue,
+                Err(TrySendError::Full(_)) => {
+                    warn!(target: "rx", camera = camera.header.component_id, command = ?command_long.command, "Camera busy, rejecting command");
+                    MavResult::MAV_RESULT_TEMPORARILY_REJECTED
+                }
+                Err(TrySendError::Closed(_)) => MavResult::MAV_RESULT_FAILED,
+            };
+
+            send_command_ack(
+                &self.link,
+                &camera.header,
+                recv_header,
+                command_long.command,
+                result,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Answers a `TIMESYNC` request with the component's time, so the sender
+    /// can work out how far its clock is off and match up the logs. The
+    /// cameras share the clock, so the first one answers for all.
+    fn answer_timesync(&self, recv_header: &MavHeader, request: &TIMESYNC_DATA) -> Result<()> {
+        let reply = MavMessage::TIMESYNC(TIMESYNC_DATA {
+            tc1: self.time.boot_ns(),
+            ts1: request.ts1,
             ..Default::default()
-        }),
-    ) {
-        eprintln!("Failed to send command ack");
+        });
+        self.link
+            .send_to(&self.cameras[0].header, recv_header, reply)
+    }
+
+    /// Answers a `PING` request from every camera, so the sender can tell
+    /// the latency of the link to the companion computer apart from the one
+    /// to the autopilot.
+    fn answer_ping(&self, recv_header: &MavHeader, request: &PING_DATA) -> Result<()> {
+        for camera in &self.cameras {
+            let reply = MavMessage::PING(PING_DATA {
+                time_usec: request.time_usec,
+                seq: request.seq,
+                target_system: recv_header.system_id,
+                target_component: recv_header.component_id,
+            });
+            self.link.send_to(&camera.header, recv_header, reply)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds the sender of `heartbeat` to the peers if it's a ground station
+    /// or an autopilot, or notes that it's still there.
+    fn track_peer(&self, recv_header: &MavHeader, heartbeat: &HEARTBEAT_DATA) {
+        let kind = if heartbeat.mavtype == MavType::MAV_TYPE_GCS {
+            PeerKind::GroundStation
+        } else if heartbeat.autopilot != MavAutopilot::MAV_AUTOPILOT_INVALID {
+            PeerKind::Autopilot
+        } else {
+            return;
+        };
+        let Ok(mut status) = self.status.lock() else {
+            return;
+        };
+
+        let key = (recv_header.system_id, recv_header.component_id);
+        let peer = Peer {
+            kind,
+            last_heartbeat: Instant::now(),
+        };
+        if status.peers.insert(key, peer).is_none() {
+            info!(
+                target: "rx",
+                system = key.0,
+                component = key.1,
+                kind = kind.name(),
+                "Found peer"
+            );
+        }
+    }
+
+    /// Hands a `PARAM_EXT` request to the cameras it addresses. Nothing is
+    /// acked, so a busy camera just misses it and the ground station retries.
+    fn route_parameter_request(&self, recv_header: &MavHeader, message: &MavMessage) {
+        let Some((system, component)) = parameter_target(message) else {
+            return;
+        };
+        let targets = self
+            .cameras
+            .iter()
+            .filter(|camera| camera.is_addressed_by(system, component));
+
+        for camera in targets {
+            let request = Request::Parameter(Box::new(message.clone()));
+            if camera.inbox.try_send((*recv_header, request)).is_err() {
+                warn!(target: "rx", camera = camera.header.component_id, "Camera busy, dropping parameter request");
+            }
+        }
+    }
+
+    /// Hands a [`CameraDialect::capture_request`] to the cameras it addresses.
+    /// Nothing is acked, so a busy camera just misses it.
+    fn route_capture_request(&self, recv_header: &MavHeader, system: u8, component: u8) {
+        let targets = self
+            .cameras
+            .iter()
+            .filter(|camera| camera.is_addressed_by(system, component));
+
+        for camera in targets {
+            if camera
+                .inbox
+                .try_send((*recv_header, Request::Capture))
+                .is_err()
+            {
+                warn!(target: "rx", camera = camera.header.component_id, "Camera busy, dropping capture request");
+            }
+        }
     }
 }
 
-pub fn camera_information() -> MavMessage {
+/// Builds the `CAMERA_INFORMATION` message describing the `vendor` `model`
+/// camera with the `sensor`, if known, and its camera definition as version
+/// and URI, if there's one. The time, lens and further capabilities are left
+/// for the sender to fill in.
+pub fn camera_information(
+    vendor: &str,
+    model: &str,
+    sensor: Option<Sensor>,
+    definition: Option<(u16, &str)>,
+) -> MavMessage {
+    let (definition_version, definition_uri) = definition.unwrap_or((0, ""));
+    // Zero tells ground stations the geometry is unknown.
+    let Sensor {
+        size_mm: (sensor_size_h, sensor_size_v),
+        resolution: (resolution_h, resolution_v),
+    } = sensor.unwrap_or(Sensor {
+        size_mm: (0.0, 0.0),
+        resolution: (0, 0),
+    });
     MavMessage::CAMERA_INFORMATION(mavlink::common::CAMERA_INFORMATION_DATA {
-        time_boot_ms: (sys_info::boottime().unwrap().tv_usec / 1000) as u32,
-        firmware_version: (1 & 0xff) << 24 | 0 << 16 | 0 << 8,
+        time_boot_ms: 0,
+        firmware_version: 1 << 24,
         focal_length: 0.0,
-        sensor_size_h: 35.9,
-        sensor_size_v: 24.0,
+        sensor_size_h,
+        sensor_size_v,
         flags: CameraCapFlags::CAMERA_CAP_FLAGS_CAPTURE_IMAGE
             | CameraCapFlags::CAMERA_CAP_FLAGS_HAS_IMAGE_SURVEY_MODE,
-        resolution_h: 7952,
-        resolution_v: 5304,
-        cam_definition_version: 1,
-        vendor_name: str_to_fixed_arr("Davis Vendor"),
-        model_name: str_to_fixed_arr("Sony a7r ii"),
+        resolution_h,
+        resolution_v,
+        cam_definition_version: definition_version,
+        vendor_name: str_to_fixed_arr(vendor),
+        model_name: str_to_fixed_arr(model),
         lens_id: 0,
-        cam_definition_uri: string_to_uri("Nill"),
+        cam_definition_uri: string_to_uri(definition_uri),
     })
 }
 
-fn str_to_fixed_arr<const N: usize>(src: &str) -> [u8; N] {
+pub(crate) fn str_to_fixed_arr<const N: usize>(src: &str) -> [u8; N] {
     let bytes = src.as_bytes();
     let mut dst = [0u8; N];
     let len = std::cmp::min(bytes.len(), N);
@@ -182,6 +1173,8 @@ fn str_to_fixed_arr<const N: usize>(src: &str) -> [u8; N] {
     dst
 }
 
-fn string_to_uri<const N: usize>(src: &str) -> Vec<u8, N> {
-    Vec::from_slice(src.as_bytes()).unwrap()
+pub(crate) fn string_to_uri<const N: usize>(src: &str) -> heapless::Vec<u8, N> {
+    let bytes = src.as_bytes();
+    let len = std::cmp::min(bytes.len(), N);
+    heapless::Vec::from_slice(&bytes[..len]).unwrap_or_default()
 }
@@ -0,0 +1,137 @@
+//! The adapter between a MAVLink dialect and the component.
+//!
+//! Internally the component only deals in the `common` messages every dialect
+//! includes. [`CameraDialect`] converts those to and from the dialect spoken on
+//! the link, and covers the few camera messages that only some dialects have.
+
+use crate::geotag::Geotag;
+use mavlink::common;
+use mavlink::{MavlinkVersion, Message};
+
+/// A MAVLink dialect the component can speak, implemented for the generated
+/// `MavMessage` of the dialect.
+///
+/// The conversions default to re-encoding the payload, which works for any
+/// dialect that includes `common` unchanged. Custom dialects usually only need
+/// an empty `impl`.
+pub trait CameraDialect:
+    Message + Clone + PartialEq + std::fmt::Debug + Send + Sync + 'static
+{
+    /// Returns this message as its `common` equivalent, `None` for messages
+    /// specific to the dialect.
+    fn to_common(&self) -> Option<common::MavMessage> {
+        let mut payload = [0; 255];
+        let len = self.ser(MavlinkVersion::V2, &mut payload);
+        common::MavMessage::parse(MavlinkVersion::V2, self.message_id(), &payload[..len]).ok()
+    }
+
+    /// Converts a `common` message into this dialect, `None` if the dialect
+    /// doesn't have it.
+    fn from_common(message: &common::MavMessage) -> Option<Self> {
+        let mut payload = [0; 255];
+        let len = message.ser(MavlinkVersion::V2, &mut payload);
+        Self::parse(MavlinkVersion::V2, message.message_id(), &payload[..len]).ok()
+    }
+
+    /// Returns the target system and component if this asks for a picture
+    /// outside of the camera protocol, e.g. ArduPilot's `DIGICAM_CONTROL`.
+    fn capture_request(&self) -> Option<(u8, u8)> {
+        None
+    }
+
+    /// Builds the dialect's report of a successful capture sent next to
+    /// `CAMERA_IMAGE_CAPTURED`, e.g. ArduPilot's `CAMERA_FEEDBACK`.
+    fn capture_feedback(_feedback: &CaptureFeedback) -> Option<Self> {
+        None
+    }
+
+    /// Returns the free space of a telemetry radio's transmit buffer in
+    /// percent if this is a radio's status, e.g. `RADIO_STATUS`.
+    fn radio_txbuf(&self) -> Option<u8> {
+        if self.message_name() != "RADIO_STATUS" {
+            return None;
+        }
+
+        match self.to_common()? {
+            common::MavMessage::RADIO_STATUS(status) => Some(status.txbuf),
+            _ => None,
+        }
+    }
+}
+
+/// A successful, geotagged capture, see [`CameraDialect::capture_feedback`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptureFeedback {
+    /// When the image was taken in microseconds since the Unix epoch.
+    pub time_usec: u64,
+    /// Index of the image, as in `CAMERA_IMAGE_CAPTURED`.
+    pub image_index: i32,
+    /// The camera's component id.
+    pub camera: u8,
+    pub geotag: Geotag,
+    /// Whether the time came from the hot-shoe rather than the trigger.
+    pub closed_loop: bool,
+}
+
+impl CameraDialect for common::MavMessage {
+    fn to_common(&self) -> Option<common::MavMessage> {
+        Some(self.clone())
+    }
+
+    fn from_common(message: &common::MavMessage) -> Option<Self> {
+        Some(message.clone())
+    }
+}
+
+#[cfg(feature = "ardupilotmega")]
+impl CameraDialect for mavlink::ardupilotmega::MavMessage {
+    fn capture_request(&self) -> Option<(u8, u8)> {
+        match self {
+            Self::DIGICAM_CONTROL(control) if control.shot != 0 => {
+                Some((control.target_system, control.target_component))
+            }
+            _ => None,
+        }
+    }
+
+    /// `CAMERA_FEEDBACK`, which ArduPilot's dataflash logs and geotagging
+    /// scripts use.
+    fn capture_feedback(feedback: &CaptureFeedback) -> Option<Self> {
+        use mavlink::ardupilotmega::{CameraFeedbackFlags, MavComponent, CAMERA_FEEDBACK_DATA};
+
+        let geotag = &feedback.geotag;
+        let [roll, pitch, yaw] = geotag.attitude.unwrap_or_default();
+
+        Some(Self::CAMERA_FEEDBACK(CAMERA_FEEDBACK_DATA {
+            time_usec: feedback.time_usec,
+            lat: geotag.lat,
+            lng: geotag.lon,
+            alt_msl: geotag.alt as f32 / 1000.0,
+            alt_rel: geotag.relative_alt as f32 / 1000.0,
+            roll: roll.to_degrees(),
+            pitch: pitch.to_degrees(),
+            yaw: yaw.to_degrees(),
+            img_idx: feedback.image_index as u16,
+            // Camera 1 is MAV_COMP_ID_CAMERA, camera 2 MAV_COMP_ID_CAMERA2 and so on.
+            cam_idx: feedback
+                .camera
+                .saturating_sub(MavComponent::MAV_COMP_ID_CAMERA as u8),
+            flags: if feedback.closed_loop {
+                CameraFeedbackFlags::CAMERA_FEEDBACK_CLOSEDLOOP
+            } else {
+                CameraFeedbackFlags::CAMERA_FEEDBACK_PHOTO
+            },
+            completed_captures: (feedback.image_index + 1) as u16,
+            ..Default::default()
+        }))
+    }
+
+    /// Also ArduPilot's `RADIO`, which older SiK firmware sends instead.
+    fn radio_txbuf(&self) -> Option<u8> {
+        match self {
+            Self::RADIO_STATUS(status) => Some(status.txbuf),
+            Self::RADIO(radio) => Some(radio.txbuf),
+            _ => None,
+        }
+    }
+}
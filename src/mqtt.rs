@@ -0,0 +1,149 @@
+//! Bridge to an MQTT broker for cloud fleet management, publishing under the
+//! configured topic prefix:
+//!
+//! - `<prefix>/status`: `online`, or `offline` once the component is gone,
+//!   retained
+//! - `<prefix>/cameras/<camera>/state`: the camera's state as JSON, retained
+//! - `<prefix>/cameras/<camera>/events`: the camera's [`crate::CameraEvent`]s
+//!   as JSON, the same as on the REST API's WebSocket
+//! - `<prefix>/events`: events about the whole component, e.g. a lost link
+//!
+//! where `<camera>` is the camera's component id. Image URLs point at the
+//! HTTP server when it serves the images and are `null` otherwise.
+
+use crate::control::Control;
+use crate::error::Result;
+use crate::http::HttpServerOptions;
+use crate::state::CameraState;
+use crate::status::{WorkerReporter, WorkerStatus};
+use rumqttc::{AsyncClient, Event, LastWill, Packet, QoS};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+
+/// How often the broker is pinged while nothing is published.
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// Messages that may wait for the broker before new ones are dropped.
+const QUEUE: usize = 64;
+
+/// How long to wait before connecting again after losing the broker.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The broker to publish to and under which topics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttOptions {
+    pub host: String,
+    pub port: u16,
+    /// Unique among the clients of the broker.
+    pub client_id: String,
+    /// What every topic starts with, e.g. `fleet/drone-7/camera`.
+    pub topic_prefix: String,
+    /// Credentials for brokers that want them.
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl MqttOptions {
+    /// Publishes to the broker at `host` on the standard port, under
+    /// `camera/`.
+    pub fn new(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            port: 1883,
+            client_id: "camera".to_owned(),
+            topic_prefix: "camera".to_owned(),
+            username: None,
+            password: None,
+        }
+    }
+
+    fn topic(&self, topic: &str) -> String {
+        format!("{}/{topic}", self.topic_prefix.trim_end_matches('/'))
+    }
+}
+
+/// Publishes the states of the cameras and every event until the component
+/// shuts down, connecting again whenever the broker goes away. `http` says
+/// where images are served from.
+pub(crate) async fn run(
+    options: MqttOptions,
+    control: Control,
+    http: Option<HttpServerOptions>,
+    reporter: WorkerReporter,
+) -> Result<()> {
+    let status_topic = options.topic("status");
+    let mut mqtt = rumqttc::MqttOptions::new(&options.client_id, &options.host, options.port);
+    mqtt.set_keep_alive(KEEP_ALIVE);
+    mqtt.set_last_will(LastWill::new(
+        &status_topic,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &options.username {
+        mqtt.set_credentials(username, options.password.clone().unwrap_or_default());
+    }
+    let (client, mut connection) = AsyncClient::new(mqtt, QUEUE);
+
+    // Publishing never waits: the client's queue is only emptied by polling
+    // the connection, which this task does too.
+    let publish = |topic: String, retain: bool, payload: String| {
+        if let Err(error) = client.try_publish(&topic, QoS::AtLeastOnce, retain, payload) {
+            debug!(target: "backend", topic, "Dropped MQTT message: {error}");
+        }
+    };
+    let url = |route: &str, camera, path: &Path| http.as_ref()?.file_url(route, camera, path);
+    let mut events = control.subscribe();
+    let mut changes = control.state_changes();
+    let mut states = BTreeMap::new();
+
+    loop {
+        tokio::select! {
+            polled = connection.poll() => match polled {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!(target: "backend", host = options.host, "Connected to MQTT broker");
+                    reporter.running();
+                    publish(status_topic.clone(), true, "online".to_owned());
+                    // Brokers may have lost what they retained.
+                    for (camera, state) in &states {
+                        publish(state_topic(&options, *camera), true, state_json(state));
+                    }
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    warn!(target: "backend", host = options.host, "MQTT broker unreachable: {error}");
+                    reporter.set(WorkerStatus::Degraded(format!("MQTT broker: {error}")));
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let topic = match event.camera() {
+                        Some(camera) => options.topic(&format!("cameras/{camera}/events")),
+                        None => options.topic("events"),
+                    };
+                    publish(topic, false, event.json(url));
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(target: "backend", skipped, "MQTT bridge fell behind, events skipped");
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            Some((camera, state)) = changes.recv() => {
+                publish(state_topic(&options, camera), true, state_json(&state));
+                states.insert(camera, state);
+            }
+        }
+    }
+}
+
+fn state_topic(options: &MqttOptions, camera: u8) -> String {
+    options.topic(&format!("cameras/{camera}/state"))
+}
+
+fn state_json(state: &CameraState) -> String {
+    format!("{{{}}}", state.json_fields())
+}
@@ -0,0 +1,203 @@
+//! File names for downloaded images from a template such as
+//! `{flight}/{date}/{seq:05}_{lat}_{lon}.{ext}`, to match the folder layout
+//! photogrammetry pipelines expect.
+//!
+//! Placeholders:
+//!
+//! - `{flight}`: UTC time the vehicle last armed as `YYYYMMDD_HHMMSS`, or
+//!   when the component started if it hasn't
+//! - `{date}` and `{time}`: UTC capture time as `YYYY-MM-DD` and `HHMMSS`
+//! - `{seq}`: the image index, `{camera}`: the camera's component id. Both
+//!   take a width, `{seq:05}` pads with zeros to 5 digits
+//! - `{lat}`, `{lon}` in degrees and `{alt}` above home in metres, `unknown`
+//!   without a position fix
+//! - `{name}` and `{ext}`: the camera's own file name and extension
+//!
+//! Names are relative to the camera's image directory and `/` starts a
+//! subdirectory.
+
+use crate::geotag::Geotag;
+use chrono::{DateTime, Utc};
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A parsed file name template, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(Field, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Flight,
+    Date,
+    Time,
+    Seq,
+    Camera,
+    Lat,
+    Lon,
+    Alt,
+    Name,
+    Ext,
+}
+
+/// Why a template was rejected.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("unknown placeholder {{{0}}}")]
+    UnknownPlaceholder(String),
+    #[error("invalid width in {{{0}}}")]
+    InvalidWidth(String),
+    #[error("unclosed {{")]
+    Unclosed,
+    #[error("the template must give a relative path without `..`")]
+    NotRelative,
+}
+
+/// What a capture's name is made of.
+#[derive(Clone)]
+pub(crate) struct NameContext {
+    pub flight: DateTime<Utc>,
+    pub taken: DateTime<Utc>,
+    pub seq: i32,
+    pub camera: u8,
+    pub geotag: Option<Geotag>,
+}
+
+impl FromStr for FilenameTemplate {
+    type Err = TemplateError;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        if template.starts_with('/') || template.split('/').any(|segment| segment == "..") {
+            return Err(TemplateError::NotRelative);
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_owned()));
+            }
+            let end = rest[start..].find('}').ok_or(TemplateError::Unclosed)? + start;
+            let placeholder = &rest[start + 1..end];
+
+            let (name, width) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+            let field = match name {
+                "flight" => Field::Flight,
+                "date" => Field::Date,
+                "time" => Field::Time,
+                "seq" => Field::Seq,
+                "camera" => Field::Camera,
+                "lat" => Field::Lat,
+                "lon" => Field::Lon,
+                "alt" => Field::Alt,
+                "name" => Field::Name,
+                "ext" => Field::Ext,
+                _ => return Err(TemplateError::UnknownPlaceholder(placeholder.to_owned())),
+            };
+            let width = match (field, width) {
+                (_, "") => 0,
+                (Field::Seq | Field::Camera, width) => width
+                    .parse()
+                    .map_err(|_| TemplateError::InvalidWidth(placeholder.to_owned()))?,
+                _ => return Err(TemplateError::InvalidWidth(placeholder.to_owned())),
+            };
+            parts.push(Part::Field(field, width));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_owned()));
+        }
+
+        Ok(Self { parts })
+    }
+}
+
+impl FilenameTemplate {
+    /// The name of `image` relative to its directory.
+    fn render(&self, image: &Path, context: &NameContext) -> String {
+        let stem = image.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = image.extension().unwrap_or_default().to_string_lossy();
+        let coordinate = |value: Option<f64>, precision: usize| match value {
+            Some(value) => format!("{value:.precision$}"),
+            None => "unknown".to_owned(),
+        };
+
+        let mut name = String::new();
+        for part in &self.parts {
+            let _ = match part {
+                Part::Literal(literal) => name.write_str(literal),
+                Part::Field(field, width) => match field {
+                    Field::Flight => write!(name, "{}", context.flight.format("%Y%m%d_%H%M%S")),
+                    Field::Date => write!(name, "{}", context.taken.format("%Y-%m-%d")),
+                    Field::Time => write!(name, "{}", context.taken.format("%H%M%S")),
+                    Field::Seq => write!(name, "{:0width$}", context.seq),
+                    Field::Camera => write!(name, "{:0width$}", context.camera),
+                    Field::Lat => name.write_str(&coordinate(
+                        context.geotag.map(|geotag| f64::from(geotag.lat) / 1e7),
+                        7,
+                    )),
+                    Field::Lon => name.write_str(&coordinate(
+                        context.geotag.map(|geotag| f64::from(geotag.lon) / 1e7),
+                        7,
+                    )),
+                    Field::Alt => name.write_str(&coordinate(
+                        context
+                            .geotag
+                            .map(|geotag| f64::from(geotag.relative_alt) / 1000.0),
+                        1,
+                    )),
+                    Field::Name => name.write_str(&stem),
+                    Field::Ext => name.write_str(&extension),
+                },
+            };
+        }
+        name
+    }
+
+    /// Moves the downloaded `image` to its templated name next to it and
+    /// returns the new path. An existing file isn't overwritten, `_1`, `_2`
+    /// and so on are added to the name instead. Blocks on the disk.
+    pub(crate) fn rename(&self, image: &Path, context: &NameContext) -> io::Result<PathBuf> {
+        let directory = image.parent().unwrap_or(Path::new("."));
+        let target = directory.join(self.render(image, context));
+        if target == image {
+            return Ok(target);
+        }
+
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let target = free_path(target);
+        std::fs::rename(image, &target)?;
+
+        Ok(target)
+    }
+}
+
+/// `path`, or the first of `path_1`, `path_2`, ... that doesn't exist yet.
+fn free_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|suffix| path.with_file_name(format!("{stem}_{suffix}{extension}")))
+        .find(|candidate| !candidate.exists())
+        .expect("some suffix is free")
+}
@@ -0,0 +1,34 @@
+//! Post-flight offload of the pictures left on the camera's card, either as
+//! [`crate::ComponentOptions::keep_on_card`] keeps every picture there to
+//! capture as fast as the camera can or as the download queue was full.
+
+use mavlink::common::{MavCmd, MavSeverity};
+
+/// Downloads the pictures left on the camera, oldest first. It's acked as in
+/// progress with the share downloaded after every picture and with the
+/// outcome once done, which also goes out as `STATUSTEXT`. Pictures that fail
+/// to download stay for the next offload.
+pub const OFFLOAD_COMMAND: MavCmd = MavCmd::MAV_CMD_USER_5;
+
+/// The share of `total` pictures `done` is, in percent for `COMMAND_ACK`.
+pub(crate) fn progress(done: usize, total: usize) -> u8 {
+    (done * 100).checked_div(total).unwrap_or(100) as u8
+}
+
+/// The `STATUSTEXT` severity and text once `total` pictures were offloaded,
+/// `failed` of them not.
+pub(crate) fn report(total: usize, failed: usize) -> (MavSeverity, String) {
+    match failed {
+        0 => (
+            MavSeverity::MAV_SEVERITY_NOTICE,
+            format!("Offloaded {total} pictures"),
+        ),
+        failed => (
+            MavSeverity::MAV_SEVERITY_WARNING,
+            format!(
+                "Offloaded {} of {total} pictures, {failed} failed",
+                total - failed
+            ),
+        ),
+    }
+}
@@ -0,0 +1,842 @@
+//! Camera settings as MAVLink extended parameters (`PARAM_EXT_*`), with the
+//! camera definition file that tells ground stations what they mean.
+//!
+//! Settings with a list of values are `uint32` parameters holding the index of
+//! the camera's current choice, and the definition lists the choices by name
+//! so ground stations show `1/1000` rather than `2`. Numeric settings hold the
+//! value itself: a `uint32` for the colour temperature, a `float` for exposure
+//! compensation. The shutter count is a `uint32` that can only be read.
+//!
+//! `CAM_AFSHOT` turns autofocus before every shot off and on. It's kept by
+//! the component rather than the camera, and only shown for cameras that
+//! can autofocus on command. `CAM_FOCUSDRV` drives the manual focus a small,
+//! medium or large step nearer or farther for fine-tuning while watching the
+//! live view, and reads `Hold` again once the focus moved. It's only shown
+//! for cameras that drive their focus.
+
+use crate::backend::{CameraBackend, SettingChoices};
+use crate::mavlink_camera::str_to_fixed_arr;
+use mavlink::common::{
+    MavMessage, MavParamExtType, ParamAck, PARAM_EXT_ACK_DATA, PARAM_EXT_VALUE_DATA,
+};
+use std::fmt::Write as _;
+use std::iter;
+use std::sync::Arc;
+
+/// A camera setting exposed as a parameter.
+#[derive(Debug)]
+struct ParameterSpec {
+    /// The name ground stations know the setting by.
+    name: &'static str,
+    description: &'static str,
+    /// Backend keys of the setting, drivers differ. The first one the camera
+    /// has is used.
+    keys: &'static [&'static str],
+    /// The parameters ground stations grey out while the setting has the
+    /// given choice.
+    excludes: fn(&str) -> &'static [&'static str],
+    /// Shown as a `float` range even if the camera lists its values, e.g.
+    /// exposure compensation in thirds of a stop.
+    real: bool,
+}
+
+static PARAMETERS: [ParameterSpec; 9] = [
+    ParameterSpec {
+        name: "CAM_EXPMODE",
+        description: "Exposure Mode",
+        keys: &["expprogram", "autoexposuremode", "autoexposuremodedial"],
+        excludes: exposure_mode_excludes,
+        real: false,
+    },
+    ParameterSpec {
+        name: "CAM_APERTURE",
+        description: "Aperture",
+        keys: &["f-number", "aperture"],
+        excludes: excludes_nothing,
+        real: false,
+    },
+    ParameterSpec {
+        name: "CAM_SHUTTERSPD",
+        description: "Shutter Speed",
+        keys: &["shutterspeed"],
+        excludes: excludes_nothing,
+        real: false,
+    },
+    ParameterSpec {
+        name: "CAM_ISO",
+        description: "ISO",
+        keys: &["iso"],
+        excludes: excludes_nothing,
+        real: false,
+    },
+    ParameterSpec {
+        name: "CAM_WBMODE",
+        description: "White Balance",
+        keys: &["whitebalance"],
+        excludes: white_balance_excludes,
+        real: false,
+    },
+    ParameterSpec {
+        name: "CAM_COLORTEMP",
+        description: "Color Temperature",
+        keys: &["colortemperature"],
+        excludes: excludes_nothing,
+        real: false,
+    },
+    ParameterSpec {
+        name: "CAM_PHOTOFMT",
+        description: "Image Format",
+        keys: &["imageformat", "imagequality"],
+        excludes: excludes_nothing,
+        real: false,
+    },
+    ParameterSpec {
+        name: "CAM_EV",
+        description: "Exposure Compensation",
+        keys: &["exposurecompensation"],
+        excludes: excludes_nothing,
+        real: true,
+    },
+    ParameterSpec {
+        name: "CAM_CAPTARGET",
+        description: "Capture Target",
+        keys: &["capturetarget"],
+        excludes: excludes_nothing,
+        real: false,
+    },
+];
+
+/// How often the shutter fired, kept by the component rather than read as a
+/// setting.
+static SHUTTER_COUNT: ParameterSpec = ParameterSpec {
+    name: "CAM_SHUTTERCNT",
+    description: "Shutter Count",
+    keys: &[],
+    excludes: excludes_nothing,
+    real: false,
+};
+
+/// Whether the camera focuses before every shot, kept by the component.
+static AUTOFOCUS: ParameterSpec = ParameterSpec {
+    name: "CAM_AFSHOT",
+    description: "Autofocus Before Shot",
+    keys: &[],
+    excludes: excludes_nothing,
+    real: false,
+};
+
+/// The choices of `CAM_AFSHOT`, by whether it's on.
+const AUTOFOCUS_CHOICES: [&str; 2] = ["Off", "On"];
+
+/// Drives the manual focus when written, kept by the component.
+static FOCUS_DRIVE: ParameterSpec = ParameterSpec {
+    name: "CAM_FOCUSDRV",
+    description: "Focus Drive",
+    keys: &[],
+    excludes: excludes_nothing,
+    real: false,
+};
+
+/// The choices of `CAM_FOCUSDRV` with the focus steps they drive, in the
+/// camera's smallest steps and negative towards the camera.
+const FOCUS_DRIVE_STEPS: [(&str, i32); 7] = [
+    ("Near Large", -100),
+    ("Near Medium", -10),
+    ("Near Small", -1),
+    ("Hold", 0),
+    ("Far Small", 1),
+    ("Far Medium", 10),
+    ("Far Large", 100),
+];
+
+/// The choice `CAM_FOCUSDRV` rests at, `Hold`.
+const FOCUS_DRIVE_HOLD: u32 = 3;
+
+fn excludes_nothing(_choice: &str) -> &'static [&'static str] {
+    &[]
+}
+
+/// What the camera picks itself in each exposure program. Drivers name them
+/// differently, e.g. `A`, `Av` or `Aperture Priority`. Unknown programs such
+/// as scene modes are left alone rather than guessed at.
+fn exposure_mode_excludes(choice: &str) -> &'static [&'static str] {
+    let choice = choice.to_ascii_lowercase();
+    match choice.as_str() {
+        "p" | "program" | "program ae" => &["CAM_APERTURE", "CAM_SHUTTERSPD"],
+        "a" | "av" | "aperture priority" => &["CAM_SHUTTERSPD"],
+        "s" | "tv" | "shutter priority" => &["CAM_APERTURE"],
+        _ if choice.contains("auto") => &["CAM_APERTURE", "CAM_SHUTTERSPD"],
+        _ => &[],
+    }
+}
+
+/// The colour temperature only applies in the white balance mode setting it.
+fn white_balance_excludes(choice: &str) -> &'static [&'static str] {
+    let choice = choice.to_ascii_lowercase();
+    if choice.contains("temperature") || choice.contains("kelvin") {
+        &[]
+    } else {
+        &["CAM_COLORTEMP"]
+    }
+}
+
+/// One parameter as read from the camera.
+#[derive(Debug, Clone)]
+struct Parameter {
+    spec: &'static ParameterSpec,
+    /// The backend key it's read and written with.
+    key: &'static str,
+    values: Values,
+    /// Index of the current choice, or the current value of a range.
+    value: Value,
+}
+
+/// What a parameter can be set to.
+#[derive(Debug, Clone)]
+enum Values {
+    Choices(Vec<String>),
+    Range {
+        min: u32,
+        max: u32,
+        step: u32,
+    },
+    /// `stops` are the values the camera takes with how it names them, the
+    /// nearest one is picked. Empty if the camera takes any step in the range.
+    Real {
+        min: f32,
+        max: f32,
+        step: f32,
+        stops: Vec<(f32, String)>,
+    },
+    /// A count that can't be set.
+    Count,
+}
+
+/// A parameter value as it goes over MAVLink.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Uint32(u32),
+    Real32(f32),
+}
+
+/// The parameters of one camera, in the order they're listed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Parameters {
+    parameters: Vec<Parameter>,
+}
+
+/// A `PARAM_EXT_SET` checked against the camera's choices.
+pub(crate) struct ParameterChange {
+    index: usize,
+    /// The parameter's name, e.g. `CAM_ISO`.
+    pub name: &'static str,
+    /// Backend key and value to write.
+    pub key: &'static str,
+    pub setting: String,
+    value: Value,
+}
+
+impl ParameterChange {
+    /// Whether the change turns autofocus before every shot on, `None` if
+    /// it's to another parameter.
+    pub fn autofocus(&self) -> Option<bool> {
+        (self.name == AUTOFOCUS.name).then(|| self.setting == AUTOFOCUS_CHOICES[1])
+    }
+
+    /// The focus steps the change drives, `None` if it's to another
+    /// parameter.
+    pub fn focus_drive(&self) -> Option<i32> {
+        if self.name != FOCUS_DRIVE.name {
+            return None;
+        }
+        FOCUS_DRIVE_STEPS
+            .iter()
+            .find(|(choice, _)| *choice == self.setting)
+            .map(|(_, steps)| *steps)
+    }
+}
+
+/// The generated definition of one camera, served to ground stations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CameraDefinition {
+    /// Changes with the contents so ground stations drop a cached copy.
+    pub version: u16,
+    pub xml: Arc<str>,
+}
+
+impl Parameters {
+    /// Reads the settings the camera has. Settings whose current value isn't
+    /// one of the choices are left out. `CAM_AFSHOT` is added, off, if the
+    /// camera can autofocus on command and `CAM_FOCUSDRV` if it can drive its
+    /// focus. Blocks on the camera.
+    pub fn read(backend: &mut dyn CameraBackend) -> anyhow::Result<Self> {
+        let mut parameters = Vec::new();
+
+        for spec in &PARAMETERS {
+            for &key in spec.keys {
+                if let Some(parameter) = read_parameter(backend, spec, key)? {
+                    parameters.push(parameter);
+                    break;
+                }
+            }
+        }
+        let capabilities = backend.capabilities()?;
+        if capabilities.autofocus {
+            parameters.push(Parameter {
+                spec: &AUTOFOCUS,
+                key: "",
+                values: Values::Choices(AUTOFOCUS_CHOICES.map(str::to_owned).to_vec()),
+                value: Value::Uint32(0),
+            });
+        }
+        if capabilities.focus {
+            parameters.push(Parameter {
+                spec: &FOCUS_DRIVE,
+                key: "",
+                values: Values::Choices(
+                    FOCUS_DRIVE_STEPS
+                        .iter()
+                        .map(|(choice, _)| choice.to_string())
+                        .collect(),
+                ),
+                value: Value::Uint32(FOCUS_DRIVE_HOLD),
+            });
+        }
+
+        Ok(Self { parameters })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parameters.is_empty()
+    }
+
+    /// Whether the camera has parameter `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.parameters
+            .iter()
+            .any(|parameter| parameter.spec.name == name)
+    }
+
+    /// `PARAM_EXT_VALUE` of every parameter.
+    pub fn values(&self) -> Vec<MavMessage> {
+        (0..self.parameters.len())
+            .map(|index| self.value(index))
+            .collect()
+    }
+
+    /// `PARAM_EXT_VALUE` of the parameter with `id`, or at `index` if it isn't
+    /// negative.
+    pub fn read_value(&self, id: &[u8; 16], index: i16) -> Option<MavMessage> {
+        let index = match usize::try_from(index) {
+            Ok(index) => (index < self.parameters.len()).then_some(index)?,
+            Err(_) => self.position(id)?,
+        };
+        Some(self.value(index))
+    }
+
+    /// Resolves a `PARAM_EXT_SET` to the setting to write.
+    pub fn change(
+        &self,
+        id: &[u8; 16],
+        value: &[u8],
+        value_type: MavParamExtType,
+    ) -> Result<ParameterChange, ParamAck> {
+        let index = self.position(id).ok_or(ParamAck::PARAM_ACK_FAILED)?;
+        let parameter = &self.parameters[index];
+        let (setting, value) = Value::decode(value, value_type)
+            .and_then(|value| parameter.values.setting(value))
+            .ok_or(ParamAck::PARAM_ACK_VALUE_UNSUPPORTED)?;
+
+        Ok(ParameterChange {
+            index,
+            name: parameter.spec.name,
+            key: parameter.key,
+            setting,
+            value,
+        })
+    }
+
+    /// Every parameter's name with the camera's current setting, as the
+    /// camera names it.
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        self.parameters
+            .iter()
+            .filter_map(|parameter| {
+                let (setting, _) = parameter.values.setting(parameter.value)?;
+                Some((parameter.spec.name, setting))
+            })
+            .collect()
+    }
+
+    /// Resolves parameter `name` set to `setting` as the camera names it,
+    /// e.g. `1/1000` for `CAM_SHUTTERSPD`, to the setting to write.
+    pub fn change_setting(&self, name: &str, setting: &str) -> Result<ParameterChange, ParamAck> {
+        let index = self
+            .parameters
+            .iter()
+            .position(|parameter| parameter.spec.name == name)
+            .ok_or(ParamAck::PARAM_ACK_FAILED)?;
+        let parameter = &self.parameters[index];
+        let value = match &parameter.values {
+            Values::Choices(choices) => choices
+                .iter()
+                .position(|choice| choice == setting)
+                .map(|choice| Value::Uint32(choice as u32)),
+            Values::Range { .. } => setting.parse().ok().map(Value::Uint32),
+            Values::Real { stops, .. } => stops
+                .iter()
+                .find(|(_, name)| name == setting)
+                .map(|(stop, _)| *stop)
+                .or_else(|| setting.parse().ok())
+                .map(Value::Real32),
+            Values::Count => None,
+        };
+        let (setting, value) = value
+            .and_then(|value| parameter.values.setting(value))
+            .ok_or(ParamAck::PARAM_ACK_VALUE_UNSUPPORTED)?;
+
+        Ok(ParameterChange {
+            index,
+            name: parameter.spec.name,
+            key: parameter.key,
+            setting,
+            value,
+        })
+    }
+
+    /// `PARAM_EXT_VALUE` of the parameter `change` was made to.
+    pub fn changed_value(&self, change: &ParameterChange) -> MavMessage {
+        self.value(change.index)
+    }
+
+    /// The exposure compensation changes for a bracket: one per `offsets` EV
+    /// from the current setting, clamped to what the camera takes, and the
+    /// change back to it. `None` if the camera has no exposure compensation.
+    pub fn exposure_bracket(
+        &self,
+        offsets: &[f32],
+    ) -> Option<(Vec<ParameterChange>, ParameterChange)> {
+        let index = self
+            .parameters
+            .iter()
+            .position(|parameter| parameter.spec.name == "CAM_EV")?;
+        let parameter = &self.parameters[index];
+        let (Values::Real { min, max, .. }, Value::Real32(current)) =
+            (&parameter.values, parameter.value)
+        else {
+            return None;
+        };
+
+        let change = |value: f32| {
+            let (setting, value) = parameter
+                .values
+                .setting(Value::Real32(value.clamp(*min, *max)))?;
+            Some(ParameterChange {
+                index,
+                name: parameter.spec.name,
+                key: parameter.key,
+                setting,
+                value,
+            })
+        };
+        let shots = offsets
+            .iter()
+            .map(|offset| change(current + offset))
+            .collect::<Option<_>>()?;
+        Some((shots, change(current)?))
+    }
+
+    /// Sets the shutter count, adding its parameter after the settings if
+    /// it isn't there yet.
+    pub fn set_shutter_count(&mut self, count: u64) {
+        let value = Value::Uint32(count.min(u32::MAX.into()) as u32);
+        match self
+            .parameters
+            .iter_mut()
+            .find(|parameter| parameter.spec.name == SHUTTER_COUNT.name)
+        {
+            Some(parameter) => parameter.value = value,
+            None => self.parameters.push(Parameter {
+                spec: &SHUTTER_COUNT,
+                key: "",
+                values: Values::Count,
+                value,
+            }),
+        }
+    }
+
+    /// Sets whether `CAM_AFSHOT` is on, if the camera has it.
+    pub fn set_autofocus(&mut self, enabled: bool) {
+        if let Some(parameter) = self
+            .parameters
+            .iter_mut()
+            .find(|parameter| parameter.spec.name == AUTOFOCUS.name)
+        {
+            parameter.value = Value::Uint32(enabled.into());
+        }
+    }
+
+    /// Records a change the camera took.
+    pub fn apply(&mut self, change: &ParameterChange) {
+        self.parameters[change.index].value = change.value;
+    }
+
+    /// `PARAM_EXT_ACK` for a `PARAM_EXT_SET` of `id`, with the value the
+    /// parameter has now.
+    pub fn ack(&self, id: &[u8; 16], result: ParamAck) -> MavMessage {
+        let value = self
+            .position(id)
+            .map_or(Value::Uint32(0), |index| self.parameters[index].value);
+        let (param_value, param_type) = value.encode();
+
+        MavMessage::PARAM_EXT_ACK(PARAM_EXT_ACK_DATA {
+            param_id: *id,
+            param_value,
+            param_type,
+            param_result: result,
+        })
+    }
+
+    /// The camera definition file for ground stations, see
+    /// <https://mavlink.io/en/services/camera_def.html>.
+    pub fn definition(&self, vendor: &str, model: &str) -> CameraDefinition {
+        let mut parameters = String::new();
+        for parameter in &self.parameters {
+            let _ = write!(
+                parameters,
+                r#"        <parameter name="{}""#,
+                parameter.spec.name
+            );
+            match &parameter.values {
+                Values::Choices(_) | Values::Count => {
+                    parameters.push_str(r#" type="uint32" default="0">"#);
+                }
+                Values::Range { min, max, step } => {
+                    let _ = write!(
+                        parameters,
+                        r#" type="uint32" default="{min}" min="{min}" max="{max}" step="{step}">"#
+                    );
+                }
+                Values::Real { min, max, step, .. } => {
+                    let _ = write!(
+                        parameters,
+                        r#" type="float" default="{}" min="{}" max="{}" step="{}">"#,
+                        number(0.0_f32.clamp(*min, *max)),
+                        number(*min),
+                        number(*max),
+                        number(*step),
+                    );
+                }
+            }
+            let _ = writeln!(
+                parameters,
+                "\n            <description>{}</description>",
+                parameter.spec.description
+            );
+            if let Values::Choices(choices) = &parameter.values {
+                parameters.push_str("            <options>\n");
+                for (value, choice) in choices.iter().enumerate() {
+                    let _ = write!(
+                        parameters,
+                        r#"                <option name="{}" value="{value}""#,
+                        escape(choice)
+                    );
+                    // Only what this camera has, the rest isn't shown anyway.
+                    let excluded: Vec<_> = (parameter.spec.excludes)(choice)
+                        .iter()
+                        .filter(|name| {
+                            self.parameters
+                                .iter()
+                                .any(|other| other.spec.name == **name)
+                        })
+                        .collect();
+                    if excluded.is_empty() {
+                        parameters.push_str(" />\n");
+                        continue;
+                    }
+
+                    parameters.push_str(">\n                    <exclusions>\n");
+                    for name in excluded {
+                        let _ = writeln!(
+                            parameters,
+                            "                        <exclude>{name}</exclude>"
+                        );
+                    }
+                    parameters
+                        .push_str("                    </exclusions>\n                </option>\n");
+                }
+                parameters.push_str("            </options>\n");
+            }
+            parameters.push_str("        </parameter>\n");
+        }
+        let version = checksum([vendor, model, &parameters]);
+
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n<mavlinkcamera>\n");
+        let _ = writeln!(xml, r#"    <definition version="{version}">"#);
+        let _ = writeln!(xml, "        <model>{}</model>", escape(model));
+        let _ = writeln!(xml, "        <vendor>{}</vendor>", escape(vendor));
+        xml.push_str("    </definition>\n    <parameters>\n");
+        xml.push_str(&parameters);
+        xml.push_str("    </parameters>\n</mavlinkcamera>\n");
+
+        CameraDefinition {
+            version,
+            xml: xml.into(),
+        }
+    }
+
+    fn position(&self, id: &[u8; 16]) -> Option<usize> {
+        let length = id.iter().position(|&byte| byte == 0).unwrap_or(id.len());
+        self.parameters
+            .iter()
+            .position(|parameter| parameter.spec.name.as_bytes() == &id[..length])
+    }
+
+    fn value(&self, index: usize) -> MavMessage {
+        let parameter = &self.parameters[index];
+        let (param_value, param_type) = parameter.value.encode();
+
+        MavMessage::PARAM_EXT_VALUE(PARAM_EXT_VALUE_DATA {
+            param_count: self.parameters.len() as u16,
+            param_index: index as u16,
+            param_id: str_to_fixed_arr(parameter.spec.name),
+            param_value,
+            param_type,
+        })
+    }
+}
+
+impl Values {
+    /// The backend value for `value` and the value the parameter ends up
+    /// with, `None` if it can't take it.
+    fn setting(&self, value: Value) -> Option<(String, Value)> {
+        match (self, value) {
+            (Values::Choices(choices), Value::Uint32(index)) => {
+                let choice = choices.get(index as usize)?;
+                Some((choice.clone(), value))
+            }
+            (Values::Range { min, max, step }, Value::Uint32(value)) => {
+                let valid = (*min..=*max).contains(&value)
+                    && (*step == 0 || (value - min).is_multiple_of(*step));
+                valid.then(|| (value.to_string(), Value::Uint32(value)))
+            }
+            (
+                Values::Real {
+                    min,
+                    max,
+                    step,
+                    stops,
+                },
+                Value::Real32(value),
+            ) => {
+                // Ground stations round, so allow half a step around the ends.
+                if !(min - step / 2.0..=max + step / 2.0).contains(&value) {
+                    return None;
+                }
+                if let Some((stop, name)) = stops
+                    .iter()
+                    .min_by(|(a, _), (b, _)| (a - value).abs().total_cmp(&(b - value).abs()))
+                {
+                    return Some((name.clone(), Value::Real32(*stop)));
+                }
+
+                let stepped = if *step > 0.0 {
+                    min + ((value - min) / step).round() * step
+                } else {
+                    value
+                };
+                let stepped = stepped.clamp(*min, *max);
+                Some((number(stepped), Value::Real32(stepped)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Value {
+    /// Numbers go in the value field as their little-endian bytes.
+    fn encode(self) -> (heapless::Vec<u8, 128>, MavParamExtType) {
+        let (bytes, value_type) = match self {
+            Value::Uint32(value) => (
+                value.to_le_bytes(),
+                MavParamExtType::MAV_PARAM_EXT_TYPE_UINT32,
+            ),
+            Value::Real32(value) => (
+                value.to_le_bytes(),
+                MavParamExtType::MAV_PARAM_EXT_TYPE_REAL32,
+            ),
+        };
+        (
+            heapless::Vec::from_slice(&bytes).unwrap_or_default(),
+            value_type,
+        )
+    }
+
+    /// MAVLink 2 trims trailing zeros, so missing bytes are zero.
+    fn decode(value: &[u8], value_type: MavParamExtType) -> Option<Self> {
+        let mut bytes = [0; 4];
+        let length = value.len().min(4);
+        bytes[..length].copy_from_slice(&value[..length]);
+
+        match value_type {
+            MavParamExtType::MAV_PARAM_EXT_TYPE_UINT32 => {
+                Some(Value::Uint32(u32::from_le_bytes(bytes)))
+            }
+            MavParamExtType::MAV_PARAM_EXT_TYPE_REAL32 => {
+                Some(Value::Real32(f32::from_le_bytes(bytes)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reads the setting `key` for `spec`, `None` if the camera doesn't have it
+/// or it can't be a parameter.
+fn read_parameter(
+    backend: &mut dyn CameraBackend,
+    spec: &'static ParameterSpec,
+    key: &'static str,
+) -> anyhow::Result<Option<Parameter>> {
+    let (values, value) = if let Some(setting) = backend.setting_choices(key)? {
+        if let Some(real) = spec.real.then(|| real_stops(&setting)).flatten() {
+            real
+        } else {
+            let Some(value) = setting
+                .choices
+                .iter()
+                .position(|choice| *choice == setting.current)
+            else {
+                return Ok(None);
+            };
+            (
+                Values::Choices(setting.choices),
+                Value::Uint32(value as u32),
+            )
+        }
+    } else if let Some(setting) = backend.setting_range(key)? {
+        if spec.real {
+            let values = Values::Real {
+                min: *setting.range.start(),
+                max: *setting.range.end(),
+                step: setting.step,
+                stops: Vec::new(),
+            };
+            (values, Value::Real32(setting.current))
+        } else {
+            // Kelvin and the like don't need fractions.
+            let values = Values::Range {
+                min: setting.range.start().max(0.0).round() as u32,
+                max: setting.range.end().max(0.0).round() as u32,
+                step: setting.step.max(0.0).round() as u32,
+            };
+            (
+                values,
+                Value::Uint32(setting.current.max(0.0).round() as u32),
+            )
+        }
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(Parameter {
+        spec,
+        key,
+        values,
+        value,
+    }))
+}
+
+/// The target of a `PARAM_EXT` request, `None` for other messages.
+pub(crate) fn parameter_target(message: &MavMessage) -> Option<(u8, u8)> {
+    match message {
+        MavMessage::PARAM_EXT_REQUEST_LIST(request) => {
+            Some((request.target_system, request.target_component))
+        }
+        MavMessage::PARAM_EXT_REQUEST_READ(request) => {
+            Some((request.target_system, request.target_component))
+        }
+        MavMessage::PARAM_EXT_SET(set) => Some((set.target_system, set.target_component)),
+        _ => None,
+    }
+}
+
+/// A list of numbers such as `-0.333`, `0`, `+0.333` as a range with the
+/// smallest step between them. `None` if any choice isn't a number.
+fn real_stops(setting: &SettingChoices) -> Option<(Values, Value)> {
+    let numbers = setting
+        .choices
+        .iter()
+        .map(|choice| choice.trim().trim_start_matches('+').parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    // Some drivers count in thousandths of a stop.
+    let scale = if numbers.iter().any(|number| number.abs() > 100.0) {
+        1000.0
+    } else {
+        1.0
+    };
+    let mut stops: Vec<_> = numbers
+        .into_iter()
+        .map(|number| number / scale)
+        .zip(setting.choices.iter().cloned())
+        .collect();
+    stops.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let (min, max) = (stops.first()?.0, stops.last()?.0);
+    let step = stops
+        .windows(2)
+        .map(|pair| pair[1].0 - pair[0].0)
+        .filter(|step| *step > 0.0)
+        .min_by(f32::total_cmp)
+        .unwrap_or(0.0);
+    let current = stops
+        .iter()
+        .find(|(_, name)| *name == setting.current)
+        .map_or(0.0, |(stop, _)| *stop);
+
+    Some((
+        Values::Real {
+            min,
+            max,
+            step,
+            stops,
+        },
+        Value::Real32(current),
+    ))
+}
+
+/// `value` with at most three decimals, as the definition shows it.
+fn number(value: f32) -> String {
+    let text = format!("{value:.3}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_owned(),
+        _ => text.to_owned(),
+    }
+}
+
+/// A 16 bit FNV-1a of `parts`, never 0 as that means no definition.
+fn checksum<'a>(parts: impl IntoIterator<Item = &'a str>) -> u16 {
+    let hash = parts
+        .into_iter()
+        // Separated so moving a character between parts changes the hash.
+        .flat_map(|part| part.bytes().chain(iter::once(0)))
+        .fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        });
+    ((hash >> 16) as u16 ^ hash as u16).max(1)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
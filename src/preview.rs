@@ -0,0 +1,69 @@
+//! Downscaled copies of captures for links too slow for the full image.
+//!
+//! JPEGs are decoded as they are. RAW files are too slow and too varied to
+//! develop, so the JPEG thumbnail most TIFF based formats (CR2, NEF, ARW, DNG)
+//! embed in their EXIF is used instead.
+
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageReader};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+/// A downscaled capture as a JPEG.
+pub(crate) struct Preview {
+    pub jpeg: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Scales the image at `image` down so its longest side is at most
+/// `max_size` pixels and encodes it with `quality` (1-100). Blocks on the
+/// disk and the CPU.
+pub(crate) fn downscale(image: &Path, max_size: u32, quality: u8) -> Result<Preview> {
+    let decoded = match decode(image) {
+        Ok(decoded) => decoded,
+        Err(error) => embedded_thumbnail(image).ok_or(error)?,
+    };
+    let scaled = decoded.thumbnail(max_size, max_size).into_rgb8();
+
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, quality.clamp(1, 100))
+        .encode_image(&scaled)
+        .context("Failed to encode preview")?;
+
+    Ok(Preview {
+        jpeg,
+        width: scaled.width(),
+        height: scaled.height(),
+    })
+}
+
+fn decode(image: &Path) -> Result<DynamicImage> {
+    ImageReader::open(image)
+        .with_context(|| format!("Failed to open {}", image.display()))?
+        .with_guessed_format()?
+        .decode()
+        .with_context(|| format!("Failed to decode {}", image.display()))
+}
+
+/// The JPEG thumbnail in the EXIF of `image`, if it has one.
+fn embedded_thumbnail(image: &Path) -> Option<DynamicImage> {
+    let file = File::open(image).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let field = |tag| {
+        exif.get_field(tag, exif::In::THUMBNAIL)
+            .and_then(|field| field.value.get_uint(0))
+            .map(|value| value as usize)
+    };
+    let offset = field(exif::Tag::JPEGInterchangeFormat)?;
+    let length = field(exif::Tag::JPEGInterchangeFormatLength)?;
+    let jpeg = exif.buf().get(offset..offset.checked_add(length)?)?;
+
+    ImageReader::with_format(Cursor::new(jpeg), image::ImageFormat::Jpeg)
+        .decode()
+        .ok()
+}
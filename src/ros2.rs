@@ -0,0 +1,182 @@
+//! ROS 2 node for research platforms that already run ROS, next to the
+//! MAVLink side, with names below the node's private namespace:
+//!
+//! - `~/events` (`std_msgs/String`): every [`crate::CameraEvent`] as JSON,
+//!   the same as on the REST API's WebSocket
+//! - `~/camera_<camera>/state` (`std_msgs/String`): the camera's state as
+//!   JSON, transient local so late subscribers get the current one
+//! - `~/camera_<camera>/trigger` (`std_msgs/Empty`): takes a picture
+//! - `~/camera_<camera>/capture` (`std_srvs/Trigger`): takes a picture,
+//!   failing when the camera can't take it now
+//! - `~/get_status` (`std_srvs/Trigger`): the REST API's status as JSON in
+//!   the response's `message`
+//!
+//! where `<camera>` is the camera's component id.
+
+use crate::control::Control;
+use crate::error::Result;
+use crate::status::{WorkerReporter, WorkerStatus};
+use futures::{Stream, StreamExt};
+use r2r::std_msgs::msg;
+use r2r::std_srvs::srv::Trigger;
+use r2r::{Node, Publisher, QosProfile, ServiceRequest};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+
+/// How often the node handles what arrived from ROS.
+const SPIN_PERIOD: Duration = Duration::from_millis(10);
+
+/// The name of the node and where it lives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ros2Options {
+    pub node_name: String,
+    /// Namespace of the node, e.g. `/drone_7`. The root one when empty.
+    pub namespace: String,
+}
+
+impl Default for Ros2Options {
+    /// A node named `camera` in the root namespace.
+    fn default() -> Self {
+        Self {
+            node_name: "camera".to_owned(),
+            namespace: String::new(),
+        }
+    }
+}
+
+/// The node, joined to the ROS graph but not yet serving, see
+/// [`Ros2Node::run`].
+pub(crate) struct Ros2Node {
+    node: Node,
+}
+
+impl Ros2Node {
+    /// Creates the node right away so a missing ROS environment fails the
+    /// startup.
+    pub fn create(options: &Ros2Options) -> Result<Self> {
+        let context = r2r::Context::create()?;
+        let node = Node::create(context, &options.node_name, &options.namespace)?;
+        info!(target: "rx", node = options.node_name, namespace = options.namespace, "Joined ROS 2");
+
+        Ok(Self { node })
+    }
+
+    /// Publishes the events and states of the cameras in `control` and
+    /// takes their triggers until the component shuts down.
+    pub async fn run(self, control: Control, reporter: WorkerReporter) -> Result<()> {
+        let mut node = self.node;
+        let events_topic =
+            node.create_publisher::<msg::String>("~/events", QosProfile::default())?;
+        let mut states = BTreeMap::new();
+        let mut camera_tasks = Vec::new();
+        for (camera, _) in control.states() {
+            let prefix = format!("~/camera_{camera}");
+            let state = node.create_publisher(
+                &format!("{prefix}/state"),
+                QosProfile::default().transient_local(),
+            )?;
+            states.insert(camera, state);
+            let triggers = node.subscribe(&format!("{prefix}/trigger"), QosProfile::default())?;
+            let captures =
+                node.create_service(&format!("{prefix}/capture"), QosProfile::default())?;
+            camera_tasks.push(tokio::spawn(take_pictures(
+                camera,
+                triggers,
+                captures,
+                control.clone(),
+            )));
+        }
+        let mut status_requests =
+            node.create_service::<Trigger::Service>("~/get_status", QosProfile::default())?;
+
+        let mut events = control.subscribe();
+        let mut changes = control.state_changes();
+        let mut spin = tokio::time::interval(SPIN_PERIOD);
+        reporter.running();
+
+        loop {
+            tokio::select! {
+                _ = spin.tick() => node.spin_once(Duration::ZERO),
+                event = events.recv() => match event {
+                    // The images are on this computer, the paths are enough.
+                    Ok(event) => publish(&events_topic, event.json(|_, _, _| None), &reporter),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(target: "backend", skipped, "ROS 2 node fell behind, events skipped");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                Some((camera, state)) = changes.recv() => {
+                    if let Some(topic) = states.get(&camera) {
+                        publish(topic, format!("{{{}}}", state.json_fields()), &reporter);
+                    }
+                }
+                Some(request) = status_requests.next() => {
+                    let response = match control.status_json() {
+                        Ok(message) => Trigger::Response { success: true, message },
+                        Err(error) => Trigger::Response {
+                            success: false,
+                            message: error.to_string(),
+                        },
+                    };
+                    respond(request, response);
+                }
+            }
+        }
+
+        for task in camera_tasks {
+            task.abort();
+        }
+        Ok(())
+    }
+}
+
+/// Takes a picture with `camera` for every message on its trigger topic and
+/// every call of its capture service.
+async fn take_pictures(
+    camera: u8,
+    mut triggers: impl Stream<Item = msg::Empty> + Unpin,
+    mut captures: impl Stream<Item = ServiceRequest<Trigger::Service>> + Unpin,
+    control: Control,
+) {
+    loop {
+        tokio::select! {
+            Some(_) = triggers.next() => {
+                if let Err(refused) = control.capture(camera) {
+                    warn!(target: "rx", camera, "Dropped ROS 2 trigger: {refused}");
+                }
+            }
+            Some(request) = captures.next() => {
+                let response = match control.capture(camera) {
+                    Ok(()) => Trigger::Response {
+                        success: true,
+                        message: String::new(),
+                    },
+                    Err(refused) => Trigger::Response {
+                        success: false,
+                        message: refused.to_string(),
+                    },
+                };
+                respond(request, response);
+            }
+            else => break,
+        }
+    }
+}
+
+fn publish(topic: &Publisher<msg::String>, data: String, reporter: &WorkerReporter) {
+    match topic.publish(&msg::String { data }) {
+        Ok(()) => reporter.running(),
+        Err(error) => reporter.set(WorkerStatus::Degraded(format!(
+            "publishing failed: {error}"
+        ))),
+    }
+}
+
+fn respond(request: ServiceRequest<Trigger::Service>, response: Trigger::Response) {
+    // The client may have given up waiting.
+    if let Err(error) = request.respond(response) {
+        debug!(target: "rx", "Can't answer ROS 2 service call: {error}");
+    }
+}
@@ -0,0 +1,46 @@
+//! Pre-flight self-test so crews can check the payload before takeoff: the
+//! camera responds, takes and downloads a picture and there's room for more.
+
+use crate::backend::CapturedImage;
+use mavlink::common::{MavCmd, MavSeverity};
+use std::path::Path;
+
+/// Runs the self-test. It's acked as in progress right away and with the
+/// outcome once done, every check is reported with `STATUSTEXT`.
+pub const SELF_TEST_COMMAND: MavCmd = MavCmd::MAV_CMD_USER_3;
+
+/// What one check found, or why it failed.
+pub(crate) type CheckResult = std::result::Result<String, String>;
+
+/// The `STATUSTEXT` severity and text of a check.
+pub(crate) fn report(check: &str, result: &CheckResult) -> (MavSeverity, String) {
+    match result {
+        Ok(found) => (
+            MavSeverity::MAV_SEVERITY_INFO,
+            format!("Self-test {check}: {found}"),
+        ),
+        Err(reason) => (
+            MavSeverity::MAV_SEVERITY_ERROR,
+            format!("Self-test {check} FAILED: {reason}"),
+        ),
+    }
+}
+
+/// Checks the test capture made it to disk whole and deletes it again so it
+/// doesn't end up with the survey images. Blocks on the disk.
+pub(crate) fn check_download(image: &CapturedImage) -> CheckResult {
+    let size = |path: &Path| std::fs::metadata(path).map(|metadata| metadata.len());
+
+    let mut total = 0;
+    for path in std::iter::once(&image.path).chain(&image.companions) {
+        let bytes = size(path).map_err(|error| format!("{}: {error}", path.display()))?;
+        if bytes == 0 {
+            return Err(format!("{} is empty", path.display()));
+        }
+        total += bytes;
+        // Left behind it's only a stray file, the download itself was fine.
+        let _ = std::fs::remove_file(path);
+    }
+
+    Ok(format!("{} KiB downloaded", total / 1024))
+}
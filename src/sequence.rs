@@ -0,0 +1,99 @@
+//! The image counter and the last capture, kept on disk so a restart
+//! mid-mission doesn't reset the index ground stations count photos by and
+//! geotags are matched with. So is what the camera was last commanded, so it
+//! doesn't silently go back to its defaults either.
+
+use crate::geotag::Geotag;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A capture as reported with `CAMERA_IMAGE_CAPTURED`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct CaptureReport {
+    pub image_index: i32,
+    /// UTC time the image was taken in microseconds.
+    pub time_utc: u64,
+    /// When the image was taken in milliseconds since the component started,
+    /// 0 for captures from before a restart.
+    #[serde(skip)]
+    pub time_boot_ms: u32,
+    /// Empty if the image isn't served.
+    pub file_url: String,
+    // Last, as TOML needs tables after values.
+    pub geotag: Option<Geotag>,
+}
+
+/// What's kept of one camera's captures.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct CaptureSequence {
+    /// Index of the next capture.
+    pub image_index: i32,
+    /// How often the shutter fired, counted by the component for cameras
+    /// that don't tell.
+    #[serde(default)]
+    pub shutter_count: u64,
+    /// The last successful capture.
+    pub last_capture: Option<CaptureReport>,
+    #[serde(default)]
+    pub commanded: Commanded,
+}
+
+/// What ground stations last asked a camera for, restored at startup.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Commanded {
+    /// The `CAMERA_MODE`, 0 for pictures.
+    pub mode: u32,
+    /// Seconds between the pictures of the running time-lapse, if any.
+    pub timelapse_interval_s: Option<f32>,
+    /// Pictures the time-lapse still takes, `None` until it's stopped.
+    pub timelapse_left: Option<u32>,
+    /// Parameters set since by name, e.g. `CAM_ISO`, with their setting.
+    pub parameters: BTreeMap<String, String>,
+}
+
+/// Where one camera's [`CaptureSequence`] is kept.
+#[derive(Debug, Clone)]
+pub(crate) struct SequenceFile {
+    path: PathBuf,
+}
+
+impl SequenceFile {
+    pub fn new(directory: &Path, camera: u8) -> Self {
+        Self {
+            path: directory.join(format!("camera-{camera}.toml")),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the sequence, a fresh one if nothing was saved yet.
+    pub fn load(&self) -> io::Result<CaptureSequence> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(CaptureSequence::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Replaces the saved sequence. The file is swapped in whole so a power
+    /// cut can't leave half of it. Blocks on the disk.
+    pub fn save(&self, sequence: &CaptureSequence) -> io::Result<()> {
+        let contents = toml::to_string(sequence)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        if let Some(directory) = self.path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+
+        let temporary = self.path.with_extension("toml.tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, &self.path)
+    }
+}
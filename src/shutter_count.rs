@@ -0,0 +1,93 @@
+//! How often each camera's shutter fired, asked from the camera or read from
+//! the EXIF of its captures where they tell and counted otherwise, so ground
+//! stations are warned before the shutter reaches the life it's rated for.
+
+use crate::lens::read_exif;
+use std::path::Path;
+
+/// The `ImageNumber` EXIF tag, which some bodies record their actuations in.
+const IMAGE_NUMBER: u16 = 0x9211;
+
+/// Ground stations are warned once the count reaches this many tenths of the
+/// rated life.
+const WARNING_TENTHS: u64 = 9;
+
+/// The actuations of one camera.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ShutterCount {
+    count: u64,
+    /// Actuations the shutter is rated for, if known.
+    rated_life: Option<u64>,
+    /// Whether the camera tells its own count, which is then asked again
+    /// after every capture rather than counted.
+    from_camera: bool,
+    warned: bool,
+}
+
+impl ShutterCount {
+    /// Continues from `count`, as saved before a restart.
+    pub fn new(count: u64, rated_life: Option<u64>) -> Self {
+        Self {
+            count,
+            rated_life,
+            from_camera: false,
+            warned: false,
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_from_camera(&self) -> bool {
+        self.from_camera
+    }
+
+    /// Counts one picture taken.
+    pub fn fired(&mut self) {
+        self.count += 1;
+    }
+
+    /// Takes the count the camera keeps itself.
+    pub fn set_from_camera(&mut self, count: u64) {
+        self.count = count;
+        self.from_camera = true;
+    }
+
+    /// Takes the count recorded in a capture's EXIF, unless it's behind, as
+    /// a reset file number would be.
+    pub fn set_from_exif(&mut self, count: u64) {
+        self.count = self.count.max(count);
+    }
+
+    /// The `STATUSTEXT` to warn with, once, when the count nears or passes
+    /// the rated life.
+    pub fn take_warning(&mut self) -> Option<String> {
+        let rated_life = self.rated_life?;
+        if self.warned || self.count * 10 < rated_life * WARNING_TENTHS {
+            return None;
+        }
+
+        self.warned = true;
+        Some(if self.count >= rated_life {
+            format!("Shutter count {} past rated {rated_life}", self.count)
+        } else {
+            format!("Shutter count {} near rated {rated_life}", self.count)
+        })
+    }
+}
+
+/// The actuations recorded in the EXIF of `image`, `None` for bodies that
+/// don't record them.
+pub(crate) fn from_exif(image: &Path) -> Option<u64> {
+    let exif = read_exif(image)?;
+    let field = [exif::Context::Exif, exif::Context::Tiff]
+        .into_iter()
+        .find_map(|context| exif.get_field(exif::Tag(context, IMAGE_NUMBER), exif::In::PRIMARY))?;
+
+    field
+        .value
+        .get_uint(0)
+        .map(u64::from)
+        .filter(|count| *count > 0)
+}
@@ -0,0 +1,145 @@
+//! What the camera is doing right now, as announced in the heartbeat.
+
+use crate::capture_log::json_string;
+use crate::stream_rates::StreamRates;
+use mavlink::common::{CameraMode, MavState};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+/// Live camera state shared from the receive task to the heartbeat through a
+/// `watch` channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CameraState {
+    pub mode: CameraMode,
+    pub capturing: bool,
+    pub camera_connected: bool,
+    pub storage_full: bool,
+    /// Whether the live-view stream runs, if the camera has one.
+    pub streaming: bool,
+    /// Whether a task the camera relies on stalled, see
+    /// [`crate::WatchdogOptions`].
+    pub degraded: bool,
+    /// How often the shutter fired, from the camera where it tells.
+    pub shutter_count: u64,
+    /// Body temperature in °C, if the camera tells.
+    pub temperature: Option<f32>,
+    /// Pictures taken that wait for their download.
+    pub pending_downloads: usize,
+    /// Whether the camera records a movie.
+    pub recording: bool,
+    /// How often the heartbeat and the streamed messages are sent.
+    pub stream_rates: StreamRates,
+}
+
+impl Default for CameraState {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::CAMERA_MODE_IMAGE,
+            capturing: false,
+            camera_connected: true,
+            storage_full: false,
+            streaming: false,
+            degraded: false,
+            shutter_count: 0,
+            temperature: None,
+            pending_downloads: 0,
+            recording: false,
+            stream_rates: StreamRates::default(),
+        }
+    }
+}
+
+/// The `CAMERA_MODE` with `id`, `None` for modes this version doesn't know.
+pub(crate) fn camera_mode(id: u32) -> Option<CameraMode> {
+    [
+        CameraMode::CAMERA_MODE_IMAGE,
+        CameraMode::CAMERA_MODE_VIDEO,
+        CameraMode::CAMERA_MODE_IMAGE_SURVEY,
+    ]
+    .into_iter()
+    .find(|mode| *mode as u32 == id)
+}
+
+impl CameraState {
+    /// `HEARTBEAT.system_status`: critical while the camera can't take photos
+    /// or is degraded, active while it's busy or recording.
+    pub fn system_status(&self) -> MavState {
+        if !self.camera_connected || self.storage_full || self.degraded {
+            MavState::MAV_STATE_CRITICAL
+        } else if self.capturing || self.recording {
+            MavState::MAV_STATE_ACTIVE
+        } else {
+            MavState::MAV_STATE_STANDBY
+        }
+    }
+
+    /// `HEARTBEAT.custom_mode`, the current `CAMERA_MODE` so ground stations
+    /// can show it.
+    pub fn custom_mode(&self) -> u32 {
+        self.mode as u32
+    }
+
+    /// The state as the fields of a JSON object, without the braces.
+    pub fn json_fields(&self) -> String {
+        format!(
+            r#""mode":{},"capturing":{},"connected":{},"storage_full":{},"streaming":{},"degraded":{},"shutter_count":{},"temperature_c":{},"pending_downloads":{},"recording":{},"message_intervals_us":{}"#,
+            json_string(&format!("{:?}", self.mode)),
+            self.capturing,
+            self.camera_connected,
+            self.storage_full,
+            self.streaming,
+            self.degraded,
+            self.shutter_count,
+            self.temperature
+                .map_or("null".to_owned(), |temperature| temperature.to_string()),
+            self.pending_downloads,
+            self.recording,
+            self.stream_rates.json()
+        )
+    }
+}
+
+/// The states of several cameras as one stream of `(camera, state)`,
+/// starting with the current ones, for the bridges to the outside.
+pub(crate) struct StateChanges {
+    changes: mpsc::UnboundedReceiver<(u8, CameraState)>,
+    forwarders: Vec<JoinHandle<()>>,
+}
+
+impl StateChanges {
+    pub fn new(cameras: impl IntoIterator<Item = (u8, watch::Receiver<CameraState>)>) -> Self {
+        let (changed, changes) = mpsc::unbounded_channel();
+        let forwarders = cameras
+            .into_iter()
+            .map(|(camera, mut state)| {
+                let changed = changed.clone();
+                let _ = changed.send((camera, *state.borrow_and_update()));
+                tokio::spawn(async move {
+                    while state.changed().await.is_ok() {
+                        if changed.send((camera, *state.borrow_and_update())).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            changes,
+            forwarders,
+        }
+    }
+
+    /// The next change, `None` once every camera has shut down.
+    pub async fn recv(&mut self) -> Option<(u8, CameraState)> {
+        self.changes.recv().await
+    }
+}
+
+impl Drop for StateChanges {
+    fn drop(&mut self) {
+        for forwarder in &self.forwarders {
+            forwarder.abort();
+        }
+    }
+}
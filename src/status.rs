@@ -0,0 +1,158 @@
+//! Health reporting for the component's worker tasks.
+
+use crate::error::{CameraError, Result};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+use tracing::error;
+
+/// Health of one of the component's worker tasks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum WorkerStatus {
+    #[default]
+    Running,
+    /// The last operation failed but the worker keeps going.
+    Degraded(String),
+    /// The worker exited because of an unrecoverable error.
+    Failed(String),
+}
+
+/// Snapshot of the component's health, see [`crate::MavLinkCameraHandle::status`].
+#[derive(Debug, Clone, Default)]
+pub struct ComponentStatus {
+    /// The IO task of each MAVLink endpoint, keyed by connection string.
+    pub links: BTreeMap<String, WorkerStatus>,
+    /// The task routing incoming messages.
+    pub receiver: WorkerStatus,
+    /// The HTTP server, `Running` when it's off.
+    pub http: WorkerStatus,
+    /// The gRPC server, `Running` when it's off.
+    pub grpc: WorkerStatus,
+    /// The ROS 2 node, `Running` when it's off.
+    pub ros2: WorkerStatus,
+    /// The task noticing when the others stall.
+    pub watchdog: WorkerStatus,
+    /// The bridge to the MQTT broker, `Running` when it's off.
+    pub mqtt: WorkerStatus,
+    /// Each camera's tasks, keyed by component id.
+    pub cameras: BTreeMap<u8, CameraStatus>,
+    /// The ground stations and autopilots heard from, keyed by system and
+    /// component id.
+    pub peers: BTreeMap<(u8, u8), Peer>,
+    /// The frames each MAVLink endpoint received, keyed by connection string.
+    pub frames: BTreeMap<String, FrameCounts>,
+    /// The frames received by sender, keyed by the system and component id
+    /// in their header. A corrupt frame may name the wrong sender, and the
+    /// frames `mavlink::connect` endpoints drop have none.
+    pub frames_by_sender: BTreeMap<(u8, u8), FrameCounts>,
+}
+
+/// Frames received from an endpoint or sender, passed on or dropped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameCounts {
+    pub received: u64,
+    /// Dropped for failing their checksum, e.g. on a noisy radio link. A
+    /// corrupt frame may count more than once while the link resyncs.
+    pub bad_checksum: u64,
+    /// Dropped for being of a message the dialect doesn't have.
+    pub unknown_message: u64,
+    /// Dropped for a field out of range, e.g. an enum value the dialect
+    /// doesn't have.
+    pub invalid: u64,
+}
+
+impl FrameCounts {
+    /// The frames dropped, for any reason.
+    pub fn dropped(&self) -> u64 {
+        self.bad_checksum + self.unknown_message + self.invalid
+    }
+}
+
+/// A ground station or autopilot sending heartbeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peer {
+    pub kind: PeerKind,
+    pub last_heartbeat: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerKind {
+    GroundStation,
+    Autopilot,
+}
+
+impl PeerKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::GroundStation => "ground_station",
+            Self::Autopilot => "autopilot",
+        }
+    }
+}
+
+/// Health of the tasks of one camera body.
+#[derive(Debug, Clone, Default)]
+pub struct CameraStatus {
+    pub heartbeat: WorkerStatus,
+    /// The task that executes the camera's commands.
+    pub commands: WorkerStatus,
+    /// The task feeding the live-view stream, `Running` for cameras without.
+    pub live_view: WorkerStatus,
+    /// The task sending capture previews over MAVLink, `Running` for cameras
+    /// without.
+    pub image_transmission: WorkerStatus,
+}
+
+/// Records the health of a single worker in the shared [`ComponentStatus`].
+#[derive(Clone)]
+pub(crate) struct WorkerReporter {
+    status: Arc<Mutex<ComponentStatus>>,
+    worker: Arc<dyn Fn(&mut ComponentStatus) -> &mut WorkerStatus + Send + Sync>,
+}
+
+impl WorkerReporter {
+    pub fn new(
+        status: &Arc<Mutex<ComponentStatus>>,
+        worker: impl Fn(&mut ComponentStatus) -> &mut WorkerStatus + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            status: status.clone(),
+            worker: Arc::new(worker),
+        }
+    }
+
+    pub fn set(&self, new_status: WorkerStatus) {
+        if let Ok(mut status) = self.status.lock() {
+            *(self.worker)(&mut status) = new_status;
+        }
+    }
+
+    pub fn running(&self) {
+        self.set(WorkerStatus::Running);
+    }
+
+    pub fn degraded(&self, error: &CameraError) {
+        self.set(WorkerStatus::Degraded(error.to_string()));
+    }
+}
+
+/// Spawns `worker` on the runtime, marking it failed if it returns an error.
+pub(crate) fn spawn_worker<F>(
+    name: &'static str,
+    reporter: WorkerReporter,
+    worker: impl FnOnce(WorkerReporter) -> F,
+) -> JoinHandle<()>
+where
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    let task = worker(reporter.clone());
+
+    tokio::spawn(async move {
+        if let Err(error) = task.await {
+            error!("The {name} worker stopped: {error}");
+            reporter.set(WorkerStatus::Failed(error.to_string()));
+        }
+    })
+}
@@ -0,0 +1,88 @@
+//! `STATUSTEXT` for what the crew should know about without a look at the
+//! companion's log: the camera dropping out, failed captures, storage running
+//! out and self-test results.
+
+use mavlink::common::{MavMessage, MavSeverity, STATUSTEXT_DATA};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Room for text in one `STATUSTEXT`.
+const CHUNK_LEN: usize = 50;
+
+/// The same text isn't sent again for this long, so e.g. a failing
+/// time-lapse doesn't flood the link.
+const REPEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The `STATUSTEXT` of one camera.
+#[derive(Debug, Default)]
+pub(crate) struct StatusTexts {
+    /// When each text recently sent went out.
+    sent: HashMap<String, Instant>,
+    /// Id of the last text split into chunks.
+    last_id: u16,
+}
+
+impl StatusTexts {
+    /// The messages with `text`. Longer texts are split into chunks sharing
+    /// an id, which MAVLink 2 ground stations join again.
+    pub fn messages(&mut self, severity: MavSeverity, text: &str) -> Vec<MavMessage> {
+        let chunks = chunks(text);
+        let id = if chunks.len() > 1 {
+            // 0 is for texts that fit one message.
+            self.last_id = self.last_id.checked_add(1).unwrap_or(1);
+            self.last_id
+        } else {
+            0
+        };
+
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(chunk_seq, chunk)| {
+                MavMessage::STATUSTEXT(STATUSTEXT_DATA {
+                    severity,
+                    text: heapless::Vec::from_slice(chunk.as_bytes()).unwrap_or_default(),
+                    id,
+                    chunk_seq: chunk_seq as u8,
+                })
+            })
+            .collect()
+    }
+
+    /// Like [`StatusTexts::messages`], but none if the same text went out
+    /// just now.
+    pub fn unless_repeated(&mut self, severity: MavSeverity, text: &str) -> Vec<MavMessage> {
+        let now = Instant::now();
+        self.sent
+            .retain(|_, sent| now.duration_since(*sent) < REPEAT_INTERVAL);
+        if self.sent.contains_key(text) {
+            return Vec::new();
+        }
+
+        self.sent.insert(text.to_owned(), now);
+        self.messages(severity, text)
+    }
+}
+
+/// `text` in pieces of at most [`CHUNK_LEN`] bytes, split between characters.
+/// A text filling its last chunk gets an empty one to end it, as ground
+/// stations wait for a chunk with room to spare.
+fn chunks(text: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+
+    while rest.len() > CHUNK_LEN {
+        let mut end = CHUNK_LEN;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        chunks.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    if rest.len() == CHUNK_LEN && !chunks.is_empty() {
+        chunks.push(rest);
+        rest = "";
+    }
+    chunks.push(rest);
+    chunks
+}
@@ -0,0 +1,157 @@
+//! Space on the companion computer for downloaded images: warnings as it fills
+//! up, no more captures once it's full and optional cleanup of old images to
+//! stay within a quota.
+
+use crate::thumbnail::{thumbnail_path, THUMBNAIL_DIR};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info};
+
+/// Extensions of the files cleanup may delete, so logs and other files in the
+/// image directory are left alone.
+const IMAGE_EXTENSIONS: [&str; 14] = [
+    "jpg", "jpeg", "tif", "tiff", "dng", "cr2", "cr3", "nef", "arw", "raf", "orf", "rw2", "pef",
+    "srw",
+];
+
+/// Free space thresholds and cleanup of the image directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageOptions {
+    /// Free space below which ground stations are warned.
+    pub low_bytes: u64,
+    /// Free space below which captures are refused.
+    pub full_bytes: u64,
+    /// Oldest images are deleted once a camera's images take more than this.
+    pub quota_bytes: Option<u64>,
+    /// Images older than this are deleted.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for StorageOptions {
+    /// Warnings below 512 MiB and no captures below 64 MiB, without cleanup.
+    fn default() -> Self {
+        Self {
+            low_bytes: 512 * 1024 * 1024,
+            full_bytes: 64 * 1024 * 1024,
+            quota_bytes: None,
+            max_age: None,
+        }
+    }
+}
+
+/// How full the disk with the images is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum SpaceLevel {
+    #[default]
+    Ok,
+    Low,
+    Full,
+}
+
+impl StorageOptions {
+    pub(crate) fn level(&self, available_bytes: u64) -> SpaceLevel {
+        if available_bytes < self.full_bytes {
+            SpaceLevel::Full
+        } else if available_bytes < self.low_bytes {
+            SpaceLevel::Low
+        } else {
+            SpaceLevel::Ok
+        }
+    }
+
+    fn cleans_up(&self) -> bool {
+        self.quota_bytes.is_some() || self.max_age.is_some()
+    }
+}
+
+/// Free space on the disk holding `directory`.
+pub(crate) fn available_space(directory: &Path) -> io::Result<u64> {
+    let directory = if directory.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        directory
+    };
+    fs2::available_space(directory)
+}
+
+/// Deletes images in `directory` and below that are older than the maximum
+/// age, then the oldest ones until the rest fit the quota. `keep` is never
+/// deleted. Returns how many images were deleted. Blocks on the disk.
+pub(crate) fn clean_up(
+    directory: &Path,
+    options: &StorageOptions,
+    keep: &Path,
+) -> io::Result<usize> {
+    if !options.cleans_up() {
+        return Ok(0);
+    }
+
+    let images = list_images(directory)?;
+    let now = SystemTime::now();
+    let mut total: u64 = images.iter().map(|(_, _, size)| size).sum();
+    let mut deleted = 0;
+    for (path, modified, size) in images {
+        if path == keep {
+            continue;
+        }
+        let too_old = options
+            .max_age
+            .is_some_and(|max_age| now.duration_since(modified).unwrap_or_default() > max_age);
+        let over_quota = options.quota_bytes.is_some_and(|quota| total > quota);
+        if !too_old && !over_quota {
+            continue;
+        }
+
+        std::fs::remove_file(&path)?;
+        if let Some(thumbnail) = thumbnail_path(&path) {
+            let _ = std::fs::remove_file(thumbnail);
+        }
+        debug!(target: "backend", path = %path.display(), too_old, "Deleted image");
+        total -= size;
+        deleted += 1;
+    }
+
+    if deleted > 0 {
+        info!(target: "backend", deleted, directory = %directory.display(), "Cleaned up images");
+    }
+    Ok(deleted)
+}
+
+/// The images in `directory` and below with when they were last modified
+/// and their size, oldest first. Blocks on the disk.
+pub(crate) fn list_images(directory: &Path) -> io::Result<Vec<(PathBuf, SystemTime, u64)>> {
+    let mut images = Vec::new();
+    collect_images(directory, &mut images)?;
+    images.sort_by_key(|(_, modified, _)| *modified);
+    Ok(images)
+}
+
+fn collect_images(
+    directory: &Path,
+    images: &mut Vec<(PathBuf, SystemTime, u64)>,
+) -> io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+
+        if metadata.is_dir() {
+            // Thumbnails go with their images.
+            if entry.file_name() != THUMBNAIL_DIR {
+                collect_images(&path, images)?;
+            }
+        } else if metadata.is_file() && is_image(&path) {
+            images.push((path, metadata.modified()?, metadata.len()));
+        }
+    }
+    Ok(())
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        })
+}
@@ -0,0 +1,167 @@
+//! How often each camera sends the messages it streams unasked, configured at
+//! startup and changed by ground stations with `MAV_CMD_SET_MESSAGE_INTERVAL`.
+
+use crate::capture_log::json_string;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How often each streamed message is sent, `None` for not at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamRates {
+    /// Can't be turned off, ground stations drop components whose
+    /// heartbeats stop.
+    pub heartbeat: Duration,
+    pub camera_settings: Option<Duration>,
+    pub storage_information: Option<Duration>,
+    pub capture_status: Option<Duration>,
+}
+
+impl Default for StreamRates {
+    fn default() -> Self {
+        Self {
+            heartbeat: Duration::from_secs(1),
+            camera_settings: None,
+            storage_information: None,
+            capture_status: None,
+        }
+    }
+}
+
+/// A message [`StreamRates`] has a rate for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Streamed {
+    Heartbeat,
+    CameraSettings,
+    StorageInformation,
+    CaptureStatus,
+}
+
+impl Streamed {
+    const ALL: [Self; 4] = [
+        Self::Heartbeat,
+        Self::CameraSettings,
+        Self::StorageInformation,
+        Self::CaptureStatus,
+    ];
+
+    /// The streamed message with the MAVLink id `id`.
+    pub fn from_id(id: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|message| message.id() == id)
+    }
+
+    pub fn id(self) -> u32 {
+        match self {
+            Self::Heartbeat => 0,
+            Self::CameraSettings => 260,
+            Self::StorageInformation => 261,
+            Self::CaptureStatus => 262,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Heartbeat => "HEARTBEAT",
+            Self::CameraSettings => "CAMERA_SETTINGS",
+            Self::StorageInformation => "STORAGE_INFORMATION",
+            Self::CaptureStatus => "CAMERA_CAPTURE_STATUS",
+        }
+    }
+}
+
+impl StreamRates {
+    pub(crate) fn interval(&self, message: Streamed) -> Option<Duration> {
+        match message {
+            Streamed::Heartbeat => Some(self.heartbeat),
+            Streamed::CameraSettings => self.camera_settings,
+            Streamed::StorageInformation => self.storage_information,
+            Streamed::CaptureStatus => self.capture_status,
+        }
+    }
+
+    /// Sends `message` every `interval`, or stops it. Returns false for
+    /// stopping the heartbeat, which is left as it is.
+    pub(crate) fn set(&mut self, message: Streamed, interval: Option<Duration>) -> bool {
+        match (message, interval) {
+            (Streamed::Heartbeat, None) => return false,
+            (Streamed::Heartbeat, Some(interval)) => self.heartbeat = interval,
+            (Streamed::CameraSettings, interval) => self.camera_settings = interval,
+            (Streamed::StorageInformation, interval) => self.storage_information = interval,
+            (Streamed::CaptureStatus, interval) => self.capture_status = interval,
+        }
+        true
+    }
+
+    /// `MESSAGE_INTERVAL.interval_us` of `message`, -1 when it's off.
+    pub(crate) fn interval_us(&self, message: Streamed) -> i32 {
+        self.interval(message).map_or(-1, |interval| {
+            i32::try_from(interval.as_micros()).unwrap_or(i32::MAX)
+        })
+    }
+
+    /// The intervals in µs as a JSON object by message name, -1 when off.
+    pub(crate) fn json(&self) -> String {
+        let fields = self
+            .intervals_us()
+            .map(|(name, interval_us)| format!("{}:{interval_us}", json_string(name)))
+            .collect::<Vec<_>>();
+        format!("{{{}}}", fields.join(","))
+    }
+
+    /// The intervals in µs by message name, -1 when off.
+    pub(crate) fn intervals_us(&self) -> impl Iterator<Item = (&'static str, i32)> + '_ {
+        Streamed::ALL
+            .into_iter()
+            .map(|message| (message.name(), self.interval_us(message)))
+    }
+}
+
+/// When each message the command task streams is due next. The heartbeat has
+/// a task of its own.
+#[derive(Debug, Default)]
+pub(crate) struct StreamSchedule {
+    due: Vec<(Streamed, Instant)>,
+}
+
+impl StreamSchedule {
+    /// Starts over with `rates`, each message first sent an interval from now.
+    pub fn new(rates: &StreamRates) -> Self {
+        let now = Instant::now();
+        let due = Streamed::ALL
+            .into_iter()
+            .filter(|message| *message != Streamed::Heartbeat)
+            .filter_map(|message| Some((message, now + rates.interval(message)?)))
+            .collect();
+
+        Self { due }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.due.is_empty()
+    }
+
+    /// Waits until the next message is due and returns it. Cancel safe.
+    pub async fn next(&self) -> Streamed {
+        match self.due.iter().min_by_key(|(_, due)| *due) {
+            Some((message, due)) => {
+                tokio::time::sleep_until(*due).await;
+                *message
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Records that `message` was sent, it's due again `slowdown` intervals
+    /// from now.
+    pub fn sent(&mut self, message: Streamed, rates: &StreamRates, slowdown: u32) {
+        let Some(interval) = rates.interval(message) else {
+            return;
+        };
+        if let Some((_, due)) = self
+            .due
+            .iter_mut()
+            .find(|(streamed, _)| *streamed == message)
+        {
+            *due = Instant::now() + interval * slowdown;
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! MJPEG over HTTP, serving the camera's live-view JPEGs as they are. Needs no
+//! encoder, so it works without GStreamer, at the cost of more bandwidth.
+
+use super::{FrameSink, LIVE_VIEW_PATH};
+use anyhow::{Context as _, Result};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tracing::debug;
+
+const BOUNDARY: &str = "frame";
+
+/// Frames a slow client may fall behind before it skips some.
+const FRAME_QUEUE: usize = 2;
+
+pub(super) struct MjpegSink {
+    frames: broadcast::Sender<Arc<Vec<u8>>>,
+}
+
+/// Listens for HTTP clients on `port`.
+pub(super) async fn start(port: u16) -> Result<MjpegSink> {
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .await
+        .with_context(|| format!("Failed to serve MJPEG on port {port}"))?;
+    let frames = broadcast::channel(FRAME_QUEUE).0;
+
+    let sender = frames.clone();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, address)) => {
+                    let frames = sender.subscribe();
+                    tokio::spawn(async move {
+                        if let Err(error) = serve(stream, frames).await {
+                            debug!(target: "backend", %address, "Live-view client gone: {error}");
+                        }
+                    });
+                }
+                Err(error) => {
+                    debug!(target: "backend", "Failed to accept live-view client: {error}")
+                }
+            }
+        }
+    });
+
+    Ok(MjpegSink { frames })
+}
+
+/// Answers one request, streaming frames until the client disconnects.
+async fn serve(
+    mut stream: TcpStream,
+    mut frames: broadcast::Receiver<Arc<Vec<u8>>>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&mut stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // Skip the headers, nothing in them matters here.
+    let mut header = String::new();
+    while reader.read_line(&mut header).await? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    if path != LIVE_VIEW_PATH {
+        return stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await;
+    }
+
+    stream
+        .write_all(
+            format!(
+                concat!(
+                    "HTTP/1.1 200 OK\r\n",
+                    "Content-Type: multipart/x-mixed-replace; boundary={}\r\n",
+                    "Cache-Control: no-cache\r\n",
+                    "Connection: close\r\n\r\n",
+                ),
+                BOUNDARY
+            )
+            .as_bytes(),
+        )
+        .await?;
+    debug!(target: "backend", "Live-view client connected");
+
+    loop {
+        let frame = match frames.recv().await {
+            Ok(frame) => frame,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+
+        let part = format!(
+            "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            frame.len()
+        );
+        stream.write_all(part.as_bytes()).await?;
+        stream.write_all(&frame).await?;
+        stream.write_all(b"\r\n").await?;
+    }
+}
+
+impl FrameSink for MjpegSink {
+    fn wants_frames(&self) -> bool {
+        self.frames.receiver_count() > 0
+    }
+
+    fn push(&mut self, frame: Vec<u8>) {
+        let _ = self.frames.send(Arc::new(frame));
+    }
+}
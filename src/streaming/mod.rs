@@ -0,0 +1,117 @@
+//! Live view for ground stations: preview frames are pulled from the camera
+//! and handed to a streaming server while a client watches and the stream
+//! hasn't been stopped with `MAV_CMD_VIDEO_STOP_STREAMING`. The stream's
+//! settings follow [`crate::video::STREAM_SETTINGS_COMMAND`].
+
+mod mjpeg;
+#[cfg(feature = "rtsp")]
+mod rtsp;
+
+#[cfg(feature = "rtsp")]
+pub use rtsp::H264Encoder;
+
+use crate::dispatcher::{with_backend, Backend};
+use crate::error::{CameraError, Result};
+use crate::state::CameraState;
+use crate::status::WorkerReporter;
+use crate::video::{StreamChange, VideoStreamOptions};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{Interval, MissedTickBehavior};
+use tracing::{debug, info};
+
+/// Path the stream is served at.
+pub const LIVE_VIEW_PATH: &str = "/live";
+
+/// How long to wait after the camera failed to give a frame.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// How a camera's live view is served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LiveViewServer {
+    /// The camera's JPEG frames as MJPEG over HTTP at
+    /// `http://<host>:<port>/live`.
+    Mjpeg { port: u16 },
+    /// H.264 over RTSP through GStreamer at `rtsp://<host>:<port>/live`.
+    #[cfg(feature = "rtsp")]
+    Rtsp { port: u16, encoder: H264Encoder },
+}
+
+impl LiveViewServer {
+    /// Whether the server can make `change` to its stream. MJPEG frames go
+    /// out as the camera gives them, only their rate changes.
+    pub(crate) fn can_change(&self, change: &StreamChange) -> bool {
+        match self {
+            LiveViewServer::Mjpeg { .. } => change.resolution.is_none() && change.bitrate.is_none(),
+            #[cfg(feature = "rtsp")]
+            LiveViewServer::Rtsp { .. } => true,
+        }
+    }
+}
+
+/// Where the frames go.
+trait FrameSink: Send {
+    /// Whether anyone watches, frames are only pulled from the camera then.
+    fn wants_frames(&self) -> bool;
+
+    fn push(&mut self, frame: Vec<u8>);
+
+    /// Changes the stream to `stream`, as far as the sink can.
+    fn configure(&mut self, _stream: &VideoStreamOptions) {}
+}
+
+/// Starts `server` and feeds it frames from `backend` at the rate of
+/// `stream` while `state` says the stream runs.
+pub(crate) async fn run(
+    server: LiveViewServer,
+    backend: Backend,
+    state: watch::Receiver<CameraState>,
+    mut stream: watch::Receiver<VideoStreamOptions>,
+    reporter: WorkerReporter,
+) -> Result<()> {
+    let mut sink: Box<dyn FrameSink> = match server {
+        LiveViewServer::Mjpeg { port } => {
+            Box::new(mjpeg::start(port).await.map_err(CameraError::LiveView)?)
+        }
+        #[cfg(feature = "rtsp")]
+        LiveViewServer::Rtsp { port, encoder } => Box::new(
+            rtsp::start(port, encoder, stream.borrow_and_update().clone())
+                .map_err(CameraError::LiveView)?,
+        ),
+    };
+    info!(target: "backend", ?server, "Serving live view");
+
+    let mut interval = frame_interval(stream.borrow_and_update().framerate);
+    loop {
+        interval.tick().await;
+        if stream.has_changed().unwrap_or(false) {
+            let settings = stream.borrow_and_update().clone();
+            debug!(target: "backend", ?settings, "Changing live view");
+            interval = frame_interval(settings.framerate);
+            sink.configure(&settings);
+        }
+        if !state.borrow().streaming || !sink.wants_frames() {
+            continue;
+        }
+
+        match with_backend(&backend, |backend| backend.preview_frame()).await {
+            Ok(frame) => {
+                sink.push(frame);
+                reporter.running();
+            }
+            Err(error) => {
+                debug!(target: "backend", "Failed to get a live-view frame: {error}");
+                reporter.degraded(&error);
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Ticks `framerate` times per second, at least once.
+fn frame_interval(framerate: f32) -> Interval {
+    let mut interval = tokio::time::interval(Duration::from_secs_f32(1.0 / framerate.max(1.0)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    interval
+}
@@ -0,0 +1,223 @@
+//! RTSP server fed through a GStreamer `appsrc`, which re-encodes the camera's
+//! JPEG frames to H.264 for ground stations like QGroundControl.
+//!
+//! x264 can take most of a Raspberry Pi's CPU, so the encoder can be swapped
+//! for the SoC's hardware one. Frames are scaled to the stream's resolution,
+//! which like the bitrate changes while the pipeline runs.
+
+use super::{FrameSink, LIVE_VIEW_PATH};
+use crate::video::VideoStreamOptions;
+use anyhow::{bail, Context as _, Result};
+use gstreamer::{self as gst, glib, prelude::*};
+use gstreamer_app::AppSrc;
+use gstreamer_rtsp_server::{prelude::*, RTSPMediaFactory, RTSPServer};
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// Which element encodes the stream to H.264.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum H264Encoder {
+    /// x264 in software, works everywhere.
+    #[default]
+    X264,
+    /// The V4L2 memory-to-memory encoder of the Raspberry Pi 4 and others.
+    V4l2,
+    /// The OpenMAX encoder of older Raspberry Pi OS releases.
+    Omx,
+}
+
+impl H264Encoder {
+    /// The GStreamer element, which has to be installed.
+    fn element(self) -> &'static str {
+        match self {
+            H264Encoder::X264 => "x264enc",
+            H264Encoder::V4l2 => "v4l2h264enc",
+            H264Encoder::Omx => "omxh264enc",
+        }
+    }
+
+    /// The encoder and what it needs around it to take the decoded frames
+    /// and give a stream `rtph264pay` accepts, tuned for low latency.
+    fn pipeline(self) -> &'static str {
+        match self {
+            H264Encoder::X264 => concat!(
+                "videoconvert",
+                " ! x264enc name=encoder tune=zerolatency speed-preset=ultrafast key-int-max=30",
+            ),
+            H264Encoder::V4l2 => concat!(
+                "videoconvert ! video/x-raw,format=I420",
+                " ! v4l2h264enc name=encoder extra-controls=\"controls,repeat_sequence_header=1,h264_i_frame_period=30\"",
+                " ! video/x-h264,level=(string)4 ! h264parse",
+            ),
+            // `periodicty-idr` is how gst-omx spells it.
+            H264Encoder::Omx => concat!(
+                "videoconvert ! video/x-raw,format=I420",
+                " ! omxh264enc name=encoder control-rate=variable periodicty-idr=30 inline-header=true",
+                " ! h264parse",
+            ),
+        }
+    }
+
+    /// Sets the bitrate of the running `element` to `bitrate` bits per
+    /// second.
+    fn set_bitrate(self, element: &gst::Element, bitrate: u32) {
+        match self {
+            H264Encoder::X264 => {
+                element.set_property_from_str("bitrate", &(bitrate / 1000).max(1).to_string())
+            }
+            // The controls of `pipeline` with the bitrate.
+            H264Encoder::V4l2 => element.set_property_from_str(
+                "extra-controls",
+                &format!(
+                    "controls,repeat_sequence_header=1,h264_i_frame_period=30,video_bitrate={bitrate}"
+                ),
+            ),
+            H264Encoder::Omx => element.set_property_from_str("target-bitrate", &bitrate.to_string()),
+        }
+    }
+}
+
+/// Decodes the frames pushed to `src`, scales them with `size` and encodes
+/// them with `encoder`.
+fn pipeline(encoder: H264Encoder) -> String {
+    format!(
+        concat!(
+            "( appsrc name=src is-live=true do-timestamp=true format=time caps=image/jpeg",
+            " ! jpegparse ! jpegdec ! videoscale ! capsfilter name=size ! {}",
+            " ! rtph264pay name=pay0 pt=96 config-interval=1 )",
+        ),
+        encoder.pipeline()
+    )
+}
+
+/// The elements of the running pipeline.
+struct Pipeline {
+    source: AppSrc,
+    size: Option<gst::Element>,
+    encoder: Option<gst::Element>,
+}
+
+impl Pipeline {
+    /// Scales to the resolution of `stream` and encodes at its bitrate, or
+    /// the encoder's default for 0.
+    fn configure(&self, encoder: H264Encoder, stream: &VideoStreamOptions) {
+        if let Some(size) = &self.size {
+            let caps = format!(
+                "video/x-raw,width={},height={}",
+                stream.width, stream.height
+            );
+            size.set_property_from_str("caps", &caps);
+        }
+        if let Some(element) = self.encoder.as_ref().filter(|_| stream.bitrate > 0) {
+            encoder.set_bitrate(element, stream.bitrate);
+        }
+    }
+}
+
+/// The running pipeline and the settings of the stream, for the next one
+/// too. The pipeline is `None` while nobody watches.
+type Shared = Arc<Mutex<(Option<Pipeline>, VideoStreamOptions)>>;
+
+pub(super) struct RtspSink {
+    shared: Shared,
+    encoder: H264Encoder,
+}
+
+/// Starts the RTSP server on `port` with its own GLib main loop thread,
+/// serving `stream`.
+pub(super) fn start(
+    port: u16,
+    encoder: H264Encoder,
+    stream: VideoStreamOptions,
+) -> Result<RtspSink> {
+    gst::init().context("Failed to initialise GStreamer")?;
+    // The pipeline is only built for the first client, check the encoder
+    // exists while the error still reaches the log.
+    if gst::ElementFactory::find(encoder.element()).is_none() {
+        bail!("GStreamer element {} is not installed", encoder.element());
+    }
+
+    let server = RTSPServer::new();
+    server.set_service(&port.to_string());
+
+    let factory = RTSPMediaFactory::new();
+    factory.set_launch(&pipeline(encoder));
+    // One pipeline for all clients, so every frame is only pulled once.
+    factory.set_shared(true);
+
+    let shared = Arc::new(Mutex::new((None, stream)));
+    let configured = shared.clone();
+    factory.connect_media_configure(move |_, media| {
+        let bin = media.element().downcast::<gst::Bin>().ok();
+        let element = |name: &str| bin.as_ref().and_then(|bin| bin.by_name(name));
+        let pipeline = element("src")
+            .and_then(|element| element.downcast::<AppSrc>().ok())
+            .map(|source| Pipeline {
+                source,
+                size: element("size"),
+                encoder: element("encoder"),
+            });
+        debug!(target: "backend", "Live-view client connected");
+        if let Ok(mut shared) = configured.lock() {
+            let (running, stream) = &mut *shared;
+            if let Some(pipeline) = &pipeline {
+                pipeline.configure(encoder, stream);
+            }
+            *running = pipeline;
+        }
+    });
+
+    server
+        .mount_points()
+        .context("RTSP server has no mount points")?
+        .add_factory(LIVE_VIEW_PATH, factory);
+    server
+        .attach(None)
+        .with_context(|| format!("Failed to serve RTSP on port {port}"))?;
+
+    let main_loop = glib::MainLoop::new(None, false);
+    std::thread::Builder::new()
+        .name("rtsp".to_owned())
+        .spawn(move || {
+            let _server = server;
+            main_loop.run();
+        })?;
+
+    Ok(RtspSink { shared, encoder })
+}
+
+impl FrameSink for RtspSink {
+    fn wants_frames(&self) -> bool {
+        self.shared.lock().is_ok_and(|shared| shared.0.is_some())
+    }
+
+    fn push(&mut self, frame: Vec<u8>) {
+        let Ok(mut shared) = self.shared.lock() else {
+            return;
+        };
+        let Some(pipeline) = &shared.0 else {
+            return;
+        };
+
+        // Fails once the last client left and the pipeline shut down.
+        if let Err(error) = pipeline
+            .source
+            .push_buffer(gst::Buffer::from_mut_slice(frame))
+        {
+            debug!(target: "backend", "Live-view client gone: {error}");
+            shared.0 = None;
+        }
+    }
+
+    fn configure(&mut self, stream: &VideoStreamOptions) {
+        let Ok(mut shared) = self.shared.lock() else {
+            return;
+        };
+        if let Some(pipeline) = &shared.0 {
+            pipeline.configure(self.encoder, stream);
+        }
+        shared.1 = stream.clone();
+    }
+}
@@ -0,0 +1,37 @@
+//! The vehicle's system id, which the camera protocol has cameras share, taken
+//! from the autopilot's heartbeat before the cameras start.
+
+use crate::connection::Incoming;
+use crate::message::CameraDialect;
+use mavlink::common::{MavAutopilot, MavMessage, MavType};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Waits up to `timeout` for an autopilot's heartbeat on `incoming` and
+/// returns its system id, `None` if none was heard.
+pub(crate) async fn autopilot_system_id<M: CameraDialect>(
+    incoming: &mut Incoming<M>,
+    timeout: Duration,
+) -> Option<u8> {
+    info!(?timeout, "Waiting for the autopilot's system id");
+    let deadline = Instant::now() + timeout;
+    while let Ok(Some((header, message))) = tokio::time::timeout_at(deadline, incoming.recv()).await
+    {
+        let Some(MavMessage::HEARTBEAT(heartbeat)) = message.to_common() else {
+            continue;
+        };
+        if heartbeat.mavtype != MavType::MAV_TYPE_GCS
+            && heartbeat.autopilot != MavAutopilot::MAV_AUTOPILOT_INVALID
+        {
+            info!(
+                system = header.system_id,
+                "Taking the autopilot's system id"
+            );
+            return Some(header.system_id);
+        }
+    }
+
+    warn!("No autopilot heard, keeping the configured system id");
+    None
+}
@@ -0,0 +1,77 @@
+//! Small JPEG thumbnails of the captures, so photo review on the ground
+//! station stays quick over slow links. They are written to a `thumbnails`
+//! directory next to the images and served by the HTTP server.
+
+use crate::event::{CameraEvent, EventSender};
+use crate::preview;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Directory next to the images the thumbnails are written to.
+pub(crate) const THUMBNAIL_DIR: &str = "thumbnails";
+
+/// Size and quality of the thumbnails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThumbnailOptions {
+    /// Longest side in pixels.
+    pub max_size: u32,
+    /// JPEG quality, 1-100.
+    pub jpeg_quality: u8,
+}
+
+impl Default for ThumbnailOptions {
+    /// About what ground station photo galleries show.
+    fn default() -> Self {
+        Self {
+            max_size: 256,
+            jpeg_quality: 75,
+        }
+    }
+}
+
+/// Where the thumbnail of `image` goes: `thumbnails/<stem>.jpg` next to it,
+/// so a RAW and a JPEG of the same shot share one.
+pub(crate) fn thumbnail_path(image: &Path) -> Option<PathBuf> {
+    let mut name = image.file_stem()?.to_owned();
+    name.push(".jpg");
+    let directory = image.parent().unwrap_or(Path::new("."));
+
+    Some(directory.join(THUMBNAIL_DIR).join(name))
+}
+
+/// Writes the thumbnail of camera `camera`'s `image` in the background and
+/// announces it with [`CameraEvent::PreviewReady`]. Failures are only logged
+/// since the capture itself succeeded.
+pub(crate) fn spawn_write(
+    image: PathBuf,
+    options: ThumbnailOptions,
+    camera: u8,
+    events: EventSender,
+) {
+    tokio::task::spawn_blocking(move || match write(&image, options) {
+        Ok(thumbnail) => {
+            debug!(target: "backend", path = %thumbnail.display(), "Wrote thumbnail");
+            events.emit(CameraEvent::PreviewReady {
+                camera,
+                image,
+                thumbnail,
+            });
+        }
+        Err(error) => warn!(target: "backend", "Failed to write thumbnail: {error:#}"),
+    });
+}
+
+fn write(image: &Path, options: ThumbnailOptions) -> Result<PathBuf> {
+    let path = thumbnail_path(image).context("Image has no file name")?;
+    let thumbnail = preview::downscale(image, options.max_size, options.jpeg_quality)?;
+
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+    }
+    std::fs::write(&path, thumbnail.jpeg)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(path)
+}
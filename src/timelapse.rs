@@ -0,0 +1,66 @@
+//! Time-lapse for `MAV_CMD_IMAGE_START_CAPTURE` with an interval. Captures
+//! are due at fixed deadlines from the start on the monotonic clock rather
+//! than an interval after the previous one, so the time captures take
+//! doesn't add up over a long flight.
+
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A running time-lapse of one camera.
+#[derive(Debug)]
+pub(crate) struct TimeLapse {
+    start: Instant,
+    interval: Duration,
+    /// Number of the deadline the next capture is due at, 0 at the start.
+    next: u32,
+    /// Captures taken so far.
+    taken: u32,
+    /// Captures to take, `None` until stopped.
+    total: Option<u32>,
+}
+
+impl TimeLapse {
+    /// Starts with a capture right away.
+    pub fn new(interval: Duration, total: Option<u32>) -> Self {
+        Self {
+            start: Instant::now(),
+            interval,
+            next: 0,
+            taken: 0,
+            total,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Captures still to take, `None` until stopped.
+    pub fn left(&self) -> Option<u32> {
+        self.total.map(|total| total.saturating_sub(self.taken))
+    }
+
+    /// `None` if it's too far out to tell.
+    fn deadline(&self) -> Option<Instant> {
+        self.start
+            .checked_add(self.interval.checked_mul(self.next)?)
+    }
+
+    /// Records a capture and returns whether more are due. Deadlines a slow
+    /// capture overran are skipped, not caught up with.
+    pub fn captured(&mut self) -> bool {
+        self.taken += 1;
+        let elapsed = self.start.elapsed().as_secs_f64() / self.interval.as_secs_f64();
+        self.next = (self.next + 1).max(elapsed.ceil() as u32);
+
+        self.total.is_none_or(|total| self.taken < total)
+    }
+}
+
+/// Waits until the time-lapse's next capture is due, forever without one.
+pub(crate) async fn due(timelapse: Option<&TimeLapse>) {
+    match timelapse.and_then(TimeLapse::deadline) {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,121 @@
+//! The legacy image transmission protocol: a downscaled preview of each
+//! capture is announced with `DATA_TRANSMISSION_HANDSHAKE` and sent in
+//! `ENCAPSULATED_DATA` packets, for ground stations that only have the
+//! telemetry link.
+
+use crate::connection::LinkSender;
+use crate::error::{CameraError, Result};
+use crate::message::CameraDialect;
+use crate::preview::{self, Preview};
+use crate::status::WorkerReporter;
+use mavlink::common::{
+    MavMessage, MavlinkDataStreamType, DATA_TRANSMISSION_HANDSHAKE_DATA, ENCAPSULATED_DATA_DATA,
+};
+use mavlink::MavHeader;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+/// Image bytes per `ENCAPSULATED_DATA`.
+const PAYLOAD: usize = 253;
+
+/// How previews are made and sent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageTransmissionOptions {
+    /// Longest side of the preview in pixels.
+    pub max_size: u32,
+    /// JPEG quality of the preview, 1-100.
+    pub jpeg_quality: u8,
+    /// `ENCAPSULATED_DATA` packets per second, to leave room on the link for
+    /// telemetry. 10 takes about 2.5 kB/s.
+    pub packet_rate: f32,
+}
+
+impl Default for ImageTransmissionOptions {
+    /// A 320 pixel preview of a few kB, sent in a few seconds.
+    fn default() -> Self {
+        Self {
+            max_size: 320,
+            jpeg_quality: 50,
+            packet_rate: 10.0,
+        }
+    }
+}
+
+/// Hands captures to a camera's transmission worker.
+pub(crate) struct ImageTransmitter(watch::Sender<Option<PathBuf>>);
+
+impl ImageTransmitter {
+    pub fn new() -> (Self, watch::Receiver<Option<PathBuf>>) {
+        let (sender, receiver) = watch::channel(None);
+        (Self(sender), receiver)
+    }
+
+    /// Sends a preview of `image` once the one being sent is done. Images
+    /// queued meanwhile replace each other, so a slow link only falls behind
+    /// by one.
+    pub fn queue(&self, image: PathBuf) {
+        self.0.send_replace(Some(image));
+    }
+}
+
+/// Sends a preview of every image queued on `images` from `header`.
+pub(crate) async fn run<M: CameraDialect>(
+    link: LinkSender<M>,
+    header: MavHeader,
+    options: ImageTransmissionOptions,
+    mut images: watch::Receiver<Option<PathBuf>>,
+    reporter: WorkerReporter,
+) -> Result<()> {
+    let packet_period = Duration::from_secs_f32(1.0 / options.packet_rate.max(1.0));
+
+    loop {
+        images.changed().await.map_err(|_| CameraError::Stopped)?;
+        let Some(path) = images.borrow_and_update().clone() else {
+            continue;
+        };
+
+        let preview = tokio::task::spawn_blocking(move || {
+            preview::downscale(&path, options.max_size, options.jpeg_quality)
+        })
+        .await;
+        let preview = match preview {
+            Ok(Ok(preview)) => preview,
+            Ok(Err(error)) => {
+                debug!(target: "backend", "No preview to transmit: {error:#}");
+                continue;
+            }
+            Err(error) => {
+                debug!(target: "backend", "No preview to transmit: {error}");
+                continue;
+            }
+        };
+
+        info!(target: "rx", bytes = preview.jpeg.len(), "Transmitting preview");
+        link.send(&header, handshake(&preview, options.jpeg_quality))?;
+        for (seqnr, chunk) in preview.jpeg.chunks(PAYLOAD).enumerate() {
+            // Slower while the telemetry radio can't keep up.
+            tokio::time::sleep(packet_period * link.slowdown()).await;
+            let data = ENCAPSULATED_DATA_DATA {
+                seqnr: seqnr as u16,
+                data: heapless::Vec::from_slice(chunk).unwrap_or_default(),
+            };
+            link.send(&header, MavMessage::ENCAPSULATED_DATA(data))?;
+        }
+        reporter.running();
+    }
+}
+
+/// Announces `preview` so the ground station can put the packets together.
+fn handshake(preview: &Preview, jpeg_quality: u8) -> MavMessage {
+    MavMessage::DATA_TRANSMISSION_HANDSHAKE(DATA_TRANSMISSION_HANDSHAKE_DATA {
+        size: preview.jpeg.len() as u32,
+        width: preview.width as u16,
+        height: preview.height as u16,
+        packets: preview.jpeg.len().div_ceil(PAYLOAD) as u16,
+        mavtype: MavlinkDataStreamType::MAVLINK_DATA_STREAM_IMG_JPEG,
+        payload: PAYLOAD as u8,
+        jpg_quality: jpeg_quality,
+    })
+}
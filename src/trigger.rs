@@ -0,0 +1,111 @@
+//! Distance triggering as autopilots do it for `MAV_CMD_DO_SET_CAM_TRIGG_DIST`:
+//! a capture every so many metres of ground track, measured from the
+//! autopilot's `GLOBAL_POSITION_INT`.
+
+use std::time::{Duration, Instant};
+
+/// Mean earth radius in metres, plenty for the few metres between positions.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Decides when one camera captures for distance triggering.
+#[derive(Debug, Default)]
+pub(crate) struct DistanceTrigger {
+    /// Ground distance between captures in metres, 0 when off.
+    spacing: f32,
+    /// Switched off by `MAV_CMD_DO_TRIGGER_CONTROL`, keeping the spacing.
+    disabled: bool,
+    /// Paused by `MAV_CMD_DO_TRIGGER_CONTROL`: the track is followed but
+    /// nothing is counted, so captures resume where the pause ended.
+    paused: bool,
+    /// Captures closer together than this are skipped until it has passed.
+    min_interval: Duration,
+    /// Ground track covered since the last capture in metres.
+    travelled: f64,
+    /// Latitude and longitude in degrees * 1E7 the track was last measured at.
+    last_position: Option<(i32, i32)>,
+    last_capture: Option<Instant>,
+}
+
+impl DistanceTrigger {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            ..Default::default()
+        }
+    }
+
+    /// Whether the vehicle's position needs to be followed.
+    pub fn is_active(&self) -> bool {
+        self.spacing > 0.0 && !self.disabled
+    }
+
+    /// Captures every `spacing` metres from now on, or stops at 0. Also
+    /// undoes [`DistanceTrigger::set_enabled`] and
+    /// [`DistanceTrigger::set_paused`].
+    pub fn set_spacing(&mut self, spacing: f32) {
+        self.spacing = spacing.max(0.0);
+        self.disabled = false;
+        self.paused = false;
+        self.reset();
+    }
+
+    /// Switches triggering off or back on with the last spacing. Returns
+    /// whether it's now triggering.
+    pub fn set_enabled(&mut self, enabled: bool) -> bool {
+        if enabled && self.disabled {
+            self.reset();
+        }
+        self.disabled = !enabled;
+        self.is_active() && !self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Starts counting the distance from the next position again.
+    pub fn reset(&mut self) {
+        self.travelled = 0.0;
+        self.last_position = None;
+    }
+
+    /// Adds the way to the vehicle's latest position to the track and
+    /// returns whether it's time for a capture.
+    pub fn update(&mut self, lat: i32, lon: i32) -> bool {
+        if !self.is_active() {
+            return false;
+        }
+
+        let last = self.last_position.replace((lat, lon));
+        if self.paused {
+            return false;
+        }
+        if let Some(last) = last {
+            self.travelled += ground_distance(last, (lat, lon));
+        }
+
+        self.travelled >= f64::from(self.spacing)
+            && self
+                .last_capture
+                .is_none_or(|captured| captured.elapsed() >= self.min_interval)
+    }
+
+    /// Restarts the count after any capture, so a picture taken on request
+    /// isn't followed by another one right away.
+    pub fn captured(&mut self) {
+        self.travelled = 0.0;
+        self.last_capture = Some(Instant::now());
+    }
+}
+
+/// Distance in metres between two positions in degrees * 1E7, flat earth
+/// approximated around their mean latitude.
+fn ground_distance((lat1, lon1): (i32, i32), (lat2, lon2): (i32, i32)) -> f64 {
+    let radians = |value: i32| (f64::from(value) / 1e7).to_radians();
+
+    let north = radians(lat2) - radians(lat1);
+    let mean_lat = (radians(lat1) + radians(lat2)) / 2.0;
+    let east = (radians(lon2) - radians(lon1)) * mean_lat.cos();
+
+    north.hypot(east) * EARTH_RADIUS_M
+}
@@ -0,0 +1,136 @@
+//! What the component knows about the vehicle it's mounted on, used to
+//! geotag and timestamp captures and to notice the autopilot going silent.
+
+use crate::clock::UtcSource;
+use crate::geotag::Geotag;
+use chrono::{DateTime, TimeDelta, Utc};
+use mavlink::common::{
+    MavComponent, MavMessage, MavModeFlag, ATTITUDE_DATA, GLOBAL_POSITION_INT_DATA,
+};
+use mavlink::MavHeader;
+use std::time::{Duration, Instant};
+
+/// Fixes older than this aren't used for geotagging.
+const MAX_FIX_AGE: Duration = Duration::from_secs(2);
+
+/// The autopilot's latest position and attitude.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct VehicleState {
+    /// The system id of the vehicle the cameras are on. Autopilots of other
+    /// vehicles sharing the link are ignored.
+    system_id: u8,
+    position: Option<(GLOBAL_POSITION_INT_DATA, Instant)>,
+    attitude: Option<(ATTITUDE_DATA, Instant)>,
+    armed: bool,
+    /// When the vehicle last armed, the start of the flight.
+    armed_at: Option<DateTime<Utc>>,
+    /// How far the autopilot's clock is ahead of the companion's, once it
+    /// has sent its time.
+    clock_offset: Option<TimeDelta>,
+    /// When the autopilot's last `HEARTBEAT` arrived.
+    last_heartbeat: Option<Instant>,
+}
+
+impl VehicleState {
+    /// Knows nothing yet about the vehicle with `system_id`.
+    pub fn new(system_id: u8) -> Self {
+        Self {
+            system_id,
+            ..Default::default()
+        }
+    }
+
+    /// Takes note of `message` if it's position, attitude or time from the
+    /// vehicle's autopilot. Returns whether anything changed that captures
+    /// wait for.
+    pub fn update(&mut self, header: &MavHeader, message: &MavMessage) -> bool {
+        if header.system_id != self.system_id
+            || header.component_id != MavComponent::MAV_COMP_ID_AUTOPILOT1 as u8
+        {
+            return false;
+        }
+
+        match message {
+            MavMessage::GLOBAL_POSITION_INT(position) => {
+                self.position = Some((position.clone(), Instant::now()));
+                true
+            }
+            MavMessage::ATTITUDE(attitude) => {
+                self.attitude = Some((attitude.clone(), Instant::now()));
+                true
+            }
+            MavMessage::HEARTBEAT(heartbeat) => {
+                self.last_heartbeat = Some(Instant::now());
+                let armed = heartbeat
+                    .base_mode
+                    .contains(MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED);
+                if armed && !self.armed {
+                    self.armed_at = Some(self.utc(Utc::now()).0);
+                }
+                std::mem::replace(&mut self.armed, armed) != armed
+            }
+            // 0 until the autopilot knows the time, e.g. from GPS.
+            MavMessage::SYSTEM_TIME(time) if time.time_unix_usec != 0 => {
+                if let Some(vehicle) = DateTime::from_timestamp_micros(time.time_unix_usec as i64) {
+                    self.clock_offset = Some(vehicle - Utc::now());
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Converts `local`, a time of the companion's clock, to the autopilot's
+    /// clock if it has sent its time.
+    pub fn utc(&self, local: DateTime<Utc>) -> (DateTime<Utc>, UtcSource) {
+        match self.clock_offset {
+            Some(offset) => (local + offset, UtcSource::Vehicle),
+            None => (local, UtcSource::Companion),
+        }
+    }
+
+    /// The autopilot's time now, `None` until it has sent it.
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        self.clock_offset.map(|offset| Utc::now() + offset)
+    }
+
+    /// Whether the autopilot was heard from but hasn't sent a heartbeat for
+    /// `timeout`.
+    pub fn is_lost(&self, timeout: Duration) -> bool {
+        self.last_heartbeat
+            .is_some_and(|heartbeat| heartbeat.elapsed() >= timeout)
+    }
+
+    /// Returns the latest position, or `None` without a recent fix.
+    pub fn position(&self) -> Option<&GLOBAL_POSITION_INT_DATA> {
+        self.position
+            .as_ref()
+            .filter(|(_, received)| received.elapsed() < MAX_FIX_AGE)
+            .map(|(position, _)| position)
+    }
+
+    /// Returns when the vehicle last armed, `None` if it hasn't since the
+    /// component started.
+    pub fn armed_at(&self) -> Option<DateTime<Utc>> {
+        self.armed_at
+    }
+
+    /// Returns the current geotag, or `None` without a recent position fix.
+    pub fn geotag(&self) -> Option<Geotag> {
+        let position = self.position()?;
+
+        let attitude = self
+            .attitude
+            .as_ref()
+            .filter(|(_, received)| received.elapsed() < MAX_FIX_AGE)
+            .map(|(attitude, _)| [attitude.roll, attitude.pitch, attitude.yaw]);
+
+        Some(Geotag {
+            lat: position.lat,
+            lon: position.lon,
+            alt: position.alt,
+            relative_alt: position.relative_alt,
+            attitude,
+        })
+    }
+}
@@ -0,0 +1,170 @@
+//! The live-view video stream as advertised to ground stations, which play it
+//! from the URI in `VIDEO_STREAM_INFORMATION`. Ground stations can trade its
+//! quality for bandwidth with [`STREAM_SETTINGS_COMMAND`] while the component
+//! serves it.
+
+use crate::mavlink_camera::{str_to_fixed_arr, string_to_uri};
+use mavlink::common::{
+    MavCmd, MavMessage, VideoStreamStatusFlags, VideoStreamType, COMMAND_LONG_DATA,
+    VIDEO_STREAM_INFORMATION_DATA, VIDEO_STREAM_STATUS_DATA,
+};
+use std::fmt;
+
+/// Changes the live view the component serves: param 1 is the stream id of
+/// `VIDEO_STREAM_INFORMATION` or 0, params 2 and 3 the width and height in
+/// pixels, param 4 the frames per second and param 5 the bits per second.
+/// Settings given as 0 stay as they are. MAVLink has no command for this.
+pub const STREAM_SETTINGS_COMMAND: MavCmd = MavCmd::MAV_CMD_SPATIAL_USER_1;
+
+/// Most frames per second a ground station may ask for.
+const MAX_FRAMERATE: f32 = 60.0;
+
+/// A camera's live-view stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoStreamOptions {
+    /// Where ground stations can play the stream, e.g.
+    /// `rtsp://192.168.144.10:8554/live`.
+    pub uri: String,
+    /// Human readable name shown by the ground station.
+    pub name: String,
+    pub stream_type: VideoStreamType,
+    pub encoding: VideoEncoding,
+    pub width: u16,
+    pub height: u16,
+    /// Frames per second.
+    pub framerate: f32,
+    /// Bits per second, 0 if unknown.
+    pub bitrate: u32,
+}
+
+impl VideoStreamOptions {
+    /// An H.264 RTSP stream at `uri` in 1024x680 at 25 frames per second,
+    /// about what DSLR live view gives.
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            name: "Live view".to_owned(),
+            stream_type: VideoStreamType::VIDEO_STREAM_TYPE_RTSP,
+            encoding: VideoEncoding::H264,
+            width: 1024,
+            height: 680,
+            framerate: 25.0,
+            bitrate: 0,
+        }
+    }
+}
+
+/// Codec of the stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VideoEncoding {
+    #[default]
+    H264,
+    H265,
+    Mjpeg,
+}
+
+impl fmt::Display for VideoEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VideoEncoding::H264 => "H.264",
+            VideoEncoding::H265 => "H.265",
+            VideoEncoding::Mjpeg => "MJPEG",
+        })
+    }
+}
+
+/// The stream ids of a camera; it only ever has one.
+const STREAM_ID: u8 = 1;
+
+/// What [`STREAM_SETTINGS_COMMAND`] changes, `None` for what stays.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct StreamChange {
+    pub resolution: Option<(u16, u16)>,
+    pub framerate: Option<f32>,
+    pub bitrate: Option<u32>,
+}
+
+impl StreamChange {
+    /// The change `command_long` asks for, `None` if it's for another stream
+    /// or out of range.
+    pub fn from_command(command_long: &COMMAND_LONG_DATA) -> Option<Self> {
+        if ![0.0, f32::from(STREAM_ID)].contains(&command_long.param1) {
+            return None;
+        }
+        let whole = |value: f32, max: f32| {
+            ((0.0..=max).contains(&value) && value.fract() == 0.0).then_some(value)
+        };
+        let width = whole(command_long.param2, f32::from(u16::MAX))? as u16;
+        let height = whole(command_long.param3, f32::from(u16::MAX))? as u16;
+        let bitrate = whole(command_long.param5, u32::MAX as f32)? as u32;
+        let framerate = command_long.param4;
+        if !(0.0..=MAX_FRAMERATE).contains(&framerate) {
+            return None;
+        }
+        let resolution = match (width, height) {
+            (0, 0) => None,
+            (0, _) | (_, 0) => return None,
+            resolution => Some(resolution),
+        };
+
+        Some(Self {
+            resolution,
+            framerate: (framerate > 0.0).then_some(framerate),
+            bitrate: (bitrate > 0).then_some(bitrate),
+        })
+    }
+
+    /// `stream` with the change made.
+    pub fn apply(self, stream: &mut VideoStreamOptions) {
+        if let Some((width, height)) = self.resolution {
+            (stream.width, stream.height) = (width, height);
+        }
+        if let Some(framerate) = self.framerate {
+            stream.framerate = framerate;
+        }
+        if let Some(bitrate) = self.bitrate {
+            stream.bitrate = bitrate;
+        }
+    }
+}
+
+fn status_flags(running: bool) -> VideoStreamStatusFlags {
+    if running {
+        VideoStreamStatusFlags::VIDEO_STREAM_STATUS_FLAGS_RUNNING
+    } else {
+        VideoStreamStatusFlags::empty()
+    }
+}
+
+/// `VIDEO_STREAM_INFORMATION` for `stream`. This version of MAVLink has no
+/// field for the encoding, so it's added to the name.
+pub(crate) fn stream_information(stream: &VideoStreamOptions, running: bool) -> MavMessage {
+    let name = format!("{} ({})", stream.name, stream.encoding);
+
+    MavMessage::VIDEO_STREAM_INFORMATION(VIDEO_STREAM_INFORMATION_DATA {
+        framerate: stream.framerate,
+        bitrate: stream.bitrate,
+        flags: status_flags(running),
+        resolution_h: stream.width,
+        resolution_v: stream.height,
+        stream_id: STREAM_ID,
+        count: 1,
+        mavtype: stream.stream_type,
+        name: str_to_fixed_arr(&name),
+        uri: string_to_uri(&stream.uri),
+        ..Default::default()
+    })
+}
+
+/// `VIDEO_STREAM_STATUS` for `stream`, sent periodically while it runs.
+pub(crate) fn stream_status(stream: &VideoStreamOptions, running: bool) -> MavMessage {
+    MavMessage::VIDEO_STREAM_STATUS(VIDEO_STREAM_STATUS_DATA {
+        framerate: stream.framerate,
+        bitrate: stream.bitrate,
+        flags: status_flags(running),
+        resolution_h: stream.width,
+        resolution_v: stream.height,
+        stream_id: STREAM_ID,
+        ..Default::default()
+    })
+}
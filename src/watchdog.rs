@@ -0,0 +1,293 @@
+//! Internal health watchdog: notices when the heartbeat, the receive loop or
+//! a camera's command task stops making progress, e.g. on a camera call that
+//! never returns, marks the cameras relying on it degraded and tells the crew.
+
+use crate::connection::LinkSender;
+use crate::error::Result;
+use crate::event::{CameraEvent, EventSender};
+use crate::mavlink_camera::heartbeat_message;
+use crate::message::CameraDialect;
+use crate::state::CameraState;
+use crate::status::{WorkerReporter, WorkerStatus};
+use crate::statustext::StatusTexts;
+use mavlink::common::MavSeverity;
+use mavlink::MavHeader;
+use std::mem::replace;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+/// How often the watched tasks are checked.
+const CHECK_PERIOD: Duration = Duration::from_secs(1);
+
+/// When a task counts as stalled and what happens then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogOptions {
+    /// A task busy with the same work for longer than this is stalled. Long
+    /// exposures and bursts get their expected duration on top.
+    pub stall_timeout: Duration,
+    /// Exits the process once a stall lasts this long, for a supervisor such
+    /// as systemd to start it again. A camera call hanging in the driver
+    /// can't be cancelled from within. Never when `None`.
+    pub exit_after: Option<Duration>,
+}
+
+impl Default for WatchdogOptions {
+    /// Stalled after 30 seconds, without exiting.
+    fn default() -> Self {
+        Self {
+            stall_timeout: Duration::from_secs(30),
+            exit_after: None,
+        }
+    }
+}
+
+/// A task the watchdog keeps an eye on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Task {
+    /// The heartbeat of the camera with this component id.
+    Heartbeat(u8),
+    /// The command task of the camera with this component id, which makes
+    /// all the backend calls.
+    Commands(u8),
+    /// The task routing incoming messages to every camera.
+    Receive,
+}
+
+impl Task {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Heartbeat(_) => "heartbeat",
+            Self::Commands(_) => "command",
+            Self::Receive => "receive",
+        }
+    }
+
+    fn camera(self) -> Option<u8> {
+        match self {
+            Self::Heartbeat(camera) | Self::Commands(camera) => Some(camera),
+            Self::Receive => None,
+        }
+    }
+
+    /// Whether camera `camera` relies on this task.
+    fn affects(self, camera: u8) -> bool {
+        self.camera().is_none_or(|own| own == camera)
+    }
+}
+
+/// Progress of a watched task, reported by the task itself.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Pulse(Arc<Mutex<PulseState>>);
+
+#[derive(Debug, Default)]
+struct PulseState {
+    /// Since when the task works on what it does now and how much longer
+    /// than usual that may take, `None` while it waits for work.
+    busy: Option<(Instant, Duration)>,
+    /// Whether the watchdog found the task stalled since it last asked.
+    stalled: bool,
+}
+
+impl Pulse {
+    /// The task made progress and goes on with something new.
+    pub fn beat(&self) {
+        self.busy_for(Duration::ZERO);
+    }
+
+    /// Like [`Pulse::beat`], for work expected to take `allowance` longer
+    /// than usual, e.g. a long exposure.
+    pub fn busy_for(&self, allowance: Duration) {
+        if let Ok(mut pulse) = self.0.lock() {
+            pulse.busy = Some((Instant::now(), allowance));
+        }
+    }
+
+    /// The task waits for work, which it can't stall on.
+    pub fn idle(&self) {
+        if let Ok(mut pulse) = self.0.lock() {
+            pulse.busy = None;
+        }
+    }
+
+    /// Whether the task was found stalled since the last call, so it can
+    /// clean up after what it was stuck on.
+    pub fn take_stalled(&self) -> bool {
+        self.0
+            .lock()
+            .is_ok_and(|mut pulse| replace(&mut pulse.stalled, false))
+    }
+
+    /// How long the task has been busy if that's beyond `timeout` and its
+    /// allowance.
+    fn stalled_for(&self, timeout: Duration) -> Option<Duration> {
+        let mut pulse = self.0.lock().ok()?;
+        let (since, allowance) = pulse.busy?;
+        let busy = since.elapsed();
+        if busy <= timeout.saturating_add(allowance) {
+            return None;
+        }
+
+        pulse.stalled = true;
+        Some(busy)
+    }
+}
+
+struct Watched {
+    task: Task,
+    pulse: Pulse,
+    reporter: WorkerReporter,
+    stalled: bool,
+}
+
+/// A camera whose heartbeat announces the stalls of the tasks it relies on.
+struct WatchedCamera {
+    header: MavHeader,
+    state: watch::Sender<CameraState>,
+}
+
+/// Checks the watched tasks, see [`run`].
+pub(crate) struct Watchdog<M> {
+    options: WatchdogOptions,
+    link: LinkSender<M>,
+    events: EventSender,
+    cameras: Vec<WatchedCamera>,
+    tasks: Vec<Watched>,
+    status_texts: StatusTexts,
+}
+
+impl<M: CameraDialect> Watchdog<M> {
+    pub fn new(options: WatchdogOptions, link: LinkSender<M>, events: EventSender) -> Self {
+        Self {
+            options,
+            link,
+            events,
+            cameras: Vec::new(),
+            tasks: Vec::new(),
+            status_texts: StatusTexts::default(),
+        }
+    }
+
+    /// Announces stalls of the tasks camera `header` relies on in its
+    /// heartbeat through `state`.
+    pub fn add_camera(&mut self, header: MavHeader, state: watch::Sender<CameraState>) {
+        self.cameras.push(WatchedCamera { header, state });
+    }
+
+    /// Watches `task`, which reports its progress on the returned pulse and
+    /// its health through `reporter`.
+    pub fn watch(&mut self, task: Task, reporter: WorkerReporter) -> Pulse {
+        let pulse = Pulse::default();
+        self.tasks.push(Watched {
+            task,
+            pulse: pulse.clone(),
+            reporter,
+            stalled: false,
+        });
+        pulse
+    }
+
+    fn check(&mut self) -> Result<()> {
+        for index in 0..self.tasks.len() {
+            let watched = &mut self.tasks[index];
+            let task = watched.task;
+            let busy = watched.pulse.stalled_for(self.options.stall_timeout);
+
+            match busy {
+                Some(busy) if !watched.stalled => {
+                    watched.stalled = true;
+                    watched.reporter.set(WorkerStatus::Degraded(format!(
+                        "stalled for {}s",
+                        busy.as_secs()
+                    )));
+                    warn!(
+                        task = task.name(),
+                        camera = task.camera(),
+                        ?busy,
+                        "Task stalled"
+                    );
+                    self.events.emit(CameraEvent::TaskStalled {
+                        task: task.name(),
+                        camera: task.camera(),
+                    });
+                    let text = format!("Camera {} task stalled", task.name());
+                    self.notify(task, MavSeverity::MAV_SEVERITY_CRITICAL, &text)?;
+                }
+                None if watched.stalled => {
+                    watched.stalled = false;
+                    watched.reporter.running();
+                    info!(task = task.name(), camera = task.camera(), "Task recovered");
+                    self.events.emit(CameraEvent::TaskRecovered {
+                        task: task.name(),
+                        camera: task.camera(),
+                    });
+                    let text = format!("Camera {} task responding again", task.name());
+                    self.notify(task, MavSeverity::MAV_SEVERITY_INFO, &text)?;
+                }
+                _ => {}
+            }
+
+            if let (Some(busy), Some(exit_after)) = (busy, self.options.exit_after) {
+                if busy >= exit_after {
+                    error!(
+                        task = task.name(),
+                        camera = task.camera(),
+                        "Task stalled for {}s, exiting to be restarted",
+                        busy.as_secs()
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        for camera in &self.cameras {
+            let id = camera.header.component_id;
+            let stalled = |task: &Watched| task.stalled && task.task.affects(id);
+            let degraded = self.tasks.iter().any(stalled);
+            camera
+                .state
+                .send_if_modified(|state| replace(&mut state.degraded, degraded) != degraded);
+
+            // Stand in for a stalled heartbeat so the camera doesn't vanish
+            // from ground stations, which would hide that it's degraded.
+            if self
+                .tasks
+                .iter()
+                .any(|task| task.stalled && task.task == Task::Heartbeat(id))
+            {
+                let state = *camera.state.borrow();
+                self.link.send(&camera.header, heartbeat_message(&state))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Tells ground stations with `STATUSTEXT` from every camera `task`
+    /// affects.
+    fn notify(&mut self, task: Task, severity: MavSeverity, text: &str) -> Result<()> {
+        for camera in &self.cameras {
+            if task.affects(camera.header.component_id) {
+                for message in self.status_texts.messages(severity, text) {
+                    self.link.send(&camera.header, message)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks the watched tasks every second until the component shuts down.
+pub(crate) async fn run<M: CameraDialect>(
+    mut watchdog: Watchdog<M>,
+    reporter: WorkerReporter,
+) -> Result<()> {
+    let mut interval = tokio::time::interval(CHECK_PERIOD);
+
+    loop {
+        interval.tick().await;
+        watchdog.check()?;
+        reporter.running();
+    }
+}
@@ -0,0 +1,2562 @@
+//! End to end tests against a simulated camera.
+//!
+//! The test plays the ground station: it listens on a loopback TCP port, lets
+//! the component connect to it and then talks raw MAVLink over that socket.
+//! Run with `cargo sitl`, or `cargo test --features sim`.
+
+use camera::backend::{
+    CameraBackend, Capabilities, CapturedImage, CorruptDownload, PendingShot, RetryOptions,
+    SimCamera, TrackingTarget,
+};
+use camera::dialect::{
+    CameraCapFlags, CameraMode, MavAutopilot, MavCmd, MavMessage, MavParamExtType, MavResult,
+    MavSeverity, MavState, MavType, ParamAck, StorageUsageFlag, COMMAND_LONG_DATA,
+    GLOBAL_POSITION_INT_DATA, HEARTBEAT_DATA, PARAM_EXT_REQUEST_LIST_DATA,
+    PARAM_EXT_REQUEST_READ_DATA, PARAM_EXT_SET_DATA, PING_DATA, STORAGE_INFORMATION_DATA,
+    SYSTEM_TIME_DATA, TIMESYNC_DATA,
+};
+use camera::{
+    BracketingOptions, CameraError, CameraEvent, CaptureLogFormat, CaptureLogOptions,
+    CaptureQueueOptions, ComponentOptions, DownloadQueueOptions, IdConflict, IdConflictCheck,
+    ImageTransmissionOptions, LiveViewServer, MavLinkCameraHandle, MavlinkCameraComponent,
+    PeerKind, QueuePolicy, StorageOptions, ThumbnailOptions, VideoStreamOptions, WatchdogOptions,
+};
+use mavlink::{MavHeader, MavlinkVersion};
+use std::io::{ErrorKind, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+
+const SYSTEM_ID: u8 = 1;
+const COMPONENT_ID: u8 = 100;
+
+/// How long to wait for an expected message before failing.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const GCS: MavHeader = MavHeader {
+    system_id: 255,
+    component_id: 190,
+    sequence: 0,
+};
+
+const AUTOPILOT: MavHeader = MavHeader {
+    system_id: SYSTEM_ID,
+    component_id: 1,
+    sequence: 0,
+};
+
+/// A running component with a simulated camera and the ground station end of
+/// its connection.
+struct Sitl {
+    handle: MavLinkCameraHandle,
+    gcs: Gcs,
+    images: TempDir,
+}
+
+impl Sitl {
+    async fn start() -> Self {
+        Self::start_with(ComponentOptions::default()).await
+    }
+
+    async fn start_with(options: ComponentOptions) -> Self {
+        Self::start_with_backend(options, |images| Box::new(SimCamera::new(images).unwrap())).await
+    }
+
+    async fn start_with_backend(
+        options: ComponentOptions,
+        backend: impl FnOnce(&Path) -> Box<dyn CameraBackend>,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let images = TempDir::new().unwrap();
+
+        let handle = MavLinkCameraHandle::try_with_options(
+            vec![format!("tcpout:{address}")],
+            vec![(
+                MavlinkCameraComponent {
+                    system_id: SYSTEM_ID,
+                    component_id: COMPONENT_ID,
+                    ..Default::default()
+                },
+                backend(images.path()),
+            )],
+            options,
+        )
+        .await
+        .unwrap();
+
+        let gcs = Gcs::accept(&listener).await;
+
+        Self {
+            handle,
+            gcs,
+            images,
+        }
+    }
+
+    /// Like [`Sitl::start_with`], with a simulated camera for each of
+    /// `component_ids` and `links` ground station connections. The first one
+    /// is `gcs`, the others are returned.
+    async fn start_with_cameras(
+        options: ComponentOptions,
+        component_ids: &[u8],
+        links: usize,
+    ) -> (Self, Vec<Gcs>) {
+        let mut listeners = Vec::new();
+        for _ in 0..links {
+            listeners.push(TcpListener::bind("127.0.0.1:0").await.unwrap());
+        }
+        let images = TempDir::new().unwrap();
+        let cameras = component_ids
+            .iter()
+            .map(|&component_id| {
+                let image_dir = images.path().join(format!("camera{component_id}"));
+                let backend: Box<dyn CameraBackend> = Box::new(SimCamera::new(image_dir).unwrap());
+                (
+                    MavlinkCameraComponent {
+                        system_id: SYSTEM_ID,
+                        component_id,
+                        ..Default::default()
+                    },
+                    backend,
+                )
+            })
+            .collect();
+
+        let handle = MavLinkCameraHandle::try_with_options(
+            listeners
+                .iter()
+                .map(|listener| format!("tcpout:{}", listener.local_addr().unwrap()))
+                .collect(),
+            cameras,
+            options,
+        )
+        .await
+        .unwrap();
+
+        let mut stations = Vec::new();
+        for listener in &listeners {
+            stations.push(Gcs::accept(listener).await);
+        }
+        let mut stations = stations.into_iter();
+        let sitl = Self {
+            handle,
+            gcs: stations.next().unwrap(),
+            images,
+        };
+        (sitl, stations.collect())
+    }
+
+    /// Like [`Sitl::start_with`], with `header` sending `heartbeat` as soon as
+    /// the component connects, before its cameras start.
+    async fn start_after_heartbeat(
+        options: ComponentOptions,
+        header: MavHeader,
+        heartbeat: HEARTBEAT_DATA,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let images = TempDir::new().unwrap();
+        let backend: Box<dyn CameraBackend> = Box::new(SimCamera::new(images.path()).unwrap());
+
+        let (handle, gcs) = tokio::join!(
+            MavLinkCameraHandle::try_with_options(
+                vec![format!("tcpout:{address}")],
+                vec![(
+                    MavlinkCameraComponent {
+                        system_id: SYSTEM_ID,
+                        component_id: COMPONENT_ID,
+                        ..Default::default()
+                    },
+                    backend,
+                )],
+                options,
+            ),
+            async {
+                let mut gcs = Gcs::accept(&listener).await;
+                gcs.send_as(header, MavMessage::HEARTBEAT(heartbeat));
+                gcs
+            }
+        );
+
+        Self {
+            handle: handle.unwrap(),
+            gcs,
+            images,
+        }
+    }
+}
+
+/// The ground station side of the link. Reads block, so tests using it run on
+/// the multi threaded runtime.
+struct Gcs {
+    stream: TcpStream,
+    /// What the ground station speaks and reads, MAVLink 2 unless a test
+    /// switches it.
+    version: MavlinkVersion,
+}
+
+impl Gcs {
+    async fn accept(listener: &TcpListener) -> Self {
+        let (stream, _) = listener.accept().await.unwrap();
+        let stream = stream.into_std().unwrap();
+        stream.set_nonblocking(false).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+
+        Self {
+            stream,
+            version: MavlinkVersion::V2,
+        }
+    }
+
+    fn send(&mut self, message: MavMessage) {
+        self.send_as(GCS, message);
+    }
+
+    fn send_as(&mut self, header: MavHeader, message: MavMessage) {
+        mavlink::write_versioned_msg(&mut self.stream, self.version, header, &message).unwrap();
+    }
+
+    fn command(&mut self, command: MavCmd, param1: f32) {
+        self.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            command,
+            param1,
+            target_system: SYSTEM_ID,
+            target_component: COMPONENT_ID,
+            ..Default::default()
+        }));
+    }
+
+    /// Reads messages from the camera until `matches` returns `Some`, skipping
+    /// everything else such as heartbeats.
+    fn expect<T>(&mut self, matches: impl FnMut(&MavMessage) -> Option<T>) -> T {
+        self.expect_from(SYSTEM_ID, COMPONENT_ID, matches)
+    }
+
+    /// Like [`Gcs::expect`] for the camera with the ids `system` and
+    /// `component`.
+    fn expect_from<T>(
+        &mut self,
+        system: u8,
+        component: u8,
+        mut matches: impl FnMut(&MavMessage) -> Option<T>,
+    ) -> T {
+        let deadline = Instant::now() + TIMEOUT;
+
+        while Instant::now() < deadline {
+            match mavlink::read_versioned_msg(&mut self.stream, self.version) {
+                Ok((header, message)) => {
+                    if header.system_id != system || header.component_id != component {
+                        continue;
+                    }
+                    if let Some(value) = matches(&message) {
+                        return value;
+                    }
+                }
+                Err(mavlink::error::MessageReadError::Io(error))
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(error) => panic!("Failed to read from the camera: {error}"),
+            }
+        }
+
+        panic!("Timed out waiting for a message from the camera");
+    }
+
+    fn expect_ack(&mut self, command: MavCmd) -> MavResult {
+        self.expect(|message| match message {
+            MavMessage::COMMAND_ACK(ack) if ack.command == command => {
+                assert_eq!(ack.target_system, GCS.system_id);
+                assert_eq!(ack.target_component, GCS.component_id);
+                Some(ack.result)
+            }
+            _ => None,
+        })
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn announces_itself_as_camera() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.expect(|message| match message {
+        MavMessage::HEARTBEAT(heartbeat) => {
+            assert_eq!(heartbeat.mavtype, MavType::MAV_TYPE_CAMERA);
+            Some(())
+        }
+        _ => None,
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn answers_camera_information_request() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 259.0);
+
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_REQUEST_MESSAGE),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_INFORMATION(_) => Some(()),
+        _ => None,
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn names_the_camera_as_it_tells() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 259.0);
+    let information = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_INFORMATION(information) => Some(information.clone()),
+        _ => None,
+    });
+    assert!(information.vendor_name.starts_with(b"Simulated\0"));
+    assert!(information.model_name.starts_with(b"SITL camera\0"));
+    assert_eq!(
+        (information.sensor_size_h, information.sensor_size_v),
+        (36.0, 27.0)
+    );
+    assert_eq!(
+        (information.resolution_h, information.resolution_v),
+        (640, 480)
+    );
+    // Without an HTTP server there's no definition to point to.
+    assert_eq!(information.cam_definition_version, 0);
+    assert!(information.cam_definition_uri.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_the_lens_and_its_focal_length() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 259.0);
+    let (lens_id, focal_length) = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_INFORMATION(information) => {
+            Some((information.lens_id, information.focal_length))
+        }
+        _ => None,
+    });
+    assert_ne!(lens_id, 0);
+    // The simulated 24-70 mm zoom starts out wide.
+    assert_eq!(focal_length, 24.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn advertises_and_moves_the_zoom() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 259.0);
+    let flags = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_INFORMATION(information) => Some(information.flags),
+        _ => None,
+    });
+    assert!(flags.contains(CameraCapFlags::CAMERA_CAP_FLAGS_HAS_BASIC_ZOOM));
+    assert!(!flags.contains(CameraCapFlags::CAMERA_CAP_FLAGS_HAS_VIDEO_STREAM));
+
+    // Zoom to half the range.
+    sitl.gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_SET_CAMERA_ZOOM,
+        param1: 2.0,
+        param2: 50.0,
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        ..Default::default()
+    }));
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_SET_CAMERA_ZOOM),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn answers_camera_settings_request_with_uptime() {
+    let mut sitl = Sitl::start().await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 260.0);
+
+    let settings = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_SETTINGS(settings) => Some(settings.clone()),
+        _ => None,
+    });
+    assert_eq!(settings.mode_id, CameraMode::CAMERA_MODE_IMAGE);
+    assert!((200..TIMEOUT.as_millis() as u32).contains(&settings.time_boot_ms));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn answers_storage_information_request() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 261.0);
+
+    let storage = sitl.gcs.expect(|message| match message {
+        MavMessage::STORAGE_INFORMATION(storage) => Some(storage.clone()),
+        _ => None,
+    });
+    assert_eq!(storage.storage_count, 2);
+    assert!(storage.total_capacity > 0.0);
+    assert_eq!(storage.used_capacity, 0.0);
+}
+
+/// Reads the `STORAGE_INFORMATION` of both simulated cards.
+fn expect_card_slots(gcs: &mut Gcs) -> [STORAGE_INFORMATION_DATA; 2] {
+    let mut slots = [(); 2].map(|_| None);
+    while slots.iter().any(Option::is_none) {
+        let storage = gcs.expect(|message| match message {
+            MavMessage::STORAGE_INFORMATION(storage) => Some(storage.clone()),
+            _ => None,
+        });
+        assert_eq!(storage.storage_count, 2);
+        let slot = usize::from(storage.storage_id) - 1;
+        slots[slot] = Some(storage);
+    }
+    slots.map(Option::unwrap)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn picks_and_formats_card_slots_independently() {
+    let mut sitl = Sitl::start().await;
+    let photo =
+        StorageUsageFlag::STORAGE_USAGE_FLAG_SET | StorageUsageFlag::STORAGE_USAGE_FLAG_PHOTO;
+    let video =
+        StorageUsageFlag::STORAGE_USAGE_FLAG_SET | StorageUsageFlag::STORAGE_USAGE_FLAG_VIDEO;
+
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_REQUEST_STORAGE_INFORMATION, 0.0);
+    let [first, second] = expect_card_slots(&mut sitl.gcs);
+    assert_eq!(first.storage_usage, photo | video);
+    assert_eq!(
+        second.storage_usage,
+        StorageUsageFlag::STORAGE_USAGE_FLAG_SET
+    );
+
+    // Photos to the second card, videos stay on the first.
+    sitl.gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_SET_STORAGE_USAGE,
+        param1: 2.0,
+        param2: StorageUsageFlag::STORAGE_USAGE_FLAG_PHOTO.bits() as f32,
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        ..Default::default()
+    }));
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_SET_STORAGE_USAGE),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    let [first, second] = expect_card_slots(&mut sitl.gcs);
+    assert_eq!(first.storage_usage, video);
+    assert_eq!(second.storage_usage, photo);
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(_) => Some(()),
+        _ => None,
+    });
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_REQUEST_STORAGE_INFORMATION, 0.0);
+    let [first, second] = expect_card_slots(&mut sitl.gcs);
+    assert_eq!(first.used_capacity, 0.0);
+    assert!(second.used_capacity > 0.0);
+
+    // Formatting the second card leaves the first alone and restarts the
+    // image index.
+    sitl.gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_STORAGE_FORMAT,
+        param1: 2.0,
+        param2: 1.0,
+        param3: 1.0,
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        ..Default::default()
+    }));
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_STORAGE_FORMAT),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    let [_, second] = expect_card_slots(&mut sitl.gcs);
+    assert_eq!(second.used_capacity, 0.0);
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    let image_index = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.image_index),
+        _ => None,
+    });
+    assert_eq!(image_index, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn captures_images() {
+    let mut sitl = Sitl::start().await;
+    let mut events = sitl.handle.subscribe();
+
+    for expected_index in 0..2 {
+        sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+
+        assert_eq!(
+            sitl.gcs.expect_ack(MavCmd::MAV_CMD_IMAGE_START_CAPTURE),
+            MavResult::MAV_RESULT_ACCEPTED
+        );
+        let (image_index, capture_result) = sitl.gcs.expect(|message| match message {
+            MavMessage::CAMERA_IMAGE_CAPTURED(captured) => {
+                Some((captured.image_index, captured.capture_result))
+            }
+            _ => None,
+        });
+        assert_eq!(image_index, expected_index);
+        assert_eq!(capture_result, 1);
+    }
+
+    let mut paths = Vec::new();
+    while paths.len() < 2 {
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let CameraEvent::ImageCaptured { camera, path, .. } = event {
+            assert_eq!(camera, COMPONENT_ID);
+            paths.push(path);
+        }
+    }
+    for path in paths {
+        assert!(path.starts_with(sitl.images.path()));
+        let image = std::fs::read(&path).unwrap();
+        assert_eq!(image[..2], [0xff, 0xd8], "{} is not a JPEG", path.display());
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn geotags_captures_with_the_autopilot_position() {
+    let mut sitl = Sitl::start().await;
+    let mut events = sitl.handle.subscribe();
+
+    sitl.gcs.send_as(
+        AUTOPILOT,
+        MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+            lat: 473_977_420,
+            lon: 85_455_940,
+            alt: 488_000,
+            relative_alt: 50_000,
+            ..Default::default()
+        }),
+    );
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+
+    let (lat, lon, relative_alt) = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => {
+            Some((captured.lat, captured.lon, captured.relative_alt))
+        }
+        _ => None,
+    });
+    assert_eq!((lat, lon, relative_alt), (473_977_420, 85_455_940, 50_000));
+
+    let path = loop {
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let CameraEvent::ImageCaptured { path, .. } = event {
+            break path;
+        }
+    };
+    let file = std::fs::File::open(path).unwrap();
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .unwrap();
+    let latitude = exif
+        .get_field(exif::Tag::GPSLatitude, exif::In::PRIMARY)
+        .unwrap();
+    assert_eq!(
+        latitude.display_value().to_string(),
+        "47 deg 23 min 51.8712 sec"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ignores_the_autopilots_of_other_vehicles() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send_as(
+        MavHeader {
+            system_id: SYSTEM_ID + 1,
+            ..AUTOPILOT
+        },
+        MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+            lat: 473_977_420,
+            lon: 85_455_940,
+            ..Default::default()
+        }),
+    );
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+
+    let (lat, lon) = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some((captured.lat, captured.lon)),
+        _ => None,
+    });
+    assert_eq!((lat, lon), (0, 0));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn timestamps_captures_with_the_autopilot_time() {
+    const VEHICLE_TIME_USEC: u64 = 1_577_836_800_000_000;
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send_as(
+        AUTOPILOT,
+        MavMessage::SYSTEM_TIME(SYSTEM_TIME_DATA {
+            time_unix_usec: VEHICLE_TIME_USEC,
+            ..Default::default()
+        }),
+    );
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+
+    let time_utc = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.time_utc),
+        _ => None,
+    });
+    let elapsed = Duration::from_micros(time_utc - VEHICLE_TIME_USEC);
+    assert!(elapsed < TIMEOUT, "{elapsed:?} after the autopilot's time");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sets_the_camera_clock_once_the_vehicle_time_is_known() {
+    let mut sitl = Sitl::start().await;
+
+    // camera::SET_CLOCK_COMMAND, in the dialect the test speaks.
+    sitl.gcs.command(MavCmd::MAV_CMD_USER_4, 0.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_USER_4),
+        MavResult::MAV_RESULT_TEMPORARILY_REJECTED
+    );
+
+    sitl.gcs.send_as(
+        AUTOPILOT,
+        MavMessage::SYSTEM_TIME(SYSTEM_TIME_DATA {
+            time_unix_usec: 1_577_836_800_000_000,
+            ..Default::default()
+        }),
+    );
+    sitl.gcs.command(MavCmd::MAV_CMD_USER_4, 0.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_USER_4),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn answers_timesync_requests() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send_as(
+        AUTOPILOT,
+        MavMessage::TIMESYNC(TIMESYNC_DATA {
+            tc1: 0,
+            ts1: 123_456_789,
+            ..Default::default()
+        }),
+    );
+
+    let (tc1, ts1) = sitl.gcs.expect(|message| match message {
+        MavMessage::TIMESYNC(timesync) => Some((timesync.tc1, timesync.ts1)),
+        _ => None,
+    });
+    assert_eq!(ts1, 123_456_789);
+    assert!(tc1 > 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn answers_ping_requests() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send(MavMessage::PING(PING_DATA {
+        time_usec: 42_000_000,
+        seq: 7,
+        target_system: 0,
+        target_component: 0,
+    }));
+
+    let ping = sitl.gcs.expect(|message| match message {
+        MavMessage::PING(ping) => Some(ping.clone()),
+        _ => None,
+    });
+    assert_eq!((ping.time_usec, ping.seq), (42_000_000, 7));
+    assert_eq!(
+        (ping.target_system, ping.target_component),
+        (GCS.system_id, GCS.component_id)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn answers_a_mavlink_1_ground_station_in_mavlink_1() {
+    let mut sitl = Sitl::start().await;
+
+    // Only MAVLink 1 frames are read from here on.
+    sitl.gcs.version = MavlinkVersion::V1;
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_IMAGE_START_CAPTURE),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    sitl.gcs.expect(|message| match message {
+        MavMessage::HEARTBEAT(_) => Some(()),
+        _ => None,
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn acts_once_on_a_command_heard_on_two_links() {
+    let (mut sitl, mut others) =
+        Sitl::start_with_cameras(ComponentOptions::default(), &[COMPONENT_ID], 2).await;
+    let mut wifi = others.remove(0);
+
+    // A ground station on both links, e.g. through a router, sends every frame
+    // over each.
+    let header = MavHeader { sequence: 7, ..GCS };
+    let capture = MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_IMAGE_START_CAPTURE,
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        ..Default::default()
+    });
+    sitl.gcs.send_as(header, capture.clone());
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_IMAGE_START_CAPTURE),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    wifi.send_as(header, capture);
+
+    // Asked over the second link, so after its copy of the capture.
+    wifi.command(MavCmd::MAV_CMD_REQUEST_CAMERA_CAPTURE_STATUS, 0.0);
+    let image_count = wifi.expect(|message| match message {
+        MavMessage::CAMERA_CAPTURE_STATUS(status) => Some(status.image_count),
+        _ => None,
+    });
+    assert_eq!(image_count, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn answers_on_the_component_id_of_each_camera() {
+    let other = COMPONENT_ID + 1;
+    let (mut sitl, _) =
+        Sitl::start_with_cameras(ComponentOptions::default(), &[COMPONENT_ID, other], 1).await;
+    let command = |gcs: &mut Gcs, target_component: u8, command: MavCmd| {
+        gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            command,
+            target_system: SYSTEM_ID,
+            target_component,
+            ..Default::default()
+        }));
+    };
+
+    command(&mut sitl.gcs, other, MavCmd::MAV_CMD_IMAGE_START_CAPTURE);
+    let result = sitl
+        .gcs
+        .expect_from(SYSTEM_ID, other, |message| match message {
+            MavMessage::COMMAND_ACK(ack) if ack.command == MavCmd::MAV_CMD_IMAGE_START_CAPTURE => {
+                Some(ack.result)
+            }
+            _ => None,
+        });
+    assert_eq!(result, MavResult::MAV_RESULT_ACCEPTED);
+
+    // Only the camera the capture was for took a picture.
+    for (component, expected) in [(COMPONENT_ID, 0), (other, 1)] {
+        command(
+            &mut sitl.gcs,
+            component,
+            MavCmd::MAV_CMD_REQUEST_CAMERA_CAPTURE_STATUS,
+        );
+        let image_count = sitl
+            .gcs
+            .expect_from(SYSTEM_ID, component, |message| match message {
+                MavMessage::CAMERA_CAPTURE_STATUS(status) => Some(status.image_count),
+                _ => None,
+            });
+        assert_eq!(image_count, expected);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refuses_cameras_of_different_vehicles() {
+    let images = TempDir::new().unwrap();
+    let cameras = [(SYSTEM_ID, COMPONENT_ID), (SYSTEM_ID + 1, COMPONENT_ID + 1)]
+        .into_iter()
+        .map(|(system_id, component_id)| {
+            let image_dir = images.path().join(format!("camera{component_id}"));
+            let backend: Box<dyn CameraBackend> = Box::new(SimCamera::new(image_dir).unwrap());
+            (
+                MavlinkCameraComponent {
+                    system_id,
+                    component_id,
+                    ..Default::default()
+                },
+                backend,
+            )
+        })
+        .collect();
+
+    let result =
+        MavLinkCameraHandle::try_with_cameras(vec!["tcpout:127.0.0.1:5762".to_owned()], cameras)
+            .await;
+    assert!(matches!(
+        result,
+        Err(CameraError::MixedSystemIds(SYSTEM_ID, 2))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tracks_ground_stations_heard_from() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send(MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+        mavtype: MavType::MAV_TYPE_GCS,
+        autopilot: MavAutopilot::MAV_AUTOPILOT_INVALID,
+        ..Default::default()
+    }));
+    // Answered after the heartbeat was handled.
+    sitl.gcs.send(MavMessage::PING(PING_DATA::default()));
+    sitl.gcs.expect(|message| match message {
+        MavMessage::PING(_) => Some(()),
+        _ => None,
+    });
+
+    let status = sitl.handle.status().unwrap();
+    let peer = status.peers[&(GCS.system_id, GCS.component_id)];
+    assert_eq!(peer.kind, PeerKind::GroundStation);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drops_bad_frames_and_keeps_receiving() {
+    let mut sitl = Sitl::start().await;
+
+    let mut corrupt = Vec::new();
+    mavlink::write_versioned_msg(
+        &mut corrupt,
+        MavlinkVersion::V2,
+        GCS,
+        &MavMessage::PING(PING_DATA::default()),
+    )
+    .unwrap();
+    *corrupt.last_mut().unwrap() ^= 0xff;
+    // Message id 0x7fffff, which no dialect has.
+    let unknown = [
+        0xfd,
+        1,
+        0,
+        0,
+        0,
+        GCS.system_id,
+        GCS.component_id,
+        0xff,
+        0xff,
+        0x7f,
+        0,
+        0,
+        0,
+    ];
+    for bytes in [&b"noise"[..], &corrupt, &unknown] {
+        sitl.gcs.stream.write_all(bytes).unwrap();
+    }
+
+    sitl.gcs.send(MavMessage::PING(PING_DATA::default()));
+    sitl.gcs.expect(|message| match message {
+        MavMessage::PING(_) => Some(()),
+        _ => None,
+    });
+
+    let status = sitl.handle.status().unwrap();
+    let frames = status.frames_by_sender[&(GCS.system_id, GCS.component_id)];
+    assert_eq!(frames.received, 1);
+    assert_eq!(frames.bad_checksum, 1);
+    assert_eq!(frames.unknown_message, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn captures_on_do_digicam_control() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_DO_DIGICAM_CONTROL,
+        param2: 40.0,
+        param4: 1.0,
+        param5: 1.0,
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        ..Default::default()
+    }));
+
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_DO_DIGICAM_CONTROL),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    let capture_result = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.capture_result),
+        _ => None,
+    });
+    assert_eq!(capture_result, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transmits_capture_previews() {
+    let mut options = ComponentOptions::default();
+    options.image_transmission = Some(ImageTransmissionOptions {
+        packet_rate: 100.0,
+        ..Default::default()
+    });
+    let mut sitl = Sitl::start_with(options).await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+
+    let (size, packets, width) = sitl.gcs.expect(|message| match message {
+        MavMessage::DATA_TRANSMISSION_HANDSHAKE(handshake) => {
+            Some((handshake.size, handshake.packets, handshake.width))
+        }
+        _ => None,
+    });
+    assert_eq!(width, 320);
+    let mut preview = Vec::new();
+    for seqnr in 0..packets {
+        let data = sitl.gcs.expect(|message| match message {
+            MavMessage::ENCAPSULATED_DATA(data) => Some(data.clone()),
+            _ => None,
+        });
+        assert_eq!(data.seqnr, seqnr);
+        preview.extend_from_slice(&data.data);
+    }
+    preview.truncate(size as usize);
+    assert_eq!(preview[..2], [0xff, 0xd8]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn names_captures_after_the_template() {
+    let mut options = ComponentOptions::default();
+    options.filename_template = Some("{camera}/{seq:03}_{name}.{ext}".parse().unwrap());
+    let mut sitl = Sitl::start_with(options).await;
+    let mut events = sitl.handle.subscribe();
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+
+    let path = loop {
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let CameraEvent::ImageCaptured { path, .. } = event {
+            break path;
+        }
+    };
+    let expected = sitl.images.path().join("100").join("000_SIM_00000.jpg");
+    assert_eq!(path, expected);
+    assert!(expected.exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keeps_the_image_counter_across_restarts() {
+    let state = TempDir::new().unwrap();
+    let mut options = ComponentOptions::default();
+    options.state_dir = Some(state.path().to_owned());
+
+    let mut sitl = Sitl::start_with(options.clone()).await;
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_REQUEST_CAMERA_CAPTURE_STATUS, 0.0);
+    let image_count = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_CAPTURE_STATUS(status) => Some(status.image_count),
+        _ => None,
+    });
+    assert_eq!(image_count, 1);
+    drop(sitl);
+
+    let mut sitl = Sitl::start_with(options).await;
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_REQUEST_CAMERA_CAPTURE_STATUS, 0.0);
+    let image_count = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_CAPTURE_STATUS(status) => Some(status.image_count),
+        _ => None,
+    });
+    assert_eq!(image_count, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn restores_the_mode_and_time_lapse_after_a_restart() {
+    let state = TempDir::new().unwrap();
+    let mut options = ComponentOptions::default();
+    options.state_dir = Some(state.path().to_owned());
+    let capture_status = |gcs: &mut Gcs| {
+        gcs.command(MavCmd::MAV_CMD_REQUEST_CAMERA_CAPTURE_STATUS, 0.0);
+        gcs.expect(|message| match message {
+            MavMessage::CAMERA_CAPTURE_STATUS(status) => {
+                Some((status.image_status, status.image_interval))
+            }
+            _ => None,
+        })
+    };
+
+    let mut sitl = Sitl::start_with(options.clone()).await;
+    for (command, param2, param3) in [
+        (MavCmd::MAV_CMD_SET_CAMERA_MODE, 2.0, 0.0),
+        (MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 60.0, 5.0),
+    ] {
+        sitl.gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            command,
+            param2,
+            param3,
+            target_system: SYSTEM_ID,
+            target_component: COMPONENT_ID,
+            ..Default::default()
+        }));
+        let result = sitl.gcs.expect(|message| match message {
+            MavMessage::COMMAND_ACK(ack) if ack.command == command => Some(ack.result),
+            _ => None,
+        });
+        assert_eq!(result, MavResult::MAV_RESULT_ACCEPTED);
+    }
+    // Answered once the commands before were handled.
+    assert_eq!(capture_status(&mut sitl.gcs).1, 60.0);
+    drop(sitl);
+
+    let mut sitl = Sitl::start_with(options).await;
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_REQUEST_CAMERA_SETTINGS, 0.0);
+    let mode = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_SETTINGS(settings) => Some(settings.mode_id),
+        _ => None,
+    });
+    assert_eq!(mode, CameraMode::CAMERA_MODE_IMAGE_SURVEY);
+    let (image_status, interval) = capture_status(&mut sitl.gcs);
+    assert_eq!(image_status & 2, 2);
+    assert_eq!(interval, 60.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refuses_captures_when_storage_is_full() {
+    let mut options = ComponentOptions::default();
+    options.storage = StorageOptions {
+        low_bytes: u64::MAX,
+        full_bytes: u64::MAX,
+        ..Default::default()
+    };
+    let mut sitl = Sitl::start_with(options).await;
+
+    // The image directory is only known after the first capture.
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    let severity = sitl.gcs.expect(|message| match message {
+        MavMessage::STATUSTEXT(status) => Some(status.severity),
+        _ => None,
+    });
+    assert_eq!(severity, MavSeverity::MAV_SEVERITY_CRITICAL);
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_IMAGE_START_CAPTURE),
+        MavResult::MAV_RESULT_DENIED
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refuses_do_digicam_control_captures_when_storage_is_full() {
+    let mut options = ComponentOptions::default();
+    options.storage = StorageOptions {
+        low_bytes: u64::MAX,
+        full_bytes: u64::MAX,
+        ..Default::default()
+    };
+    let mut sitl = Sitl::start_with(options).await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    sitl.gcs.expect(|message| match message {
+        MavMessage::STATUSTEXT(status) if status.severity == MavSeverity::MAV_SEVERITY_CRITICAL => {
+            Some(())
+        }
+        _ => None,
+    });
+
+    sitl.gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_DO_DIGICAM_CONTROL,
+        param5: 1.0,
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        ..Default::default()
+    }));
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_DO_DIGICAM_CONTROL),
+        MavResult::MAV_RESULT_DENIED
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writes_thumbnails() {
+    let mut options = ComponentOptions::default();
+    options.thumbnails = Some(ThumbnailOptions::default());
+    let mut sitl = Sitl::start_with(options).await;
+    let mut events = sitl.handle.subscribe();
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+
+    let path = loop {
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let CameraEvent::ImageCaptured { path, .. } = event {
+            break path;
+        }
+    };
+    let thumbnail = path
+        .parent()
+        .unwrap()
+        .join("thumbnails")
+        .join(path.file_stem().unwrap())
+        .with_extension("jpg");
+    let deadline = Instant::now() + TIMEOUT;
+    while !thumbnail.exists() {
+        assert!(
+            Instant::now() < deadline,
+            "No thumbnail at {}",
+            thumbnail.display()
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn captures_by_distance() {
+    let mut sitl = Sitl::start().await;
+    let position = |lat| {
+        MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+            lat,
+            lon: 85_509_000,
+            ..Default::default()
+        })
+    };
+
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_DO_SET_CAM_TRIGG_DIST, 10.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_DO_SET_CAM_TRIGG_DIST),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+
+    // 1E-4 degrees of latitude is about 11 m.
+    for lat in [473_977_000, 473_978_000] {
+        sitl.gcs.send_as(AUTOPILOT, position(lat));
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    let image_index = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.image_index),
+        _ => None,
+    });
+    assert_eq!(image_index, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn captures_when_trigger_control_enables_distance_triggering() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_DO_SET_CAM_TRIGG_DIST, 10.0);
+    sitl.gcs.command(MavCmd::MAV_CMD_DO_TRIGGER_CONTROL, 0.0);
+    sitl.gcs.command(MavCmd::MAV_CMD_DO_TRIGGER_CONTROL, 1.0);
+
+    let image_index = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.image_index),
+        _ => None,
+    });
+    assert_eq!(image_index, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_video_stream_requests_without_a_stream() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_REQUEST_VIDEO_STREAM_INFORMATION, 0.0);
+
+    assert_eq!(
+        sitl.gcs
+            .expect_ack(MavCmd::MAV_CMD_REQUEST_VIDEO_STREAM_INFORMATION),
+        MavResult::MAV_RESULT_UNSUPPORTED
+    );
+}
+
+#[cfg(feature = "ardupilotmega")]
+#[tokio::test(flavor = "multi_thread")]
+async fn sends_ardupilot_camera_feedback() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send_as(
+        AUTOPILOT,
+        MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+            lat: 473_977_420,
+            lon: 85_455_940,
+            ..Default::default()
+        }),
+    );
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+
+    let feedback = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_FEEDBACK(feedback) => Some(feedback.clone()),
+        _ => None,
+    });
+    assert_eq!((feedback.lat, feedback.lng), (473_977_420, 85_455_940));
+    assert_eq!((feedback.img_idx, feedback.cam_idx), (0, 0));
+}
+
+#[cfg(feature = "ardupilotmega")]
+#[tokio::test(flavor = "multi_thread")]
+async fn captures_on_digicam_control_message() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send_as(
+        AUTOPILOT,
+        MavMessage::DIGICAM_CONTROL(camera::dialect::DIGICAM_CONTROL_DATA {
+            target_system: SYSTEM_ID,
+            target_component: COMPONENT_ID,
+            shot: 1,
+            ..Default::default()
+        }),
+    );
+
+    let capture_result = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.capture_result),
+        _ => None,
+    });
+    assert_eq!(capture_result, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lists_camera_parameters() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send(MavMessage::PARAM_EXT_REQUEST_LIST(
+        PARAM_EXT_REQUEST_LIST_DATA {
+            target_system: SYSTEM_ID,
+            target_component: COMPONENT_ID,
+        },
+    ));
+
+    let mut names = Vec::new();
+    while names.len() < 11 {
+        let (name, count) = sitl.gcs.expect(|message| match message {
+            MavMessage::PARAM_EXT_VALUE(value) => {
+                Some((param_name(&value.param_id), value.param_count))
+            }
+            _ => None,
+        });
+        assert_eq!(count, 12);
+        names.push(name);
+    }
+    assert_eq!(
+        names,
+        [
+            "CAM_EXPMODE",
+            "CAM_APERTURE",
+            "CAM_SHUTTERSPD",
+            "CAM_ISO",
+            "CAM_WBMODE",
+            "CAM_COLORTEMP",
+            "CAM_PHOTOFMT",
+            "CAM_EV",
+            "CAM_CAPTARGET",
+            "CAM_AFSHOT",
+            "CAM_FOCUSDRV",
+            "CAM_SHUTTERCNT"
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn counts_the_shutter_and_warns_near_its_rated_life() {
+    let mut options = ComponentOptions::default();
+    options.rated_shutter_lives.insert(COMPONENT_ID, 2);
+    let mut sitl = Sitl::start_with(options).await;
+
+    for _ in 0..2 {
+        sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+        assert_eq!(
+            sitl.gcs.expect_ack(MavCmd::MAV_CMD_IMAGE_START_CAPTURE),
+            MavResult::MAV_RESULT_ACCEPTED
+        );
+    }
+    let severity = sitl.gcs.expect(|message| match message {
+        MavMessage::STATUSTEXT(status) if status.text.starts_with(b"Shutter count 2") => {
+            Some(status.severity)
+        }
+        _ => None,
+    });
+    assert_eq!(severity, MavSeverity::MAV_SEVERITY_WARNING);
+
+    let mut param_id = [0; 16];
+    param_id[..14].copy_from_slice(b"CAM_SHUTTERCNT");
+    sitl.gcs.send(MavMessage::PARAM_EXT_REQUEST_READ(
+        PARAM_EXT_REQUEST_READ_DATA {
+            target_system: SYSTEM_ID,
+            target_component: COMPONENT_ID,
+            param_id,
+            param_index: -1,
+        },
+    ));
+    let value = sitl.gcs.expect(|message| match message {
+        MavMessage::PARAM_EXT_VALUE(value) if value.param_id == param_id => {
+            Some(value.param_value.clone())
+        }
+        _ => None,
+    });
+    assert_eq!(value[..4], 2u32.to_le_bytes());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sets_exposure_parameters() {
+    let mut sitl = Sitl::start().await;
+    let mut param_id = [0; 16];
+    param_id[..7].copy_from_slice(b"CAM_ISO");
+
+    // The simulated camera's third ISO is 400.
+    sitl.gcs.send(MavMessage::PARAM_EXT_SET(PARAM_EXT_SET_DATA {
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        param_id,
+        param_value: heapless::Vec::from_slice(&2u32.to_le_bytes()).unwrap(),
+        param_type: MavParamExtType::MAV_PARAM_EXT_TYPE_UINT32,
+    }));
+    let result = sitl.gcs.expect(|message| match message {
+        MavMessage::PARAM_EXT_ACK(ack) => Some(ack.param_result),
+        _ => None,
+    });
+    assert_eq!(result, ParamAck::PARAM_ACK_ACCEPTED);
+
+    sitl.gcs.send(MavMessage::PARAM_EXT_SET(PARAM_EXT_SET_DATA {
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        param_id,
+        param_value: heapless::Vec::from_slice(&99u32.to_le_bytes()).unwrap(),
+        param_type: MavParamExtType::MAV_PARAM_EXT_TYPE_UINT32,
+    }));
+    let result = sitl.gcs.expect(|message| match message {
+        MavMessage::PARAM_EXT_ACK(ack) => Some(ack.param_result),
+        _ => None,
+    });
+    assert_eq!(result, ParamAck::PARAM_ACK_VALUE_UNSUPPORTED);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn downloads_every_file_of_raw_and_jpeg_shots() {
+    let mut sitl = Sitl::start().await;
+    let mut events = sitl.handle.subscribe();
+    let mut param_id = [0; 16];
+    param_id[..12].copy_from_slice(b"CAM_PHOTOFMT");
+
+    // RAW + JPEG.
+    sitl.gcs.send(MavMessage::PARAM_EXT_SET(PARAM_EXT_SET_DATA {
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        param_id,
+        param_value: heapless::Vec::from_slice(&2u32.to_le_bytes()).unwrap(),
+        param_type: MavParamExtType::MAV_PARAM_EXT_TYPE_UINT32,
+    }));
+    let result = sitl.gcs.expect(|message| match message {
+        MavMessage::PARAM_EXT_ACK(ack) => Some(ack.param_result),
+        _ => None,
+    });
+    assert_eq!(result, ParamAck::PARAM_ACK_ACCEPTED);
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    let path = loop {
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let CameraEvent::ImageCaptured { path, .. } = event {
+            break path;
+        }
+    };
+    assert_eq!(path, sitl.images.path().join("SIM_00000.jpg"));
+    assert!(sitl.images.path().join("SIM_00000.dng").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn captures_to_ram_without_filling_the_card() {
+    let mut sitl = Sitl::start().await;
+    let mut events = sitl.handle.subscribe();
+    let mut param_id = [0; 16];
+    param_id[..13].copy_from_slice(b"CAM_CAPTARGET");
+
+    // Internal RAM.
+    sitl.gcs.send(MavMessage::PARAM_EXT_SET(PARAM_EXT_SET_DATA {
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        param_id,
+        param_value: heapless::Vec::from_slice(&0u32.to_le_bytes()).unwrap(),
+        param_type: MavParamExtType::MAV_PARAM_EXT_TYPE_UINT32,
+    }));
+    let result = sitl.gcs.expect(|message| match message {
+        MavMessage::PARAM_EXT_ACK(ack) => Some(ack.param_result),
+        _ => None,
+    });
+    assert_eq!(result, ParamAck::PARAM_ACK_ACCEPTED);
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    let path = loop {
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let CameraEvent::ImageCaptured { path, .. } = event {
+            break path;
+        }
+    };
+    assert!(path.exists());
+
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 261.0);
+    let storage = sitl.gcs.expect(|message| match message {
+        MavMessage::STORAGE_INFORMATION(storage) => Some(storage.clone()),
+        _ => None,
+    });
+    assert_eq!(storage.used_capacity, 0.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deletes_downloads_from_the_card() {
+    let mut sitl = Sitl::start_with_backend(ComponentOptions::default(), |images| {
+        Box::new(
+            SimCamera::new(images)
+                .unwrap()
+                .with_delete_after_download(true),
+        )
+    })
+    .await;
+    let mut events = sitl.handle.subscribe();
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    let path = loop {
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let CameraEvent::ImageCaptured { path, .. } = event {
+            break path;
+        }
+    };
+    assert!(path.exists());
+
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 261.0);
+    let storage = sitl.gcs.expect(|message| match message {
+        MavMessage::STORAGE_INFORMATION(storage) => Some(storage.clone()),
+        _ => None,
+    });
+    assert_eq!(storage.used_capacity, 0.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snaps_exposure_compensation_to_the_camera_steps() {
+    let mut sitl = Sitl::start().await;
+    let mut param_id = [0; 16];
+    param_id[..6].copy_from_slice(b"CAM_EV");
+
+    sitl.gcs.send(MavMessage::PARAM_EXT_SET(PARAM_EXT_SET_DATA {
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        param_id,
+        param_value: heapless::Vec::from_slice(&0.7f32.to_le_bytes()).unwrap(),
+        param_type: MavParamExtType::MAV_PARAM_EXT_TYPE_REAL32,
+    }));
+    let (result, value) = sitl.gcs.expect(|message| match message {
+        MavMessage::PARAM_EXT_ACK(ack) => {
+            let mut bytes = [0; 4];
+            bytes[..ack.param_value.len().min(4)]
+                .copy_from_slice(&ack.param_value[..ack.param_value.len().min(4)]);
+            Some((ack.param_result, f32::from_le_bytes(bytes)))
+        }
+        _ => None,
+    });
+    assert_eq!(result, ParamAck::PARAM_ACK_ACCEPTED);
+    assert_eq!(value, 0.666);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sets_the_color_temperature_in_steps() {
+    let mut sitl = Sitl::start().await;
+    let mut param_id = [0; 16];
+    param_id[..13].copy_from_slice(b"CAM_COLORTEMP");
+
+    for (kelvin, expected) in [
+        (6500u32, ParamAck::PARAM_ACK_ACCEPTED),
+        (6550, ParamAck::PARAM_ACK_VALUE_UNSUPPORTED),
+    ] {
+        sitl.gcs.send(MavMessage::PARAM_EXT_SET(PARAM_EXT_SET_DATA {
+            target_system: SYSTEM_ID,
+            target_component: COMPONENT_ID,
+            param_id,
+            param_value: heapless::Vec::from_slice(&kelvin.to_le_bytes()).unwrap(),
+            param_type: MavParamExtType::MAV_PARAM_EXT_TYPE_UINT32,
+        }));
+        let result = sitl.gcs.expect(|message| match message {
+            MavMessage::PARAM_EXT_ACK(ack) => Some(ack.param_result),
+            _ => None,
+        });
+        assert_eq!(result, expected, "{kelvin} K");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn takes_an_exposure_bracket_per_trigger() {
+    let mut options = ComponentOptions::default();
+    options.bracketing = Some(BracketingOptions::default());
+    let mut sitl = Sitl::start_with(options).await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    for expected in 0..3 {
+        let (image_index, capture_result) = sitl.gcs.expect(|message| match message {
+            MavMessage::CAMERA_IMAGE_CAPTURED(captured) => {
+                Some((captured.image_index, captured.capture_result))
+            }
+            _ => None,
+        });
+        assert_eq!(image_index, expected);
+        assert_eq!(capture_result, 1);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn captures_a_burst_without_an_interval() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_IMAGE_START_CAPTURE,
+        param2: 0.0,
+        param3: 3.0,
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        ..Default::default()
+    }));
+    for expected in 0..3 {
+        let (image_index, capture_result) = sitl.gcs.expect(|message| match message {
+            MavMessage::CAMERA_IMAGE_CAPTURED(captured) => {
+                Some((captured.image_index, captured.capture_result))
+            }
+            _ => None,
+        });
+        assert_eq!(image_index, expected);
+        assert_eq!(capture_result, 1);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn captures_a_time_lapse_at_the_interval() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_IMAGE_START_CAPTURE,
+        param2: 0.5,
+        param3: 3.0,
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        ..Default::default()
+    }));
+    let started = Instant::now();
+    for expected in 0..3 {
+        let image_index = sitl.gcs.expect(|message| match message {
+            MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.image_index),
+            _ => None,
+        });
+        assert_eq!(image_index, expected);
+    }
+    assert!(started.elapsed() >= Duration::from_secs(1));
+
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_REQUEST_CAMERA_CAPTURE_STATUS, 0.0);
+    let (image_status, image_count) = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_CAPTURE_STATUS(status) => {
+            Some((status.image_status, status.image_count))
+        }
+        _ => None,
+    });
+    assert_eq!((image_status, image_count), (0, 3));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stops_distance_triggering_without_the_autopilot() {
+    let mut options = ComponentOptions::default();
+    options.autopilot_timeout = Some(Duration::from_secs(1));
+    let mut sitl = Sitl::start_with(options).await;
+
+    sitl.gcs
+        .send_as(AUTOPILOT, MavMessage::HEARTBEAT(HEARTBEAT_DATA::default()));
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_DO_SET_CAM_TRIGG_DIST, 10.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_DO_SET_CAM_TRIGG_DIST),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+
+    let severity = sitl.gcs.expect(|message| match message {
+        MavMessage::STATUSTEXT(status) if status.text.starts_with(b"Autopilot lost") => {
+            Some(status.severity)
+        }
+        _ => None,
+    });
+    assert_eq!(severity, MavSeverity::MAV_SEVERITY_WARNING);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pauses_the_live_view_when_overheating() {
+    let mut options = ComponentOptions::default();
+    options.video_streams.insert(
+        COMPONENT_ID,
+        VideoStreamOptions::new("rtsp://camera:8554/live"),
+    );
+    // The simulated camera idles at 35 °C.
+    options.overheat_temperature = Some(30.0);
+    let mut sitl = Sitl::start_with(options).await;
+
+    let temperature = sitl.gcs.expect(|message| match message {
+        MavMessage::NAMED_VALUE_FLOAT(value) if value.name.starts_with(b"CAM_TEMP") => {
+            Some(value.value)
+        }
+        _ => None,
+    });
+    assert_eq!(temperature, 35.0);
+    let severity = sitl.gcs.expect(|message| match message {
+        MavMessage::STATUSTEXT(status) if status.text.starts_with(b"Camera overheating") => {
+            Some(status.severity)
+        }
+        _ => None,
+    });
+    assert_eq!(severity, MavSeverity::MAV_SEVERITY_WARNING);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn changes_the_framerate_of_the_mjpeg_live_view() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut options = ComponentOptions::default();
+    options.video_streams.insert(
+        COMPONENT_ID,
+        VideoStreamOptions::new(format!("http://127.0.0.1:{port}/live")),
+    );
+    options
+        .live_views
+        .insert(COMPONENT_ID, LiveViewServer::Mjpeg { port });
+    let mut sitl = Sitl::start_with(options).await;
+
+    // camera::STREAM_SETTINGS_COMMAND, in the dialect the test speaks.
+    let change = |gcs: &mut Gcs, width: f32, height: f32, framerate: f32| {
+        gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            command: MavCmd::MAV_CMD_SPATIAL_USER_1,
+            param1: 1.0,
+            param2: width,
+            param3: height,
+            param4: framerate,
+            target_system: SYSTEM_ID,
+            target_component: COMPONENT_ID,
+            ..Default::default()
+        }));
+        gcs.expect(|message| match message {
+            MavMessage::COMMAND_ACK(ack) if ack.command == MavCmd::MAV_CMD_SPATIAL_USER_1 => {
+                Some(ack.result)
+            }
+            _ => None,
+        })
+    };
+
+    // MJPEG frames go out as the camera gives them.
+    let result = change(&mut sitl.gcs, 640.0, 480.0, 0.0);
+    assert_eq!(result, MavResult::MAV_RESULT_UNSUPPORTED);
+    let result = change(&mut sitl.gcs, 640.0, 0.0, 0.0);
+    assert_eq!(result, MavResult::MAV_RESULT_DENIED);
+
+    let result = change(&mut sitl.gcs, 0.0, 0.0, 10.0);
+    assert_eq!(result, MavResult::MAV_RESULT_ACCEPTED);
+    let information = sitl.gcs.expect(|message| match message {
+        MavMessage::VIDEO_STREAM_INFORMATION(information) => Some(information.clone()),
+        _ => None,
+    });
+    assert_eq!(information.framerate, 10.0);
+    assert_eq!(
+        (information.resolution_h, information.resolution_v),
+        (1024, 680)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn streams_messages_at_the_interval_asked_for() {
+    let mut sitl = Sitl::start().await;
+    let set_interval = |gcs: &mut Gcs, message_id: f32, interval_us: f32| {
+        gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            command: MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL,
+            param1: message_id,
+            param2: interval_us,
+            target_system: SYSTEM_ID,
+            target_component: COMPONENT_ID,
+            ..Default::default()
+        }));
+        gcs.expect_ack(MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL)
+    };
+
+    assert_eq!(
+        set_interval(&mut sitl.gcs, 262.0, 200_000.0),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_CAPTURE_STATUS(_) => Some(()),
+        _ => None,
+    });
+
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_GET_MESSAGE_INTERVAL, 262.0);
+    let interval_us = sitl.gcs.expect(|message| match message {
+        MavMessage::MESSAGE_INTERVAL(interval) if interval.message_id == 262 => {
+            Some(interval.interval_us)
+        }
+        _ => None,
+    });
+    assert_eq!(interval_us, 200_000);
+
+    // Ground stations drop components whose heartbeats stop.
+    assert_eq!(
+        set_interval(&mut sitl.gcs, 0.0, -1.0),
+        MavResult::MAV_RESULT_DENIED
+    );
+    assert_eq!(
+        set_interval(&mut sitl.gcs, 33.0, 100_000.0),
+        MavResult::MAV_RESULT_UNSUPPORTED
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn passes_the_self_test() {
+    let mut sitl = Sitl::start().await;
+
+    // camera::SELF_TEST_COMMAND, in the dialect the test speaks.
+    sitl.gcs.command(MavCmd::MAV_CMD_USER_3, 0.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_USER_3),
+        MavResult::MAV_RESULT_IN_PROGRESS
+    );
+    let summary = sitl.gcs.expect(|message| match message {
+        MavMessage::STATUSTEXT(status) if status.text.starts_with(b"Camera self-test") => Some(
+            String::from_utf8_lossy(&status.text)
+                .trim_end_matches('\0')
+                .to_owned(),
+        ),
+        _ => None,
+    });
+    assert_eq!(summary, "Camera self-test passed");
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_USER_3),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    // The test capture doesn't stay around.
+    assert_eq!(std::fs::read_dir(sitl.images.path()).unwrap().count(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refuses_to_capture_to_ram_while_keeping_pictures_on_the_card() {
+    let mut options = ComponentOptions::default();
+    options.keep_on_card = true;
+    let mut sitl = Sitl::start_with(options).await;
+    let mut param_id = [0; 16];
+    param_id[..13].copy_from_slice(b"CAM_CAPTARGET");
+
+    // The simulated camera's first target is its RAM.
+    sitl.gcs.send(MavMessage::PARAM_EXT_SET(PARAM_EXT_SET_DATA {
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        param_id,
+        param_value: heapless::Vec::from_slice(&0u32.to_le_bytes()).unwrap(),
+        param_type: MavParamExtType::MAV_PARAM_EXT_TYPE_UINT32,
+    }));
+    let result = sitl.gcs.expect(|message| match message {
+        MavMessage::PARAM_EXT_ACK(ack) => Some(ack.param_result),
+        _ => None,
+    });
+    assert_eq!(result, ParamAck::PARAM_ACK_FAILED);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn offloads_pictures_kept_on_the_card_after_the_flight() {
+    let mut options = ComponentOptions::default();
+    options.keep_on_card = true;
+    let mut sitl = Sitl::start_with(options).await;
+
+    for expected_index in 0..2 {
+        sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+        let captured = sitl.gcs.expect(|message| match message {
+            MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.clone()),
+            _ => None,
+        });
+        assert_eq!(captured.image_index, expected_index);
+        assert_eq!(captured.capture_result, 1);
+    }
+    assert_eq!(std::fs::read_dir(sitl.images.path()).unwrap().count(), 0);
+
+    // camera::OFFLOAD_COMMAND, in the dialect the test speaks.
+    sitl.gcs.command(MavCmd::MAV_CMD_USER_5, 0.0);
+    let mut progress = Vec::new();
+    loop {
+        let (result, percent) = sitl.gcs.expect(|message| match message {
+            MavMessage::COMMAND_ACK(ack) if ack.command == MavCmd::MAV_CMD_USER_5 => {
+                Some((ack.result, ack.progress))
+            }
+            _ => None,
+        });
+        if result != MavResult::MAV_RESULT_IN_PROGRESS {
+            assert_eq!(result, MavResult::MAV_RESULT_ACCEPTED);
+            break;
+        }
+        progress.push(percent);
+    }
+    assert_eq!(progress, [0, 50, 100]);
+    assert!(sitl.images.path().join("SIM_00000.jpg").exists());
+    assert!(sitl.images.path().join("SIM_00001.jpg").exists());
+
+    // Nothing is left for a second offload.
+    sitl.gcs.command(MavCmd::MAV_CMD_USER_5, 0.0);
+    let summary = sitl.gcs.expect(|message| match message {
+        MavMessage::STATUSTEXT(status) if status.text.starts_with(b"Offloaded") => Some(
+            String::from_utf8_lossy(&status.text)
+                .trim_end_matches('\0')
+                .to_owned(),
+        ),
+        _ => None,
+    });
+    assert_eq!(summary, "Offloaded 0 pictures");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_the_recording_time_and_downloads_the_movie() {
+    let log = TempDir::new().unwrap();
+    let log_path = log.path().join("captures.json");
+    let mut options = ComponentOptions::default();
+    options.download_video = true;
+    options.capture_log = Some(CaptureLogOptions {
+        path: log_path.clone(),
+        format: CaptureLogFormat::Json,
+    });
+    let mut sitl = Sitl::start_with(options).await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 259.0);
+    let flags = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_INFORMATION(information) => Some(information.flags),
+        _ => None,
+    });
+    assert!(flags.contains(CameraCapFlags::CAMERA_CAP_FLAGS_CAPTURE_VIDEO));
+
+    let mut record = |command| {
+        sitl.gcs.command(command, 0.0);
+        let result = sitl.gcs.expect(|message| match message {
+            MavMessage::COMMAND_ACK(ack) if ack.command == command => Some(ack.result),
+            _ => None,
+        });
+        assert_eq!(result, MavResult::MAV_RESULT_ACCEPTED);
+        sitl.gcs.expect(|message| match message {
+            MavMessage::CAMERA_CAPTURE_STATUS(status) => {
+                Some((status.video_status, status.recording_time_ms))
+            }
+            _ => None,
+        })
+    };
+    assert_eq!(record(MavCmd::MAV_CMD_VIDEO_START_CAPTURE).0, 1);
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    assert_eq!(record(MavCmd::MAV_CMD_VIDEO_STOP_CAPTURE), (0, 0));
+
+    sitl.gcs
+        .command(MavCmd::MAV_CMD_REQUEST_CAMERA_CAPTURE_STATUS, 0.0);
+    let status = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_CAPTURE_STATUS(status) => Some(status.clone()),
+        _ => None,
+    });
+    assert_eq!(status.video_status, 0);
+    // A movie isn't an image.
+    assert_eq!(status.image_count, 0);
+
+    let deadline = Instant::now() + TIMEOUT;
+    let line = loop {
+        let log = std::fs::read_to_string(&log_path).unwrap_or_default();
+        if let Some(line) = log.lines().next() {
+            break line.to_owned();
+        }
+        assert!(Instant::now() < deadline, "Nothing in the capture log");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    assert!(line.contains(r#""seq":-1"#), "{line}");
+    assert!(line.contains(r#""result":"ok""#), "{line}");
+    assert!(sitl.images.path().join("SIM_00000.mjpg").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn follows_tracking_targets_only_with_a_tracker() {
+    let track = |gcs: &mut Gcs, command, params: [f32; 4]| {
+        gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            command,
+            param1: params[0],
+            param2: params[1],
+            param3: params[2],
+            param4: params[3],
+            target_system: SYSTEM_ID,
+            target_component: COMPONENT_ID,
+            ..Default::default()
+        }));
+        gcs.expect(|message| match message {
+            MavMessage::COMMAND_ACK(ack) if ack.command == command => Some(ack.result),
+            _ => None,
+        })
+    };
+    let point = MavCmd::MAV_CMD_CAMERA_TRACK_POINT;
+
+    let mut sitl = Sitl::start().await;
+    let result = track(&mut sitl.gcs, point, [0.5, 0.5, 0.1, 0.0]);
+    assert_eq!(result, MavResult::MAV_RESULT_UNSUPPORTED);
+    sitl.handle.shutdown().await;
+
+    let target = Arc::new(Mutex::new(None));
+    let followed = target.clone();
+    let mut sitl = Sitl::start_with_backend(ComponentOptions::default(), move |images| {
+        Box::new(TrackingCamera {
+            camera: SimCamera::new(images).unwrap(),
+            target: followed,
+        })
+    })
+    .await;
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 259.0);
+    let flags = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_INFORMATION(information) => Some(information.flags),
+        _ => None,
+    });
+    assert!(flags.contains(CameraCapFlags::CAMERA_CAP_FLAGS_HAS_TRACKING_RECTANGLE));
+    assert!(!flags.contains(CameraCapFlags::CAMERA_CAP_FLAGS_HAS_TRACKING_POINT));
+
+    let rectangle = MavCmd::MAV_CMD_CAMERA_TRACK_RECTANGLE;
+    let result = track(&mut sitl.gcs, rectangle, [0.6, 0.2, 0.4, 0.8]);
+    assert_eq!(result, MavResult::MAV_RESULT_DENIED);
+    let result = track(&mut sitl.gcs, rectangle, [0.2, 0.2, 0.4, 0.8]);
+    assert_eq!(result, MavResult::MAV_RESULT_ACCEPTED);
+    assert_eq!(
+        *target.lock().unwrap(),
+        Some(TrackingTarget::Rectangle {
+            left: 0.2,
+            top: 0.2,
+            right: 0.4,
+            bottom: 0.8,
+        })
+    );
+    let result = track(
+        &mut sitl.gcs,
+        MavCmd::MAV_CMD_CAMERA_STOP_TRACKING,
+        [0.0; 4],
+    );
+    assert_eq!(result, MavResult::MAV_RESULT_ACCEPTED);
+    assert_eq!(*target.lock().unwrap(), None);
+}
+
+/// A simulated camera that takes a while to respond to a capture.
+struct SlowCamera {
+    camera: SimCamera,
+    delay: Duration,
+}
+
+impl CameraBackend for SlowCamera {
+    fn capture_image(&mut self) -> anyhow::Result<CapturedImage> {
+        std::thread::sleep(self.delay);
+        self.camera.capture_image()
+    }
+}
+
+/// A simulated camera whose first captures fail, like a busy camera.
+struct BusyCamera {
+    camera: SimCamera,
+    failures: u32,
+}
+
+impl CameraBackend for BusyCamera {
+    fn capture_image(&mut self) -> anyhow::Result<CapturedImage> {
+        if self.failures > 0 {
+            self.failures -= 1;
+            anyhow::bail!("Camera busy");
+        }
+        self.camera.capture_image()
+    }
+}
+
+/// A simulated camera with a tracker that follows rectangles.
+struct TrackingCamera {
+    camera: SimCamera,
+    target: Arc<Mutex<Option<TrackingTarget>>>,
+}
+
+impl CameraBackend for TrackingCamera {
+    fn capture_image(&mut self) -> anyhow::Result<CapturedImage> {
+        self.camera.capture_image()
+    }
+
+    fn capabilities(&mut self) -> anyhow::Result<Capabilities> {
+        Ok(Capabilities {
+            track_rectangle: true,
+            ..self.camera.capabilities()?
+        })
+    }
+
+    fn track(&mut self, target: TrackingTarget) -> anyhow::Result<()> {
+        *self.target.lock().unwrap() = Some(target);
+        Ok(())
+    }
+
+    fn stop_tracking(&mut self) -> anyhow::Result<()> {
+        *self.target.lock().unwrap() = None;
+        Ok(())
+    }
+}
+
+/// A simulated camera that never finds focus, counting how often it tried.
+struct UnfocusableCamera {
+    camera: SimCamera,
+    attempts: Arc<AtomicU32>,
+}
+
+impl CameraBackend for UnfocusableCamera {
+    fn capture_image(&mut self) -> anyhow::Result<CapturedImage> {
+        self.camera.capture_image()
+    }
+
+    fn capabilities(&mut self) -> anyhow::Result<Capabilities> {
+        self.camera.capabilities()
+    }
+
+    fn autofocus(&mut self, _timeout: Duration) -> anyhow::Result<()> {
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+        anyhow::bail!("No contrast")
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn focuses_before_shots_once_switched_on_and_shoots_anyway() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let backend_attempts = attempts.clone();
+    let mut sitl = Sitl::start_with_backend(ComponentOptions::default(), move |images| {
+        Box::new(UnfocusableCamera {
+            camera: SimCamera::new(images).unwrap(),
+            attempts: backend_attempts,
+        })
+    })
+    .await;
+
+    let mut param_id = [0; 16];
+    param_id[..10].copy_from_slice(b"CAM_AFSHOT");
+    sitl.gcs.send(MavMessage::PARAM_EXT_SET(PARAM_EXT_SET_DATA {
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        param_id,
+        param_value: heapless::Vec::from_slice(&1u32.to_le_bytes()).unwrap(),
+        param_type: MavParamExtType::MAV_PARAM_EXT_TYPE_UINT32,
+    }));
+    let result = sitl.gcs.expect(|message| match message {
+        MavMessage::PARAM_EXT_ACK(ack) => Some(ack.param_result),
+        _ => None,
+    });
+    assert_eq!(result, ParamAck::PARAM_ACK_ACCEPTED);
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    let capture_result = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.capture_result),
+        _ => None,
+    });
+    assert_eq!(capture_result, 1);
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn turns_down_captures_faster_than_the_minimum_interval() {
+    let mut options = ComponentOptions::default();
+    options.capture_queue = CaptureQueueOptions {
+        policy: QueuePolicy::Reject,
+        max_depth: 1,
+        min_interval: Duration::from_secs(60),
+    };
+    let mut sitl = Sitl::start_with(options).await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_IMAGE_START_CAPTURE),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(_) => Some(()),
+        _ => None,
+    });
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_IMAGE_START_CAPTURE),
+        MavResult::MAV_RESULT_TEMPORARILY_REJECTED
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retries_failed_captures() {
+    let mut options = ComponentOptions::default();
+    options.capture_retry = RetryOptions {
+        retries: 2,
+        backoff: Duration::from_millis(10),
+    };
+    let mut sitl = Sitl::start_with_backend(options, |images| {
+        Box::new(BusyCamera {
+            camera: SimCamera::new(images).unwrap(),
+            failures: 2,
+        })
+    })
+    .await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    let (image_index, capture_result) = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => {
+            Some((captured.image_index, captured.capture_result))
+        }
+        _ => None,
+    });
+    assert_eq!(image_index, 0);
+    assert_eq!(capture_result, 1);
+}
+
+/// A simulated camera whose downloads are always cut short.
+struct CorruptCamera {
+    camera: SimCamera,
+}
+
+impl CameraBackend for CorruptCamera {
+    fn capture_image(&mut self) -> anyhow::Result<CapturedImage> {
+        let image = self.camera.capture_image()?;
+        let data = std::fs::read(&image.path)?;
+        std::fs::write(&image.path, &data[..data.len() / 2])?;
+        Err(CorruptDownload {
+            path: image.path,
+            reason: "is missing its JPEG start or end marker".to_owned(),
+        }
+        .into())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flags_corrupt_downloads_in_the_capture_log() {
+    let log = TempDir::new().unwrap();
+    let log_path = log.path().join("captures.json");
+    let mut options = ComponentOptions::default();
+    options.capture_retry = RetryOptions::NONE;
+    options.capture_log = Some(CaptureLogOptions {
+        path: log_path.clone(),
+        format: CaptureLogFormat::Json,
+    });
+    let mut sitl = Sitl::start_with_backend(options, |images| {
+        Box::new(CorruptCamera {
+            camera: SimCamera::new(images).unwrap(),
+        })
+    })
+    .await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    let capture_result = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.capture_result),
+        _ => None,
+    });
+    assert_eq!(capture_result, 0);
+
+    let deadline = Instant::now() + TIMEOUT;
+    let line = loop {
+        let log = std::fs::read_to_string(&log_path).unwrap_or_default();
+        if let Some(line) = log.lines().next() {
+            break line.to_owned();
+        }
+        assert!(Instant::now() < deadline, "Nothing in the capture log");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    assert!(line.contains(r#""result":"corrupt""#), "{line}");
+    assert!(line.contains("SIM_00000.jpg"), "{line}");
+}
+
+/// A simulated camera that takes a while to download every picture.
+struct SlowDownloadCamera {
+    camera: SimCamera,
+    delay: Duration,
+}
+
+impl CameraBackend for SlowDownloadCamera {
+    fn capture_image(&mut self) -> anyhow::Result<CapturedImage> {
+        let shot = self.trigger()?;
+        self.download(shot)
+    }
+
+    fn trigger(&mut self) -> anyhow::Result<PendingShot> {
+        self.camera.trigger()
+    }
+
+    fn download(&mut self, shot: PendingShot) -> anyhow::Result<CapturedImage> {
+        std::thread::sleep(self.delay);
+        self.camera.download(shot)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn leaves_pictures_on_the_camera_while_the_download_queue_is_full() {
+    let log = TempDir::new().unwrap();
+    let log_path = log.path().join("captures.json");
+    let mut options = ComponentOptions::default();
+    options.download_queue = Some(DownloadQueueOptions { max_depth: 1 });
+    options.capture_log = Some(CaptureLogOptions {
+        path: log_path.clone(),
+        format: CaptureLogFormat::Json,
+    });
+    let mut sitl = Sitl::start_with_backend(options, |images| {
+        Box::new(SlowDownloadCamera {
+            camera: SimCamera::new(images).unwrap(),
+            delay: Duration::from_millis(500),
+        })
+    })
+    .await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    for expected_index in 0..2 {
+        let (image_index, capture_result) = sitl.gcs.expect(|message| match message {
+            MavMessage::CAMERA_IMAGE_CAPTURED(captured) => {
+                Some((captured.image_index, captured.capture_result))
+            }
+            _ => None,
+        });
+        assert_eq!(image_index, expected_index);
+        assert_eq!(capture_result, 1);
+    }
+
+    let deadline = Instant::now() + TIMEOUT;
+    let log = loop {
+        let log = std::fs::read_to_string(&log_path).unwrap_or_default();
+        if log.lines().count() == 2 {
+            break log;
+        }
+        assert!(Instant::now() < deadline, "Captures missing from the log");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    let results: Vec<_> = log
+        .lines()
+        .map(|line| line.contains(r#""result":"ok""#))
+        .collect();
+    assert_eq!(results, [true, false]);
+    assert!(log.contains(r#""result":"on_camera""#), "{log}");
+    assert!(sitl.images.path().join("SIM_00000.jpg").exists());
+    assert!(!sitl.images.path().join("SIM_00001.jpg").exists());
+}
+
+/// A simulated camera that downloads slowly and fails every trigger after
+/// the first.
+struct FailingTriggerCamera {
+    camera: SlowDownloadCamera,
+    triggered: bool,
+}
+
+impl CameraBackend for FailingTriggerCamera {
+    fn capture_image(&mut self) -> anyhow::Result<CapturedImage> {
+        let shot = self.trigger()?;
+        self.download(shot)
+    }
+
+    fn trigger(&mut self) -> anyhow::Result<PendingShot> {
+        if self.triggered {
+            anyhow::bail!("shutter jammed");
+        }
+        self.triggered = true;
+        self.camera.trigger()
+    }
+
+    fn download(&mut self, shot: PendingShot) -> anyhow::Result<CapturedImage> {
+        self.camera.download(shot)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_failed_shots_after_the_downloads_before_them() {
+    let mut options = ComponentOptions::default();
+    options.capture_retry = RetryOptions::NONE;
+    options.download_queue = Some(DownloadQueueOptions::default());
+    let mut sitl = Sitl::start_with_backend(options, |images| {
+        Box::new(FailingTriggerCamera {
+            camera: SlowDownloadCamera {
+                camera: SimCamera::new(images).unwrap(),
+                delay: Duration::from_millis(500),
+            },
+            triggered: false,
+        })
+    })
+    .await;
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    let mut results = Vec::new();
+    for _ in 0..2 {
+        results.push(sitl.gcs.expect(|message| match message {
+            MavMessage::CAMERA_IMAGE_CAPTURED(captured) => {
+                Some((captured.image_index, captured.capture_result))
+            }
+            _ => None,
+        }));
+    }
+    assert_eq!(results, [(0, 1), (1, 0)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_a_stalled_command_task() {
+    let mut options = ComponentOptions::default();
+    options.watchdog = WatchdogOptions {
+        stall_timeout: Duration::from_secs(1),
+        exit_after: None,
+    };
+    let mut sitl = Sitl::start_with_backend(options, |images| {
+        Box::new(SlowCamera {
+            camera: SimCamera::new(images).unwrap(),
+            delay: Duration::from_secs(3),
+        })
+    })
+    .await;
+    let status_text = |gcs: &mut Gcs| {
+        gcs.expect(|message| match message {
+            MavMessage::STATUSTEXT(status) if status.text.starts_with(b"Camera command") => Some(
+                String::from_utf8_lossy(&status.text)
+                    .trim_end_matches('\0')
+                    .to_owned(),
+            ),
+            _ => None,
+        })
+    };
+
+    sitl.gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    assert_eq!(status_text(&mut sitl.gcs), "Camera command task stalled");
+    sitl.gcs.expect(|message| match message {
+        MavMessage::HEARTBEAT(heartbeat) => {
+            (heartbeat.system_status == MavState::MAV_STATE_CRITICAL).then_some(())
+        }
+        _ => None,
+    });
+    assert_eq!(
+        status_text(&mut sitl.gcs),
+        "Camera command task responding again"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn takes_bulb_exposures_on_command() {
+    let mut sitl = Sitl::start().await;
+
+    // camera::BULB_COMMAND, in the dialect the test speaks.
+    for (seconds, expected) in [
+        (0.0, MavResult::MAV_RESULT_DENIED),
+        (0.5, MavResult::MAV_RESULT_ACCEPTED),
+    ] {
+        sitl.gcs.command(MavCmd::MAV_CMD_USER_2, seconds);
+        assert_eq!(sitl.gcs.expect_ack(MavCmd::MAV_CMD_USER_2), expected);
+    }
+    let capture_result = sitl.gcs.expect(|message| match message {
+        MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.capture_result),
+        _ => None,
+    });
+    assert_eq!(capture_result, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drives_the_focus_from_a_parameter_and_holds_again() {
+    let mut sitl = Sitl::start().await;
+    let mut param_id = [0; 16];
+    param_id[..12].copy_from_slice(b"CAM_FOCUSDRV");
+
+    // Far Large.
+    sitl.gcs.send(MavMessage::PARAM_EXT_SET(PARAM_EXT_SET_DATA {
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID,
+        param_id,
+        param_value: heapless::Vec::from_slice(&6u32.to_le_bytes()).unwrap(),
+        param_type: MavParamExtType::MAV_PARAM_EXT_TYPE_UINT32,
+    }));
+    let (result, value) = sitl.gcs.expect(|message| match message {
+        MavMessage::PARAM_EXT_ACK(ack) => Some((ack.param_result, ack.param_value.clone())),
+        _ => None,
+    });
+    assert_eq!(result, ParamAck::PARAM_ACK_ACCEPTED);
+    // Hold.
+    assert_eq!(value[..1], [3]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn takes_a_focus_stack_on_command() {
+    let mut sitl = Sitl::start().await;
+
+    // camera::FOCUS_STACK_COMMAND, in the dialect the test speaks.
+    sitl.gcs.command(MavCmd::MAV_CMD_USER_1, 3.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_USER_1),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    for expected in 0..3 {
+        let image_index = sitl.gcs.expect(|message| match message {
+            MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.image_index),
+            _ => None,
+        });
+        assert_eq!(image_index, expected);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn leaves_a_moved_user_command_to_the_application() {
+    let mut options = ComponentOptions::default();
+    options.commands.focus_stack = Some(31000);
+    let mut sitl = Sitl::start_with(options).await;
+
+    // Handlers get and return the common dialect's types.
+    use mavlink::common::MavResult as HandlerResult;
+    assert!(matches!(
+        sitl.handle.on_command(camera::BULB_COMMAND, |_| {
+            HandlerResult::MAV_RESULT_ACCEPTED
+        }),
+        Err(CameraError::CommandTaken(31011))
+    ));
+    sitl.handle
+        .on_command(camera::FOCUS_STACK_COMMAND, |_| {
+            HandlerResult::MAV_RESULT_DENIED
+        })
+        .unwrap();
+
+    sitl.gcs.command(MavCmd::MAV_CMD_USER_1, 3.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_USER_1),
+        MavResult::MAV_RESULT_DENIED
+    );
+    sitl.gcs.command(MavCmd::MAV_CMD_WAYPOINT_USER_1, 2.0);
+    assert_eq!(
+        sitl.gcs.expect_ack(MavCmd::MAV_CMD_WAYPOINT_USER_1),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    for expected in 0..2 {
+        let image_index = sitl.gcs.expect(|message| match message {
+            MavMessage::CAMERA_IMAGE_CAPTURED(captured) => Some(captured.image_index),
+            _ => None,
+        });
+        assert_eq!(image_index, expected);
+    }
+}
+
+fn param_name(id: &[u8; 16]) -> String {
+    let length = id.iter().position(|&byte| byte == 0).unwrap_or(id.len());
+    String::from_utf8_lossy(&id[..length]).into_owned()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn moves_a_camera_whose_ids_are_taken() {
+    let mut options = ComponentOptions::default();
+    options.id_conflict = Some(IdConflictCheck {
+        listen: Duration::from_secs(1),
+        resolution: IdConflict::Bump,
+    });
+    // Another camera already on the link.
+    let other_camera = MavHeader {
+        system_id: SYSTEM_ID,
+        component_id: COMPONENT_ID,
+        sequence: 0,
+    };
+    let mut sitl = Sitl::start_after_heartbeat(
+        options,
+        other_camera,
+        HEARTBEAT_DATA {
+            mavtype: MavType::MAV_TYPE_CAMERA,
+            autopilot: MavAutopilot::MAV_AUTOPILOT_INVALID,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    sitl.gcs
+        .expect_from(SYSTEM_ID, COMPONENT_ID + 1, |message| match message {
+            MavMessage::HEARTBEAT(_) => Some(()),
+            _ => None,
+        });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn adopts_the_autopilots_system_id() {
+    let mut options = ComponentOptions::default();
+    options.adopt_system_id = Some(TIMEOUT);
+    let autopilot = MavHeader {
+        system_id: 42,
+        ..AUTOPILOT
+    };
+    let mut sitl = Sitl::start_after_heartbeat(
+        options,
+        autopilot,
+        HEARTBEAT_DATA {
+            mavtype: MavType::MAV_TYPE_QUADROTOR,
+            autopilot: MavAutopilot::MAV_AUTOPILOT_ARDUPILOTMEGA,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    sitl.gcs
+        .expect_from(42, COMPONENT_ID, |message| match message {
+            MavMessage::CAMERA_INFORMATION(_) => Some(()),
+            _ => None,
+        });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ignores_commands_for_other_components() {
+    let mut sitl = Sitl::start().await;
+
+    sitl.gcs.send(MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_IMAGE_START_CAPTURE,
+        target_system: SYSTEM_ID,
+        target_component: COMPONENT_ID + 1,
+        ..Default::default()
+    }));
+    sitl.gcs.command(MavCmd::MAV_CMD_REQUEST_MESSAGE, 259.0);
+
+    // Acks are sent in order, so the first one has to be for the second command.
+    let command = sitl.gcs.expect(|message| match message {
+        MavMessage::COMMAND_ACK(ack) => Some(ack.command),
+        _ => None,
+    });
+    assert_eq!(command, MavCmd::MAV_CMD_REQUEST_MESSAGE);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn finishes_the_capture_in_progress_on_shutdown() {
+    let Sitl {
+        handle,
+        mut gcs,
+        images,
+    } = Sitl::start().await;
+    let mut events = handle.subscribe();
+
+    gcs.command(MavCmd::MAV_CMD_IMAGE_START_CAPTURE, 0.0);
+    assert_eq!(
+        gcs.expect_ack(MavCmd::MAV_CMD_IMAGE_START_CAPTURE),
+        MavResult::MAV_RESULT_ACCEPTED
+    );
+    tokio::time::timeout(TIMEOUT, handle.shutdown())
+        .await
+        .unwrap();
+
+    loop {
+        match events.try_recv().unwrap() {
+            CameraEvent::ImageCaptured { path, .. } => {
+                assert!(path.starts_with(images.path()));
+                assert!(path.exists());
+                break;
+            }
+            _ => continue,
+        }
+    }
+}